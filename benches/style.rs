@@ -0,0 +1,68 @@
+//! Counts the allocations of styling many nodes with interned and non-interned keywords.
+//!
+//! Run with `cargo bench --bench style`.
+
+use mini_web_browser_tutorial::css::{self, CSSValue};
+use mini_web_browser_tutorial::html;
+use mini_web_browser_tutorial::style::to_styled_node;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const NODES: usize = 10_000;
+
+fn count_allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn main() {
+    let document = html::parse(&format!(
+        "<div>{}</div>",
+        "<p class=\"a\"><span>text</span></p>".repeat(NODES)
+    ));
+    let source = "div, p { display: block; font-weight: normal; } span { display: inline; }";
+    let interned = css::parse(source);
+
+    // the same stylesheet, but every keyword owns its own string
+    let mut owned = css::parse(source);
+    for declaration in owned
+        .rules
+        .iter_mut()
+        .flat_map(|r| r.declarations.iter_mut())
+    {
+        if let CSSValue::Keyword(keyword) = &declaration.value {
+            declaration.value = CSSValue::Keyword(Cow::Owned(keyword.to_string()));
+        }
+    }
+
+    let interned_count = count_allocations(|| {
+        to_styled_node(&document, &interned);
+    });
+    let owned_count = count_allocations(|| {
+        to_styled_node(&document, &owned);
+    });
+
+    println!("styling {} nodes", NODES * 3 + 1);
+    println!("  interned keywords: {} allocations", interned_count);
+    println!("  owned keywords:    {} allocations", owned_count);
+}
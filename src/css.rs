@@ -1,12 +1,13 @@
 use super::dom::{Node, NodeType};
 use combine::{
-    choice,
+    attempt, choice, eof,
     error::StreamError,
-    many, many1, optional,
-    parser::char::{self, letter, newline, space},
-    sep_by, sep_end_by, ParseError, Parser, Stream,
+    many, many1, not_followed_by, optional, parser,
+    parser::char::{self, alpha_num, letter, newline, space},
+    satisfy, sep_by, sep_by1, sep_end_by, ParseError, Parser, Stream,
 };
-// use thiserror::Error;
+use std::{borrow::Cow, collections::BTreeMap, fmt};
+use thiserror::Error;
 
 /// `Stylesheet` represents a single stylesheet.
 /// It consists of multiple rules, which are called "rule-list" in the standard (https://www.w3.org/TR/css-syntax-3/).
@@ -19,51 +20,535 @@ impl Stylesheet {
     pub fn new(rules: Vec<Rule>) -> Self {
         Stylesheet { rules: rules }
     }
+
+    /// `merge` concatenates `stylesheets` into one keeping their order, e.g. the UA stylesheet followed by author ones.
+    /// Use `with_origin` beforehand to tell where each rule comes from.
+    pub fn merge(stylesheets: impl IntoIterator<Item = Stylesheet>) -> Self {
+        Stylesheet::new(stylesheets.into_iter().flat_map(|s| s.rules).collect())
+    }
+
+    /// `with_origin` sets `origin` to all the rules in the stylesheet.
+    pub fn with_origin(mut self, origin: Origin) -> Self {
+        for rule in &mut self.rules {
+            rule.origin = origin;
+        }
+        self
+    }
+
+    /// `rules_for` returns the rules whose selectors match `n` in the source order.
+    /// Conditions like `@media` are not evaluated. It is useful to debug the cascade with `Rule::origin`.
+    pub fn rules_for(&self, n: &Node, context: &MatchContext) -> Vec<&Rule> {
+        self.rules
+            .iter()
+            .filter(|r| r.matches(n, context))
+            .collect()
+    }
+
+    /// `filter_rules_by_selector_kind` returns the rules which have a selector of `kind`.
+    /// It is useful to find overly broad rules (e.g. universal selectors) or high-specificity rules (e.g. ID selectors).
+    pub fn filter_rules_by_selector_kind(&self, kind: SelectorKind) -> Vec<&Rule> {
+        self.rules
+            .iter()
+            .filter(|r| r.selectors.iter().any(|s| s.kind() == kind))
+            .collect()
+    }
+
+    /// `specificity_histogram` counts the selectors of all the rules by their specificity,
+    /// in the ascending order of the specificity. It is useful to see how complex a stylesheet is.
+    pub fn specificity_histogram(&self) -> BTreeMap<Specificity, usize> {
+        let mut histogram = BTreeMap::new();
+        for selector in self.rules.iter().flat_map(|r| &r.selectors) {
+            *histogram.entry(selector.specificity()).or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    /// `into_sorted` sorts the selectors of the rules in the cascade order once,
+    /// so that styling many nodes does not sort the matched declarations for each node.
+    pub fn into_sorted(self) -> SortedStylesheet {
+        let mut order = self
+            .rules
+            .iter()
+            .enumerate()
+            .flat_map(|(i, rule)| {
+                rule.selectors.iter().enumerate().map(move |(j, selector)| {
+                    let key = (
+                        rule.origin.cascade_order(false),
+                        rule.layer_order(),
+                        selector.specificity(),
+                    );
+                    (i, j, key)
+                })
+            })
+            .collect::<Vec<_>>();
+        // `sort_by_key` is stable, so the source order is kept among the same origin, layer and specificity
+        order.sort_by_key(|(_, _, key)| *key);
+        SortedStylesheet {
+            rules: self.rules,
+            order: order.into_iter().map(|(i, j, _)| (i, j)).collect(),
+        }
+    }
 }
 
-/// `Rule` represents a single CSS rule.
+/// `SortedStylesheet` is a stylesheet whose selectors are sorted in the cascade order by `Stylesheet::into_sorted`.
+///
+/// NOTE: The selectors are sorted by origin, layer, specificity and the source order,
+/// without the reversed order of the origins of `!important` declarations, which is applied in the cascade.
 #[derive(Debug, PartialEq)]
+pub struct SortedStylesheet {
+    rules: Vec<Rule>,
+    /// `order` is the pairs of the index of a rule and the index of one of its selectors
+    /// in the ascending order of the origin and the layer of the rule and the specificity of the selector.
+    pub(crate) order: Vec<(usize, usize)>,
+}
+
+impl SortedStylesheet {
+    /// `rules` returns the rules in the source order.
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+}
+
+/// `Rule` represents a single CSS rule.
+#[derive(Debug, PartialEq, Default)]
 pub struct Rule {
     pub selectors: Vec<Selector>,
     pub declarations: Vec<Declaration>,
+    /// `media` is the condition of the `@media` rule which contains this rule, if any.
+    pub media: Option<MediaQuery>,
+    /// `supports` is the condition of the `@supports` rule which contains this rule, if any.
+    pub supports: Option<SupportsCondition>,
+    pub origin: Origin,
+    /// `layer` is the position of the cascade layer which contains this rule in the order of the layers, if any.
+    /// The layers are ordered by their first appearance in the stylesheet, e.g. by `@layer base, components;`.
+    /// See https://www.w3.org/TR/css-cascade-5/#layering
+    ///
+    /// NOTE: Layers are numbered per stylesheet, so `Stylesheet::merge` does not reconcile the layers with the same name.
+    pub layer: Option<usize>,
+}
+
+/// `Origin` is the source which a rule comes from.
+/// See https://www.w3.org/TR/css-cascade-3/#cascading-origins
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Origin {
+    UserAgent,
+    User,
+    #[default]
+    Author,
+}
+
+impl Origin {
+    /// `cascade_order` returns the precedence of the origin in the cascade, where the larger one wins.
+    /// Author declarations win over user ones, which win over UA ones,
+    /// and the order is reversed for `!important` declarations.
+    /// See https://www.w3.org/TR/css-cascade-3/#cascade-origin
+    pub fn cascade_order(self, important: bool) -> usize {
+        let order = match self {
+            Origin::UserAgent => 0,
+            Origin::User => 1,
+            Origin::Author => 2,
+        };
+        if important {
+            2 - order
+        } else {
+            order
+        }
+    }
+}
+
+/// `NestedRule` is a style rule nested in another one like `.card { & .title { font-weight: bold } }`.
+/// See https://www.w3.org/TR/css-nesting-1/
+///
+/// NOTE: Nested rules cannot contain nested rules.
+#[derive(Debug, PartialEq)]
+pub struct NestedRule {
+    pub selectors: Vec<NestedSelector>,
+    pub declarations: Vec<Declaration>,
+}
+
+/// `NestedSelector` is a selector of a nested rule, which is relative to the nesting selector `&`.
+/// See https://www.w3.org/TR/css-nesting-1/#nest-selector
+#[derive(Debug, PartialEq)]
+pub enum NestedSelector {
+    /// `Parent` is `&` alone.
+    Parent,
+    /// `Compound` is `&` followed by a simple selector without whitespace, e.g. `&.active`.
+    Compound(SimpleSelector),
+    /// `Descendant` is `&` followed by whitespace and a simple selector, e.g. `& .title`.
+    Descendant(SimpleSelector),
+}
+
+impl NestedSelector {
+    /// `resolve` returns the selector where `&` is replaced with `parent`.
+    pub fn resolve(&self, parent: &SimpleSelector) -> SimpleSelector {
+        match self {
+            NestedSelector::Parent => parent.clone(),
+            // the pseudo-element stays at the end, e.g. `.a { &.b::first-line {} }` is `.a.b::first-line`
+            NestedSelector::Compound(SimpleSelector::PseudoElementSelector {
+                selector,
+                pseudo_element,
+            }) => SimpleSelector::PseudoElementSelector {
+                selector: Box::new(
+                    NestedSelector::Compound(selector.as_ref().clone()).resolve(parent),
+                ),
+                pseudo_element: *pseudo_element,
+            },
+            NestedSelector::Compound(selector) => {
+                let mut selectors = vec![];
+                for s in [parent, selector] {
+                    match s {
+                        SimpleSelector::CompoundSelector { selectors: s } => {
+                            selectors.extend(s.iter().cloned())
+                        }
+                        s => selectors.push(s.clone()),
+                    }
+                }
+                SimpleSelector::CompoundSelector { selectors }
+            }
+            NestedSelector::Descendant(SimpleSelector::PseudoElementSelector {
+                selector,
+                pseudo_element,
+            }) => SimpleSelector::PseudoElementSelector {
+                selector: Box::new(
+                    NestedSelector::Descendant(selector.as_ref().clone()).resolve(parent),
+                ),
+                pseudo_element: *pseudo_element,
+            },
+            NestedSelector::Descendant(selector) => SimpleSelector::DescendantSelector {
+                ancestor: Box::new(parent.clone()),
+                selector: Box::new(selector.clone()),
+            },
+        }
+    }
+}
+
+impl NestedRule {
+    /// `desugar` turns the nested rule into a flat rule by replacing `&` with each selector of `parent`.
+    /// The media query, the supports condition and the origin are taken from `parent`.
+    pub fn desugar(self, parent: &Rule) -> Rule {
+        Rule {
+            selectors: parent
+                .selectors
+                .iter()
+                .flat_map(|p| self.selectors.iter().map(move |s| s.resolve(p)))
+                .collect(),
+            declarations: self.declarations,
+            media: parent.media.clone(),
+            supports: parent.supports.clone(),
+            origin: parent.origin,
+            layer: parent.layer,
+        }
+    }
 }
 
 impl Rule {
-    pub fn matches(&self, n: &Box<Node>) -> bool {
-        self.selectors.iter().any(|s| s.matches(n))
+    /// `layer_order` returns the position of the rule in the cascade by its layer.
+    /// Rules in later layers win, and unlayered rules win over all the layered ones.
+    /// See https://www.w3.org/TR/css-cascade-5/#cascade-layering
+    pub fn layer_order(&self) -> usize {
+        self.layer.unwrap_or(usize::MAX)
+    }
+
+    pub fn matches(&self, n: &Node, context: &MatchContext) -> bool {
+        self.selectors.iter().any(|s| s.matches(n, context))
+    }
+
+    /// `specificity` returns the highest specificity among the selectors matching `n`,
+    /// or `None` when the rule does not match `n`.
+    pub fn specificity(&self, n: &Node, context: &MatchContext) -> Option<Specificity> {
+        self.selectors
+            .iter()
+            .filter(|s| s.matches(n, context))
+            .map(|s| s.specificity())
+            .max()
+    }
+
+    /// `pseudo_element_specificity` is the same as `specificity`,
+    /// except that it looks for the selectors of `pseudo_element` of `n`, like `p::first-letter`.
+    pub fn pseudo_element_specificity(
+        &self,
+        n: &Node,
+        context: &MatchContext,
+        pseudo_element: PseudoElement,
+    ) -> Option<Specificity> {
+        self.selectors
+            .iter()
+            .filter(|s| s.matches_pseudo_element(n, context, pseudo_element))
+            .map(|s| s.specificity())
+            .max()
+    }
+}
+
+/// `MatchContext` holds the information about the surroundings of the node being matched,
+/// which is needed by some pseudo-classes.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MatchContext<'a> {
+    /// `siblings` are the children of the parent of the node, which includes the node itself.
+    /// It is empty when the node has no parent.
+    pub siblings: &'a [Box<Node>],
+    /// `lang` is the language inherited from the closest ancestor with the `lang` attribute.
+    /// It defaults to the language of the document, i.e. the `lang` attribute of the root element.
+    /// See https://html.spec.whatwg.org/multipage/dom.html#the-lang-and-xml:lang-attributes
+    pub lang: Option<&'a str>,
+    /// `dir` is the direction inherited from the closest ancestor with the `dir` attribute,
+    /// which defaults to the direction of the document in the same way as `lang`.
+    /// See https://html.spec.whatwg.org/multipage/dom.html#the-dir-attribute
+    pub dir: Direction,
+    /// `parent` is the parent of the node and the context to match it, which is `None` for the root.
+    pub parent: Option<(&'a Node, &'a MatchContext<'a>)>,
+}
+
+impl<'a> MatchContext<'a> {
+    /// `for_document` returns the context to match `root`, the root element of a document.
+    pub fn for_document(root: &'a Node) -> Self {
+        MatchContext::default().inherit(root)
+    }
+
+    /// `for_children` returns the context to match the children of `parent`,
+    /// where `self` is the context of `parent`.
+    pub fn for_children(&'a self, parent: &'a Node) -> Self {
+        MatchContext {
+            siblings: &parent.children,
+            parent: Some((parent, self)),
+            ..self.inherit(parent)
+        }
+    }
+
+    /// `inherit` overrides the language and direction with the attributes of `n`, if any.
+    fn inherit(&self, n: &'a Node) -> Self {
+        match n.node_type {
+            NodeType::Element(ref e) => MatchContext {
+                lang: e.attributes.get("lang").map(|l| l.as_str()).or(self.lang),
+                dir: e
+                    .attributes
+                    .get("dir")
+                    .and_then(|d| Direction::from_keyword(d))
+                    .unwrap_or(self.dir),
+                ..*self
+            },
+            _ => *self,
+        }
+    }
+}
+
+/// `Direction` is the directionality of text.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Direction {
+    #[default]
+    Ltr,
+    Rtl,
+}
+
+impl Direction {
+    /// `from_keyword` returns `None` for the values other than `ltr` and `rtl`, including `auto`.
+    pub fn from_keyword(keyword: &str) -> Option<Direction> {
+        match keyword.to_ascii_lowercase().as_str() {
+            "ltr" => Some(Direction::Ltr),
+            "rtl" => Some(Direction::Rtl),
+            _ => None,
+        }
+    }
+
+    pub fn to_keyword(&self) -> &'static str {
+        match self {
+            Direction::Ltr => "ltr",
+            Direction::Rtl => "rtl",
+        }
     }
 }
 
+/// `Specificity` is the specificity of a selector, which is a tuple of
+/// the numbers of ID selectors, class-like selectors, and type selectors in it.
+/// Tuples are compared lexicographically, just as specificities are.
+/// See https://www.w3.org/TR/selectors-3/#specificity
+pub type Specificity = (usize, usize, usize);
+
 /// NOTE: This is not compliant to the standard for simplicity.
 ///
 /// In the standard, *a selector* is *a chain* of one or more sequences of simple selectors separated by combinators,
 /// where a sequence of simple selectors is a chain of simple selectors that are not separated by a combinator.
 /// Hence `Selector` is in fact something like `Vec<Vec<SimpleSelector>>`.
+/// Here the chain is nested into `DescendantSelector` and `ChildSelector` from the left instead.
 pub type Selector = SimpleSelector;
 
 /// `SimpleSelector` represents a simple selector defined in the following standard:
 /// https://www.w3.org/TR/selectors-3/#selector-syntax
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum SimpleSelector {
     UniversalSelector,
     TypeSelector {
         tag_name: String,
     },
+    /// `AttributeSelector` represents an attribute selector like `a[href=foo]`.
+    /// `tag_name` is `None` when the selector has no type selector, e.g. `[disabled]`.
     AttributeSelector {
-        tag_name: String,
+        tag_name: Option<String>,
         op: AttributeSelectorOp,
         attribute: String,
         value: String,
     },
+    /// `ClassSelector` represents one or more class selectors like `.a.b`.
+    /// It matches elements which have all of `class_names`.
     ClassSelector {
-        class_name: String,
+        class_names: Vec<String>,
+    },
+    IdSelector {
+        id: String,
+    },
+    /// `PseudoClassSelector` represents a pseudo-class like `p:first-of-type`.
+    /// `tag_name` is `None` when the selector has no type selector, e.g. `:first-of-type`.
+    PseudoClassSelector {
+        tag_name: Option<String>,
+        pseudo_class: PseudoClass,
+    },
+    /// `PseudoElementSelector` represents a pseudo-element like `p::first-letter`, which follows `selector`.
+    /// It never matches nodes by itself. Use `matches_pseudo_element` instead.
+    PseudoElementSelector {
+        selector: Box<SimpleSelector>,
+        pseudo_element: PseudoElement,
+    },
+    /// `CompoundSelector` represents a sequence of simple selectors like `.title:is(h1, h2)`,
+    /// which matches elements matching all of `selectors`.
+    ///
+    /// NOTE: Only class selectors, ID selectors and pseudo-classes can follow the first simple selector.
+    CompoundSelector {
+        selectors: Vec<SimpleSelector>,
+    },
+    /// `DescendantSelector` represents a descendant combinator like `.card .title`,
+    /// which matches elements matching `selector` that have an ancestor matching `ancestor`.
+    /// It is also made by de-sugaring nested rules like `.card { & .title {} }`.
+    /// See https://www.w3.org/TR/selectors-3/#descendant-combinators
+    DescendantSelector {
+        ancestor: Box<SimpleSelector>,
+        selector: Box<SimpleSelector>,
+    },
+    /// `ChildSelector` represents a child combinator like `ul > li`,
+    /// which matches elements matching `selector` whose parent matches `parent`.
+    /// See https://www.w3.org/TR/selectors-3/#child-combinators
+    ChildSelector {
+        parent: Box<SimpleSelector>,
+        selector: Box<SimpleSelector>,
     },
     // TODO (enhancement): support multiple attribute selectors like `a[href=bar][ping=foo]`
     // TODO (enhancement): support more attribute selectors
 }
 
+/// `SelectorKind` is the kind of a simple selector, which corresponds to a variant of `SimpleSelector`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SelectorKind {
+    Universal,
+    Type,
+    Attribute,
+    Class,
+    Id,
+    PseudoClass,
+    PseudoElement,
+    Compound,
+    Descendant,
+    Child,
+}
+
 impl SimpleSelector {
-    pub fn matches(&self, n: &Box<Node>) -> bool {
+    pub fn kind(&self) -> SelectorKind {
+        match self {
+            SimpleSelector::UniversalSelector => SelectorKind::Universal,
+            SimpleSelector::TypeSelector { .. } => SelectorKind::Type,
+            SimpleSelector::AttributeSelector { .. } => SelectorKind::Attribute,
+            SimpleSelector::ClassSelector { .. } => SelectorKind::Class,
+            SimpleSelector::IdSelector { .. } => SelectorKind::Id,
+            SimpleSelector::PseudoClassSelector { .. } => SelectorKind::PseudoClass,
+            SimpleSelector::PseudoElementSelector { .. } => SelectorKind::PseudoElement,
+            SimpleSelector::CompoundSelector { .. } => SelectorKind::Compound,
+            SimpleSelector::DescendantSelector { .. } => SelectorKind::Descendant,
+            SimpleSelector::ChildSelector { .. } => SelectorKind::Child,
+        }
+    }
+
+    /// `classes` returns the class names required by a class selector, or `None` for other selectors.
+    pub fn classes(&self) -> Option<&[String]> {
+        match self {
+            SimpleSelector::ClassSelector { class_names } => Some(class_names),
+            _ => None,
+        }
+    }
+
+    /// `is_expensive` returns whether matching the selector needs more than comparing a single attribute,
+    /// e.g. splitting attribute values or scanning siblings.
+    fn is_expensive(&self) -> bool {
+        match self {
+            SimpleSelector::AttributeSelector {
+                op: AttributeSelectorOp::Contain,
+                ..
+            }
+            | SimpleSelector::PseudoClassSelector { .. }
+            | SimpleSelector::DescendantSelector { .. }
+            | SimpleSelector::ChildSelector { .. } => true,
+            SimpleSelector::CompoundSelector { selectors } => {
+                selectors.iter().any(|s| s.is_expensive())
+            }
+            _ => false,
+        }
+    }
+
+    pub fn specificity(&self) -> Specificity {
+        match self {
+            SimpleSelector::UniversalSelector => (0, 0, 0),
+            SimpleSelector::TypeSelector { .. } => (0, 0, 1),
+            SimpleSelector::AttributeSelector { tag_name, .. } => {
+                (0, 1, if tag_name.is_some() { 1 } else { 0 })
+            }
+            SimpleSelector::ClassSelector { class_names } => (0, class_names.len(), 0),
+            SimpleSelector::IdSelector { .. } => (1, 0, 0),
+            SimpleSelector::PseudoClassSelector {
+                tag_name,
+                pseudo_class,
+            } => {
+                let type_selectors = if tag_name.is_some() { 1 } else { 0 };
+                match pseudo_class {
+                    // `:not()` itself counts nothing, but its argument does
+                    PseudoClass::Not(selector) => {
+                        let (ids, classes, types) = selector.specificity();
+                        (ids, classes, types + type_selectors)
+                    }
+                    // `:is()` counts its most specific argument, and `:where()` counts nothing
+                    PseudoClass::Is(selectors) => {
+                        let (ids, classes, types) = selectors
+                            .iter()
+                            .map(|s| s.specificity())
+                            .max()
+                            .unwrap_or_default();
+                        (ids, classes, types + type_selectors)
+                    }
+                    PseudoClass::Where(_) => (0, 0, type_selectors),
+                    _ => (0, 1, type_selectors),
+                }
+            }
+            SimpleSelector::PseudoElementSelector { selector, .. } => {
+                let (ids, classes, types) = selector.specificity();
+                (ids, classes, types + 1)
+            }
+            SimpleSelector::CompoundSelector { selectors } => selectors
+                .iter()
+                .map(|s| s.specificity())
+                .fold((0, 0, 0), |(a1, b1, c1), (a2, b2, c2)| {
+                    (a1 + a2, b1 + b2, c1 + c2)
+                }),
+            SimpleSelector::DescendantSelector {
+                ancestor: left,
+                selector: right,
+            }
+            | SimpleSelector::ChildSelector {
+                parent: left,
+                selector: right,
+            } => {
+                let (a1, b1, c1) = left.specificity();
+                let (a2, b2, c2) = right.specificity();
+                (a1 + a2, b1 + b2, c1 + c2)
+            }
+        }
+    }
+
+    pub fn matches(&self, n: &Node, context: &MatchContext) -> bool {
         match self {
             SimpleSelector::UniversalSelector => true,
             SimpleSelector::TypeSelector { tag_name } => match n.node_type {
@@ -77,39 +562,294 @@ impl SimpleSelector {
                 value,
             } => match n.node_type {
                 NodeType::Element(ref e) => {
-                    e.tag_name.as_str() == tag_name
+                    tag_name.as_ref().is_none_or(|t| &e.tag_name == t)
                         && match op {
+                            AttributeSelectorOp::Exists => e.attributes.contains_key(attribute),
                             AttributeSelectorOp::Eq => e.attributes.get(attribute) == Some(value),
-                            AttributeSelectorOp::Contain => e
-                                .attributes
-                                .get(attribute)
-                                .map(|value| {
-                                    value
-                                        .split_ascii_whitespace()
-                                        .find(|v| v == value)
-                                        .is_some()
-                                })
-                                .unwrap_or(false),
+                            AttributeSelectorOp::Contain => e.has_token(attribute, value),
                         }
                 }
                 _ => false,
             },
-            SimpleSelector::ClassSelector { class_name } => match n.node_type {
-                NodeType::Element(ref e) => e.attributes.get("class") == Some(class_name),
+            SimpleSelector::ClassSelector { class_names } => match n.node_type {
+                NodeType::Element(ref e) => {
+                    class_names.iter().all(|name| e.has_token("class", name))
+                }
+                _ => false,
+            },
+            SimpleSelector::IdSelector { id } => match n.node_type {
+                NodeType::Element(ref e) => e.attributes.get("id") == Some(id),
+                _ => false,
+            },
+            SimpleSelector::PseudoClassSelector {
+                tag_name,
+                pseudo_class,
+            } => match n.node_type {
+                NodeType::Element(ref e) => {
+                    tag_name.as_ref().is_none_or(|t| &e.tag_name == t)
+                        && pseudo_class.matches(n, context)
+                }
                 _ => false,
             },
+            SimpleSelector::PseudoElementSelector { .. } => false,
+            // the cheap selectors (e.g. type, class and ID selectors) are checked before the expensive ones,
+            // so that attribute values are split and siblings are scanned only for the candidates
+            SimpleSelector::CompoundSelector { selectors } => {
+                selectors
+                    .iter()
+                    .filter(|s| !s.is_expensive())
+                    .all(|s| s.matches(n, context))
+                    && selectors
+                        .iter()
+                        .filter(|s| s.is_expensive())
+                        .all(|s| s.matches(n, context))
+            }
+            SimpleSelector::DescendantSelector { ancestor, selector } => {
+                if !selector.matches(n, context) {
+                    return false;
+                }
+                let mut parent = context.parent;
+                while let Some((node, parent_context)) = parent {
+                    if ancestor.matches(node, parent_context) {
+                        return true;
+                    }
+                    parent = parent_context.parent;
+                }
+                false
+            }
+            SimpleSelector::ChildSelector { parent, selector } => {
+                selector.matches(n, context)
+                    && context
+                        .parent
+                        .is_some_and(|(node, parent_context)| parent.matches(node, parent_context))
+            }
+        }
+    }
+
+    /// `matches_pseudo_element` returns whether the selector represents `pseudo_element` of the element `n`.
+    pub fn matches_pseudo_element(
+        &self,
+        n: &Node,
+        context: &MatchContext,
+        pseudo_element: PseudoElement,
+    ) -> bool {
+        match (self, &n.node_type) {
+            (
+                SimpleSelector::PseudoElementSelector {
+                    selector,
+                    pseudo_element: p,
+                },
+                NodeType::Element(_),
+            ) => *p == pseudo_element && selector.matches(n, context),
+            _ => false,
+        }
+    }
+}
+
+/// `PseudoClass` represents a pseudo-class which is allowed to use.
+/// See https://www.w3.org/TR/selectors-3/#pseudo-classes to check the full list of pseudo-classes.
+#[derive(Debug, PartialEq, Clone)]
+pub enum PseudoClass {
+    FirstOfType,    // :first-of-type
+    NthOfType(Nth), // :nth-of-type(an+b)
+    OnlyChild,      // :only-child
+    OnlyOfType,     // :only-of-type
+    Lang(String),   // :lang(en)
+    /// `Not` is the negation pseudo-class like `:not(.foo)`, whose argument is a simple selector.
+    /// See https://www.w3.org/TR/selectors-3/#negation
+    Not(Box<SimpleSelector>),
+    /// `Is` is the matches-any pseudo-class like `:is(h1, h2)`,
+    /// whose specificity is the one of its most specific argument.
+    /// See https://www.w3.org/TR/selectors-4/#matches
+    Is(Vec<SimpleSelector>),
+    /// `Where` is the same as `Is` except that its specificity is always zero, e.g. `:where(h1, h2)`.
+    /// See https://www.w3.org/TR/selectors-4/#zero-matches
+    Where(Vec<SimpleSelector>),
+}
+
+impl PseudoClass {
+    pub fn matches(&self, n: &Node, context: &MatchContext) -> bool {
+        match self {
+            PseudoClass::FirstOfType => index_of_type(n, context.siblings) == 1,
+            PseudoClass::NthOfType(nth) => nth.matches(index_of_type(n, context.siblings)),
+            PseudoClass::OnlyChild => {
+                context
+                    .siblings
+                    .iter()
+                    .filter(|s| matches!(s.node_type, NodeType::Element(_)))
+                    .count()
+                    <= 1
+            }
+            PseudoClass::OnlyOfType => count_of_type(n, context.siblings) <= 1,
+            PseudoClass::Lang(range) => context
+                .inherit(n)
+                .lang
+                .is_some_and(|lang| matches_lang(lang, range)),
+            PseudoClass::Not(selector) => !selector.matches(n, context),
+            PseudoClass::Is(selectors) | PseudoClass::Where(selectors) => {
+                selectors.iter().any(|s| s.matches(n, context))
+            }
+        }
+    }
+}
+
+/// `PseudoElement` represents a pseudo-element which is allowed to use.
+/// See https://www.w3.org/TR/selectors-3/#pseudo-elements
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PseudoElement {
+    FirstLine,   // ::first-line
+    FirstLetter, // ::first-letter
+}
+
+impl PseudoElement {
+    pub const ALL: [PseudoElement; 2] = [PseudoElement::FirstLine, PseudoElement::FirstLetter];
+
+    pub fn from_name(name: &str) -> Option<PseudoElement> {
+        match name.to_ascii_lowercase().as_str() {
+            "first-line" => Some(PseudoElement::FirstLine),
+            "first-letter" => Some(PseudoElement::FirstLetter),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PseudoElement::FirstLine => "first-line",
+            PseudoElement::FirstLetter => "first-letter",
+        }
+    }
+}
+
+/// `matches_lang` returns whether `lang` is `range` or its sub-language like `en-US` for `en`,
+/// ignoring ASCII case.
+/// See https://www.w3.org/TR/selectors-3/#lang-pseudo
+fn matches_lang(lang: &str, range: &str) -> bool {
+    let lang = lang.to_ascii_lowercase();
+    let range = range.to_ascii_lowercase();
+    lang == range || lang.starts_with(&format!("{}-", range))
+}
+
+/// `index_of_type` returns the 1-based index of `n` among `siblings` with the same tag name.
+/// Siblings after `n` are not counted, so `n` is regarded as the first one if `siblings` is empty.
+fn index_of_type(n: &Node, siblings: &[Box<Node>]) -> usize {
+    let tag_name = match n.node_type {
+        NodeType::Element(ref e) => &e.tag_name,
+        _ => return 0,
+    };
+    siblings
+        .iter()
+        .take_while(|s| !std::ptr::eq(s.as_ref(), n))
+        .filter(|s| matches!(s.node_type, NodeType::Element(ref e) if &e.tag_name == tag_name))
+        .count()
+        + 1
+}
+
+/// `count_of_type` returns the number of `siblings` with the same tag name as `n`, including `n` itself.
+/// Like `index_of_type`, `siblings` is empty when `n` has no parent.
+fn count_of_type(n: &Node, siblings: &[Box<Node>]) -> usize {
+    let tag_name = match n.node_type {
+        NodeType::Element(ref e) => &e.tag_name,
+        _ => return 0,
+    };
+    siblings
+        .iter()
+        .filter(|s| matches!(s.node_type, NodeType::Element(ref e) if &e.tag_name == tag_name))
+        .count()
+}
+
+/// `Nth` represents the `an+b` notation, which matches the `a*n+b`-th elements for every non-negative integer `n`.
+/// See https://www.w3.org/TR/css-syntax-3/#anb-microsyntax
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Nth {
+    pub a: isize,
+    pub b: isize,
+}
+
+impl Nth {
+    /// `matches` returns whether the `index`-th (1-based) element is matched.
+    pub fn matches(&self, index: usize) -> bool {
+        let diff = index as isize - self.b;
+        if self.a == 0 {
+            diff == 0
+        } else {
+            diff % self.a == 0 && diff / self.a >= 0
+        }
+    }
+
+    /// `from_notation` reads notations like `odd`, `even`, `3`, `2n+1` or `-n+3`.
+    /// It returns `None` for invalid notations.
+    pub fn from_notation(s: &str) -> Option<Nth> {
+        let s = s.trim();
+        match s {
+            "odd" => return Some(Nth { a: 2, b: 1 }),
+            "even" => return Some(Nth { a: 2, b: 0 }),
+            _ => {}
+        }
+        let s = s.split_ascii_whitespace().collect::<String>();
+        match s.split_once('n') {
+            Some((a, b)) => {
+                let a = match a {
+                    "" | "+" => 1,
+                    "-" => -1,
+                    _ => a.parse().ok()?,
+                };
+                let b = match b {
+                    "" => 0,
+                    _ if b.starts_with(['+', '-']) => b.parse().ok()?,
+                    _ => return None,
+                };
+                Some(Nth { a, b })
+            }
+            None => Some(Nth {
+                a: 0,
+                b: s.parse().ok()?,
+            }),
         }
     }
 }
 
 /// `AttributeSelectorOp` is an operator which is allowed to use.
 /// See https://www.w3.org/TR/selectors-3/#attribute-selectors to check the full list of available operators.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum AttributeSelectorOp {
+    /// `Exists` is `[attr]`, which only needs the attribute to be present whatever its value is.
+    /// The `value` of the selector is empty with it.
+    Exists,
     Eq,      // =
     Contain, // ~=
 }
 
+/// `MediaQuery` represents a media query like `(prefers-color-scheme: dark) and (min-width: 600px)`,
+/// which matches when all of its features match.
+///
+/// NOTE: Media types (e.g. `screen`), `not`, `only` and comma-separated query lists are not supported for simplicity.
+/// See https://www.w3.org/TR/mediaqueries-4/#media for the full syntax.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MediaQuery {
+    pub features: Vec<MediaFeature>,
+}
+
+/// `MediaFeature` represents a single media feature like `(prefers-color-scheme: dark)`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MediaFeature {
+    pub name: String,
+    pub value: CSSValue,
+}
+
+/// `SupportsCondition` represents a condition of `@supports` like `(display: flex) and (width: 1px)`.
+/// See https://drafts.csswg.org/css-conditional-3/#at-supports
+///
+/// NOTE: Nested conditions like `((a) or (b)) and (c)` and `selector()` are not supported for simplicity.
+#[derive(Debug, PartialEq, Clone)]
+pub enum SupportsCondition {
+    Declaration(Declaration),
+    Not(Box<SupportsCondition>),
+    And(Vec<SupportsCondition>),
+    Or(Vec<SupportsCondition>),
+    /// `Unknown` is a condition which failed to be parsed. It always evaluates to false.
+    Unknown,
+}
+
 /// `Declaration` represents a CSS declaration defined at [CSS Syntax Module Level 3](https://www.w3.org/TR/css-syntax-3/#declaration)
 ///
 /// Declarations are further categorized into the followings:
@@ -117,224 +857,1697 @@ pub enum AttributeSelectorOp {
 /// - properties, which are mostly used in "qualified rules" like `.foo {bar: piyo}` https://www.w3.org/Style/CSS/all-descriptors.en.html
 ///
 /// For simplicity, we handle two types of declarations together.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Declaration {
     pub name: String,
     pub value: CSSValue,
-    // TODO (enhancement): add a field for `!important`
+    /// `important` is whether the declaration is marked with `!important`,
+    /// which makes it win over the normal declarations regardless of their specificities.
+    /// See https://www.w3.org/TR/css-cascade-3/#importance
+    pub important: bool,
 }
 
 /// `CSSValue` represents some of *component value types* defined at [CSS Values and Units Module Level 3](https://www.w3.org/TR/css-values-3/#component-types).
 #[derive(Debug, PartialEq, Clone)]
 pub enum CSSValue {
-    Keyword(String),
-    Length((usize, Unit)),
+    /// `Keyword` shares a static string for common keywords like `block` to avoid allocations.
+    /// Use `CSSValue::keyword` to create it from a string.
+    Keyword(Cow<'static, str>),
+    Length((f32, Unit)),
+    /// `Number` represents a `<number>` without a unit like `100`.
+    Number(f32),
+    /// `Ratio` represents a `<ratio>` like `16 / 9`. See https://www.w3.org/TR/css-values-4/#ratios
+    Ratio((usize, usize)),
+    /// `List` represents space-separated values like `1fr 100px`.
+    List(Vec<CSSValue>),
+    /// `Slash` is the `/` delimiter in a list like `16px/1.5`.
+    Slash,
+    /// `Comma` is the `,` delimiter in a list like `2px 2px red, 4px 4px blue`.
+    Comma,
+    /// `Color` represents a `<color>` written in the hex notation like `#00f`, or computed by `color-mix()`.
+    /// Named colors like `blue` are kept as `Keyword`, and resolved by `CSSValue::to_color`.
+    Color(Color),
+    /// `String` represents a quoted `<string>` like `"Helvetica Neue"`.
+    String(String),
+    /// `Url` represents a `<url>` like `url(image.png)`. See https://www.w3.org/TR/css-values-3/#urls
+    Url(String),
+    /// `Transform` represents a `<transform-function>` like `translate(10px, 5px)`.
+    Transform(TransformFunction),
+    /// `Function` represents a function which is not supported otherwise like `rotate(45deg)` or `calc(1px + 2px)`,
+    /// which is kept as the name and the raw arguments so that the declaration is ignored instead of failing to parse.
+    Function(String, String),
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum Unit {
-    Px,
-    Em,
-    Rem,
-    Percent,
-    // TODO (enhancement): add more units here from the definition.
+/// `COMMON_KEYWORDS` are the keywords which `CSSValue::keyword` creates without allocations.
+const COMMON_KEYWORDS: [&str; 14] = [
+    "inline",
+    "block",
+    "grid",
+    "none",
+    "normal",
+    "auto",
+    "bold",
+    "repeat",
+    "no-repeat",
+    "transparent",
+    "black",
+    "white",
+    "ltr",
+    "rtl",
+];
+
+/// `CSSValue` is serialized in the CSS syntax like `16px / 1.5`.
+/// See https://www.w3.org/TR/cssom-1/#serializing-css-values
+///
+/// NOTE: Colors are always serialized in the hex notation, e.g. `#ff000080`, unlike the standard.
+impl fmt::Display for CSSValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CSSValue::Keyword(keyword) => write!(f, "{}", keyword),
+            CSSValue::Length((num, unit)) => write!(f, "{}{}", num, unit),
+            CSSValue::Number(num) => write!(f, "{}", num),
+            CSSValue::Ratio((width, height)) => write!(f, "{} / {}", width, height),
+            CSSValue::List(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 && *value != CSSValue::Comma {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                Ok(())
+            }
+            CSSValue::Slash => write!(f, "/"),
+            CSSValue::Comma => write!(f, ","),
+            CSSValue::Color(Color { r, g, b, a: 255 }) => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+            CSSValue::Color(Color { r, g, b, a }) => {
+                write!(f, "#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+            }
+            CSSValue::String(s) => write!(f, "{:?}", s),
+            CSSValue::Url(url) => write!(f, "url({:?})", url),
+            CSSValue::Transform(TransformFunction::Translate((x, ux), (y, uy))) => {
+                write!(f, "translate({}{}, {}{})", x, ux, y, uy)
+            }
+            CSSValue::Transform(TransformFunction::Scale(x, y)) => write!(f, "scale({}, {})", x, y),
+            CSSValue::Function(name, arguments) => write!(f, "{}({})", name, arguments),
+        }
+    }
 }
 
-// #[derive(Error, Debug, PartialEq)]
-// pub enum CSSParseError {
-//     #[error("failed to parse: {0}")]
-//     InvalidResourceError(StringStreamError),
-// }
+impl CSSValue {
+    /// `keyword` creates `CSSValue::Keyword`, which refers to a static string for common keywords.
+    pub fn keyword<S: AsRef<str> + Into<String>>(keyword: S) -> CSSValue {
+        match COMMON_KEYWORDS.iter().find(|k| **k == keyword.as_ref()) {
+            Some(k) => CSSValue::Keyword(Cow::Borrowed(k)),
+            None => CSSValue::Keyword(Cow::Owned(keyword.into())),
+        }
+    }
+
+    /// `to_color` returns the color which the value represents, if any.
+    pub fn to_color(&self) -> Option<Color> {
+        match self {
+            CSSValue::Color(color) => Some(*color),
+            CSSValue::Keyword(keyword) => Color::from_keyword(keyword),
+            _ => None,
+        }
+    }
 
-pub fn parse(raw: &str) -> Stylesheet {
-    rules()
-        .parse(raw)
-        .map(|(rules, _)| Stylesheet::new(rules))
-        .unwrap()
+    /// `to_px` resolves a length into px against `context`.
+    /// It returns `None` for non-length values and flexible lengths (`fr`), which depend on the available space.
+    pub fn to_px(&self, context: &LengthContext) -> Option<f32> {
+        match self {
+            CSSValue::Length((num, unit)) => match unit {
+                Unit::Px => Some(*num),
+                Unit::Em => Some(num * context.font_size),
+                Unit::Rem => Some(num * context.root_font_size),
+                Unit::Percent => Some(context.percentage_basis * num / 100.0),
+                Unit::Fr => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// `add` sums two lengths in the same unit, or two numbers.
+    /// It returns `None` for the other combinations including lengths in different units like `1em + 10px`,
+    /// which need to be resolved by `to_px` first.
+    pub fn add(&self, other: &CSSValue) -> Option<CSSValue> {
+        match (self, other) {
+            (CSSValue::Length((a, unit)), CSSValue::Length((b, other_unit)))
+                if unit == other_unit =>
+            {
+                Some(CSSValue::Length((a + b, unit.clone())))
+            }
+            (CSSValue::Number(a), CSSValue::Number(b)) => Some(CSSValue::Number(a + b)),
+            _ => None,
+        }
+    }
+
+    /// `scale` multiplies a length or a number by `factor`. It returns `None` for the other values.
+    pub fn scale(&self, factor: f32) -> Option<CSSValue> {
+        match self {
+            CSSValue::Length((num, unit)) => Some(CSSValue::Length((num * factor, unit.clone()))),
+            CSSValue::Number(num) => Some(CSSValue::Number(num * factor)),
+            _ => None,
+        }
+    }
 }
 
-fn whitespaces<Input>() -> impl Parser<Input, Output = String>
-where
-    Input: Stream<Token = char>,
-    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
-{
-    many::<String, _, _>(space().or(newline()))
+/// `LengthContext` holds the values which relative lengths are resolved against.
+/// See https://www.w3.org/TR/css-values-3/#relative-lengths
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LengthContext {
+    /// `font_size` is the computed font size of the element in px, which `em` refers to.
+    pub font_size: f32,
+    /// `root_font_size` is the computed font size of the root element in px, which `rem` refers to.
+    pub root_font_size: f32,
+    /// `percentage_basis` is the length in px which `100%` refers to, e.g. the width of the containing block.
+    pub percentage_basis: f32,
 }
 
-fn rules<Input>() -> impl Parser<Input, Output = Vec<Rule>>
-where
-    Input: Stream<Token = char>,
-    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
-{
-    (whitespaces(), many(rule().skip(whitespaces()))).map(|(_, rules)| rules)
+/// `Color` is a color in the sRGB color space with an alpha channel.
+/// See https://www.w3.org/TR/css-color-4/
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
 }
 
-fn rule<Input>() -> impl Parser<Input, Output = Rule>
-where
+/// `NAMED_COLORS` is the named colors in CSS sorted by name, with their RGB values in `0xRRGGBB`.
+/// See https://www.w3.org/TR/css-color-4/#named-colors
+const NAMED_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+impl Color {
+    pub fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Color { r, g, b, a: 255 }
+    }
+
+    /// `from_hex` reads the digits of the hex notation like `00f`, `00ff` or `0000ff`.
+    /// See https://www.w3.org/TR/css-color-4/#hex-notation
+    pub fn from_hex(hex: &str) -> Option<Color> {
+        let digits = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<Vec<_>>>()?;
+        match digits[..] {
+            [r, g, b] => Some(Color::rgb(r * 17, g * 17, b * 17)),
+            [r, g, b, a] => Some(Color {
+                r: r * 17,
+                g: g * 17,
+                b: b * 17,
+                a: a * 17,
+            }),
+            [r1, r2, g1, g2, b1, b2] => Some(Color::rgb(r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2)),
+            [r1, r2, g1, g2, b1, b2, a1, a2] => Some(Color {
+                r: r1 * 16 + r2,
+                g: g1 * 16 + g2,
+                b: b1 * 16 + b2,
+                a: a1 * 16 + a2,
+            }),
+            _ => None,
+        }
+    }
+
+    /// `from_rgb_function` evaluates the arguments of `rgb()` or `rgba()`
+    /// like `255, 0, 0`, `100% 0% 0% / 50%` or `255, 0, 0, 0.5`.
+    /// Channels are numbers from 0 to 255 or percentages, and the alpha is a number from 0 to 1 or a percentage.
+    /// Out-of-range values are clamped.
+    /// See https://www.w3.org/TR/css-color-4/#rgb-functions
+    ///
+    /// NOTE: Mixing numbers and percentages, or commas and whitespaces, is accepted for simplicity.
+    pub fn from_rgb_function(arguments: &str) -> Option<Color> {
+        let (channels, alpha) = match arguments.split_once('/') {
+            Some((channels, alpha)) => (channels, Some(alpha.trim())),
+            None => (arguments, None),
+        };
+        let tokens = channels
+            .split(|c: char| c == ',' || c.is_ascii_whitespace())
+            .filter(|t| !t.is_empty())
+            .collect::<Vec<_>>();
+        let (channels, alpha) = match (&tokens[..], alpha) {
+            (&[r, g, b], alpha) => ([r, g, b], alpha),
+            (&[r, g, b, a], None) => ([r, g, b], Some(a)),
+            _ => return None,
+        };
+        // `ratio` reads a number or a percentage as the ratio to `max`
+        let ratio = |token: &str, max: f32| match token.strip_suffix('%') {
+            Some(percentage) => percentage.parse::<f32>().ok().map(|p| p / 100.0),
+            None => token.parse::<f32>().ok().map(|n| n / max),
+        };
+        let to_u8 = |ratio: f32| (ratio.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let [r, g, b] = channels.map(|c| ratio(c, 255.0).map(to_u8));
+        Some(Color {
+            r: r?,
+            g: g?,
+            b: b?,
+            a: match alpha {
+                Some(alpha) => to_u8(ratio(alpha, 1.0)?),
+                None => 255,
+            },
+        })
+    }
+
+    /// `from_keyword` resolves a named color or `transparent`, which is case-insensitive.
+    pub fn from_keyword(keyword: &str) -> Option<Color> {
+        let keyword = keyword.to_ascii_lowercase();
+        if keyword == "transparent" {
+            return Some(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            });
+        }
+        let index = NAMED_COLORS
+            .binary_search_by(|(name, _)| name.cmp(&keyword.as_str()))
+            .ok()?;
+        let rgb = NAMED_COLORS[index].1;
+        Some(Color::rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+    }
+
+    /// `mix` blends the color with `other` in sRGB, where `weight` is the proportion of this color from 0 to 1.
+    /// The channels are interpolated with premultiplied alpha.
+    /// See https://www.w3.org/TR/css-color-5/#color-mix-result
+    pub fn mix(self, other: Color, weight: f32) -> Color {
+        let alpha = |c: Color| c.a as f32 / 255.0;
+        let (w1, w2) = (weight * alpha(self), (1.0 - weight) * alpha(other));
+        let a = w1 + w2;
+        let channel = |c1: u8, c2: u8| {
+            if a == 0.0 {
+                0
+            } else {
+                ((c1 as f32 * w1 + c2 as f32 * w2) / a).round() as u8
+            }
+        };
+        Color {
+            r: channel(self.r, other.r),
+            g: channel(self.g, other.g),
+            b: channel(self.b, other.b),
+            a: (a * 255.0).round() as u8,
+        }
+    }
+
+    /// `from_color_mix` evaluates the arguments of `color-mix()` like `in srgb, red 50%, blue`.
+    /// Omitted percentages are complemented to 100% (or 50% each), and the percentages are normalized to sum to 100%.
+    /// See https://www.w3.org/TR/css-color-5/#color-mix
+    ///
+    /// NOTE: Colors are always mixed in sRGB, and the other color spaces fall back to it.
+    /// Only hex colors and named colors are accepted as the colors to mix.
+    pub fn from_color_mix(arguments: &str) -> Option<Color> {
+        let arguments = arguments.split(',').map(|a| a.trim()).collect::<Vec<_>>();
+        let (space, colors) = arguments.split_first()?;
+        let _ = space.strip_prefix("in ")?;
+        let colors = colors
+            .iter()
+            .map(|argument| {
+                let mut tokens = argument.split_ascii_whitespace();
+                let color = tokens.next()?;
+                let color = match color.strip_prefix('#') {
+                    Some(hex) => Color::from_hex(hex)?,
+                    None => Color::from_keyword(color)?,
+                };
+                let percentage = match tokens.next() {
+                    Some(token) => Some(token.strip_suffix('%')?.parse::<f32>().ok()?),
+                    None => None,
+                };
+                match tokens.next() {
+                    Some(_) => None,
+                    None => Some((color, percentage)),
+                }
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let (p1, p2) = match colors[..] {
+            [(_, Some(p1)), (_, Some(p2))] => (p1, p2),
+            [(_, Some(p1)), (_, None)] => (p1, 100.0 - p1),
+            [(_, None), (_, Some(p2))] => (100.0 - p2, p2),
+            [(_, None), (_, None)] => (50.0, 50.0),
+            _ => return None,
+        };
+        if p1 < 0.0 || p2 < 0.0 || p1 + p2 == 0.0 {
+            return None;
+        }
+        Some(colors[0].0.mix(colors[1].0, p1 / (p1 + p2)))
+    }
+}
+
+/// `TransformFunction` is a 2D `<transform-function>` which moves or scales a box.
+/// See https://www.w3.org/TR/css-transforms-1/#two-d-transform-functions
+///
+/// NOTE: Only `translate()` and `scale()` and their `X`/`Y` variants are supported.
+#[derive(Debug, PartialEq, Clone)]
+pub enum TransformFunction {
+    /// `Translate` moves a box by the lengths, whose percentages refer to the size of the border box.
+    Translate((f32, Unit), (f32, Unit)),
+    Scale(f32, f32),
+}
+
+impl TransformFunction {
+    /// `from_notation` creates the function from its name and arguments like `translate` and `10px, 5px`.
+    /// An omitted argument is `0` for `translate()`, and the same as the first one for `scale()`.
+    pub fn from_notation(name: &str, arguments: &str) -> Option<TransformFunction> {
+        let arguments = arguments.split(',').map(|a| a.trim()).collect::<Vec<_>>();
+        let length = |s: &str| {
+            let (number, unit) = [
+                ("px", Unit::Px),
+                ("rem", Unit::Rem),
+                ("em", Unit::Em),
+                ("%", Unit::Percent),
+            ]
+            .into_iter()
+            .find_map(|(suffix, unit)| s.strip_suffix(suffix).map(|n| (n, unit)))
+            .unwrap_or((s, Unit::Px));
+            match number.parse::<f32>() {
+                // unitless lengths must be zero
+                Ok(n) if n == 0.0 || number != s => Some((n, unit)),
+                _ => None,
+            }
+        };
+        let number = |s: &str| s.parse::<f32>().ok();
+        let zero = (0.0, Unit::Px);
+        match (name.to_ascii_lowercase().as_str(), &arguments[..]) {
+            ("translate", [x]) => Some(TransformFunction::Translate(length(x)?, zero)),
+            ("translate", [x, y]) => Some(TransformFunction::Translate(length(x)?, length(y)?)),
+            ("translatex", [x]) => Some(TransformFunction::Translate(length(x)?, zero)),
+            ("translatey", [y]) => Some(TransformFunction::Translate(zero, length(y)?)),
+            ("scale", [s]) => number(s).map(|s| TransformFunction::Scale(s, s)),
+            ("scale", [x, y]) => Some(TransformFunction::Scale(number(x)?, number(y)?)),
+            ("scalex", [x]) => Some(TransformFunction::Scale(number(x)?, 1.0)),
+            ("scaley", [y]) => Some(TransformFunction::Scale(1.0, number(y)?)),
+            _ => None,
+        }
+    }
+}
+
+/// `BackgroundPosition` is the position of a background image given by `background-position` like `right top`.
+/// Each offset is a length or a percentage, which refers to the size of the rect minus the size of the image,
+/// so that `100%` aligns the right or bottom edge of the image with the one of the rect.
+/// Percentages are kept as they are since the image size is only known to a rasterizer.
+/// See https://www.w3.org/TR/css-backgrounds-3/#background-position
+///
+/// NOTE: Only one or two values are supported, e.g. `right 10px bottom 20px` is invalid.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BackgroundPosition {
+    pub x: (f32, Unit),
+    pub y: (f32, Unit),
+}
+
+impl BackgroundPosition {
+    /// `INITIAL` is the initial value `0% 0%`, i.e. the top-left corner.
+    pub const INITIAL: BackgroundPosition = BackgroundPosition {
+        x: (0.0, Unit::Percent),
+        y: (0.0, Unit::Percent),
+    };
+
+    /// `from_value` parses the value of `background-position`. It returns `None` for invalid values.
+    /// A single value is for the horizontal position unless it is `top` or `bottom`, and the other one is `center`.
+    /// Two keywords can be in either order like `top left`, while lengths are in the order of x and y.
+    pub fn from_value(value: &CSSValue) -> Option<BackgroundPosition> {
+        let values = match value {
+            CSSValue::List(values) => values.as_slice(),
+            value => std::slice::from_ref(value),
+        };
+        let keyword = |value: &CSSValue, keywords: [&str; 3]| match value {
+            CSSValue::Keyword(keyword) => keywords
+                .iter()
+                .position(|k| k == keyword)
+                .map(|i| (i as f32 * 50.0, Unit::Percent)),
+            _ => None,
+        };
+        let horizontal = |value| keyword(value, ["left", "center", "right"]);
+        let vertical = |value| keyword(value, ["top", "center", "bottom"]);
+        let offset = |value: &CSSValue| match value {
+            CSSValue::Length((_, Unit::Fr)) => None,
+            CSSValue::Length(length) => Some(length.clone()),
+            CSSValue::Number(num) if *num == 0.0 => Some((0.0, Unit::Px)),
+            _ => None,
+        };
+        let center = (50.0, Unit::Percent);
+        let (x, y) = match values {
+            [value] => match (horizontal(value).or_else(|| offset(value)), vertical(value)) {
+                (Some(x), _) => (x, center),
+                (None, Some(y)) => (center, y),
+                (None, None) => return None,
+            },
+            [first, second] => match (
+                horizontal(first).or_else(|| offset(first)),
+                vertical(second).or_else(|| offset(second)),
+            ) {
+                (Some(x), Some(y)) => (x, y),
+                _ => (horizontal(second)?, vertical(first)?),
+            },
+            _ => return None,
+        };
+        Some(BackgroundPosition { x, y })
+    }
+}
+
+/// `BoxShadow` is a shadow given by `box-shadow` like `2px 2px 4px black`.
+/// See https://www.w3.org/TR/css-backgrounds-3/#box-shadow
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BoxShadow {
+    pub offset_x: f32,
+    pub offset_y: f32,
+    pub blur: f32,
+    pub spread: f32,
+    pub color: Color,
+}
+
+impl BoxShadow {
+    /// `from_values` parses one of the comma-separated shadows of `box-shadow`.
+    /// It returns `None` for invalid shadows and `inset` ones.
+    /// `current_color` is used when the color is omitted, and relative lengths are resolved against `context`.
+    ///
+    /// NOTE: Inner shadows by `inset` are not supported.
+    pub fn from_values(
+        values: &[CSSValue],
+        current_color: Color,
+        context: &LengthContext,
+    ) -> Option<BoxShadow> {
+        let mut lengths = vec![];
+        let mut color = None;
+        for value in values {
+            match value {
+                CSSValue::Length(_) => lengths.push(value.to_px(context)?),
+                CSSValue::Number(n) if *n == 0.0 => lengths.push(0.0),
+                _ if color.is_none() && value.to_color().is_some() => color = value.to_color(),
+                _ => return None,
+            }
+        }
+        let (offset_x, offset_y, blur, spread) = match lengths[..] {
+            [x, y] => (x, y, 0.0, 0.0),
+            [x, y, blur] => (x, y, blur, 0.0),
+            [x, y, blur, spread] => (x, y, blur, spread),
+            _ => return None,
+        };
+        if blur < 0.0 {
+            return None;
+        }
+        Some(BoxShadow {
+            offset_x,
+            offset_y,
+            blur,
+            spread,
+            color: color.unwrap_or(current_color),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Unit {
+    Px,
+    Em,
+    Rem,
+    Percent,
+    /// `Fr` is a flexible length used in grid layout. See https://drafts.csswg.org/css-grid/#fr-unit
+    Fr,
+    // TODO (enhancement): add more units here from the definition.
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = match self {
+            Unit::Px => "px",
+            Unit::Em => "em",
+            Unit::Rem => "rem",
+            Unit::Percent => "%",
+            Unit::Fr => "fr",
+        };
+        write!(f, "{}", unit)
+    }
+}
+
+/// `CssParseError` represents errors of parsing CSS.
+#[derive(Error, Debug, PartialEq)]
+pub enum CssParseError {
+    #[error("invalid selector: {0}")]
+    InvalidSelector(String),
+    #[error("invalid stylesheet: {0}")]
+    InvalidStylesheet(String),
+}
+
+/// `parse` parses a stylesheet, and panics when it is invalid.
+/// Use `try_parse` for stylesheets from untrusted sources like web pages.
+pub fn parse(raw: &str) -> Stylesheet {
+    try_parse(raw).unwrap()
+}
+
+/// `try_parse` parses a stylesheet like `parse`, but returns an error instead of panicking when it is invalid.
+/// The whole stylesheet is invalid when any part of it is, i.e. the rules before a syntax error are not kept.
+pub fn try_parse(raw: &str) -> Result<Stylesheet, CssParseError> {
+    rules()
+        .skip(eof())
+        .parse(raw)
+        .map(|(rules, _)| Stylesheet::new(rules))
+        .map_err(|e| CssParseError::InvalidStylesheet(e.to_string()))
+}
+
+/// `parse_declarations` parses the contents of a declaration block like `color: red; margin: 0`,
+/// e.g. the `style` attribute of an element.
+/// It returns nothing when the text is not a valid list of declarations.
+pub fn parse_declarations(raw: &str) -> Vec<Declaration> {
+    whitespaces()
+        .with(declarations())
+        .skip(eof())
+        .parse(raw)
+        .map(|(declarations, _)| declarations)
+        .unwrap_or_default()
+}
+
+/// `parse_selector` parses a single selector like `p.warning` or `ul > li`.
+pub fn parse_selector(raw: &str) -> Result<SimpleSelector, CssParseError> {
+    selector()
+        .skip(eof())
+        .parse(raw.trim())
+        .map(|(selector, _)| selector)
+        .map_err(|_| CssParseError::InvalidSelector(raw.to_string()))
+}
+
+fn whitespaces<Input>() -> impl Parser<Input, Output = String>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    many::<String, _, _>(space().or(newline()))
+}
+
+/// `negated_selector` parses the argument of `:not()`. Negations cannot be nested.
+///
+/// NOTE: Arguments containing `)`, e.g. `:not(:lang(en))`, are not supported
+/// since the argument is taken up to the first `)`.
+fn negated_selector(argument: &str) -> Option<SimpleSelector> {
+    match simple_selector().skip(eof()).parse(argument.trim()) {
+        Ok((
+            SimpleSelector::PseudoClassSelector {
+                pseudo_class: PseudoClass::Not(_),
+                ..
+            },
+            _,
+        ))
+        | Ok((SimpleSelector::PseudoElementSelector { .. }, _))
+        | Ok((SimpleSelector::CompoundSelector { .. }, _))
+        | Err(_) => None,
+        Ok((selector, _)) => Some(selector),
+    }
+}
+
+/// `selector_list` parses the argument of `:is()` and `:where()`, which is a comma-separated list of selectors.
+/// Pseudo-elements are not allowed in the list.
+///
+/// NOTE: Arguments containing `)` are not supported as well as `:not()`.
+fn selector_list(argument: &str) -> Option<Vec<SimpleSelector>> {
+    match selectors().skip(eof()).parse(argument.trim()) {
+        Ok((selectors, _))
+            if !selectors.is_empty()
+                && selectors
+                    .iter()
+                    .all(|s| s.kind() != SelectorKind::PseudoElement) =>
+        {
+            Some(selectors)
+        }
+        _ => None,
+    }
+}
+
+/// `attribute_condition` consumes `[<attribute>]`, `[<attribute>=<value>]` or `[<attribute>~=<value>]`,
+/// where the value is an identifier, a number or a quoted string.
+fn attribute_condition<Input>() -> impl Parser<Input, Output = (String, AttributeSelectorOp, String)>
+where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
+    let op = choice((
+        char::char('=').map(|_| AttributeSelectorOp::Eq),
+        char::string("~=").map(|_| AttributeSelectorOp::Contain),
+    ));
+    let value = choice((
+        (
+            char::char('"'),
+            many::<String, _, _>(satisfy(|c: char| c != '"')),
+            char::char('"'),
+        )
+            .map(|(_, value, _)| value),
+        many1::<String, _, _>(satisfy(|c: char| {
+            c.is_ascii_alphanumeric() || c == '-' || c == '_'
+        })),
+    ));
     (
-        selectors().skip(whitespaces()),
-        char::char('{').skip(whitespaces()),
-        declarations(),
-        char::char('}').skip(whitespaces()),
+        char::char('[').skip(whitespaces()),
+        identifier().skip(whitespaces()),
+        optional((op.skip(whitespaces()), value.skip(whitespaces()))),
+        char::char(']'),
     )
-        .map(|(selectors, _, declarations, _)| Rule {
-            selectors,
-            declarations,
+        .map(|(_, attribute, condition, _)| match condition {
+            Some((op, value)) => (attribute, op, value),
+            None => (attribute, AttributeSelectorOp::Exists, String::new()),
         })
 }
 
-fn selectors<Input>() -> impl Parser<Input, Output = Vec<Selector>>
+/// `tag_name` consumes a tag name like `h1`.
+/// The name is lowercased since tag names are case-insensitive in HTML, e.g. `DIV` matches `<div>`.
+fn tag_name<Input>() -> impl Parser<Input, Output = String>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    sep_by(
-        simple_selector().skip(whitespaces()),
-        char::char(',').skip(whitespaces()),
+    (letter(), many::<String, _, _>(alpha_num()))
+        .map(|(first, rest)| format!("{}{}", first, rest).to_ascii_lowercase())
+}
+
+/// `identifier` consumes an identifier like `font-weight` or `inline`.
+/// See https://www.w3.org/TR/css-syntax-3/#ident-token-diagram (escapes are not supported)
+fn identifier<Input>() -> impl Parser<Input, Output = String>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    (
+        satisfy(|c: char| c.is_ascii_alphabetic() || c == '-' || c == '_'),
+        many::<String, _, _>(satisfy(|c: char| {
+            c.is_ascii_alphanumeric() || c == '-' || c == '_'
+        })),
     )
+        .map(|(head, tail)| format!("{}{}", head, tail))
 }
 
-fn simple_selector<Input>() -> impl Parser<Input, Output = SimpleSelector>
+fn rules<Input>() -> impl Parser<Input, Output = Vec<Rule>>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    let universal_selector = char::char('*').map(|_| SimpleSelector::UniversalSelector);
-    let class_selector =
-        (char::char('.'), many1(letter())).map(|(_, class_name)| SimpleSelector::ClassSelector {
-            class_name: class_name,
-        });
-    let type_or_attribute_selector = (
-        many1(letter()).skip(whitespaces()),
-        optional((
-            char::char('[').skip(whitespaces()),
-            many1(letter()),
-            choice((char::string("="), char::string("~="))),
-            many1(letter()),
-            char::char(']'),
+    (
+        whitespaces(),
+        many::<Vec<(Vec<String>, Vec<Rule>)>, _, _>(choice((
+            layer_rule().skip(whitespaces()),
+            media_rule()
+                .skip(whitespaces())
+                .map(|rules| (vec![], rules)),
+            supports_rule()
+                .skip(whitespaces())
+                .map(|rules| (vec![], rules)),
+            rule().skip(whitespaces()).map(|rules| (vec![], rules)),
+        ))),
+    )
+        .map(|(_, items)| {
+            // the layers are ordered by their first appearance
+            let mut layers: Vec<String> = vec![];
+            let mut rules = vec![];
+            for (names, layer_rules) in items {
+                let mut layer = None;
+                for name in names {
+                    layer = Some(match layers.iter().position(|l| *l == name) {
+                        Some(i) => i,
+                        None => {
+                            layers.push(name);
+                            layers.len() - 1
+                        }
+                    });
+                }
+                rules.extend(layer_rules.into_iter().map(|rule| Rule { layer, ..rule }));
+            }
+            rules
+        })
+}
+
+/// `layer_rule` consumes `@layer <name>, ...;` declaring the order of the layers,
+/// or `@layer <name> { <rule> ... }`. It results in the names of the layers and the rules inside.
+///
+/// NOTE: Anonymous layers and nested layers like `@layer a.b` are not supported.
+fn layer_rule<Input>() -> impl Parser<Input, Output = (Vec<String>, Vec<Rule>)>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    (
+        attempt(char::string("@layer")).skip(whitespaces()),
+        sep_by1::<Vec<String>, _, _, _>(
+            identifier().skip(whitespaces()),
+            char::char(',').skip(whitespaces()),
+        ),
+        choice((
+            char::char(';').map(|_| None),
+            (
+                char::char('{').skip(whitespaces()),
+                many::<Vec<Vec<Rule>>, _, _>(rule().skip(whitespaces())),
+                char::char('}'),
+            )
+                .map(|(_, rules, _)| Some(rules.into_iter().flatten().collect::<Vec<_>>())),
         )),
     )
-        .and_then(|(tag_name, opts)| match opts {
-            Some((_, attribute, op, value, _)) => {
-                let op = match op {
-                    "=" => AttributeSelectorOp::Eq,
-                    "~=" => AttributeSelectorOp::Contain,
-                    _ => {
-                        return Err(<Input::Error as combine::error::ParseError<
-                            char,
-                            Input::Range,
-                            Input::Position,
-                        >>::StreamError::message_static_message(
-                            "invalid attribute selector op",
-                        ))
-                    }
-                };
-                Ok(SimpleSelector::AttributeSelector {
-                    tag_name: tag_name,
-                    attribute: attribute,
-                    op: op,
-                    value: value,
+        .and_then(|(_, names, rules)| match rules {
+            None => Ok((names, vec![])),
+            Some(rules) if names.len() == 1 => Ok((names, rules)),
+            Some(_) => Err(<Input::Error as combine::error::ParseError<
+                char,
+                Input::Range,
+                Input::Position,
+            >>::StreamError::message_static_message(
+                "a layer block must have a single name",
+            )),
+        })
+}
+
+/// `media_rule` consumes `@media <media-query> { <rule> ... }`.
+/// The rules inside are flattened with their `media` set to the query.
+fn media_rule<Input>() -> impl Parser<Input, Output = Vec<Rule>>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    (
+        attempt(char::string("@media")).skip(whitespaces()),
+        media_query().skip(whitespaces()),
+        char::char('{').skip(whitespaces()),
+        many::<Vec<Vec<Rule>>, _, _>(rule().skip(whitespaces())),
+        char::char('}'),
+    )
+        .map(|(_, media, _, rules, _)| {
+            rules
+                .into_iter()
+                .flatten()
+                .map(|rule| Rule {
+                    media: Some(media.clone()),
+                    ..rule
+                })
+                .collect()
+        })
+}
+
+/// `supports_rule` consumes `@supports <supports-condition> { <rule> ... }`.
+/// The rules inside are flattened with their `supports` set to the condition.
+fn supports_rule<Input>() -> impl Parser<Input, Output = Vec<Rule>>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    (
+        attempt(char::string("@supports")).skip(whitespaces()),
+        many1::<String, _, _>(satisfy(|c: char| c != '{')),
+        char::char('{').skip(whitespaces()),
+        many::<Vec<Vec<Rule>>, _, _>(rule().skip(whitespaces())),
+        char::char('}'),
+    )
+        .map(|(_, condition, _, rules, _)| {
+            // an unparseable condition is kept as `Unknown` so that the rules inside never apply
+            let condition = match supports_condition().parse(condition.trim()) {
+                Ok((condition, "")) => condition,
+                _ => SupportsCondition::Unknown,
+            };
+            rules
+                .into_iter()
+                .flatten()
+                .map(|rule| Rule {
+                    supports: Some(condition.clone()),
+                    ..rule
                 })
+                .collect()
+        })
+}
+
+/// `supports_condition` consumes `not (<declaration>)`, `(<declaration>) and (<declaration>) ...`
+/// or `(<declaration>) or (<declaration>) ...`.
+/// Mixing `and` and `or` without parentheses is invalid in the standard, so it results in `Unknown`.
+fn supports_condition<Input>() -> impl Parser<Input, Output = SupportsCondition>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    let supports_in_parens = || {
+        (
+            char::char('(').skip(whitespaces()),
+            declaration().skip(whitespaces()),
+            char::char(')'),
+        )
+            .map(|(_, declaration, _)| SupportsCondition::Declaration(declaration))
+    };
+    let not_condition = (
+        attempt(char::string("not")).skip(whitespaces()),
+        supports_in_parens(),
+    )
+        .map(|(_, condition)| SupportsCondition::Not(Box::new(condition)));
+    let combined_condition = (
+        supports_in_parens(),
+        many::<Vec<(&str, SupportsCondition)>, _, _>(
+            attempt((
+                whitespaces(),
+                choice((char::string("and"), char::string("or"))).skip(whitespaces()),
+                supports_in_parens(),
+            ))
+            .map(|(_, op, condition)| (op, condition)),
+        ),
+    )
+        .map(|(first, rest)| {
+            if rest.is_empty() {
+                return first;
+            }
+            let op = rest[0].0;
+            if rest.iter().any(|(o, _)| *o != op) {
+                return SupportsCondition::Unknown;
+            }
+            let conditions = std::iter::once(first)
+                .chain(rest.into_iter().map(|(_, condition)| condition))
+                .collect();
+            match op {
+                "and" => SupportsCondition::And(conditions),
+                _ => SupportsCondition::Or(conditions),
             }
-            None => Ok(SimpleSelector::TypeSelector { tag_name: tag_name }),
         });
-
-    choice((
-        universal_selector,
-        class_selector,
-        type_or_attribute_selector,
-    ))
+    choice((not_condition, combined_condition))
 }
 
-fn declarations<Input>() -> impl Parser<Input, Output = Vec<Declaration>>
+fn media_query<Input>() -> impl Parser<Input, Output = MediaQuery>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    sep_end_by(
-        declaration().skip(whitespaces()),
-        char::char(';').skip(whitespaces()),
+    sep_by1(
+        media_feature(),
+        attempt((whitespaces(), char::string("and"), whitespaces())),
     )
+    .map(|features| MediaQuery { features })
 }
 
-fn declaration<Input>() -> impl Parser<Input, Output = Declaration>
+/// `media_feature` consumes `(<name>: <value>)`.
+fn media_feature<Input>() -> impl Parser<Input, Output = MediaFeature>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     (
-        many1(letter()).skip(whitespaces()),
+        char::char('(').skip(whitespaces()),
+        identifier().skip(whitespaces()),
         char::char(':').skip(whitespaces()),
-        css_value(),
+        css_value().skip(whitespaces()),
+        char::char(')'),
     )
-        .map(|(k, _, v)| Declaration { name: k, value: v })
+        .map(|(_, name, _, value, _)| MediaFeature { name, value })
 }
 
-fn css_value<Input>() -> impl Parser<Input, Output = CSSValue>
+/// `RuleItem` is an item in the block of a style rule.
+enum RuleItem {
+    Declaration(Declaration),
+    NestedRule(NestedRule),
+}
+
+/// `rule` consumes a style rule, which results in the rule itself followed by its nested rules de-sugared.
+fn rule<Input>() -> impl Parser<Input, Output = Vec<Rule>>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    let keyword = many1(letter()).map(|s| CSSValue::Keyword(s));
-    let length = (
-        many1(char::digit()).map(|s: String| s.parse::<usize>().unwrap()),
-        choice((
-            char::string("px").map(|_| Unit::Px),
-            char::string("em").map(|_| Unit::Em),
-            char::string("rem").map(|_| Unit::Rem),
-            char::string("%").map(|_| Unit::Percent),
-        )),
+    (
+        selectors().skip(whitespaces()),
+        char::char('{').skip(whitespaces()),
+        many::<Vec<_>, _, _>(choice((
+            nested_rule().map(RuleItem::NestedRule),
+            declaration()
+                .skip(whitespaces())
+                .skip(optional(char::char(';').skip(whitespaces())))
+                .map(RuleItem::Declaration),
+        ))),
+        char::char('}').skip(whitespaces()),
     )
-        .map(|(num, _unit)| CSSValue::Length((num, _unit)));
-    choice((keyword, length))
+        .map(|(selectors, _, items, _)| {
+            let mut declarations = vec![];
+            let mut nested_rules = vec![];
+            for item in items {
+                match item {
+                    RuleItem::Declaration(declaration) => declarations.push(declaration),
+                    RuleItem::NestedRule(nested_rule) => nested_rules.push(nested_rule),
+                }
+            }
+            let rule = Rule {
+                selectors,
+                declarations,
+                media: None,
+                supports: None,
+                origin: Origin::Author,
+                layer: None,
+            };
+            let nested_rules: Vec<_> = nested_rules
+                .into_iter()
+                .map(|nested_rule| nested_rule.desugar(&rule))
+                .collect();
+            std::iter::once(rule).chain(nested_rules).collect()
+        })
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::dom::Element;
+/// `nested_rule` consumes a rule nested in a style rule like `& .title { font-weight: bold }`.
+fn nested_rule<Input>() -> impl Parser<Input, Output = NestedRule>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    let nested_selector = (char::char('&'), whitespaces(), optional(simple_selector())).map(
+        |(_, spaces, selector)| match selector {
+            None => NestedSelector::Parent,
+            Some(selector) if spaces.is_empty() => NestedSelector::Compound(selector),
+            Some(selector) => NestedSelector::Descendant(selector),
+        },
+    );
+    (
+        sep_by1(
+            nested_selector.skip(whitespaces()),
+            char::char(',').skip(whitespaces()),
+        ),
+        char::char('{').skip(whitespaces()),
+        declarations(),
+        char::char('}').skip(whitespaces()),
+    )
+        .map(|(selectors, _, declarations, _)| NestedRule {
+            selectors,
+            declarations,
+        })
+}
 
-    use super::*;
+fn selectors<Input>() -> impl Parser<Input, Output = Vec<Selector>>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    sep_by(
+        selector().skip(whitespaces()),
+        char::char(',').skip(whitespaces()),
+    )
+}
 
-    #[test]
-    fn test_stylesheet() {
-        assert_eq!(
-            rules().parse("test [foo=bar] { aa: 4px; cc: 1em } rule { ee: dd;  }"),
-            Ok((
-                vec![
-                    Rule {
-                        selectors: vec![SimpleSelector::AttributeSelector {
-                            tag_name: "test".to_string(),
-                            attribute: "foo".to_string(),
-                            op: AttributeSelectorOp::Eq,
-                            value: "bar".to_string()
-                        }],
-                        declarations: vec![
-                            Declaration {
-                                name: "aa".to_string(),
-                                value: CSSValue::Length((4, Unit::Px)),
-                            },
-                            Declaration {
-                                name: "cc".to_string(),
-                                value: CSSValue::Length((1, Unit::Em)),
-                            }
-                        ]
-                    },
-                    Rule {
-                        selectors: vec![SimpleSelector::TypeSelector {
-                            tag_name: "rule".to_string(),
-                        }],
-                        declarations: vec![Declaration {
-                            name: "ee".to_string(),
-                            value: CSSValue::Keyword("dd".to_string())
-                        }]
-                    },
-                ],
-                ""
-            ))
-        );
+/// `Combinator` is a combinator between two selectors.
+/// See https://www.w3.org/TR/selectors-3/#combinators
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+impl Combinator {
+    /// `combine` joins `left` and `right` with the combinator.
+    /// It returns `None` when `left` has a pseudo-element, which must be at the end of a selector.
+    fn combine(self, left: SimpleSelector, right: SimpleSelector) -> Option<SimpleSelector> {
+        match (left, right) {
+            (SimpleSelector::PseudoElementSelector { .. }, _) => None,
+            // the pseudo-element stays at the end, e.g. `div p::first-line`
+            (
+                left,
+                SimpleSelector::PseudoElementSelector {
+                    selector,
+                    pseudo_element,
+                },
+            ) => Some(SimpleSelector::PseudoElementSelector {
+                selector: Box::new(self.combine(left, *selector)?),
+                pseudo_element,
+            }),
+            (left, right) => Some(match self {
+                Combinator::Descendant => SimpleSelector::DescendantSelector {
+                    ancestor: Box::new(left),
+                    selector: Box::new(right),
+                },
+                Combinator::Child => SimpleSelector::ChildSelector {
+                    parent: Box::new(left),
+                    selector: Box::new(right),
+                },
+            }),
+        }
+    }
+}
+
+/// `selector` parses simple selectors separated by combinators like `div p` or `ul > li`.
+fn selector<Input>() -> impl Parser<Input, Output = Selector>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    let combinator = (whitespaces(), optional(char::char('>').skip(whitespaces()))).and_then(
+        |(spaces, child)| match child {
+            Some(_) => Ok(Combinator::Child),
+            None if !spaces.is_empty() => Ok(Combinator::Descendant),
+            None => Err(<Input::Error as combine::error::ParseError<
+                char,
+                Input::Range,
+                Input::Position,
+            >>::StreamError::message_static_message(
+                "missing combinator"
+            )),
+        },
+    );
+    (
+        simple_selector(),
+        many::<Vec<_>, _, _>(attempt((combinator, simple_selector()))),
+    )
+        .and_then(|(first, rest)| {
+            rest.into_iter()
+                .try_fold(first, |left, (combinator, right)| {
+                    combinator.combine(left, right)
+                })
+                .ok_or_else(|| {
+                    <Input::Error as combine::error::ParseError<
+                        char,
+                        Input::Range,
+                        Input::Position,
+                    >>::StreamError::message_static_message(
+                        "pseudo-element not at the end"
+                    )
+                })
+        })
+}
+
+fn simple_selector<Input>() -> impl Parser<Input, Output = SimpleSelector>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    let universal_selector = char::char('*').map(|_| SimpleSelector::UniversalSelector);
+    let class_selector = || {
+        many1((char::char('.'), identifier()).map(|(_, class_name)| class_name))
+            .map(|class_names| SimpleSelector::ClassSelector { class_names })
+    };
+    let id_selector =
+        || (char::char('#'), identifier()).map(|(_, id)| SimpleSelector::IdSelector { id });
+    let attribute_selector =
+        attribute_condition().map(|(attribute, op, value)| SimpleSelector::AttributeSelector {
+            tag_name: None,
+            attribute,
+            op,
+            value,
+        });
+    let type_or_attribute_selector = (
+        tag_name(),
+        optional(attempt((whitespaces(), attribute_condition())).map(|(_, c)| c)),
+    )
+        .map(|(tag_name, condition)| match condition {
+            Some((attribute, op, value)) => SimpleSelector::AttributeSelector {
+                tag_name: Some(tag_name),
+                attribute,
+                op,
+                value,
+            },
+            None => SimpleSelector::TypeSelector { tag_name },
+        });
+
+    let pseudo_class_selector =
+        (optional(tag_name()), pseudo_class()).map(|(tag_name, pseudo_class)| {
+            SimpleSelector::PseudoClassSelector {
+                tag_name,
+                pseudo_class,
+            }
+        });
+
+    let pseudo_element = attempt((char::string("::"), identifier())).map(|(_, name)| name);
+
+    (
+        choice((
+            universal_selector,
+            class_selector(),
+            id_selector(),
+            attribute_selector,
+            attempt(pseudo_class_selector),
+            type_or_attribute_selector,
+        )),
+        many::<Vec<_>, _, _>(choice((
+            class_selector(),
+            id_selector(),
+            // `::` starts a pseudo-element, and an invalid pseudo-class is an error rather than the end of the selector
+            (not_followed_by(attempt(char::string("::"))), pseudo_class()).map(
+                |(_, pseudo_class)| SimpleSelector::PseudoClassSelector {
+                    tag_name: None,
+                    pseudo_class,
+                },
+            ),
+        ))),
+        optional(pseudo_element),
+    )
+        .and_then(|(selector, rest, pseudo_element)| {
+            let selector = if rest.is_empty() {
+                selector
+            } else {
+                let mut selectors = vec![selector];
+                selectors.extend(rest);
+                SimpleSelector::CompoundSelector { selectors }
+            };
+            match pseudo_element {
+                None => Ok(selector),
+                Some(name) => match PseudoElement::from_name(&name) {
+                    Some(pseudo_element) => Ok(SimpleSelector::PseudoElementSelector {
+                        selector: Box::new(selector),
+                        pseudo_element,
+                    }),
+                    None => Err(<Input::Error as combine::error::ParseError<
+                        char,
+                        Input::Range,
+                        Input::Position,
+                    >>::StreamError::message_static_message(
+                        "invalid pseudo-element"
+                    )),
+                },
+            }
+        })
+}
+
+/// `pseudo_class` parses a pseudo-class like `:first-of-type` or `:nth-of-type(2n+1)`.
+fn pseudo_class<Input>() -> impl Parser<Input, Output = PseudoClass>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    (
+        char::char(':'),
+        identifier(),
+        optional((
+            char::char('('),
+            many1::<String, _, _>(satisfy(|c: char| c != ')')),
+            char::char(')'),
+        )),
+    )
+        .and_then(|(_, name, argument)| {
+            let pseudo_class = match (name.as_str(), argument) {
+                ("first-of-type", None) => Some(PseudoClass::FirstOfType),
+                ("only-child", None) => Some(PseudoClass::OnlyChild),
+                ("only-of-type", None) => Some(PseudoClass::OnlyOfType),
+                ("nth-of-type", Some((_, argument, _))) => {
+                    Nth::from_notation(&argument).map(PseudoClass::NthOfType)
+                }
+                ("lang", Some((_, argument, _))) => {
+                    Some(PseudoClass::Lang(argument.trim().to_string()))
+                }
+                ("not", Some((_, argument, _))) => {
+                    negated_selector(&argument).map(|s| PseudoClass::Not(Box::new(s)))
+                }
+                ("is", Some((_, argument, _))) => selector_list(&argument).map(PseudoClass::Is),
+                ("where", Some((_, argument, _))) => {
+                    selector_list(&argument).map(PseudoClass::Where)
+                }
+                _ => None,
+            };
+            match pseudo_class {
+                Some(pseudo_class) => Ok(pseudo_class),
+                None => Err(<Input::Error as combine::error::ParseError<
+                    char,
+                    Input::Range,
+                    Input::Position,
+                >>::StreamError::message_static_message(
+                    "invalid pseudo-class"
+                )),
+            }
+        })
+}
+
+fn declarations<Input>() -> impl Parser<Input, Output = Vec<Declaration>>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    sep_end_by(
+        declaration().skip(whitespaces()),
+        char::char(';').skip(whitespaces()),
+    )
+}
+
+fn declaration<Input>() -> impl Parser<Input, Output = Declaration>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    let important = attempt((
+        whitespaces(),
+        char::char('!'),
+        whitespaces(),
+        char::string("important"),
+    ));
+    (
+        identifier().skip(whitespaces()),
+        char::char(':').skip(whitespaces()),
+        css_values(),
+        optional(important),
+    )
+        .map(|(k, _, v, important)| Declaration {
+            name: k,
+            value: v,
+            important: important.is_some(),
+        })
+}
+
+fn css_value<Input>() -> impl Parser<Input, Output = CSSValue>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    let keyword = identifier().map(CSSValue::keyword);
+    let ratio = (
+        integer(),
+        whitespaces(),
+        char::char('/'),
+        whitespaces(),
+        integer(),
+    )
+        .map(|(width, _, _, _, height)| CSSValue::Ratio((width, height)));
+    let length = (
+        number(),
+        choice((
+            char::string("px").map(|_| Unit::Px),
+            char::string("em").map(|_| Unit::Em),
+            char::string("rem").map(|_| Unit::Rem),
+            char::string("%").map(|_| Unit::Percent),
+            char::string("fr").map(|_| Unit::Fr),
+        )),
+    )
+        .map(|(num, _unit)| CSSValue::Length((num, _unit)));
+    let color = (
+        char::char('#'),
+        many1::<String, _, _>(satisfy(|c: char| c.is_ascii_hexdigit())),
+    )
+        .and_then(|(_, hex)| match Color::from_hex(&hex) {
+            Some(color) => Ok(CSSValue::Color(color)),
+            None => Err(<Input::Error as combine::error::ParseError<
+                char,
+                Input::Range,
+                Input::Position,
+            >>::StreamError::message_static_message(
+                "invalid hex color"
+            )),
+        });
+    let color_mix = (
+        attempt(char::string("color-mix(")),
+        many::<String, _, _>(satisfy(|c: char| c != ')')),
+        char::char(')'),
+    )
+        .and_then(
+            |(_, arguments, _)| match Color::from_color_mix(&arguments) {
+                Some(color) => Ok(CSSValue::Color(color)),
+                None => Err(<Input::Error as combine::error::ParseError<
+                    char,
+                    Input::Range,
+                    Input::Position,
+                >>::StreamError::message_static_message(
+                    "invalid color-mix()"
+                )),
+            },
+        );
+    let rgb = (
+        attempt(choice((
+            attempt(char::string("rgba(")),
+            char::string("rgb("),
+        ))),
+        many::<String, _, _>(satisfy(|c: char| c != ')')),
+        char::char(')'),
+    )
+        .and_then(
+            |(_, arguments, _)| match Color::from_rgb_function(&arguments) {
+                Some(color) => Ok(CSSValue::Color(color)),
+                None => Err(<Input::Error as combine::error::ParseError<
+                    char,
+                    Input::Range,
+                    Input::Position,
+                >>::StreamError::message_static_message(
+                    "invalid rgb()"
+                )),
+            },
+        );
+    let string = choice((
+        (
+            char::char('"'),
+            many::<String, _, _>(satisfy(|c: char| c != '"')),
+            char::char('"'),
+        ),
+        (
+            char::char('\''),
+            many::<String, _, _>(satisfy(|c: char| c != '\'')),
+            char::char('\''),
+        ),
+    ))
+    .map(|(_, s, _)| CSSValue::String(s));
+    let url = (
+        attempt(char::string("url(")),
+        many::<String, _, _>(satisfy(|c: char| c != ')')),
+        char::char(')'),
+    )
+        .map(|(_, url, _)| {
+            CSSValue::Url(
+                url.trim()
+                    .trim_matches(|c| c == '"' || c == '\'')
+                    .to_string(),
+            )
+        });
+    let function = (
+        attempt((identifier(), char::char('('))),
+        function_arguments(),
+        char::char(')'),
+    )
+        .map(|((name, _), arguments, _)| {
+            match TransformFunction::from_notation(&name, &arguments) {
+                Some(function) => CSSValue::Transform(function),
+                None => CSSValue::Function(name, arguments),
+            }
+        });
+    choice((
+        attempt(ratio),
+        attempt(length),
+        // a sign may be followed by an identifier like `-webkit-box`
+        attempt(number().map(CSSValue::Number)),
+        color,
+        color_mix,
+        rgb,
+        string,
+        url,
+        function,
+        keyword,
+    ))
+}
+
+/// `function_arguments_` consumes the raw arguments of a function up to the closing parenthesis,
+/// which may contain nested parentheses like `(1px + 2px) * 2`.
+fn function_arguments_<Input>() -> impl Parser<Input, Output = String>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    many::<Vec<String>, _, _>(choice((
+        many1::<String, _, _>(satisfy(|c: char| c != '(' && c != ')')),
+        (char::char('('), function_arguments(), char::char(')'))
+            .map(|(_, arguments, _)| format!("({})", arguments)),
+    )))
+    .map(|parts| parts.concat())
+}
+
+parser! {
+    fn function_arguments[Input]()(Input) -> String
+    where [Input: Stream<Token = char>]
+    {
+        function_arguments_()
+    }
+}
+
+/// `integer` consumes a non-negative integer like `16`, and fails when it does not fit in `usize`.
+fn integer<Input>() -> impl Parser<Input, Output = usize>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    many1::<String, _, _>(char::digit()).and_then(|digits| {
+        digits.parse::<usize>().map_err(|_| {
+            <Input::Error as combine::error::ParseError<
+                char,
+                Input::Range,
+                Input::Position,
+            >>::StreamError::message_static_message("too large integer")
+        })
+    })
+}
+
+/// `number` consumes a number with an optional sign like `100`, `-1.5` or `+2`.
+fn number<Input>() -> impl Parser<Input, Output = f32>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    (
+        optional(choice((char::char('-'), char::char('+')))),
+        many1::<String, _, _>(char::digit()),
+        optional((char::char('.'), many1::<String, _, _>(char::digit()))),
+    )
+        .map(|(sign, integer, fraction)| {
+            let number = match fraction {
+                Some((_, fraction)) => format!("{}.{}", integer, fraction),
+                None => integer,
+            }
+            .parse::<f32>()
+            .unwrap();
+            match sign {
+                Some('-') => -number,
+                _ => number,
+            }
+        })
+}
+
+/// `css_values` consumes space-separated values like `1fr 100px`,
+/// which may be delimited by `/` like `16px/1.5` or `,` like `2px 2px red, 4px 4px blue`.
+/// A single value is returned as it is, and multiple values are wrapped with `CSSValue::List`.
+fn css_values<Input>() -> impl Parser<Input, Output = CSSValue>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    (
+        css_value(),
+        many::<Vec<Vec<CSSValue>>, _, _>(choice((
+            attempt((whitespaces(), char::char('/'), whitespaces(), css_value()))
+                .map(|(_, _, _, v)| vec![CSSValue::Slash, v]),
+            attempt((whitespaces(), char::char(','), whitespaces(), css_value()))
+                .map(|(_, _, _, v)| vec![CSSValue::Comma, v]),
+            attempt((many1::<String, _, _>(space().or(newline())), css_value()))
+                .map(|(_, v)| vec![v]),
+        ))),
+    )
+        .map(|(first, rest)| {
+            let rest = rest.into_iter().flatten().collect::<Vec<_>>();
+            if rest.is_empty() {
+                first
+            } else {
+                CSSValue::List(std::iter::once(first).chain(rest).collect())
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dom::{build_tree, AttrMap, Comment, Element, Text, TreeSpec};
+
+    use super::*;
+
+    #[test]
+    fn test_background_position_from_value() {
+        let keyword = |k: &str| CSSValue::Keyword(k.to_string().into());
+        let position = |x, y| Some(BackgroundPosition { x, y });
+
+        // background-position: 10px 20px
+        assert_eq!(
+            BackgroundPosition::from_value(&CSSValue::List(vec![
+                CSSValue::Length((10.0, Unit::Px)),
+                CSSValue::Length((20.0, Unit::Px)),
+            ])),
+            position((10.0, Unit::Px), (20.0, Unit::Px))
+        );
+        // background-position: center
+        assert_eq!(
+            BackgroundPosition::from_value(&keyword("center")),
+            position((50.0, Unit::Percent), (50.0, Unit::Percent))
+        );
+        // background-position: top left
+        assert_eq!(
+            BackgroundPosition::from_value(&CSSValue::List(vec![keyword("top"), keyword("left")])),
+            position((0.0, Unit::Percent), (0.0, Unit::Percent))
+        );
+        // background-position: right 25%
+        assert_eq!(
+            BackgroundPosition::from_value(&CSSValue::List(vec![
+                keyword("right"),
+                CSSValue::Length((25.0, Unit::Percent)),
+            ])),
+            position((100.0, Unit::Percent), (25.0, Unit::Percent))
+        );
+        // background-position: bottom
+        assert_eq!(
+            BackgroundPosition::from_value(&keyword("bottom")),
+            position((50.0, Unit::Percent), (100.0, Unit::Percent))
+        );
+        // background-position: left right
+        assert_eq!(
+            BackgroundPosition::from_value(&CSSValue::List(vec![
+                keyword("left"),
+                keyword("right")
+            ])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_stylesheet() {
+        assert_eq!(
+            rules().parse("test [foo=bar] { aa: 4px; cc: 1em } rule { ee: dd;  }"),
+            Ok((
+                vec![
+                    Rule {
+                        selectors: vec![SimpleSelector::AttributeSelector {
+                            tag_name: Some("test".to_string()),
+                            attribute: "foo".to_string(),
+                            op: AttributeSelectorOp::Eq,
+                            value: "bar".to_string()
+                        }],
+                        declarations: vec![
+                            Declaration {
+                                name: "aa".to_string(),
+                                value: CSSValue::Length((4.0, Unit::Px)),
+                                important: false,
+                            },
+                            Declaration {
+                                name: "cc".to_string(),
+                                value: CSSValue::Length((1.0, Unit::Em)),
+                                important: false,
+                            }
+                        ],
+                        ..Default::default()
+                    },
+                    Rule {
+                        selectors: vec![SimpleSelector::TypeSelector {
+                            tag_name: "rule".to_string(),
+                        }],
+                        declarations: vec![Declaration {
+                            name: "ee".to_string(),
+                            value: CSSValue::Keyword("dd".into()),
+                            important: false,
+                        }],
+                        ..Default::default()
+                    },
+                ],
+                ""
+            ))
+        );
     }
 
     #[test]
@@ -342,15 +2555,16 @@ mod tests {
         assert_eq!(
             rule().parse("test [foo=bar] {}"),
             Ok((
-                Rule {
+                vec![Rule {
                     selectors: vec![SimpleSelector::AttributeSelector {
-                        tag_name: "test".to_string(),
+                        tag_name: Some("test".to_string()),
                         attribute: "foo".to_string(),
                         op: AttributeSelectorOp::Eq,
                         value: "bar".to_string()
                     }],
-                    declarations: vec![]
-                },
+                    declarations: vec![],
+                    ..Default::default()
+                }],
                 ""
             ))
         );
@@ -358,129 +2572,1882 @@ mod tests {
         assert_eq!(
             rule().parse("test [aa=bb], piyo[cc~=dd] {}"),
             Ok((
-                Rule {
+                vec![Rule {
                     selectors: vec![
                         SimpleSelector::AttributeSelector {
-                            tag_name: "test".to_string(),
+                            tag_name: Some("test".to_string()),
                             attribute: "aa".to_string(),
                             op: AttributeSelectorOp::Eq,
                             value: "bb".to_string()
                         },
                         SimpleSelector::AttributeSelector {
-                            tag_name: "piyo".to_string(),
+                            tag_name: Some("piyo".to_string()),
                             attribute: "cc".to_string(),
                             op: AttributeSelectorOp::Contain,
                             value: "dd".to_string()
                         }
                     ],
-                    declarations: vec![]
-                },
+                    declarations: vec![],
+                    ..Default::default()
+                }],
+                ""
+            ))
+        );
+
+        assert_eq!(
+            rule().parse("test [foo=bar] { aa: bb; cc: 60%; }"),
+            Ok((
+                vec![Rule {
+                    selectors: vec![SimpleSelector::AttributeSelector {
+                        tag_name: Some("test".to_string()),
+                        attribute: "foo".to_string(),
+                        op: AttributeSelectorOp::Eq,
+                        value: "bar".to_string()
+                    }],
+                    declarations: vec![
+                        Declaration {
+                            name: "aa".to_string(),
+                            value: CSSValue::Keyword("bb".into()),
+                            important: false,
+                        },
+                        Declaration {
+                            name: "cc".to_string(),
+                            value: CSSValue::Length((60.0, Unit::Percent)),
+                            important: false,
+                        }
+                    ],
+                    ..Default::default()
+                }],
+                ""
+            ))
+        );
+    }
+
+    #[test]
+    fn test_declarations() {
+        assert_eq!(
+            declarations().parse("foo: bar; piyo: 1rem;"),
+            Ok((
+                vec![
+                    Declaration {
+                        name: "foo".to_string(),
+                        value: CSSValue::Keyword("bar".into()),
+                        important: false,
+                    },
+                    Declaration {
+                        name: "piyo".to_string(),
+                        value: CSSValue::Length((1.0, Unit::Rem)),
+                        important: false,
+                    }
+                ],
                 ""
             ))
         );
+    }
+
+    #[test]
+    fn test_selectors() {
+        assert_eq!(
+            selectors().parse("test [foo=bar], a"),
+            Ok((
+                vec![
+                    SimpleSelector::AttributeSelector {
+                        tag_name: Some("test".to_string()),
+                        attribute: "foo".to_string(),
+                        op: AttributeSelectorOp::Eq,
+                        value: "bar".to_string()
+                    },
+                    SimpleSelector::TypeSelector {
+                        tag_name: "a".to_string(),
+                    }
+                ],
+                ""
+            ))
+        );
+    }
+
+    #[test]
+    fn test_simple_selector() {
+        assert_eq!(
+            simple_selector().parse("*"),
+            Ok((SimpleSelector::UniversalSelector, ""))
+        );
+
+        assert_eq!(
+            simple_selector().parse("test"),
+            Ok((
+                SimpleSelector::TypeSelector {
+                    tag_name: "test".to_string(),
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            simple_selector().parse("test [foo=bar]"),
+            Ok((
+                SimpleSelector::AttributeSelector {
+                    tag_name: Some("test".to_string()),
+                    attribute: "foo".to_string(),
+                    op: AttributeSelectorOp::Eq,
+                    value: "bar".to_string()
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            simple_selector().parse(".test"),
+            Ok((
+                SimpleSelector::ClassSelector {
+                    class_names: vec!["test".to_string()],
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            simple_selector().parse(".a.b"),
+            Ok((
+                SimpleSelector::ClassSelector {
+                    class_names: vec!["a".to_string(), "b".to_string()],
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            simple_selector().parse(".btn-primary.a1.nav_item"),
+            Ok((
+                SimpleSelector::ClassSelector {
+                    class_names: vec![
+                        "btn-primary".to_string(),
+                        "a1".to_string(),
+                        "nav_item".to_string()
+                    ],
+                },
+                ""
+            ))
+        );
+        assert_eq!(parse(".btn-primary { color: red; }").rules.len(), 1);
+
+        assert_eq!(
+            simple_selector().parse("#test"),
+            Ok((
+                SimpleSelector::IdSelector {
+                    id: "test".to_string(),
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            simple_selector().parse("p:first-of-type"),
+            Ok((
+                SimpleSelector::PseudoClassSelector {
+                    tag_name: Some("p".to_string()),
+                    pseudo_class: PseudoClass::FirstOfType,
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            simple_selector().parse(":nth-of-type(2n+1)"),
+            Ok((
+                SimpleSelector::PseudoClassSelector {
+                    tag_name: None,
+                    pseudo_class: PseudoClass::NthOfType(Nth { a: 2, b: 1 }),
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            simple_selector().parse("p:lang(en)"),
+            Ok((
+                SimpleSelector::PseudoClassSelector {
+                    tag_name: Some("p".to_string()),
+                    pseudo_class: PseudoClass::Lang("en".to_string()),
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            simple_selector().parse("p:not(.a.b)"),
+            Ok((
+                SimpleSelector::PseudoClassSelector {
+                    tag_name: Some("p".to_string()),
+                    pseudo_class: PseudoClass::Not(Box::new(SimpleSelector::ClassSelector {
+                        class_names: vec!["a".to_string(), "b".to_string()],
+                    })),
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            simple_selector().parse("p::first-letter"),
+            Ok((
+                SimpleSelector::PseudoElementSelector {
+                    selector: Box::new(SimpleSelector::TypeSelector {
+                        tag_name: "p".to_string(),
+                    }),
+                    pseudo_element: PseudoElement::FirstLetter,
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            simple_selector().parse(".a::first-line"),
+            Ok((
+                SimpleSelector::PseudoElementSelector {
+                    selector: Box::new(SimpleSelector::ClassSelector {
+                        class_names: vec!["a".to_string()],
+                    }),
+                    pseudo_element: PseudoElement::FirstLine,
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            simple_selector().parse(":only-child"),
+            Ok((
+                SimpleSelector::PseudoClassSelector {
+                    tag_name: None,
+                    pseudo_class: PseudoClass::OnlyChild,
+                },
+                ""
+            ))
+        );
+
+        assert!(simple_selector().parse("p:nth-of-type(foo)").is_err());
+        assert!(simple_selector().parse("p::before").is_err());
+        assert!(simple_selector().parse(":not(p::first-line)").is_err());
+        assert!(simple_selector().parse(":not(:not(p))").is_err());
+        assert!(simple_selector().parse(":not(p q)").is_err());
+
+        assert_eq!(
+            simple_selector().parse(".title:is(h1, h2)"),
+            Ok((
+                SimpleSelector::CompoundSelector {
+                    selectors: vec![
+                        SimpleSelector::ClassSelector {
+                            class_names: vec!["title".to_string()],
+                        },
+                        SimpleSelector::PseudoClassSelector {
+                            tag_name: None,
+                            pseudo_class: PseudoClass::Is(vec![
+                                SimpleSelector::TypeSelector {
+                                    tag_name: "h1".to_string(),
+                                },
+                                SimpleSelector::TypeSelector {
+                                    tag_name: "h2".to_string(),
+                                },
+                            ]),
+                        },
+                    ],
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            simple_selector().parse(":where(#a)"),
+            Ok((
+                SimpleSelector::PseudoClassSelector {
+                    tag_name: None,
+                    pseudo_class: PseudoClass::Where(vec![SimpleSelector::IdSelector {
+                        id: "a".to_string(),
+                    }]),
+                },
+                ""
+            ))
+        );
+
+        assert!(simple_selector().parse(":is()").is_err());
+        assert!(simple_selector().parse(":is(p::first-line)").is_err());
+    }
+
+    #[test]
+    fn test_declaration() {
+        assert_eq!(
+            declaration().parse("key:1em"),
+            Ok((
+                Declaration {
+                    name: "key".to_string(),
+                    value: CSSValue::Length((1.0, Unit::Em)),
+                    important: false,
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            declaration().parse("width: 100"),
+            Ok((
+                Declaration {
+                    name: "width".to_string(),
+                    value: CSSValue::Number(100.0),
+                    important: false,
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            declaration().parse("background: #00f url(\"x.png\") no-repeat"),
+            Ok((
+                Declaration {
+                    name: "background".to_string(),
+                    value: CSSValue::List(vec![
+                        CSSValue::Color(Color::rgb(0, 0, 255)),
+                        CSSValue::Url("x.png".to_string()),
+                        CSSValue::Keyword("no-repeat".into()),
+                    ]),
+                    important: false,
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            declaration().parse("font: bold 16px / 1.5 serif"),
+            Ok((
+                Declaration {
+                    name: "font".to_string(),
+                    value: CSSValue::List(vec![
+                        CSSValue::Keyword("bold".into()),
+                        CSSValue::Length((16.0, Unit::Px)),
+                        CSSValue::Slash,
+                        CSSValue::Number(1.5),
+                        CSSValue::Keyword("serif".into()),
+                    ]),
+                    important: false,
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            declaration().parse("box-shadow: 2px 2px red, 4px 4px blue"),
+            Ok((
+                Declaration {
+                    name: "box-shadow".to_string(),
+                    value: CSSValue::List(vec![
+                        CSSValue::Length((2.0, Unit::Px)),
+                        CSSValue::Length((2.0, Unit::Px)),
+                        CSSValue::Keyword("red".into()),
+                        CSSValue::Comma,
+                        CSSValue::Length((4.0, Unit::Px)),
+                        CSSValue::Length((4.0, Unit::Px)),
+                        CSSValue::Keyword("blue".into()),
+                    ]),
+                    important: false,
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            declaration().parse("transform: translate(10px, 5px) scale(2)"),
+            Ok((
+                Declaration {
+                    name: "transform".to_string(),
+                    value: CSSValue::List(vec![
+                        CSSValue::Transform(TransformFunction::Translate(
+                            (10.0, Unit::Px),
+                            (5.0, Unit::Px)
+                        )),
+                        CSSValue::Transform(TransformFunction::Scale(2.0, 2.0)),
+                    ]),
+                    important: false,
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            declaration().parse("font-size: 1.25rem"),
+            Ok((
+                Declaration {
+                    name: "font-size".to_string(),
+                    value: CSSValue::Length((1.25, Unit::Rem)),
+                    important: false,
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            declaration().parse("keyabc : piyo "),
+            Ok((
+                Declaration {
+                    name: "keyabc".to_string(),
+                    value: CSSValue::Keyword("piyo".into()),
+                    important: false,
+                },
+                " "
+            ))
+        );
+
+        assert_eq!(
+            declaration().parse("keyhello : piyo "),
+            Ok((
+                Declaration {
+                    name: "keyhello".to_string(),
+                    value: CSSValue::Keyword("piyo".into()),
+                    important: false,
+                },
+                " "
+            ))
+        );
+
+        assert_eq!(
+            declaration().parse("aspect-ratio: 16 / 9"),
+            Ok((
+                Declaration {
+                    name: "aspect-ratio".to_string(),
+                    value: CSSValue::Ratio((16, 9)),
+                    important: false,
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            declaration().parse("aspect-ratio: 16/9"),
+            Ok((
+                Declaration {
+                    name: "aspect-ratio".to_string(),
+                    value: CSSValue::Ratio((16, 9)),
+                    important: false,
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            declaration().parse("font-weight: semi-bold"),
+            Ok((
+                Declaration {
+                    name: "font-weight".to_string(),
+                    value: CSSValue::Keyword("semi-bold".into()),
+                    important: false,
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            declaration().parse("grid-template-columns: 100px 1fr 2fr ;"),
+            Ok((
+                Declaration {
+                    name: "grid-template-columns".to_string(),
+                    value: CSSValue::List(vec![
+                        CSSValue::Length((100.0, Unit::Px)),
+                        CSSValue::Length((1.0, Unit::Fr)),
+                        CSSValue::Length((2.0, Unit::Fr)),
+                    ]),
+                    important: false,
+                },
+                " ;"
+            ))
+        );
+
+        assert_eq!(
+            declaration().parse("color: red !important;"),
+            Ok((
+                Declaration {
+                    name: "color".to_string(),
+                    value: CSSValue::Keyword("red".into()),
+                    important: true,
+                },
+                ";"
+            ))
+        );
+        assert_eq!(
+            declaration().parse("margin: 0 auto! important"),
+            Ok((
+                Declaration {
+                    name: "margin".to_string(),
+                    value: CSSValue::List(vec![
+                        CSSValue::Number(0.0),
+                        CSSValue::Keyword("auto".into()),
+                    ]),
+                    important: true,
+                },
+                ""
+            ))
+        );
+
+        assert_eq!(
+            declaration().parse("margin: -10px +2.5em -1"),
+            Ok((
+                Declaration {
+                    name: "margin".to_string(),
+                    value: CSSValue::List(vec![
+                        CSSValue::Length((-10.0, Unit::Px)),
+                        CSSValue::Length((2.5, Unit::Em)),
+                        CSSValue::Number(-1.0),
+                    ]),
+                    important: false,
+                },
+                ""
+            ))
+        );
+        assert_eq!(
+            declaration().parse("display: -webkit-box"),
+            Ok((
+                Declaration {
+                    name: "display".to_string(),
+                    value: CSSValue::Keyword("-webkit-box".into()),
+                    important: false,
+                },
+                ""
+            ))
+        );
+
+        assert!(declaration().parse("aaaaa").is_err())
+    }
+
+    #[test]
+    fn test_parse_declarations() {
+        assert_eq!(
+            parse_declarations(" color: red; margin: 0 !important; "),
+            vec![
+                Declaration {
+                    name: "color".to_string(),
+                    value: CSSValue::Keyword("red".into()),
+                    important: false,
+                },
+                Declaration {
+                    name: "margin".to_string(),
+                    value: CSSValue::Number(0.0),
+                    important: true,
+                },
+            ]
+        );
+        assert_eq!(parse_declarations(""), vec![]);
+        assert_eq!(parse_declarations("color red"), vec![]);
+    }
+
+    #[test]
+    fn test_try_parse() {
+        assert_eq!(
+            try_parse("p { color: red; }").map(|stylesheet| stylesheet.rules.len()),
+            Ok(1)
+        );
+        assert!(matches!(
+            try_parse("p { color: ; }"),
+            Err(CssParseError::InvalidStylesheet(_))
+        ));
+        assert!(try_parse("p { color: red").is_err());
+        // the rules before a syntax error are not kept
+        assert!(matches!(
+            try_parse("p { color: red; } @@@"),
+            Err(CssParseError::InvalidStylesheet(_))
+        ));
+        assert_eq!(
+            try_parse("  p { color: red; }\n").map(|stylesheet| stylesheet.rules.len()),
+            Ok(1)
+        );
+        // integers too large for a ratio are parsed as numbers instead of panicking
+        assert_eq!(
+            try_parse("p { width: 99999999999999999999px; }")
+                .map(|stylesheet| stylesheet.rules[0].declarations[0].value.clone()),
+            Ok(CSSValue::Length((1e20, Unit::Px)))
+        );
+        assert_eq!(
+            try_parse("p { aspect-ratio: 99999999999999999999 / 9; }").map(|stylesheet| stylesheet
+                .rules[0]
+                .declarations[0]
+                .value
+                .clone()),
+            Ok(CSSValue::List(vec![
+                CSSValue::Number(1e20),
+                CSSValue::Slash,
+                CSSValue::Number(9.0),
+            ]))
+        );
+        // unsupported functions are kept as opaque values instead of failing the whole sheet
+        let values = |raw: &str| {
+            try_parse(raw).map(|stylesheet| {
+                stylesheet.rules[0]
+                    .declarations
+                    .iter()
+                    .map(|d| d.value.clone())
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(
+            values("p { transform: rotate(45deg); width: calc((1px + 2px) * 2); color: hsl(0, 0%, 0%) }"),
+            Ok(vec![
+                CSSValue::Function("rotate".into(), "45deg".into()),
+                CSSValue::Function("calc".into(), "(1px + 2px) * 2".into()),
+                CSSValue::Function("hsl".into(), "0, 0%, 0%".into()),
+            ])
+        );
+        assert_eq!(
+            values("p { transform: translate(1px, 2px) scale(2) }"),
+            Ok(vec![CSSValue::List(vec![
+                CSSValue::Transform(TransformFunction::Translate(
+                    (1.0, Unit::Px),
+                    (2.0, Unit::Px)
+                )),
+                CSSValue::Transform(TransformFunction::Scale(2.0, 2.0)),
+            ])])
+        );
+        assert!(try_parse("p { width: calc((1px) }").is_err());
+    }
+
+    #[test]
+    fn test_universal_selector_behaviour() {
+        let e = &Element::new(
+            "p".to_string(),
+            [
+                ("id".to_string(), "test".to_string()),
+                ("class".to_string(), "testclass".to_string()),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            vec![],
+        );
+        assert_eq!(
+            SimpleSelector::UniversalSelector.matches(e, &MatchContext::default()),
+            true
+        );
+    }
+
+    #[test]
+    fn test_type_selector_behaviour() {
+        let e = &Element::new(
+            "p".to_string(),
+            [
+                ("id".to_string(), "test".to_string()),
+                ("class".to_string(), "testclass".to_string()),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            vec![],
+        );
+
+        assert_eq!(
+            (SimpleSelector::TypeSelector {
+                tag_name: "p".into(),
+            })
+            .matches(e, &MatchContext::default()),
+            true
+        );
+
+        assert_eq!(
+            (SimpleSelector::TypeSelector {
+                tag_name: "invalid".into(),
+            })
+            .matches(e, &MatchContext::default()),
+            false
+        );
+
+        // <DIV> and DIV { ... } are the same as <div> and div { ... }
+        let e = &crate::html::parse("<DIV></DIV>");
+        let selector = parse_selector("DIV").unwrap();
+        assert!(selector.matches(e, &MatchContext::default()));
+        assert_eq!(parse_selector("Div[id]"), parse_selector("div[id]"));
+    }
+
+    #[test]
+    fn test_attribute_selector_behaviour() {
+        let e = &Element::new(
+            "p".to_string(),
+            [
+                ("id".to_string(), "test".to_string()),
+                ("class".to_string(), "testclass".to_string()),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            vec![],
+        );
+
+        assert_eq!(
+            (SimpleSelector::AttributeSelector {
+                tag_name: Some("p".into()),
+                attribute: "id".into(),
+                value: "test".into(),
+                op: AttributeSelectorOp::Eq,
+            })
+            .matches(e, &MatchContext::default()),
+            true
+        );
+
+        assert_eq!(
+            (SimpleSelector::AttributeSelector {
+                tag_name: Some("p".into()),
+                attribute: "id".into(),
+                value: "invalid".into(),
+                op: AttributeSelectorOp::Eq,
+            })
+            .matches(e, &MatchContext::default()),
+            false
+        );
+
+        assert_eq!(
+            (SimpleSelector::AttributeSelector {
+                tag_name: Some("p".into()),
+                attribute: "invalid".into(),
+                value: "test".into(),
+                op: AttributeSelectorOp::Eq,
+            })
+            .matches(e, &MatchContext::default()),
+            false
+        );
+
+        assert_eq!(
+            (SimpleSelector::AttributeSelector {
+                tag_name: Some("invalid".into()),
+                attribute: "id".into(),
+                value: "test".into(),
+                op: AttributeSelectorOp::Eq,
+            })
+            .matches(e, &MatchContext::default()),
+            false
+        );
+
+        let e = &Element::new(
+            "p".to_string(),
+            [("title".to_string(), "foo  bar\tbaz".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            vec![],
+        );
+        let contain = |value: &str| SimpleSelector::AttributeSelector {
+            tag_name: Some("p".into()),
+            attribute: "title".into(),
+            value: value.into(),
+            op: AttributeSelectorOp::Contain,
+        };
+        for (value, expected) in [
+            ("foo", true),
+            ("bar", true),
+            ("baz", true),
+            ("ba", false),
+            ("qux", false),
+        ] {
+            assert_eq!(
+                contain(value).matches(e, &MatchContext::default()),
+                expected,
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_class_selector_behaviour() {
+        let e = &Element::new(
+            "p".to_string(),
+            [
+                ("id".to_string(), "test".to_string()),
+                ("class".to_string(), "testclass".to_string()),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            vec![],
+        );
+
+        assert_eq!(
+            (SimpleSelector::ClassSelector {
+                class_names: vec!["testclass".into()],
+            })
+            .matches(e, &MatchContext::default()),
+            true
+        );
+
+        assert_eq!(
+            (SimpleSelector::ClassSelector {
+                class_names: vec!["invalid".into()],
+            })
+            .matches(e, &MatchContext::default()),
+            false
+        );
+    }
+
+    #[test]
+    fn test_id_selector_behaviour() {
+        let e = &Element::new(
+            "p".to_string(),
+            [("id".to_string(), "test".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            vec![],
+        );
+
+        assert_eq!(
+            (SimpleSelector::IdSelector { id: "test".into() }).matches(e, &MatchContext::default()),
+            true
+        );
+
+        assert_eq!(
+            (SimpleSelector::IdSelector {
+                id: "invalid".into(),
+            })
+            .matches(e, &MatchContext::default()),
+            false
+        );
+    }
+
+    #[test]
+    fn test_filter_rules_by_selector_kind() {
+        let rule = |selectors: Vec<SimpleSelector>| Rule {
+            selectors,
+            ..Default::default()
+        };
+        // * {}
+        // p, #foo {}
+        // .bar {}
+        // #baz {}
+        let stylesheet = Stylesheet::new(vec![
+            rule(vec![SimpleSelector::UniversalSelector]),
+            rule(vec![
+                SimpleSelector::TypeSelector {
+                    tag_name: "p".into(),
+                },
+                SimpleSelector::IdSelector { id: "foo".into() },
+            ]),
+            rule(vec![SimpleSelector::ClassSelector {
+                class_names: vec!["bar".into()],
+            }]),
+            rule(vec![SimpleSelector::IdSelector { id: "baz".into() }]),
+        ]);
+
+        assert_eq!(
+            stylesheet.filter_rules_by_selector_kind(SelectorKind::Id),
+            vec![&stylesheet.rules[1], &stylesheet.rules[3]]
+        );
+        assert_eq!(
+            stylesheet.filter_rules_by_selector_kind(SelectorKind::Universal),
+            vec![&stylesheet.rules[0]]
+        );
+        assert!(stylesheet
+            .filter_rules_by_selector_kind(SelectorKind::PseudoClass)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_pseudo_class_selector_behaviour() {
+        // <div></div><p></p><span></span><p></p><p></p>
+        let siblings = vec![
+            Element::new("div".to_string(), AttrMap::new(), vec![]),
+            Element::new("p".to_string(), AttrMap::new(), vec![]),
+            Element::new("span".to_string(), AttrMap::new(), vec![]),
+            Element::new("p".to_string(), AttrMap::new(), vec![]),
+            Element::new("p".to_string(), AttrMap::new(), vec![]),
+        ];
+        let matched = |selector: SimpleSelector| {
+            siblings
+                .iter()
+                .enumerate()
+                .filter(|(_, n)| {
+                    selector.matches(
+                        n,
+                        &MatchContext {
+                            siblings: &siblings,
+                            ..Default::default()
+                        },
+                    )
+                })
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            matched(SimpleSelector::PseudoClassSelector {
+                tag_name: Some("p".into()),
+                pseudo_class: PseudoClass::FirstOfType,
+            }),
+            vec![1]
+        );
+        assert_eq!(
+            matched(SimpleSelector::PseudoClassSelector {
+                tag_name: None,
+                pseudo_class: PseudoClass::FirstOfType,
+            }),
+            vec![0, 1, 2]
+        );
+        assert_eq!(
+            matched(SimpleSelector::PseudoClassSelector {
+                tag_name: Some("p".into()),
+                pseudo_class: PseudoClass::NthOfType(Nth { a: 0, b: 2 }),
+            }),
+            vec![3]
+        );
+        assert_eq!(
+            matched(SimpleSelector::PseudoClassSelector {
+                tag_name: Some("p".into()),
+                pseudo_class: PseudoClass::NthOfType(Nth::from_notation("odd").unwrap()),
+            }),
+            vec![1, 4]
+        );
+        assert_eq!(
+            matched(SimpleSelector::PseudoClassSelector {
+                tag_name: Some("p".into()),
+                pseudo_class: PseudoClass::NthOfType(Nth::from_notation("-n+2").unwrap()),
+            }),
+            vec![1, 3]
+        );
+
+        // a node without siblings is the first of its type
+        assert!(SimpleSelector::PseudoClassSelector {
+            tag_name: Some("div".into()),
+            pseudo_class: PseudoClass::FirstOfType,
+        }
+        .matches(&siblings[0], &MatchContext::default()));
+    }
+
+    #[test]
+    fn test_only_pseudo_class_behaviour() {
+        let only_child = SimpleSelector::PseudoClassSelector {
+            tag_name: Some("p".into()),
+            pseudo_class: PseudoClass::OnlyChild,
+        };
+        let only_of_type = SimpleSelector::PseudoClassSelector {
+            tag_name: Some("p".into()),
+            pseudo_class: PseudoClass::OnlyOfType,
+        };
+        let matches = |selector: &SimpleSelector, div: &Node| {
+            let parent_context = MatchContext::default();
+            let context = parent_context.for_children(div);
+            selector.matches(&div.children[0], &context)
+        };
+
+        // <div><p></p></div>
+        let div = Element::new(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![Element::new("p".to_string(), AttrMap::new(), vec![])],
+        );
+        assert!(matches(&only_child, &div));
+        assert!(matches(&only_of_type, &div));
+
+        // <div><p></p>text<!--comment--></div>
+        let div = Element::new(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![
+                Element::new("p".to_string(), AttrMap::new(), vec![]),
+                Text::new("text".to_string()),
+                Comment::new_node("comment".to_string()),
+            ],
+        );
+        assert!(matches(&only_child, &div));
+
+        // <div><p></p><span></span></div>
+        let div = Element::new(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![
+                Element::new("p".to_string(), AttrMap::new(), vec![]),
+                Element::new("span".to_string(), AttrMap::new(), vec![]),
+            ],
+        );
+        assert!(!matches(&only_child, &div));
+        assert!(matches(&only_of_type, &div));
+
+        // <div><p></p><p></p></div>
+        let div = Element::new(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![
+                Element::new("p".to_string(), AttrMap::new(), vec![]),
+                Element::new("p".to_string(), AttrMap::new(), vec![]),
+            ],
+        );
+        assert!(!matches(&only_of_type, &div));
+    }
+
+    #[test]
+    fn test_is_where_pseudo_class_behaviour() {
+        let element = |tag_name: &str, class: &str| {
+            Element::new(
+                tag_name.to_string(),
+                [("class".to_string(), class.to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                vec![],
+            )
+        };
+        let headings = || {
+            vec![
+                SimpleSelector::TypeSelector {
+                    tag_name: "h1".into(),
+                },
+                SimpleSelector::TypeSelector {
+                    tag_name: "h2".into(),
+                },
+            ]
+        };
+        let title = || SimpleSelector::ClassSelector {
+            class_names: vec!["title".into()],
+        };
+        let compound = |pseudo_class: PseudoClass| SimpleSelector::CompoundSelector {
+            selectors: vec![
+                title(),
+                SimpleSelector::PseudoClassSelector {
+                    tag_name: None,
+                    pseudo_class,
+                },
+            ],
+        };
+
+        // .title:is(h1, h2)
+        let is = compound(PseudoClass::Is(headings()));
+        let context = MatchContext::default();
+        assert!(is.matches(&element("h1", "title"), &context));
+        assert!(is.matches(&element("h2", "title"), &context));
+        assert!(!is.matches(&element("h3", "title"), &context));
+        assert!(!is.matches(&element("h1", "subtitle"), &context));
+        assert_eq!(is.specificity(), (0, 1, 1));
+
+        // .title:where(h1, h2)
+        let r#where = compound(PseudoClass::Where(headings()));
+        assert!(r#where.matches(&element("h1", "title"), &context));
+        assert!(!r#where.matches(&element("h3", "title"), &context));
+        assert_eq!(r#where.specificity(), (0, 1, 0));
+
+        // :is(h1, h2, #main) takes its most specific argument, while :where(h1, h2) counts nothing
+        let mut arguments = headings();
+        arguments.push(SimpleSelector::IdSelector { id: "main".into() });
+        let (is, r#where) = (
+            SimpleSelector::PseudoClassSelector {
+                tag_name: None,
+                pseudo_class: PseudoClass::Is(arguments),
+            },
+            SimpleSelector::PseudoClassSelector {
+                tag_name: None,
+                pseudo_class: PseudoClass::Where(headings()),
+            },
+        );
+        assert_eq!(is.specificity(), (1, 0, 0));
+        assert_eq!(r#where.specificity(), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_lang_pseudo_class_behaviour() {
+        let selector = SimpleSelector::PseudoClassSelector {
+            tag_name: None,
+            pseudo_class: PseudoClass::Lang("en".into()),
+        };
+        let p = &Element::new("p".to_string(), AttrMap::new(), vec![]);
+        let fr = &Element::new(
+            "p".to_string(),
+            [("lang".to_string(), "fr".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            vec![],
+        );
+        let context = |lang| MatchContext {
+            lang,
+            ..Default::default()
+        };
+
+        assert_eq!(selector.matches(p, &context(None)), false);
+        assert_eq!(selector.matches(p, &context(Some("en"))), true);
+        assert_eq!(selector.matches(p, &context(Some("EN-us"))), true);
+        assert_eq!(selector.matches(p, &context(Some("eng"))), false);
+        assert_eq!(selector.matches(fr, &context(Some("en"))), false);
+    }
+
+    #[test]
+    fn test_match_context() {
+        // <html lang="en" dir="rtl"><body lang="ja"><p></p></body></html>
+        let root = &Element::new(
+            "html".to_string(),
+            [
+                ("lang".to_string(), "en".to_string()),
+                ("dir".to_string(), "rtl".to_string()),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            vec![Element::new(
+                "body".to_string(),
+                [("lang".to_string(), "ja".to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                vec![Element::new("p".to_string(), AttrMap::new(), vec![])],
+            )],
+        );
+
+        let context = MatchContext::for_document(root);
+        assert_eq!(context.lang, Some("en"));
+        assert_eq!(context.dir, Direction::Rtl);
+        assert!(context.siblings.is_empty());
+
+        let context = context.for_children(root);
+        assert_eq!(context.lang, Some("en"));
+        assert_eq!(context.dir, Direction::Rtl);
+        assert_eq!(context.siblings.len(), 1);
+
+        let body = &root.children[0];
+        let context = context.for_children(body);
+        assert_eq!(context.lang, Some("ja"));
+        assert_eq!(context.dir, Direction::Rtl);
+    }
+
+    #[test]
+    fn test_nth_from_notation() {
+        assert_eq!(Nth::from_notation("odd"), Some(Nth { a: 2, b: 1 }));
+        assert_eq!(Nth::from_notation("even"), Some(Nth { a: 2, b: 0 }));
+        assert_eq!(Nth::from_notation("3"), Some(Nth { a: 0, b: 3 }));
+        assert_eq!(Nth::from_notation("n"), Some(Nth { a: 1, b: 0 }));
+        assert_eq!(Nth::from_notation("2n + 1"), Some(Nth { a: 2, b: 1 }));
+        assert_eq!(Nth::from_notation("-n+3"), Some(Nth { a: -1, b: 3 }));
+        assert_eq!(Nth::from_notation("3n-2"), Some(Nth { a: 3, b: -2 }));
+        assert_eq!(Nth::from_notation("2n1"), None);
+        assert_eq!(Nth::from_notation("foo"), None);
+    }
+
+    #[test]
+    fn test_media_rule() {
+        assert_eq!(
+            rules().parse(
+                "p { aa: bb; } @media (prefers-color-scheme: dark) and (min-width: 600px) { p { cc: dd; } }"
+            ),
+            Ok((
+                vec![
+                    Rule {
+                        selectors: vec![SimpleSelector::TypeSelector {
+                            tag_name: "p".to_string(),
+                        }],
+                        declarations: vec![Declaration {
+                            name: "aa".to_string(),
+                            value: CSSValue::Keyword("bb".into()),
+                            important: false,
+                        }],
+                        media: None,
+                        supports: None,
+                        origin: Origin::Author,
+                        layer: None,
+                    },
+                    Rule {
+                        selectors: vec![SimpleSelector::TypeSelector {
+                            tag_name: "p".to_string(),
+                        }],
+                        declarations: vec![Declaration {
+                            name: "cc".to_string(),
+                            value: CSSValue::Keyword("dd".into()),
+                            important: false,
+                        }],
+                        media: Some(MediaQuery {
+                            features: vec![
+                                MediaFeature {
+                                    name: "prefers-color-scheme".to_string(),
+                                    value: CSSValue::Keyword("dark".into()),
+                                },
+                                MediaFeature {
+                                    name: "min-width".to_string(),
+                                    value: CSSValue::Length((600.0, Unit::Px)),
+                                }
+                            ]
+                        }),
+                        supports: None,
+                        origin: Origin::Author,
+                        layer: None,
+                    },
+                ],
+                ""
+            ))
+        );
+    }
+
+    #[test]
+    fn test_supports_rule() {
+        assert_eq!(
+            rules().parse("@supports (display: flex) { p { aa: bb; } }"),
+            Ok((
+                vec![Rule {
+                    selectors: vec![SimpleSelector::TypeSelector {
+                        tag_name: "p".to_string(),
+                    }],
+                    declarations: vec![Declaration {
+                        name: "aa".to_string(),
+                        value: CSSValue::Keyword("bb".into()),
+                        important: false,
+                    }],
+                    media: None,
+                    supports: Some(SupportsCondition::Declaration(Declaration {
+                        name: "display".to_string(),
+                        value: CSSValue::Keyword("flex".into()),
+                        important: false,
+                    })),
+                    origin: Origin::Author,
+                    layer: None,
+                }],
+                ""
+            ))
+        );
+
+        assert_eq!(
+            rules().parse("@supports ??? { p { aa: bb; } }"),
+            Ok((
+                vec![Rule {
+                    selectors: vec![SimpleSelector::TypeSelector {
+                        tag_name: "p".to_string(),
+                    }],
+                    declarations: vec![Declaration {
+                        name: "aa".to_string(),
+                        value: CSSValue::Keyword("bb".into()),
+                        important: false,
+                    }],
+                    media: None,
+                    supports: Some(SupportsCondition::Unknown),
+                    origin: Origin::Author,
+                    layer: None,
+                }],
+                ""
+            ))
+        );
+    }
+
+    #[test]
+    fn test_supports_condition() {
+        let display = |value: &str| {
+            SupportsCondition::Declaration(Declaration {
+                name: "display".to_string(),
+                value: CSSValue::Keyword(value.to_string().into()),
+                important: false,
+            })
+        };
+
+        assert_eq!(
+            supports_condition().parse("not (display: flex)"),
+            Ok((SupportsCondition::Not(Box::new(display("flex"))), ""))
+        );
+
+        assert_eq!(
+            supports_condition().parse("(display: flex) and (display: block)"),
+            Ok((
+                SupportsCondition::And(vec![display("flex"), display("block")]),
+                ""
+            ))
+        );
+
+        assert_eq!(
+            supports_condition().parse("(display: flex) or (display: block) or (display: none)"),
+            Ok((
+                SupportsCondition::Or(vec![display("flex"), display("block"), display("none")]),
+                ""
+            ))
+        );
+
+        assert_eq!(
+            supports_condition().parse("(display: flex) and (display: block) or (display: none)"),
+            Ok((SupportsCondition::Unknown, ""))
+        );
+    }
+
+    #[test]
+    fn test_color() {
+        assert_eq!(Color::from_hex("00f"), Some(Color::rgb(0, 0, 255)));
+        assert_eq!(Color::from_hex("0000FF"), Some(Color::rgb(0, 0, 255)));
+        assert_eq!(
+            Color::from_hex("ff000080"),
+            Some(Color {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 128
+            })
+        );
+        assert_eq!(Color::from_hex("12345"), None);
+        assert_eq!(Color::from_hex("xyz"), None);
+
+        assert_eq!(
+            CSSValue::Keyword("Blue".into()).to_color(),
+            Some(Color::rgb(0, 0, 255))
+        );
+        assert_eq!(CSSValue::Keyword("block".into()).to_color(), None);
+
+        assert_eq!(Color::from_keyword("orange"), Some(Color::rgb(255, 165, 0)));
+        assert_eq!(
+            Color::from_keyword("RebeccaPurple"),
+            Some(Color::rgb(102, 51, 153))
+        );
+        assert_eq!(
+            Color::from_keyword("lightgoldenrodyellow"),
+            Some(Color::rgb(250, 250, 210))
+        );
+        assert_eq!(Color::from_keyword("transparent").map(|c| c.a), Some(0));
+        assert!(NAMED_COLORS.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn test_rgb_function() {
+        let half_red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 128,
+        };
+        assert_eq!(
+            Color::from_rgb_function("255, 0, 0"),
+            Some(Color::rgb(255, 0, 0))
+        );
+        assert_eq!(Color::from_rgb_function("100% 0% 0% / 50%"), Some(half_red));
+        assert_eq!(Color::from_rgb_function("255, 0, 0, 0.5"), Some(half_red));
+        // out-of-range values are clamped
+        assert_eq!(
+            Color::from_rgb_function("300, -10, 0"),
+            Some(Color::rgb(255, 0, 0))
+        );
+        assert_eq!(Color::from_rgb_function("255, 0"), None);
+        assert_eq!(Color::from_rgb_function("255, 0, red"), None);
+
+        assert_eq!(
+            css_value().parse("rgb(0, 0, 255)"),
+            Ok((CSSValue::Color(Color::rgb(0, 0, 255)), ""))
+        );
+        assert_eq!(
+            css_value().parse("rgba(255, 0, 0, 0.5)"),
+            Ok((CSSValue::Color(half_red), ""))
+        );
+        assert!(css_value().parse("rgb(0, 0)").is_err());
+        assert_eq!(
+            css_value().parse("rgbx"),
+            Ok((CSSValue::keyword("rgbx"), ""))
+        );
+    }
+
+    #[test]
+    fn test_transform_function() {
+        assert_eq!(
+            TransformFunction::from_notation("translate", "10px, 5%"),
+            Some(TransformFunction::Translate(
+                (10.0, Unit::Px),
+                (5.0, Unit::Percent)
+            ))
+        );
+        assert_eq!(
+            TransformFunction::from_notation("translate", "-1em"),
+            Some(TransformFunction::Translate(
+                (-1.0, Unit::Em),
+                (0.0, Unit::Px)
+            ))
+        );
+        assert_eq!(
+            TransformFunction::from_notation("translateY", "0"),
+            Some(TransformFunction::Translate(
+                (0.0, Unit::Px),
+                (0.0, Unit::Px)
+            ))
+        );
+        assert_eq!(
+            TransformFunction::from_notation("scale", "2"),
+            Some(TransformFunction::Scale(2.0, 2.0))
+        );
+        assert_eq!(
+            TransformFunction::from_notation("scaleX", "0.5"),
+            Some(TransformFunction::Scale(0.5, 1.0))
+        );
+
+        assert_eq!(TransformFunction::from_notation("translate", "10"), None);
+        assert_eq!(TransformFunction::from_notation("scale", "2px"), None);
+        assert_eq!(TransformFunction::from_notation("scale", "1, 2, 3"), None);
+        assert_eq!(TransformFunction::from_notation("rotate", "45deg"), None);
+    }
+
+    #[test]
+    fn test_color_mix() {
+        let purple = Color::rgb(128, 0, 128);
+        assert_eq!(
+            Color::from_color_mix("in srgb, red 50%, blue"),
+            Some(purple)
+        );
+        assert_eq!(Color::from_color_mix("in srgb, red, blue"), Some(purple));
+        // the percentages are normalized
+        assert_eq!(
+            Color::from_color_mix("in srgb, red 20%, blue 20%"),
+            Some(purple)
+        );
+        assert_eq!(
+            Color::from_color_mix("in srgb, #f00 75%, #00f"),
+            Some(Color::rgb(191, 0, 64))
+        );
+        // unsupported color spaces fall back to sRGB
+        assert_eq!(Color::from_color_mix("in oklch, red, blue"), Some(purple));
+        // the transparent color does not tint the other one
+        assert_eq!(
+            Color::from_color_mix("in srgb, red, transparent"),
+            Some(Color {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 128
+            })
+        );
+
+        assert_eq!(Color::from_color_mix("red, blue"), None);
+        assert_eq!(Color::from_color_mix("in srgb, red"), None);
+        assert_eq!(Color::from_color_mix("in srgb, red 0%, blue 0%"), None);
+        assert_eq!(Color::from_color_mix("in srgb, nocolor, blue"), None);
+    }
+
+    #[test]
+    fn test_parse_color_mix() {
+        let purple = Color::rgb(128, 0, 128);
+        assert_eq!(
+            css_value().parse("color-mix(in srgb, red 50%, blue)"),
+            Ok((CSSValue::Color(purple), ""))
+        );
+    }
+
+    #[test]
+    fn test_keyword() {
+        assert!(matches!(
+            CSSValue::keyword("block"),
+            CSSValue::Keyword(Cow::Borrowed("block"))
+        ));
+        assert!(matches!(
+            CSSValue::keyword("flex"),
+            CSSValue::Keyword(Cow::Owned(_))
+        ));
+        assert_eq!(
+            CSSValue::keyword("none"),
+            CSSValue::Keyword(Cow::Owned("none".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_compound_selector_matches_order() {
+        // :first-of-type[title~=x].note#test
+        let selectors = || {
+            vec![
+                SimpleSelector::PseudoClassSelector {
+                    tag_name: None,
+                    pseudo_class: PseudoClass::FirstOfType,
+                },
+                SimpleSelector::AttributeSelector {
+                    tag_name: None,
+                    attribute: "title".into(),
+                    op: AttributeSelectorOp::Contain,
+                    value: "x".into(),
+                },
+                SimpleSelector::ClassSelector {
+                    class_names: vec!["note".into()],
+                },
+                SimpleSelector::IdSelector { id: "test".into() },
+            ]
+        };
+        let element = |tag_name: &str, attributes: &[(&str, &str)]| {
+            Element::new(
+                tag_name.to_string(),
+                attributes
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                vec![],
+            )
+        };
+        let siblings = vec![
+            element("p", &[("id", "test"), ("class", "note"), ("title", "x y")]),
+            element("p", &[("id", "test"), ("class", "note"), ("title", "x")]),
+            element("span", &[("class", "note"), ("title", "y")]),
+            element(
+                "div",
+                &[("id", "test"), ("class", "note"), ("title", "a x")],
+            ),
+        ];
+        let context = MatchContext {
+            siblings: &siblings,
+            ..Default::default()
+        };
+
+        // every subset of the selectors matches the same nodes as checking them in source order
+        for mask in 0..(1 << selectors().len()) {
+            let selectors = selectors()
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| mask & (1 << i) != 0)
+                .map(|(_, s)| s)
+                .collect::<Vec<_>>();
+            let compound = SimpleSelector::CompoundSelector {
+                selectors: selectors.clone(),
+            };
+            for n in &siblings {
+                assert_eq!(
+                    compound.matches(n, &context),
+                    selectors.iter().all(|s| s.matches(n, &context))
+                );
+            }
+        }
+
+        let compound = SimpleSelector::CompoundSelector {
+            selectors: selectors(),
+        };
+        assert_eq!(
+            siblings
+                .iter()
+                .map(|n| compound.matches(n, &context))
+                .collect::<Vec<_>>(),
+            vec![true, false, false, true]
+        );
+    }
+
+    #[test]
+    fn test_multiple_class_selector_behaviour() {
+        let e = &Element::new(
+            "p".to_string(),
+            [("class".to_string(), "a b  c\td e f g h".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            vec![],
+        );
+        let selector = |class_names: &[&str]| SimpleSelector::ClassSelector {
+            class_names: class_names.iter().map(|c| c.to_string()).collect(),
+        };
+
+        assert_eq!(selector(&["a"]).matches(e, &MatchContext::default()), true);
+        assert_eq!(selector(&["d"]).matches(e, &MatchContext::default()), true);
+        assert_eq!(
+            selector(&["h", "c", "a"]).matches(e, &MatchContext::default()),
+            true
+        );
+        assert_eq!(
+            selector(&["a", "x"]).matches(e, &MatchContext::default()),
+            false
+        );
+        assert_eq!(
+            selector(&["a b"]).matches(e, &MatchContext::default()),
+            false
+        );
+
+        assert_eq!(
+            selector(&["a", "b"]).classes(),
+            Some(&["a".to_string(), "b".to_string()][..])
+        );
+        assert_eq!(selector(&["a", "b"]).specificity(), (0, 2, 0));
+        assert_eq!(SimpleSelector::UniversalSelector.classes(), None);
+    }
+
+    #[test]
+    fn test_rules_for_origin() {
+        let rule = |selector: SimpleSelector| Rule {
+            selectors: vec![selector],
+            ..Default::default()
+        };
+        let ua = Stylesheet::new(vec![
+            rule(SimpleSelector::TypeSelector {
+                tag_name: "p".into(),
+            }),
+            rule(SimpleSelector::TypeSelector {
+                tag_name: "div".into(),
+            }),
+        ])
+        .with_origin(Origin::UserAgent);
+        let author = Stylesheet::new(vec![rule(SimpleSelector::ClassSelector {
+            class_names: vec!["note".into()],
+        })]);
+        let stylesheet = Stylesheet::merge([ua, author]);
+
+        let e = &Element::new(
+            "p".to_string(),
+            [("class".to_string(), "note".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            vec![],
+        );
+        let origins = stylesheet
+            .rules_for(e, &MatchContext::default())
+            .iter()
+            .map(|r| r.origin)
+            .collect::<Vec<_>>();
+        assert_eq!(origins, vec![Origin::UserAgent, Origin::Author]);
+    }
+
+    #[test]
+    fn test_length_arithmetic() {
+        let context = LengthContext {
+            font_size: 20.0,
+            root_font_size: 16.0,
+            percentage_basis: 300.0,
+        };
+
+        assert_eq!(
+            CSSValue::Length((2.0, Unit::Em)).to_px(&context),
+            Some(40.0)
+        );
+        assert_eq!(
+            CSSValue::Length((2.0, Unit::Rem)).to_px(&context),
+            Some(32.0)
+        );
+        assert_eq!(
+            CSSValue::Length((50.0, Unit::Percent)).to_px(&context),
+            Some(150.0)
+        );
+        assert_eq!(
+            CSSValue::Length((10.0, Unit::Px)).to_px(&context),
+            Some(10.0)
+        );
+        assert_eq!(CSSValue::Length((1.0, Unit::Fr)).to_px(&context), None);
+        assert_eq!(CSSValue::Number(10.0).to_px(&context), None);
+
+        assert_eq!(
+            CSSValue::Length((1.0, Unit::Em)).add(&CSSValue::Length((0.5, Unit::Em))),
+            Some(CSSValue::Length((1.5, Unit::Em)))
+        );
+        assert_eq!(
+            CSSValue::Number(1.0).add(&CSSValue::Number(2.0)),
+            Some(CSSValue::Number(3.0))
+        );
+        // mixed units cannot be added without resolving them
+        assert_eq!(
+            CSSValue::Length((1.0, Unit::Em)).add(&CSSValue::Length((10.0, Unit::Px))),
+            None
+        );
+        assert_eq!(
+            CSSValue::Length((1.0, Unit::Px)).add(&CSSValue::Number(1.0)),
+            None
+        );
 
         assert_eq!(
-            rule().parse("test [foo=bar] { aa: bb; cc: 60%; }"),
-            Ok((
-                Rule {
-                    selectors: vec![SimpleSelector::AttributeSelector {
-                        tag_name: "test".to_string(),
-                        attribute: "foo".to_string(),
-                        op: AttributeSelectorOp::Eq,
-                        value: "bar".to_string()
-                    }],
-                    declarations: vec![
-                        Declaration {
-                            name: "aa".to_string(),
-                            value: CSSValue::Keyword("bb".to_string())
-                        },
-                        Declaration {
-                            name: "cc".to_string(),
-                            value: CSSValue::Length((60, Unit::Percent)),
-                        }
-                    ]
-                },
-                ""
-            ))
+            CSSValue::Length((50.0, Unit::Percent)).scale(0.5),
+            Some(CSSValue::Length((25.0, Unit::Percent)))
         );
+        assert_eq!(CSSValue::keyword("auto").scale(2.0), None);
     }
 
     #[test]
-    fn test_declarations() {
+    fn test_pseudo_element_selector_behaviour() {
+        let p = &Element::new("p".to_string(), AttrMap::new(), vec![]);
+        let div = &Element::new("div".to_string(), AttrMap::new(), vec![]);
+        let selector = SimpleSelector::PseudoElementSelector {
+            selector: Box::new(SimpleSelector::TypeSelector {
+                tag_name: "p".into(),
+            }),
+            pseudo_element: PseudoElement::FirstLetter,
+        };
+        let context = MatchContext::default();
+
+        // pseudo-elements are not elements
+        assert_eq!(selector.matches(p, &context), false);
         assert_eq!(
-            declarations().parse("foo: bar; piyo: 1rem;"),
-            Ok((
-                vec![
-                    Declaration {
-                        name: "foo".to_string(),
-                        value: CSSValue::Keyword("bar".to_string())
-                    },
-                    Declaration {
-                        name: "piyo".to_string(),
-                        value: CSSValue::Length((1, Unit::Rem)),
-                    }
-                ],
-                ""
-            ))
+            selector.matches_pseudo_element(p, &context, PseudoElement::FirstLetter),
+            true
+        );
+        assert_eq!(
+            selector.matches_pseudo_element(p, &context, PseudoElement::FirstLine),
+            false
+        );
+        assert_eq!(
+            selector.matches_pseudo_element(div, &context, PseudoElement::FirstLetter),
+            false
+        );
+        assert_eq!(selector.specificity(), (0, 0, 2));
+
+        let rule = Rule {
+            selectors: vec![selector],
+            ..Default::default()
+        };
+        assert_eq!(rule.specificity(p, &context), None);
+        assert_eq!(
+            rule.pseudo_element_specificity(p, &context, PseudoElement::FirstLetter),
+            Some((0, 0, 2))
         );
     }
 
     #[test]
-    fn test_selectors() {
+    fn test_nested_rule_desugar() {
+        let class = |name: &str| SimpleSelector::ClassSelector {
+            class_names: vec![name.into()],
+        };
+        // .card { color: black; & .title, &.active, & { font-weight: bold } }
+        let parent = Rule {
+            selectors: vec![class("card")],
+            declarations: vec![Declaration {
+                name: "color".into(),
+                value: CSSValue::keyword("black"),
+                important: false,
+            }],
+            ..Default::default()
+        };
+        let nested = NestedRule {
+            selectors: vec![
+                NestedSelector::Descendant(class("title")),
+                NestedSelector::Compound(class("active")),
+                NestedSelector::Parent,
+            ],
+            declarations: vec![Declaration {
+                name: "font-weight".into(),
+                value: CSSValue::keyword("bold"),
+                important: false,
+            }],
+        };
+        let rule = nested.desugar(&parent);
+        let descendant = SimpleSelector::DescendantSelector {
+            ancestor: Box::new(class("card")),
+            selector: Box::new(class("title")),
+        };
         assert_eq!(
-            selectors().parse("test [foo=bar], a"),
-            Ok((
-                vec![
-                    SimpleSelector::AttributeSelector {
-                        tag_name: "test".to_string(),
-                        attribute: "foo".to_string(),
-                        op: AttributeSelectorOp::Eq,
-                        value: "bar".to_string()
-                    },
-                    SimpleSelector::TypeSelector {
-                        tag_name: "a".to_string(),
-                    }
-                ],
-                ""
-            ))
+            rule.selectors,
+            vec![
+                descendant.clone(),
+                SimpleSelector::CompoundSelector {
+                    selectors: vec![class("card"), class("active")],
+                },
+                class("card"),
+            ]
         );
+        assert_eq!(descendant.specificity(), (0, 2, 0));
+
+        // <div class="card"><section><p class="title"></p></section></div><p class="title"></p>
+        let root = build_tree(&TreeSpec::Element(
+            "body",
+            &[],
+            &[
+                TreeSpec::Element(
+                    "div",
+                    &[("class", "card")],
+                    &[TreeSpec::Element(
+                        "section",
+                        &[],
+                        &[TreeSpec::Element("p", &[("class", "title")], &[])],
+                    )],
+                ),
+                TreeSpec::Element("p", &[("class", "title")], &[]),
+            ],
+        ));
+        let body_context = MatchContext::for_document(&root);
+        let children_context = body_context.for_children(&root);
+        let card = &root.children[0];
+        let card_context = children_context.for_children(card);
+        let section = &card.children[0];
+        let section_context = card_context.for_children(section);
+
+        assert!(rule.matches(&section.children[0], &section_context));
+        assert!(!rule.matches(section, &card_context));
+        assert!(!rule.matches(&root.children[1], &children_context));
     }
 
     #[test]
-    fn test_simple_selector() {
+    fn test_parse_nested_rule() {
         assert_eq!(
-            simple_selector().parse("*"),
-            Ok((SimpleSelector::UniversalSelector, ""))
+            parse(".card { color: black; & .title { font-weight: bold } }"),
+            Stylesheet::new(vec![
+                Rule {
+                    selectors: vec![SimpleSelector::ClassSelector {
+                        class_names: vec!["card".into()],
+                    }],
+                    declarations: vec![Declaration {
+                        name: "color".into(),
+                        value: CSSValue::keyword("black"),
+                        important: false,
+                    }],
+                    ..Default::default()
+                },
+                Rule {
+                    selectors: vec![SimpleSelector::DescendantSelector {
+                        ancestor: Box::new(SimpleSelector::ClassSelector {
+                            class_names: vec!["card".into()],
+                        }),
+                        selector: Box::new(SimpleSelector::ClassSelector {
+                            class_names: vec!["title".into()],
+                        }),
+                    }],
+                    declarations: vec![Declaration {
+                        name: "font-weight".into(),
+                        value: CSSValue::keyword("bold"),
+                        important: false,
+                    }],
+                    ..Default::default()
+                },
+            ])
         );
+    }
+
+    #[test]
+    fn test_attribute_selector_without_tag() {
+        let element = |tag_name: &str, attributes: &[(&str, &str)]| {
+            Element::new(
+                tag_name.to_string(),
+                attributes
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                vec![],
+            )
+        };
+        let context = MatchContext::default();
+
+        // [data-x=1]
+        let selector = SimpleSelector::AttributeSelector {
+            tag_name: None,
+            op: AttributeSelectorOp::Eq,
+            attribute: "data-x".into(),
+            value: "1".into(),
+        };
+        assert!(selector.matches(&element("div", &[("data-x", "1")]), &context));
+        assert!(selector.matches(&element("span", &[("data-x", "1")]), &context));
+        assert!(!selector.matches(&element("span", &[("data-x", "2")]), &context));
+        assert_eq!(selector.specificity(), (0, 1, 0));
+
+        // [disabled]
+        let selector = SimpleSelector::AttributeSelector {
+            tag_name: None,
+            op: AttributeSelectorOp::Exists,
+            attribute: "disabled".into(),
+            value: "".into(),
+        };
+        assert!(selector.matches(&element("button", &[("disabled", "")]), &context));
+        assert!(!selector.matches(&element("button", &[]), &context));
+    }
 
+    #[test]
+    fn test_parse_attribute_selector_without_tag() {
         assert_eq!(
-            simple_selector().parse("test"),
+            simple_selector().parse("[data-x=1]"),
             Ok((
-                SimpleSelector::TypeSelector {
-                    tag_name: "test".to_string(),
+                SimpleSelector::AttributeSelector {
+                    tag_name: None,
+                    op: AttributeSelectorOp::Eq,
+                    attribute: "data-x".into(),
+                    value: "1".into(),
                 },
                 ""
             ))
         );
-
         assert_eq!(
-            simple_selector().parse("test [foo=bar]"),
+            simple_selector().parse("[disabled]"),
             Ok((
                 SimpleSelector::AttributeSelector {
-                    tag_name: "test".to_string(),
-                    attribute: "foo".to_string(),
-                    op: AttributeSelectorOp::Eq,
-                    value: "bar".to_string()
+                    tag_name: None,
+                    op: AttributeSelectorOp::Exists,
+                    attribute: "disabled".into(),
+                    value: "".into(),
                 },
                 ""
             ))
         );
-
         assert_eq!(
-            simple_selector().parse(".test"),
+            simple_selector().parse("a[title~=\"new tab\"]"),
             Ok((
-                SimpleSelector::ClassSelector {
-                    class_name: "test".to_string(),
+                SimpleSelector::AttributeSelector {
+                    tag_name: Some("a".into()),
+                    op: AttributeSelectorOp::Contain,
+                    attribute: "title".into(),
+                    value: "new tab".into(),
                 },
                 ""
             ))
@@ -488,177 +4455,289 @@ mod tests {
     }
 
     #[test]
-    fn test_declaration() {
+    fn test_parse_font_family_stack() {
         assert_eq!(
-            declaration().parse("key:1em"),
+            declaration().parse("font-family: \"Helvetica\", 'Arial', sans-serif"),
             Ok((
                 Declaration {
-                    name: "key".to_string(),
-                    value: CSSValue::Length((1, Unit::Em)),
+                    name: "font-family".into(),
+                    value: CSSValue::List(vec![
+                        CSSValue::String("Helvetica".into()),
+                        CSSValue::Comma,
+                        CSSValue::String("Arial".into()),
+                        CSSValue::Comma,
+                        CSSValue::keyword("sans-serif"),
+                    ]),
+                    important: false,
                 },
                 ""
             ))
         );
+    }
 
-        assert_eq!(
-            declaration().parse("keyabc : piyo "),
-            Ok((
-                Declaration {
-                    name: "keyabc".to_string(),
-                    value: CSSValue::Keyword("piyo".to_string()),
+    #[test]
+    fn test_parse_compound_selector() {
+        let p_warning = SimpleSelector::CompoundSelector {
+            selectors: vec![
+                SimpleSelector::TypeSelector {
+                    tag_name: "p".into(),
                 },
-                " "
-            ))
+                SimpleSelector::ClassSelector {
+                    class_names: vec!["warning".into()],
+                },
+            ],
+        };
+        assert_eq!(
+            simple_selector().parse("p.warning"),
+            Ok((p_warning.clone(), ""))
         );
-
+        assert_eq!(parse_selector("p.warning"), Ok(p_warning));
         assert_eq!(
-            declaration().parse("keyhello : piyo "),
+            simple_selector().parse("a#top:first-of-type"),
             Ok((
-                Declaration {
-                    name: "keyhello".to_string(),
-                    value: CSSValue::Keyword("piyo".to_string()),
+                SimpleSelector::CompoundSelector {
+                    selectors: vec![
+                        SimpleSelector::TypeSelector {
+                            tag_name: "a".into(),
+                        },
+                        SimpleSelector::IdSelector { id: "top".into() },
+                        SimpleSelector::PseudoClassSelector {
+                            tag_name: None,
+                            pseudo_class: PseudoClass::FirstOfType,
+                        },
+                    ],
                 },
-                " "
+                ""
             ))
         );
-
-        assert!(declaration().parse("aaaaa").is_err())
-    }
-
-    #[test]
-    fn test_universal_selector_behaviour() {
-        let e = &Element::new(
-            "p".to_string(),
-            [
-                ("id".to_string(), "test".to_string()),
-                ("class".to_string(), "testclass".to_string()),
-            ]
-            .iter()
-            .cloned()
-            .collect(),
-            vec![],
+        assert_eq!(
+            parse_selector("p .warning"),
+            Ok(SimpleSelector::DescendantSelector {
+                ancestor: Box::new(SimpleSelector::TypeSelector {
+                    tag_name: "p".into(),
+                }),
+                selector: Box::new(SimpleSelector::ClassSelector {
+                    class_names: vec!["warning".into()],
+                }),
+            })
         );
-        assert_eq!(SimpleSelector::UniversalSelector.matches(e), true);
     }
 
     #[test]
-    fn test_type_selector_behaviour() {
-        let e = &Element::new(
-            "p".to_string(),
-            [
-                ("id".to_string(), "test".to_string()),
-                ("class".to_string(), "testclass".to_string()),
-            ]
-            .iter()
-            .cloned()
-            .collect(),
-            vec![],
-        );
+    fn test_parse_combinators() {
+        let type_selector = |tag_name: &str| SimpleSelector::TypeSelector {
+            tag_name: tag_name.into(),
+        };
+        let descendant = |ancestor, selector| SimpleSelector::DescendantSelector {
+            ancestor: Box::new(ancestor),
+            selector: Box::new(selector),
+        };
+        let child = |parent, selector| SimpleSelector::ChildSelector {
+            parent: Box::new(parent),
+            selector: Box::new(selector),
+        };
 
         assert_eq!(
-            (SimpleSelector::TypeSelector {
-                tag_name: "p".into(),
-            })
-            .matches(e),
-            true
+            parse_selector("ul > li"),
+            Ok(child(type_selector("ul"), type_selector("li")))
         );
-
         assert_eq!(
-            (SimpleSelector::TypeSelector {
-                tag_name: "invalid".into(),
-            })
-            .matches(e),
-            false
-        );
-    }
-
-    #[test]
-    fn test_attribute_selector_behaviour() {
-        let e = &Element::new(
-            "p".to_string(),
-            [
-                ("id".to_string(), "test".to_string()),
-                ("class".to_string(), "testclass".to_string()),
-            ]
-            .iter()
-            .cloned()
-            .collect(),
-            vec![],
+            parse_selector("ul>li"),
+            Ok(child(type_selector("ul"), type_selector("li")))
         );
-
+        // combinators are nested from the left
         assert_eq!(
-            (SimpleSelector::AttributeSelector {
-                tag_name: "p".into(),
-                attribute: "id".into(),
-                value: "test".into(),
-                op: AttributeSelectorOp::Eq,
-            })
-            .matches(e),
-            true
+            parse_selector("div ul > li a"),
+            Ok(descendant(
+                child(
+                    descendant(type_selector("div"), type_selector("ul")),
+                    type_selector("li")
+                ),
+                type_selector("a")
+            ))
         );
-
+        // the pseudo-element stays at the end
         assert_eq!(
-            (SimpleSelector::AttributeSelector {
-                tag_name: "p".into(),
-                attribute: "id".into(),
-                value: "invalid".into(),
-                op: AttributeSelectorOp::Eq,
+            parse_selector("div > p::first-line"),
+            Ok(SimpleSelector::PseudoElementSelector {
+                selector: Box::new(child(type_selector("div"), type_selector("p"))),
+                pseudo_element: PseudoElement::FirstLine,
             })
-            .matches(e),
-            false
         );
+        assert!(parse_selector("p::first-line span").is_err());
+        assert!(parse_selector("ul >").is_err());
 
+        // whitespace before `{` and `,` is not a combinator
         assert_eq!(
-            (SimpleSelector::AttributeSelector {
-                tag_name: "p".into(),
-                attribute: "invalid".into(),
-                value: "test".into(),
-                op: AttributeSelectorOp::Eq,
-            })
-            .matches(e),
-            false
+            selectors().parse("div p , ul>li {"),
+            Ok((
+                vec![
+                    descendant(type_selector("div"), type_selector("p")),
+                    child(type_selector("ul"), type_selector("li")),
+                ],
+                "{"
+            ))
         );
+    }
 
-        assert_eq!(
-            (SimpleSelector::AttributeSelector {
-                tag_name: "invalid".into(),
-                attribute: "id".into(),
-                value: "test".into(),
-                op: AttributeSelectorOp::Eq,
-            })
-            .matches(e),
-            false
-        );
+    #[test]
+    fn test_combinator_behaviour() {
+        // <div><p><span></span></p></div>
+        let root = &build_tree(&TreeSpec::Element(
+            "div",
+            &[],
+            &[TreeSpec::Element(
+                "p",
+                &[],
+                &[TreeSpec::Element("span", &[], &[])],
+            )],
+        ));
+        let div_context = MatchContext::for_document(root);
+        let p = &root.children[0];
+        let p_context = div_context.for_children(root);
+        let span = &p.children[0];
+        let span_context = p_context.for_children(p);
+
+        let descendant = parse_selector("div span").unwrap();
+        let child = parse_selector("div > span").unwrap();
+        assert!(descendant.matches(span, &span_context));
+        assert!(!child.matches(span, &span_context));
+        assert!(parse_selector("p > span")
+            .unwrap()
+            .matches(span, &span_context));
+        assert!(parse_selector("div > p > span")
+            .unwrap()
+            .matches(span, &span_context));
+        // the root has no parent
+        assert!(!parse_selector("* > div")
+            .unwrap()
+            .matches(root, &div_context));
+
+        assert_eq!(child.kind(), SelectorKind::Child);
+        assert_eq!(child.specificity(), (0, 0, 2));
     }
 
     #[test]
-    fn test_class_selector_behaviour() {
-        let e = &Element::new(
-            "p".to_string(),
-            [
-                ("id".to_string(), "test".to_string()),
-                ("class".to_string(), "testclass".to_string()),
-            ]
-            .iter()
-            .cloned()
-            .collect(),
-            vec![],
-        );
+    fn test_specificity_histogram() {
+        let class = |name: &str| SimpleSelector::ClassSelector {
+            class_names: vec![name.into()],
+        };
+        let stylesheet = Stylesheet::new(vec![
+            // p, .a { }
+            Rule {
+                selectors: vec![
+                    SimpleSelector::TypeSelector {
+                        tag_name: "p".into(),
+                    },
+                    class("a"),
+                ],
+                ..Default::default()
+            },
+            // .b, #c { }
+            Rule {
+                selectors: vec![class("b"), SimpleSelector::IdSelector { id: "c".into() }],
+                ..Default::default()
+            },
+            // * { }
+            Rule {
+                selectors: vec![SimpleSelector::UniversalSelector],
+                ..Default::default()
+            },
+        ]);
 
         assert_eq!(
-            (SimpleSelector::ClassSelector {
-                class_name: "testclass".into(),
-            })
-            .matches(e),
-            true
+            stylesheet
+                .specificity_histogram()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![
+                ((0, 0, 0), 1),
+                ((0, 0, 1), 1),
+                ((0, 1, 0), 2),
+                ((1, 0, 0), 1)
+            ]
         );
+        assert!(Stylesheet::new(vec![]).specificity_histogram().is_empty());
+    }
 
+    #[test]
+    fn test_layer_rule() {
+        let rule = |tag_name: &str, layer: Option<usize>| Rule {
+            selectors: vec![SimpleSelector::TypeSelector {
+                tag_name: tag_name.to_string(),
+            }],
+            declarations: vec![Declaration {
+                name: "aa".to_string(),
+                value: CSSValue::Keyword("bb".into()),
+                important: false,
+            }],
+            layer,
+            ..Default::default()
+        };
         assert_eq!(
-            (SimpleSelector::ClassSelector {
-                class_name: "invalid".into(),
-            })
-            .matches(e),
-            false
+            rules().parse(
+                "@layer base, components; \
+                 @layer components { p { aa: bb; } } \
+                 a { aa: bb; } \
+                 @layer base { div { aa: bb; } } \
+                 @layer utilities { span { aa: bb; } }"
+            ),
+            Ok((
+                vec![
+                    rule("p", Some(1)),
+                    rule("a", None),
+                    rule("div", Some(0)),
+                    rule("span", Some(2)),
+                ],
+                ""
+            ))
         );
+        assert!(layer_rule().parse("@layer a, b { p { aa: bb; } }").is_err());
+    }
+
+    #[test]
+    fn test_css_value_display() {
+        let cases = [
+            (CSSValue::keyword("block"), "block"),
+            (CSSValue::Length((1.5, Unit::Em)), "1.5em"),
+            (CSSValue::Length((50.0, Unit::Percent)), "50%"),
+            (
+                CSSValue::List(vec![
+                    CSSValue::Length((16.0, Unit::Px)),
+                    CSSValue::Slash,
+                    CSSValue::Number(1.5),
+                    CSSValue::String("Helvetica Neue".into()),
+                    CSSValue::Comma,
+                    CSSValue::keyword("serif"),
+                ]),
+                "16px / 1.5 \"Helvetica Neue\", serif",
+            ),
+            (CSSValue::Color(Color::rgb(255, 0, 16)), "#ff0010"),
+            (
+                CSSValue::Color(Color {
+                    r: 0,
+                    g: 0,
+                    b: 255,
+                    a: 128,
+                }),
+                "#0000ff80",
+            ),
+            (CSSValue::Ratio((16, 9)), "16 / 9"),
+            (CSSValue::Url("a.png".into()), "url(\"a.png\")"),
+            (
+                CSSValue::Transform(TransformFunction::Translate(
+                    (10.0, Unit::Px),
+                    (50.0, Unit::Percent),
+                )),
+                "translate(10px, 50%)",
+            ),
+            (
+                CSSValue::Function("rotate".into(), "45deg".into()),
+                "rotate(45deg)",
+            ),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(value.to_string(), expected);
+        }
     }
 }
@@ -1,8 +1,23 @@
 pub mod chardata;
 pub use self::chardata::*;
 
+pub mod document_type;
+pub use self::document_type::*;
+
 pub mod element;
 pub use self::element::*;
 
+pub mod error;
+pub use self::error::*;
+
+pub mod focus;
+pub use self::focus::*;
+
 pub mod node;
 pub use self::node::*;
+
+pub mod sanitize;
+pub use self::sanitize::*;
+
+pub mod tree_spec;
+pub use self::tree_spec::*;
@@ -1,8 +1,8 @@
-use super::{Node, NodeType};
+use super::{DomError, Node, NodeType};
 
 pub trait CharacterData {}
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Text {
     pub data: String,
 }
@@ -16,4 +16,41 @@ impl Text {
     }
 }
 
+impl Text {
+    /// `split_text` splits the text at `offset`, which is counted in characters.
+    /// The text keeps the leading part, and the trailing part is returned as a new text node.
+    /// See https://dom.spec.whatwg.org/#dom-text-splittext
+    pub fn split_text(&mut self, offset: usize) -> Result<Box<Node>, DomError> {
+        let length = self.data.chars().count();
+        if offset > length {
+            return Err(DomError::IndexSize { offset, length });
+        }
+        let byte_offset = self
+            .data
+            .char_indices()
+            .nth(offset)
+            .map_or(self.data.len(), |(i, _)| i);
+        Ok(Text::new(self.data.split_off(byte_offset)))
+    }
+}
+
 impl CharacterData for Text {}
+
+/// `Comment` represents a comment like `<!-- comment -->`.
+/// See https://dom.spec.whatwg.org/#interface-comment
+#[derive(Debug, PartialEq, Clone)]
+pub struct Comment {
+    pub data: String,
+}
+
+impl Comment {
+    /// `new_node` creates a comment node whose data is `data`.
+    pub fn new_node(data: String) -> Box<Node> {
+        Box::new(Node {
+            node_type: NodeType::Comment(Comment { data }),
+            children: vec![],
+        })
+    }
+}
+
+impl CharacterData for Comment {}
@@ -0,0 +1,18 @@
+use super::{Node, NodeType};
+
+/// `DocumentType` represents a doctype like `<!DOCTYPE html>`.
+/// See https://dom.spec.whatwg.org/#interface-documenttype
+#[derive(Debug, PartialEq, Clone)]
+pub struct DocumentType {
+    pub name: String,
+}
+
+impl DocumentType {
+    /// `new_node` creates a doctype node whose name is `name`.
+    pub fn new_node(name: String) -> Box<Node> {
+        Box::new(Node {
+            node_type: NodeType::DocumentType(DocumentType { name }),
+            children: vec![],
+        })
+    }
+}
@@ -1,23 +1,282 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt, sync::Mutex};
 
 use super::{Node, NodeType};
 
 pub type AttrMap = HashMap<String, String>;
 
-#[derive(Debug, PartialEq)]
+/// `VOID_ELEMENTS` are the elements which never have children, and thus are written without end tags like `<img>`.
+/// See https://html.spec.whatwg.org/multipage/syntax.html#void-elements
+pub const VOID_ELEMENTS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+#[derive(Debug, Clone, Default)]
 pub struct Element {
+    /// `tag_name` is always lowercase since tag names are case-insensitive in HTML.
     pub tag_name: String,
     pub attributes: AttrMap,
+    /// `source_tag_name` is the tag name as authored, which is kept only by `new_case_preserving` when it is not lowercase.
+    /// It is ignored on comparison, so `<DIV>` equals `<div>`.
+    pub(crate) source_tag_name: Option<String>,
+    pub(crate) tokens: TokenCache,
+}
+
+/// `TokenCache` memoizes the whitespace-separated tokens of attributes like `class`.
+/// Each entry keeps the attribute value it was computed from, so it is never stale even after `attributes` changes.
+/// It is ignored on comparison and not shared between clones.
+/// It is behind a `Mutex` rather than a `RefCell` so that elements stay `Sync`, e.g. in statics.
+#[derive(Default)]
+pub(crate) struct TokenCache(Mutex<HashMap<String, (String, Vec<String>)>>);
+
+impl Clone for TokenCache {
+    fn clone(&self) -> Self {
+        TokenCache::default()
+    }
+}
+
+impl fmt::Debug for TokenCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TokenCache")
+    }
+}
+
+impl PartialEq for Element {
+    fn eq(&self, other: &Self) -> bool {
+        self.tag_name == other.tag_name && self.attributes == other.attributes
+    }
 }
 
 impl Element {
+    /// `new` creates an element whose tag name is lowercased. Use `new_case_preserving` to keep the authored one.
     pub fn new(name: String, attributes: AttrMap, children: Vec<Box<Node>>) -> Box<Node> {
         Box::new(Node {
             node_type: NodeType::Element(Element {
-                tag_name: name,
+                tag_name: name.to_ascii_lowercase(),
                 attributes: attributes,
+                source_tag_name: None,
+                tokens: TokenCache::default(),
+            }),
+            children,
+        })
+    }
+
+    /// `new_case_preserving` creates an element from the tag name as authored, e.g. `DiV`.
+    /// The tag name is lowercased for matching, and `original_tag_name` returns the authored one.
+    pub fn new_case_preserving(
+        name: String,
+        attributes: AttrMap,
+        children: Vec<Box<Node>>,
+    ) -> Box<Node> {
+        let tag_name = name.to_ascii_lowercase();
+        Box::new(Node {
+            node_type: NodeType::Element(Element {
+                source_tag_name: if tag_name == name { None } else { Some(name) },
+                tag_name,
+                attributes,
+                tokens: TokenCache::default(),
             }),
             children,
         })
     }
+
+    /// `is_void` tells whether the element is one of `VOID_ELEMENTS`.
+    pub fn is_void(&self) -> bool {
+        VOID_ELEMENTS.contains(&self.tag_name.as_str())
+    }
+
+    /// `original_tag_name` returns the tag name as authored in the source,
+    /// or `tag_name` for the elements not created by `new_case_preserving`.
+    pub fn original_tag_name(&self) -> &str {
+        self.source_tag_name.as_deref().unwrap_or(&self.tag_name)
+    }
+
+    /// `accessible_name` returns the name of the element given by `aria-label`, or `alt` for `<img>`, in this precedence.
+    /// Empty names are ignored. Use `Node::accessible_name` to fall back to the text of the descendants.
+    /// See https://www.w3.org/TR/accname-1.2/
+    ///
+    /// NOTE: This is a simplified computation, where `aria-labelledby`, `title` and roles are not taken into account.
+    pub fn accessible_name(&self) -> Option<String> {
+        let name = |attribute: &str| {
+            self.attributes
+                .get(attribute)
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+                .map(|value| value.to_string())
+        };
+        name("aria-label").or_else(|| {
+            if self.tag_name == "img" {
+                name("alt")
+            } else {
+                None
+            }
+        })
+    }
+
+    /// `classes` returns the whitespace-separated class names in the `class` attribute.
+    pub fn classes(&self) -> Vec<&str> {
+        self.attributes
+            .get("class")
+            .map(|classes| classes.split_ascii_whitespace().collect())
+            .unwrap_or_default()
+    }
+
+    /// `has_token` tells whether `token` is one of the whitespace-separated tokens in the attribute `name`,
+    /// e.g. `has_token("class", "b")` is true for `class="a b"`.
+    /// The tokens are split only once and cached as long as the attribute is unchanged.
+    pub fn has_token(&self, name: &str, token: &str) -> bool {
+        let value = match self.attributes.get(name) {
+            Some(value) => value,
+            None => return false,
+        };
+        let mut cache = self.tokens.0.lock().unwrap();
+        match cache.get(name) {
+            Some((source, tokens)) if source == value => tokens.iter().any(|t| t == token),
+            _ => {
+                let tokens = value
+                    .split_ascii_whitespace()
+                    .map(|t| t.to_string())
+                    .collect::<Vec<_>>();
+                let found = tokens.iter().any(|t| t == token);
+                cache.insert(name.to_string(), (value.clone(), tokens));
+                found
+            }
+        }
+    }
+
+    /// `attributes_sorted` returns the attributes sorted by their names.
+    /// Unlike iterating `attributes` directly, the order is deterministic.
+    pub fn attributes_sorted(&self) -> Vec<(&str, &str)> {
+        let mut attributes = self
+            .attributes
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect::<Vec<_>>();
+        attributes.sort();
+        attributes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attributes_sorted() {
+        let element = Element {
+            tag_name: "a".to_string(),
+            attributes: [
+                ("href", "/"),
+                ("class", "link"),
+                ("id", "home"),
+                ("data-x", "1"),
+                ("title", "Home"),
+            ]
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+            ..Default::default()
+        };
+        let expected = vec![
+            ("class", "link"),
+            ("data-x", "1"),
+            ("href", "/"),
+            ("id", "home"),
+            ("title", "Home"),
+        ];
+
+        assert_eq!(element.attributes_sorted(), expected);
+        // `HashMap` iterates in a different order for each instance
+        for _ in 0..10 {
+            assert_eq!(element.clone().attributes_sorted(), expected);
+        }
+    }
+
+    #[test]
+    fn test_classes() {
+        let element = |attributes: &[(&str, &str)]| Element {
+            tag_name: "p".to_string(),
+            attributes: attributes
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(element(&[]).classes(), Vec::<&str>::new());
+        assert_eq!(element(&[("class", "")]).classes(), Vec::<&str>::new());
+        assert_eq!(
+            element(&[("class", " a\tb\n c ")]).classes(),
+            vec!["a", "b", "c"]
+        );
+    }
+
+    #[test]
+    fn test_has_token() {
+        let mut element = Element {
+            tag_name: "p".to_string(),
+            attributes: [("class".to_string(), " a\tb  c ".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            ..Default::default()
+        };
+        let naive = |element: &Element, name: &str, token: &str| {
+            element
+                .attributes
+                .get(name)
+                .is_some_and(|v| v.split_ascii_whitespace().any(|t| t == token))
+        };
+
+        for token in ["a", "b", "c", "d", "a b", "", " "] {
+            assert_eq!(
+                element.has_token("class", token),
+                naive(&element, "class", token),
+                "{:?}",
+                token
+            );
+        }
+        assert_eq!(element.has_token("title", "a"), false);
+
+        // the tokens are split only once
+        let tokens = element.tokens.0.lock().unwrap()["class"].1.as_ptr();
+        assert!(element.has_token("class", "c"));
+        assert_eq!(element.tokens.0.lock().unwrap()["class"].1.as_ptr(), tokens);
+
+        // the cache follows the change of the attribute
+        element
+            .attributes
+            .insert("class".to_string(), "d e".to_string());
+        assert_eq!(element.has_token("class", "a"), false);
+        assert_eq!(element.has_token("class", "e"), true);
+        // clones have their own caches
+        assert_eq!(element.clone().tokens.0.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_original_tag_name() {
+        let element = |node: Box<Node>| match node.node_type {
+            NodeType::Element(ref e) => e.clone(),
+            _ => unreachable!(),
+        };
+
+        let e = element(Element::new_case_preserving(
+            "DiV".to_string(),
+            AttrMap::new(),
+            vec![],
+        ));
+        assert_eq!(e.tag_name, "div");
+        assert_eq!(e.original_tag_name(), "DiV");
+
+        let e = element(Element::new_case_preserving(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![],
+        ));
+        assert_eq!(e.original_tag_name(), "div");
+        assert_eq!(
+            NodeType::Element(e),
+            Element::new("div".to_string(), AttrMap::new(), vec![]).node_type
+        );
+    }
 }
@@ -0,0 +1,11 @@
+use thiserror::Error;
+
+/// `DomError` represents errors of DOM operations, modeled after some of `DOMException`.
+/// See https://webidl.spec.whatwg.org/#idl-DOMException-error-names
+#[derive(Error, Debug, PartialEq)]
+pub enum DomError {
+    #[error("offset {offset} is out of range of length {length}")]
+    IndexSize { offset: usize, length: usize },
+    #[error("the operation is not supported by this type of node")]
+    InvalidNodeType,
+}
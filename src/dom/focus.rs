@@ -0,0 +1,99 @@
+use super::{Element, Node, NodeType};
+
+/// `focusable_order` returns the focusable elements in the tree of `root` in the sequential focus navigation order,
+/// i.e. the order in which the Tab key moves the focus.
+/// Elements with a positive `tabindex` come first in the ascending order of it,
+/// followed by the ones with `tabindex="0"` or focusable by default. Ties are kept in the tree order.
+/// Elements with a negative `tabindex` are excluded.
+/// See https://html.spec.whatwg.org/multipage/interaction.html#sequential-focus-navigation
+///
+/// NOTE: Links with `href`, and `input` (except `type="hidden"`), `button`, `select` and `textarea`
+/// which are not `disabled`, are regarded as focusable by default. Hidden elements are not taken into account.
+pub fn focusable_order(root: &Node) -> Vec<&Node> {
+    let mut focusable = root
+        .depth_first()
+        .filter_map(|node| match node.node_type {
+            NodeType::Element(ref e) => tab_index(e).map(|index| (index, node)),
+            _ => None,
+        })
+        .filter(|(index, _)| *index >= 0)
+        .collect::<Vec<_>>();
+    // `sort_by_key` is stable, so the tree order is kept among the same index
+    focusable.sort_by_key(|(index, _)| match index {
+        0 => i64::MAX,
+        index => *index,
+    });
+    focusable.into_iter().map(|(_, node)| node).collect()
+}
+
+/// `tab_index` returns the `tabindex` of the element, which defaults to `0` for the elements focusable by default.
+/// It returns `None` for the elements which are not focusable.
+fn tab_index(e: &Element) -> Option<i64> {
+    if let Some(index) = e
+        .attributes
+        .get("tabindex")
+        .and_then(|v| v.trim().parse::<i64>().ok())
+    {
+        return Some(index);
+    }
+    let focusable = match e.tag_name.as_str() {
+        "a" => e.attributes.contains_key("href"),
+        "input" => {
+            !e.attributes.contains_key("disabled")
+                && e.attributes
+                    .get("type")
+                    .is_none_or(|t| !t.eq_ignore_ascii_case("hidden"))
+        }
+        "button" | "select" | "textarea" => !e.attributes.contains_key("disabled"),
+        _ => false,
+    };
+    focusable.then_some(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dom::{build_tree, TreeSpec};
+
+    use super::*;
+
+    #[test]
+    fn test_focusable_order() {
+        use TreeSpec::{Element as E, Text as T};
+
+        let root = build_tree(&E(
+            "body",
+            &[],
+            &[
+                E("a", &[("href", "/"), ("id", "link")], &[T("home")]),
+                E("a", &[("id", "anchor")], &[]),
+                E("div", &[("tabindex", "2"), ("id", "second")], &[]),
+                E(
+                    "form",
+                    &[],
+                    &[
+                        E("input", &[("id", "text")], &[]),
+                        E("input", &[("type", "hidden"), ("id", "hidden")], &[]),
+                        E("button", &[("disabled", ""), ("id", "disabled")], &[]),
+                        E("button", &[("tabindex", "-1"), ("id", "skipped")], &[]),
+                        E("textarea", &[("tabindex", "1"), ("id", "first")], &[]),
+                    ],
+                ),
+                E("span", &[("tabindex", "0"), ("id", "span")], &[]),
+                E("p", &[("tabindex", "2"), ("id", "third")], &[]),
+                E("p", &[("tabindex", "foo"), ("id", "invalid")], &[]),
+            ],
+        ));
+
+        let ids = focusable_order(&root)
+            .into_iter()
+            .map(|node| match node.node_type {
+                NodeType::Element(ref e) => e.attributes["id"].clone(),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec!["first", "second", "third", "link", "text", "span"]
+        );
+    }
+}
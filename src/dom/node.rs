@@ -1,28 +1,946 @@
-use super::{Element, Text};
+use std::{collections::VecDeque, fmt};
 
-#[derive(Debug, PartialEq)]
+use super::{AttrMap, Comment, DocumentType, DomError, Element, Text};
+use crate::css::{parse_selector, CssParseError, MatchContext};
+
+/// `NodeId` identifies a node by the list of the child indices from the root to it,
+/// which is the same as the path taken by `restyle_subtree`.
+pub type NodeId = Vec<usize>;
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Node {
     pub node_type: NodeType,
     pub children: Vec<Box<Node>>,
 }
 
 impl Node {
+    /// `inner_text` concatenates the texts of all the descendants in the tree order.
+    /// It uses an explicit stack instead of recursion so that deep trees from untrusted input never overflow the call stack.
     pub fn inner_text(&self) -> String {
-        self.children
-            .iter()
-            .clone()
-            .into_iter()
-            .map(|node| match &node.node_type {
-                NodeType::Text(t) => t.data.clone(),
-                _ => node.inner_text(),
+        let mut text = String::new();
+        let mut stack = self.children.iter().rev().collect::<Vec<_>>();
+        while let Some(node) = stack.pop() {
+            match &node.node_type {
+                NodeType::Text(t) => text.push_str(&t.data),
+                _ => stack.extend(node.children.iter().rev()),
+            }
+        }
+        text
+    }
+
+    /// `accessible_name` returns the accessible name of an element given by `Element::accessible_name`,
+    /// or its text with the whitespaces collapsed. It returns `None` for the other nodes and unnamed elements.
+    pub fn accessible_name(&self) -> Option<String> {
+        match self.node_type {
+            NodeType::Element(ref e) => e.accessible_name().or_else(|| {
+                let text = self.inner_text();
+                let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                (!text.is_empty()).then_some(text)
+            }),
+            _ => None,
+        }
+    }
+
+    /// `matches_selector_text` parses `selector` like `p.warning` and tells whether the node matches it.
+    ///
+    /// NOTE: The node is matched as if it had no parent, so pseudo-classes depending on siblings
+    /// or ancestors (e.g. `:first-of-type`) regard it as the only child.
+    pub fn matches_selector_text(&self, selector: &str) -> Result<bool, CssParseError> {
+        Ok(parse_selector(selector)?.matches(self, &MatchContext::default()))
+    }
+
+    /// `walk_mut` calls `f` for the node and all of its descendants in the depth-first pre-order.
+    /// Since a node is visited before its children, `f` can modify the children to be visited, e.g. remove comments.
+    /// Like `inner_text`, it uses an explicit stack so that deep trees never overflow the call stack.
+    pub fn walk_mut<F: FnMut(&mut Node)>(&mut self, mut f: F) {
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            f(node);
+            stack.extend(node.children.iter_mut().rev().map(|child| child.as_mut()));
+        }
+    }
+
+    /// `depth_first` iterates over the node and all of its descendants, including text nodes, in the depth-first pre-order,
+    /// i.e. the tree order.
+    pub fn depth_first(&self) -> DepthFirst<'_> {
+        DepthFirst { stack: vec![self] }
+    }
+
+    /// `breadth_first` iterates over the node and all of its descendants, including text nodes, level by level,
+    /// i.e. the node first, then its children, then its grandchildren, and so on.
+    pub fn breadth_first(&self) -> BreadthFirst<'_> {
+        BreadthFirst {
+            queue: VecDeque::from([self]),
+        }
+    }
+
+    /// `get_element_by_id` returns the first element with the `id` in the tree order among the node and its descendants.
+    /// See https://dom.spec.whatwg.org/#dom-nonelementparentnode-getelementbyid
+    pub fn get_element_by_id(&self, id: &str) -> Option<&Node> {
+        self.depth_first().find(|node| match node.node_type {
+            NodeType::Element(ref e) => e.attributes.get("id").is_some_and(|v| v == id),
+            _ => false,
+        })
+    }
+
+    /// `get_elements_by_tag_name` returns the elements named `tag_name` in the tree order
+    /// among the node and its descendants, ignoring ASCII case.
+    /// See https://dom.spec.whatwg.org/#dom-document-getelementsbytagname
+    pub fn get_elements_by_tag_name(&self, tag_name: &str) -> Vec<&Node> {
+        self.depth_first()
+            .filter(|node| match node.node_type {
+                NodeType::Element(ref e) => e.tag_name.eq_ignore_ascii_case(tag_name),
+                _ => false,
             })
-            .collect::<Vec<_>>()
-            .join("")
+            .collect()
+    }
+
+    /// `find_text` searches the text nodes among the node and its descendants for `needle` in the tree order
+    /// and returns the pairs of the text node and the char offset of each match, e.g. for find-in-page.
+    /// Matches in a text node do not overlap, and matches across text nodes are not found.
+    pub fn find_text(&self, needle: &str) -> Vec<(NodeId, usize)> {
+        self.find_text_by(needle, |a, b| a == b)
+    }
+
+    /// `find_text_ignoring_case` is the same as `find_text` except that letters are compared case-insensitively.
+    pub fn find_text_ignoring_case(&self, needle: &str) -> Vec<(NodeId, usize)> {
+        self.find_text_by(needle, |a, b| a.to_lowercase().eq(b.to_lowercase()))
+    }
+
+    fn find_text_by<F: Fn(char, char) -> bool>(&self, needle: &str, eq: F) -> Vec<(NodeId, usize)> {
+        let needle = needle.chars().collect::<Vec<_>>();
+        if needle.is_empty() {
+            return vec![];
+        }
+        let mut found = vec![];
+        let mut stack = vec![(self, vec![])];
+        while let Some((node, id)) = stack.pop() {
+            if let NodeType::Text(ref t) = node.node_type {
+                let chars = t.data.chars().collect::<Vec<_>>();
+                let mut offset = 0;
+                while offset + needle.len() <= chars.len() {
+                    if chars[offset..].iter().zip(&needle).all(|(&a, &b)| eq(a, b)) {
+                        found.push((id.clone(), offset));
+                        offset += needle.len();
+                    } else {
+                        offset += 1;
+                    }
+                }
+            }
+            stack.extend(node.children.iter().enumerate().rev().map(|(i, child)| {
+                let mut id = id.clone();
+                id.push(i);
+                (child.as_ref(), id)
+            }));
+        }
+        found
+    }
+
+    /// `find_by_attr` returns the node and its descendants in the tree order
+    /// which are elements whose attribute `name` satisfies `pred`, e.g. links with `href` starting with `https`.
+    /// `pred` is called with the name and the value of the attribute, and elements without it are skipped.
+    pub fn find_by_attr<F: Fn(&str, &str) -> bool>(&self, name: &str, pred: F) -> Vec<&Node> {
+        self.depth_first()
+            .filter(|node| match node.node_type {
+                NodeType::Element(ref e) => e
+                    .attributes
+                    .get(name)
+                    .is_some_and(|value| pred(name, value)),
+                _ => false,
+            })
+            .collect()
+    }
+
+    /// `eq_ignoring_head_order` compares two trees like `==`, except that the children of `<head>`
+    /// are compared as an unordered multiset, since the order of `<meta>`, `<link>` and `<title>` rarely matters.
+    /// The children of the other elements, including `<body>`, are still compared in order.
+    pub fn eq_ignoring_head_order(&self, other: &Node) -> bool {
+        if self.node_type != other.node_type || self.children.len() != other.children.len() {
+            return false;
+        }
+        match self.node_type {
+            NodeType::Element(ref e) if e.tag_name == "head" => {
+                let mut unmatched = other.children.iter().collect::<Vec<_>>();
+                self.children.iter().all(|child| {
+                    match unmatched
+                        .iter()
+                        .position(|o| child.eq_ignoring_head_order(o))
+                    {
+                        Some(i) => {
+                            unmatched.swap_remove(i);
+                            true
+                        }
+                        None => false,
+                    }
+                })
+            }
+            _ => self
+                .children
+                .iter()
+                .zip(other.children.iter())
+                .all(|(a, b)| a.eq_ignoring_head_order(b)),
+        }
+    }
+
+    /// `clone_tree` duplicates the node and all of its descendants.
+    /// Mutating the returned tree does not affect the original one.
+    pub fn clone_tree(&self) -> Box<Node> {
+        Box::new(self.clone())
+    }
+
+    /// `split_text` splits a text node at `offset` like `Text::split_text`.
+    /// The returned node is expected to be inserted as the next sibling of this node.
+    pub fn split_text(&mut self, offset: usize) -> Result<Box<Node>, DomError> {
+        match self.node_type {
+            NodeType::Text(ref mut t) => t.split_text(offset),
+            _ => Err(DomError::InvalidNodeType),
+        }
+    }
+
+    /// `insert_before` inserts `new` as a child before the child at `reference_index`.
+    /// When `reference_index` is out of range, `new` is appended as the last child,
+    /// just as `insertBefore` in DOM does when the reference child is null.
+    /// See https://dom.spec.whatwg.org/#dom-node-insertbefore
+    pub fn insert_before(&mut self, new: Box<Node>, reference_index: usize) {
+        let index = reference_index.min(self.children.len());
+        self.children.insert(index, new);
+    }
+
+    /// `wrap` returns a new element named `wrapper_tag` with `attrs`, whose only child is the node.
+    pub fn wrap(self: Box<Node>, wrapper_tag: &str, attrs: AttrMap) -> Box<Node> {
+        Element::new(wrapper_tag.to_string(), attrs, vec![self])
+    }
+
+    /// `to_html` serializes the node and its descendants into HTML.
+    /// The output is parsed by `html::parse` into the same tree, unless void elements like `<img>` have children,
+    /// which are not written since void elements have no end tags.
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        self.serialize(&mut html)
+            .expect("writing to a String never fails");
+        html
+    }
+
+    /// `serialize` writes the same HTML as `to_html` into `w` piece by piece,
+    /// so that large trees are serialized without building the whole document in memory.
+    /// Like `inner_text`, it uses an explicit stack so that deep trees never overflow the call stack.
+    pub fn serialize<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        // `closing` is true for the elements whose children are already written, i.e. only the close tags are left
+        let mut stack = vec![(self, false)];
+        while let Some((node, closing)) = stack.pop() {
+            match (&node.node_type, closing) {
+                (NodeType::Element(e), false) => {
+                    write!(w, "<{}", e.tag_name)?;
+                    for (name, value) in e.attributes_sorted() {
+                        write!(w, " {}=\"", name)?;
+                        for (i, part) in value.split('"').enumerate() {
+                            if i > 0 {
+                                w.write_str("&quot;")?;
+                            }
+                            w.write_str(part)?;
+                        }
+                        w.write_char('"')?;
+                    }
+                    w.write_char('>')?;
+                    // https://html.spec.whatwg.org/multipage/parsing.html#serialising-html-fragments
+                    if e.is_void() {
+                        continue;
+                    }
+                    stack.push((node, true));
+                    stack.extend(
+                        node.children
+                            .iter()
+                            .rev()
+                            .map(|child| (child.as_ref(), false)),
+                    );
+                }
+                (NodeType::Element(e), true) => write!(w, "</{}>", e.tag_name)?,
+                (NodeType::Text(t), _) => w.write_str(&t.data)?,
+                (NodeType::Comment(c), _) => write!(w, "<!--{}-->", c.data)?,
+                (NodeType::DocumentType(d), _) => write!(w, "<!DOCTYPE {}>", d.name)?,
+            }
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// `Node` is dropped with an explicit stack like `inner_text`,
+/// since the default recursive drop overflows the call stack for deep trees.
+impl Drop for Node {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.children);
+        while let Some(mut node) = stack.pop() {
+            // `node` is dropped with no children, so the drop never recurses
+            stack.append(&mut node.children);
+        }
+    }
+}
+
+/// `DepthFirst` is the iterator returned by `Node::depth_first`.
+pub struct DepthFirst<'a> {
+    stack: Vec<&'a Node>,
+}
+
+impl<'a> Iterator for DepthFirst<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.stack
+            .extend(node.children.iter().rev().map(|child| child.as_ref()));
+        Some(node)
+    }
+}
+
+/// `BreadthFirst` is the iterator returned by `Node::breadth_first`.
+pub struct BreadthFirst<'a> {
+    queue: VecDeque<&'a Node>,
+}
+
+impl<'a> Iterator for BreadthFirst<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.queue.pop_front()?;
+        self.queue
+            .extend(node.children.iter().map(|child| child.as_ref()));
+        Some(node)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum NodeType {
     Element(Element),
     Text(Text),
+    Comment(Comment),
+    DocumentType(DocumentType),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_text() {
+        use crate::dom::{
+            build_tree,
+            TreeSpec::{Element as E, Text as T},
+        };
+
+        // <body><p>The cat sat.</p><p>Cats <b>and</b> cat-like cats</p></body>
+        let root = build_tree(&E(
+            "body",
+            &[],
+            &[
+                E("p", &[], &[T("The cat sat.")]),
+                E(
+                    "p",
+                    &[],
+                    &[T("Cats "), E("b", &[], &[T("and")]), T(" cat-like cats")],
+                ),
+            ],
+        ));
+
+        assert_eq!(
+            root.find_text("cat"),
+            vec![(vec![0, 0], 4), (vec![1, 2], 1), (vec![1, 2], 10)]
+        );
+        assert_eq!(
+            root.find_text_ignoring_case("CAT"),
+            vec![
+                (vec![0, 0], 4),
+                (vec![1, 0], 0),
+                (vec![1, 2], 1),
+                (vec![1, 2], 10)
+            ]
+        );
+        assert_eq!(root.find_text("dog"), vec![]);
+        assert_eq!(root.find_text(""), vec![]);
+        // offsets are counted in chars
+        let text = build_tree(&T("日本語のテキスト"));
+        assert_eq!(text.find_text("テキスト"), vec![(vec![], 4)]);
+    }
+
+    #[test]
+    fn test_matches_selector_text() {
+        // <p class="warning note"></p>
+        let p = Element::new(
+            "p".to_string(),
+            [("class".to_string(), "warning note".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            vec![],
+        );
+        let div = Element::new("div".to_string(), AttrMap::new(), vec![]);
+
+        assert_eq!(p.matches_selector_text("p.warning"), Ok(true));
+        assert_eq!(div.matches_selector_text("p.warning"), Ok(false));
+        assert_eq!(p.matches_selector_text("p.error"), Ok(false));
+        assert_eq!(
+            p.matches_selector_text("p..warning"),
+            Err(CssParseError::InvalidSelector("p..warning".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_inner_text() {
+        // <div>a<p>b<span>c</span></p><!--d-->e</div>
+        let node = Element::new(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![
+                Text::new("a".to_string()),
+                Element::new(
+                    "p".to_string(),
+                    AttrMap::new(),
+                    vec![
+                        Text::new("b".to_string()),
+                        Element::new(
+                            "span".to_string(),
+                            AttrMap::new(),
+                            vec![Text::new("c".to_string())],
+                        ),
+                    ],
+                ),
+                Comment::new_node("d".to_string()),
+                Text::new("e".to_string()),
+            ],
+        );
+        assert_eq!(node.inner_text(), "abce");
+    }
+
+    #[test]
+    fn test_inner_text_deep() {
+        let depth = 50_000;
+        let mut node = Text::new("leaf".to_string());
+        for i in 0..depth {
+            let mut children = vec![node];
+            if i % 10_000 == 0 {
+                children.push(Text::new(i.to_string()));
+            }
+            node = Element::new("div".to_string(), AttrMap::new(), children);
+        }
+
+        assert_eq!(node.inner_text(), "leaf010000200003000040000");
+    }
+
+    #[test]
+    fn test_traversal_order() {
+        // <div>a<p>b<span>c</span></p>d</div>
+        let node = Element::new(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![
+                Text::new("a".to_string()),
+                Element::new(
+                    "p".to_string(),
+                    AttrMap::new(),
+                    vec![
+                        Text::new("b".to_string()),
+                        Element::new(
+                            "span".to_string(),
+                            AttrMap::new(),
+                            vec![Text::new("c".to_string())],
+                        ),
+                    ],
+                ),
+                Text::new("d".to_string()),
+            ],
+        );
+        let label = |n: &Node| match n.node_type {
+            NodeType::Element(ref e) => e.tag_name.clone(),
+            NodeType::Text(ref t) => t.data.clone(),
+            _ => unreachable!(),
+        };
+
+        assert_eq!(
+            node.depth_first().map(label).collect::<Vec<_>>(),
+            vec!["div", "a", "p", "b", "span", "c", "d"]
+        );
+        assert_eq!(
+            node.breadth_first().map(label).collect::<Vec<_>>(),
+            vec!["div", "a", "p", "d", "b", "span", "c"]
+        );
+
+        let text = Text::new("a".to_string());
+        assert_eq!(text.depth_first().count(), 1);
+        assert_eq!(text.breadth_first().count(), 1);
+    }
+
+    #[test]
+    fn test_get_element_by_id() {
+        let element = |tag_name: &str, id: Option<&str>, children| {
+            Element::new(
+                tag_name.to_string(),
+                id.iter()
+                    .map(|id| ("id".to_string(), id.to_string()))
+                    .collect(),
+                children,
+            )
+        };
+        // <div>a<section><p>b<span id="x">c</span></p><p id="y"></p></section><span id="x"></span></div>
+        let node = element(
+            "div",
+            None,
+            vec![
+                Text::new("a".to_string()),
+                element(
+                    "section",
+                    None,
+                    vec![
+                        element(
+                            "p",
+                            None,
+                            vec![
+                                Text::new("b".to_string()),
+                                element("span", Some("x"), vec![Text::new("c".to_string())]),
+                            ],
+                        ),
+                        element("p", Some("y"), vec![]),
+                    ],
+                ),
+                element("span", Some("x"), vec![]),
+            ],
+        );
+
+        // the first one in the tree order is returned
+        let found = node.get_element_by_id("x").unwrap();
+        assert_eq!(found.inner_text(), "c");
+        assert_eq!(
+            node.get_element_by_id("y").unwrap().to_html(),
+            "<p id=\"y\"></p>"
+        );
+        assert_eq!(node.get_element_by_id("z"), None);
+        assert_eq!(Text::new("x".to_string()).get_element_by_id("x"), None);
+
+        let paragraphs = node.get_elements_by_tag_name("P");
+        assert_eq!(paragraphs.len(), 2);
+        assert_eq!(paragraphs[0].inner_text(), "bc");
+        assert_eq!(paragraphs[1].get_element_by_id("y"), Some(paragraphs[1]));
+        assert_eq!(node.get_elements_by_tag_name("span").len(), 2);
+        assert_eq!(node.get_elements_by_tag_name("div").len(), 1);
+        assert!(node.get_elements_by_tag_name("a").is_empty());
+        assert!(Text::new("p".to_string())
+            .get_elements_by_tag_name("p")
+            .is_empty());
+    }
+
+    #[test]
+    fn test_wrap() {
+        let p = Element::new(
+            "p".to_string(),
+            [("class".to_string(), "note".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            vec![Text::new("hello".to_string())],
+        );
+        let original = p.clone();
+
+        let div = p.wrap(
+            "div",
+            [("id".to_string(), "wrapper".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+        );
+        assert_eq!(div.children, vec![original]);
+        assert_eq!(
+            div.to_html(),
+            "<div id=\"wrapper\"><p class=\"note\">hello</p></div>"
+        );
+
+        // text nodes can be wrapped too
+        let span = Text::new("a".to_string()).wrap("span", AttrMap::new());
+        assert_eq!(span.to_html(), "<span>a</span>");
+    }
+
+    #[test]
+    fn test_accessible_name() {
+        use crate::dom::{build_tree, TreeSpec};
+        use TreeSpec::{Element as E, Text as T};
+
+        let name = |spec| build_tree(&spec).accessible_name();
+        assert_eq!(
+            name(E("img", &[("alt", "logo"), ("src", "logo.png")], &[])),
+            Some("logo".to_string())
+        );
+        assert_eq!(name(E("button", &[], &[T("Go")])), Some("Go".to_string()));
+        // aria-label takes precedence over alt and the text
+        assert_eq!(
+            name(E("img", &[("aria-label", "home"), ("alt", "logo")], &[])),
+            Some("home".to_string())
+        );
+        assert_eq!(
+            name(E("a", &[("aria-label", "close")], &[T("x")])),
+            Some("close".to_string())
+        );
+        // alt is only for images, and empty names are ignored
+        assert_eq!(
+            name(E(
+                "button",
+                &[("alt", "ignored"), ("aria-label", " ")],
+                &[T(" Send\n "), E("b", &[], &[T("now")])]
+            )),
+            Some("Send now".to_string())
+        );
+        assert_eq!(name(E("img", &[("alt", "")], &[])), None);
+        assert_eq!(name(T("text")), None);
+    }
+
+    #[test]
+    fn test_walk_mut() {
+        // <div>a<p>b<!--c--><span>d</span></p>e</div>
+        let mut node = Element::new(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![
+                Text::new("a".to_string()),
+                Element::new(
+                    "p".to_string(),
+                    AttrMap::new(),
+                    vec![
+                        Text::new("b".to_string()),
+                        Comment::new_node("c".to_string()),
+                        Element::new(
+                            "span".to_string(),
+                            AttrMap::new(),
+                            vec![Text::new("d".to_string())],
+                        ),
+                    ],
+                ),
+                Text::new("e".to_string()),
+            ],
+        );
+
+        let mut visited = vec![];
+        node.walk_mut(|n| {
+            if let NodeType::Text(ref mut t) = n.node_type {
+                t.data = t.data.to_uppercase();
+                visited.push(t.data.clone());
+            }
+        });
+        assert_eq!(visited, vec!["A", "B", "D", "E"]);
+        assert_eq!(
+            node.to_html(),
+            "<div>A<p>B<!--c--><span>D</span></p>E</div>"
+        );
+
+        // children removed by `f` are not visited
+        let mut count = 0;
+        node.walk_mut(|n| {
+            count += 1;
+            n.children
+                .retain(|child| !matches!(child.node_type, NodeType::Comment(_)));
+        });
+        assert_eq!(count, 7);
+        assert_eq!(node.to_html(), "<div>A<p>B<span>D</span></p>E</div>");
+    }
+
+    #[test]
+    fn test_find_by_attr() {
+        let attributes = |attributes: &[(&str, &str)]| {
+            attributes
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<AttrMap>()
+        };
+        // <ul data-active="true">
+        //   <li data-active="false">a</li>
+        //   <li data-active="true"><a href="https://example.com" data-active="true">b</a></li>
+        //   <li>c</li>
+        // </ul>
+        let node = Element::new(
+            "ul".to_string(),
+            attributes(&[("data-active", "true")]),
+            vec![
+                Element::new(
+                    "li".to_string(),
+                    attributes(&[("data-active", "false")]),
+                    vec![Text::new("a".to_string())],
+                ),
+                Element::new(
+                    "li".to_string(),
+                    attributes(&[("data-active", "true")]),
+                    vec![Element::new(
+                        "a".to_string(),
+                        attributes(&[("href", "https://example.com"), ("data-active", "true")]),
+                        vec![Text::new("b".to_string())],
+                    )],
+                ),
+                Element::new(
+                    "li".to_string(),
+                    AttrMap::new(),
+                    vec![Text::new("c".to_string())],
+                ),
+            ],
+        );
+
+        let tag_names = |nodes: Vec<&Node>| {
+            nodes
+                .iter()
+                .map(|n| match n.node_type {
+                    NodeType::Element(ref e) => e.tag_name.clone(),
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            tag_names(node.find_by_attr("data-active", |_, value| value == "true")),
+            vec!["ul", "li", "a"]
+        );
+        assert_eq!(
+            tag_names(node.find_by_attr("href", |_, value| value.starts_with("https"))),
+            vec!["a"]
+        );
+        // elements without the attribute are never passed to the predicate
+        assert_eq!(node.find_by_attr("title", |_, _| true), Vec::<&Node>::new());
+    }
+
+    #[test]
+    fn test_eq_ignoring_head_order() {
+        let element =
+            |name: &str, children| Element::new(name.to_string(), AttrMap::new(), children);
+        let meta = |charset: &str| {
+            Element::new(
+                "meta".to_string(),
+                [("charset".to_string(), charset.to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                vec![],
+            )
+        };
+        let title = || element("title", vec![Text::new("t".to_string())]);
+        let document =
+            |head, body| element("html", vec![element("head", head), element("body", body)]);
+
+        let original = document(
+            vec![meta("utf-8"), title(), meta("utf-8")],
+            vec![element("h1", vec![]), element("p", vec![])],
+        );
+        let reordered_head = document(
+            vec![title(), meta("utf-8"), meta("utf-8")],
+            vec![element("h1", vec![]), element("p", vec![])],
+        );
+        let reordered_body = document(
+            vec![meta("utf-8"), title(), meta("utf-8")],
+            vec![element("p", vec![]), element("h1", vec![])],
+        );
+
+        assert!(original.eq_ignoring_head_order(&reordered_head));
+        assert_ne!(original, reordered_head);
+        assert!(!original.eq_ignoring_head_order(&reordered_body));
+        // each child is matched only once
+        assert!(!original.eq_ignoring_head_order(&document(
+            vec![title(), meta("utf-8"), title()],
+            vec![element("h1", vec![]), element("p", vec![])],
+        )));
+    }
+
+    #[test]
+    fn test_to_html() {
+        let node = Element::new(
+            "div".to_string(),
+            [("id".to_string(), "say \"hi\"".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            vec![
+                Comment::new_node(" greeting ".to_string()),
+                Element::new(
+                    "p".to_string(),
+                    AttrMap::new(),
+                    vec![Text::new("hello".to_string())],
+                ),
+            ],
+        );
+
+        assert_eq!(
+            node.to_html(),
+            "<div id=\"say &quot;hi&quot;\"><!-- greeting --><p>hello</p></div>"
+        );
+        let node = Element::new(
+            "a".to_string(),
+            [("id", "b"), ("href", "c"), ("class", "d")]
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            vec![],
+        );
+        assert_eq!(node.to_html(), "<a class=\"d\" href=\"c\" id=\"b\"></a>");
+
+        assert_eq!(
+            DocumentType::new_node("html".to_string()).to_html(),
+            "<!DOCTYPE html>"
+        );
+    }
+
+    #[test]
+    fn test_serialize() {
+        // <!DOCTYPE html><html lang="en"><p class="a &quot;b&quot;">x<!--y--></p></html><q title="&quot;"></q>
+        let nodes = vec![
+            DocumentType::new_node("html".to_string()),
+            Element::new(
+                "html".to_string(),
+                [("lang".to_string(), "en".to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                vec![Element::new(
+                    "p".to_string(),
+                    [("class".to_string(), "a \"b\"".to_string())]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                    vec![
+                        Text::new("x".to_string()),
+                        Comment::new_node("y".to_string()),
+                    ],
+                )],
+            ),
+            Element::new(
+                "q".to_string(),
+                [("title".to_string(), "\"".to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                vec![],
+            ),
+        ];
+
+        let mut buffer = String::new();
+        for node in &nodes {
+            node.serialize(&mut buffer).unwrap();
+        }
+        assert_eq!(
+            buffer,
+            nodes.iter().map(|node| node.to_html()).collect::<String>()
+        );
+        assert_eq!(
+            buffer,
+            "<!DOCTYPE html><html lang=\"en\"><p class=\"a &quot;b&quot;\">x<!--y--></p></html><q title=\"&quot;\"></q>"
+        );
+    }
+
+    #[test]
+    fn test_serialize_deep() {
+        let depth = 50_000;
+        let mut node = Text::new("leaf".to_string());
+        for _ in 0..depth {
+            node = Element::new("b".to_string(), AttrMap::new(), vec![node]);
+        }
+
+        assert_eq!(
+            node.to_html(),
+            format!("{}leaf{}", "<b>".repeat(depth), "</b>".repeat(depth))
+        );
+    }
+
+    #[test]
+    fn test_clone_tree() {
+        let original = Element::new(
+            "div".to_string(),
+            [("id".to_string(), "main".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            vec![Element::new(
+                "p".to_string(),
+                AttrMap::new(),
+                vec![Text::new("hello".to_string())],
+            )],
+        );
+
+        let mut cloned = original.clone_tree();
+        assert_eq!(cloned, original);
+
+        if let NodeType::Element(ref mut e) = cloned.node_type {
+            e.attributes.insert("id".to_string(), "changed".to_string());
+        }
+        if let NodeType::Text(ref mut t) = cloned.children[0].children[0].node_type {
+            t.data = "bye".to_string();
+        }
+        cloned.children.push(Text::new("added".to_string()));
+
+        assert_ne!(cloned, original);
+        assert_eq!(
+            original,
+            Element::new(
+                "div".to_string(),
+                [("id".to_string(), "main".to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                vec![Element::new(
+                    "p".to_string(),
+                    AttrMap::new(),
+                    vec![Text::new("hello".to_string())],
+                )],
+            )
+        );
+    }
+
+    #[test]
+    fn test_split_text() {
+        let mut node = Text::new("hello".to_string());
+        assert_eq!(node.split_text(2), Ok(Text::new("llo".to_string())));
+        assert_eq!(node, Text::new("he".to_string()));
+
+        let mut node = Text::new("hello".to_string());
+        assert_eq!(node.split_text(5), Ok(Text::new("".to_string())));
+        assert_eq!(node, Text::new("hello".to_string()));
+
+        let mut node = Text::new("hello".to_string());
+        assert_eq!(
+            node.split_text(6),
+            Err(DomError::IndexSize {
+                offset: 6,
+                length: 5
+            })
+        );
+        assert_eq!(node, Text::new("hello".to_string()));
+
+        let mut node = Element::new("p".to_string(), AttrMap::new(), vec![]);
+        assert_eq!(node.split_text(0), Err(DomError::InvalidNodeType));
+    }
+
+    #[test]
+    fn test_split_text_multibyte() {
+        let mut node = Text::new("café".to_string());
+        assert_eq!(node.split_text(3), Ok(Text::new("é".to_string())));
+        assert_eq!(node, Text::new("caf".to_string()));
+
+        let mut node = Text::new("日本語のテキスト".to_string());
+        assert_eq!(node.split_text(3), Ok(Text::new("のテキスト".to_string())));
+        assert_eq!(node, Text::new("日本語".to_string()));
+
+        let mut node = Text::new("日本語".to_string());
+        assert_eq!(
+            node.split_text(4),
+            Err(DomError::IndexSize {
+                offset: 4,
+                length: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_insert_before() {
+        let mut node = Element::new(
+            "ul".to_string(),
+            AttrMap::new(),
+            vec![Text::new("b".to_string()), Text::new("d".to_string())],
+        );
+
+        node.insert_before(Text::new("a".to_string()), 0);
+        assert_eq!(node.inner_text(), "abd");
+        node.insert_before(Text::new("c".to_string()), 2);
+        assert_eq!(node.inner_text(), "abcd");
+        node.insert_before(Text::new("e".to_string()), 4);
+        assert_eq!(node.inner_text(), "abcde");
+        node.insert_before(Text::new("f".to_string()), 100);
+        assert_eq!(node.inner_text(), "abcdef");
+    }
 }
@@ -0,0 +1,76 @@
+use std::collections::HashSet;
+
+use super::{Node, NodeType};
+
+/// `DELETED_TAGS` are the tags whose contents are deleted along with the elements when they are not allowed,
+/// since unwrapping them would expose scripts or style sheets as text.
+const DELETED_TAGS: [&str; 5] = ["script", "style", "template", "iframe", "object"];
+
+/// `sanitize` removes the elements and the attributes which are not allowed from the tree of `root` in place
+/// so that untrusted HTML can be embedded safely.
+/// Disallowed elements are unwrapped, i.e. replaced with their children, except for `script`, `style`
+/// and the like, which are deleted with their contents. Texts and comments are kept.
+///
+/// NOTE: `root` itself is kept even if its tag is not allowed, while its attributes are stripped.
+/// Attribute values (e.g. `javascript:` URLs in `href`) are not inspected.
+pub fn sanitize(root: &mut Node, allowed_tags: &HashSet<String>, allowed_attrs: &HashSet<String>) {
+    if let NodeType::Element(ref mut e) = root.node_type {
+        e.attributes.retain(|name, _| allowed_attrs.contains(name));
+    }
+    let children = std::mem::take(&mut root.children);
+    for mut child in children {
+        match child.node_type {
+            NodeType::Element(ref e) if !allowed_tags.contains(&e.tag_name) => {
+                if !DELETED_TAGS.contains(&e.tag_name.as_str()) {
+                    sanitize(&mut child, allowed_tags, allowed_attrs);
+                    root.children.append(&mut child.children);
+                }
+            }
+            _ => {
+                sanitize(&mut child, allowed_tags, allowed_attrs);
+                root.children.push(child);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dom::{build_tree, TreeSpec};
+
+    use super::*;
+
+    #[test]
+    fn test_sanitize() {
+        use TreeSpec::{Element as E, Text as T};
+
+        let set = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<HashSet<_>>();
+        let mut root = build_tree(&E(
+            "div",
+            &[("onclick", "steal()"), ("class", "post")],
+            &[
+                E(
+                    "p",
+                    &[("class", "lead"), ("onclick", "steal()")],
+                    &[T("hello "), E("blink", &[], &[E("b", &[], &[T("world")])])],
+                ),
+                E("script", &[], &[T("steal()")]),
+                E("style", &[], &[T("p { display: none }")]),
+            ],
+        ));
+        sanitize(&mut root, &set(&["div", "p", "b"]), &set(&["class"]));
+
+        assert_eq!(
+            root,
+            build_tree(&E(
+                "div",
+                &[("class", "post")],
+                &[E(
+                    "p",
+                    &[("class", "lead")],
+                    &[T("hello "), E("b", &[], &[T("world")])],
+                )],
+            ))
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use super::{Comment, Element, Node, Text};
+
+/// `TreeSpec` describes a tree declaratively, e.g. in tests, which `build_tree` turns into nodes
+/// without nested calls of `Element::new`.
+/// For example, `<p class="note">hi</p>` is `TreeSpec::Element("p", &[("class", "note")], &[TreeSpec::Text("hi")])`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TreeSpec<'a> {
+    /// `Element` is a tuple of the tag name, the attributes and the children.
+    Element(&'a str, &'a [(&'a str, &'a str)], &'a [TreeSpec<'a>]),
+    Text(&'a str),
+    Comment(&'a str),
+}
+
+/// `build_tree` builds the tree described by `spec`.
+pub fn build_tree(spec: &TreeSpec) -> Box<Node> {
+    match *spec {
+        TreeSpec::Element(tag_name, attributes, children) => Element::new(
+            tag_name.to_string(),
+            attributes
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            children.iter().map(build_tree).collect(),
+        ),
+        TreeSpec::Text(data) => Text::new(data.to_string()),
+        TreeSpec::Comment(data) => Comment::new_node(data.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dom::AttrMap;
+
+    use super::*;
+
+    #[test]
+    fn test_build_tree() {
+        // <div id="main"><p>a<!--b--></p><br></div>
+        let spec = TreeSpec::Element(
+            "div",
+            &[("id", "main")],
+            &[
+                TreeSpec::Element("p", &[], &[TreeSpec::Text("a"), TreeSpec::Comment("b")]),
+                TreeSpec::Element("br", &[], &[]),
+            ],
+        );
+        let expected = Element::new(
+            "div".to_string(),
+            [("id".to_string(), "main".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            vec![
+                Element::new(
+                    "p".to_string(),
+                    AttrMap::new(),
+                    vec![
+                        Text::new("a".to_string()),
+                        Comment::new_node("b".to_string()),
+                    ],
+                ),
+                Element::new("br".to_string(), AttrMap::new(), vec![]),
+            ],
+        );
+
+        assert_eq!(build_tree(&spec), expected);
+    }
+}
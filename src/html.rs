@@ -1,14 +1,35 @@
-use crate::dom::{AttrMap, Element, Node, Text};
+use crate::dom::{AttrMap, Comment, DocumentType, Element, Node, Text, VOID_ELEMENTS};
 use combine::error::{ParseError, StreamError};
-use combine::parser::char::{newline, space};
-use combine::{attempt, between, choice, many, many1, parser, sep_by, Parser, Stream};
+use combine::parser::char::{newline, space, string, string_cmp};
+use combine::parser::repeat::repeat_until;
+use combine::{
+    any, attempt, between, choice, eof, many, many1, parser, sep_by, value, Parser, Stream,
+};
 use combine::{
     parser::char::{char, letter},
     satisfy,
 };
 
 pub fn parse(raw: &str) -> Box<Node> {
-    let mut nodes = parse_raw(raw);
+    root(parse_raw(raw))
+}
+
+pub fn parse_raw(raw: &str) -> Vec<Box<Node>> {
+    let (nodes, _) = nodes(false).parse(raw).unwrap();
+    nodes
+}
+
+/// `parse_preserving_case` is `parse`, but the elements remember their tag names as authored,
+/// e.g. `Element::original_tag_name` of `<DiV>` is `DiV` while its `tag_name` is still `div`.
+pub fn parse_preserving_case(raw: &str) -> Box<Node> {
+    let (nodes, _) = nodes(true).parse(raw).unwrap();
+    root(nodes)
+}
+
+/// `root` wraps `nodes` with `<html>` unless there is only one.
+// `nodes` are boxed since they become the children of `Node` as they are
+#[allow(clippy::vec_box)]
+fn root(mut nodes: Vec<Box<Node>>) -> Box<Node> {
     if nodes.len() == 1 {
         nodes.pop().unwrap()
     } else {
@@ -16,11 +37,6 @@ pub fn parse(raw: &str) -> Box<Node> {
     }
 }
 
-pub fn parse_raw(raw: &str) -> Vec<Box<Node>> {
-    let (nodes, _) = nodes().parse(raw).unwrap();
-    nodes
-}
-
 fn whitespaces<Input>() -> impl Parser<Input, Output = String>
 where
     Input: Stream<Token = char>,
@@ -30,17 +46,51 @@ where
 }
 
 // `nodes_` (and `nodes`) tries to parse input as Element or Text.
-fn nodes_<Input>() -> impl Parser<Input, Output = Vec<Box<Node>>>
+// The elements keep their tag names as authored when `preserve_case` is true.
+fn nodes_<Input>(preserve_case: bool) -> impl Parser<Input, Output = Vec<Box<Node>>>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     attempt(many(choice((
-        attempt(element()),
+        attempt(comment().skip(whitespaces())),
+        attempt(doctype().skip(whitespaces())),
+        attempt(element(preserve_case)),
         attempt(text().skip(whitespaces())),
     ))))
 }
 
+/// `comment` consumes `<!-- comment -->`.
+fn comment<Input>() -> impl Parser<Input, Output = Box<Node>>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    (
+        string("<!--"),
+        repeat_until::<String, _, _, _>(any(), attempt(string("-->"))),
+        string("-->"),
+    )
+        .map(|(_, data, _)| Comment::new_node(data))
+}
+
+/// `doctype` consumes `<!DOCTYPE name>`. The keyword `DOCTYPE` is case-insensitive.
+fn doctype<Input>() -> impl Parser<Input, Output = Box<Node>>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    (
+        string("<!"),
+        string_cmp("DOCTYPE", |l: char, r: char| l.eq_ignore_ascii_case(&r)),
+        many1::<String, _, _>(space().or(newline())),
+        many1::<String, _, _>(letter()),
+        whitespaces(),
+        char('>'),
+    )
+        .map(|(_, _, _, name, _, _)| DocumentType::new_node(name))
+}
+
 /// `text` consumes input until `<` comes.
 fn text<Input>() -> impl Parser<Input, Output = Box<Node>>
 where
@@ -51,29 +101,51 @@ where
 }
 
 /// `element` consumes `<tag_name attr_name="attr_value" ...>(children)</tag_name>`.
-fn element<Input>() -> impl Parser<Input, Output = Box<Node>>
+/// Tag names are case-insensitive, so `<DiV></div>` is a `div` element, which remembers its original name `DiV` when `preserve_case` is true.
+/// Elements still open at the end of input are closed implicitly, e.g. `<div><p>text` is `<div><p>text</p></div>`.
+/// Void elements like `<img>` have no children and no close tags.
+/// See https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody (the end-of-file token)
+fn element<Input>(preserve_case: bool) -> impl Parser<Input, Output = Box<Node>>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    (
-        open_tag().skip(whitespaces()),
-        nodes().skip(whitespaces()),
-        close_tag().skip(whitespaces()),
-    )
-        .and_then(|((open_tag_name, attributes), children, close_tag_name)| {
-            if open_tag_name == close_tag_name {
-                Ok(Element::new(open_tag_name, attributes, children))
+    open_tag()
+        .skip(whitespaces())
+        .then_ref(move |(open_tag_name, _)| {
+            if VOID_ELEMENTS.contains(&open_tag_name.to_ascii_lowercase().as_str()) {
+                // void elements have neither children nor end tags
+                value((vec![], None)).left()
             } else {
-                Err(<Input::Error as combine::error::ParseError<
-                    char,
-                    Input::Range,
-                    Input::Position,
-                >>::StreamError::message_static_message(
-                    "tag name of open tag and close tag mismatched",
-                ))
+                (
+                    nodes(preserve_case).skip(whitespaces()),
+                    choice((
+                        attempt(close_tag().skip(whitespaces())).map(Some),
+                        eof().map(|_| None),
+                    )),
+                )
+                    .right()
             }
         })
+        .and_then(
+            move |((open_tag_name, attributes), (children, close_tag_name))| {
+                if close_tag_name.is_none_or(|name| name.eq_ignore_ascii_case(&open_tag_name)) {
+                    Ok(if preserve_case {
+                        Element::new_case_preserving(open_tag_name, attributes, children)
+                    } else {
+                        Element::new(open_tag_name, attributes, children)
+                    })
+                } else {
+                    Err(<Input::Error as combine::error::ParseError<
+                        char,
+                        Input::Range,
+                        Input::Position,
+                    >>::StreamError::message_static_message(
+                        "tag name of open tag and close tag mismatched",
+                    ))
+                }
+            },
+        )
 }
 
 /// `open_tag` consumes `<tag_name attr_name="attr_value" ...>`.
@@ -140,15 +212,20 @@ where
 }
 
 parser! {
-    fn nodes[Input]()(Input) -> Vec<Box<Node>>
+    fn nodes[Input](preserve_case: bool)(Input) -> Vec<Box<Node>>
     where [Input: Stream<Token = char>]
     {
-        nodes_()
+        nodes_(*preserve_case)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::{
+        css::{MatchContext, SimpleSelector},
+        dom::NodeType,
+    };
+
     use super::*;
 
     // parsing tests of attributes
@@ -218,12 +295,12 @@ mod tests {
     #[test]
     fn test_parse_element() {
         assert_eq!(
-            element().parse("<p></p>"),
+            element(false).parse("<p></p>"),
             Ok((Element::new("p".to_string(), AttrMap::new(), vec![]), ""))
         );
 
         assert_eq!(
-            element().parse("<p>hello world</p>"),
+            element(false).parse("<p>hello world</p>"),
             Ok((
                 Element::new(
                     "p".to_string(),
@@ -235,7 +312,7 @@ mod tests {
         );
 
         assert_eq!(
-            element().parse("<div><p>hello world</p></div>"),
+            element(false).parse("<div><p>hello world</p></div>"),
             Ok((
                 Element::new(
                     "div".to_string(),
@@ -250,7 +327,101 @@ mod tests {
             ))
         );
 
-        assert!(element().parse("<p>hello world</div>").is_err());
+        assert!(element(false).parse("<p>hello world</div>").is_err());
+    }
+
+    #[test]
+    fn test_parse_void_element() {
+        let img = |width: &str| {
+            Element::new(
+                "img".to_string(),
+                [("width".to_string(), width.to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                vec![],
+            )
+        };
+
+        // the following nodes are siblings of the void elements, not their children
+        let node = parse(r#"<body><img width="100"> text<BR><img width="50"></body>"#);
+        assert_eq!(
+            node,
+            Element::new(
+                "body".to_string(),
+                AttrMap::new(),
+                vec![
+                    img("100"),
+                    Text::new("text".to_string()),
+                    Element::new("br".to_string(), AttrMap::new(), vec![]),
+                    img("50"),
+                ],
+            )
+        );
+        assert_eq!(
+            node.to_html(),
+            r#"<body><img width="100">text<br><img width="50"></body>"#
+        );
+        assert_eq!(parse(&node.to_html()), node);
+    }
+
+    #[test]
+    fn test_parse_case_insensitive_tag_name() {
+        let node = parse("<DiV><P>hi</p></dIV>");
+        let e = match node.node_type {
+            NodeType::Element(ref e) => e,
+            _ => unreachable!(),
+        };
+        assert_eq!(e.original_tag_name(), "div");
+        assert_eq!(node, parse("<div><p>hi</p></div>"));
+
+        let node = parse_preserving_case("<DiV><P>hi</p></dIV>");
+        let e = match node.node_type {
+            NodeType::Element(ref e) => e,
+            _ => unreachable!(),
+        };
+        assert_eq!(e.original_tag_name(), "DiV");
+        assert!(SimpleSelector::TypeSelector {
+            tag_name: "div".to_string(),
+        }
+        .matches(&node, &MatchContext::default()));
+        assert_eq!(node.to_html(), "<div><p>hi</p></div>");
+        assert_eq!(node, parse("<div><p>hi</p></div>"));
+    }
+
+    #[test]
+    fn test_parse_unclosed_element() {
+        assert_eq!(
+            element(false).parse("<div><p>hi"),
+            Ok((
+                Element::new(
+                    "div".to_string(),
+                    AttrMap::new(),
+                    vec![Element::new(
+                        "p".to_string(),
+                        AttrMap::new(),
+                        vec![Text::new("hi".to_string())]
+                    )],
+                ),
+                ""
+            ))
+        );
+
+        assert_eq!(
+            parse("<div><p>hi</p><span>"),
+            Element::new(
+                "div".to_string(),
+                AttrMap::new(),
+                vec![
+                    Element::new(
+                        "p".to_string(),
+                        AttrMap::new(),
+                        vec![Text::new("hi".to_string())]
+                    ),
+                    Element::new("span".to_string(), AttrMap::new(), vec![]),
+                ],
+            )
+        );
     }
 
     #[test]
@@ -268,4 +439,73 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_parse_comment() {
+        assert_eq!(
+            comment().parse("<!-- hello <p>world</p> -->"),
+            Ok((Comment::new_node(" hello <p>world</p> ".to_string()), ""))
+        );
+
+        assert_eq!(
+            comment().parse("<!---->aaaa"),
+            Ok((Comment::new_node("".to_string()), "aaaa"))
+        );
+
+        assert!(comment().parse("<!-- hello").is_err());
+    }
+
+    #[test]
+    fn test_parse_doctype() {
+        assert_eq!(
+            doctype().parse("<!DOCTYPE html>"),
+            Ok((DocumentType::new_node("html".to_string()), ""))
+        );
+
+        assert_eq!(
+            doctype().parse("<!doctype html >"),
+            Ok((DocumentType::new_node("html".to_string()), ""))
+        );
+
+        assert!(doctype().parse("<!DOCTYPE>").is_err());
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let node = parse(
+            "<!DOCTYPE html><!-- top --><html><body><!-- inner --><p id=\"a\">hello</p></body></html>",
+        );
+
+        assert_eq!(
+            node,
+            Element::new(
+                "html".to_string(),
+                AttrMap::new(),
+                vec![
+                    DocumentType::new_node("html".to_string()),
+                    Comment::new_node(" top ".to_string()),
+                    Element::new(
+                        "html".to_string(),
+                        AttrMap::new(),
+                        vec![Element::new(
+                            "body".to_string(),
+                            AttrMap::new(),
+                            vec![
+                                Comment::new_node(" inner ".to_string()),
+                                Element::new(
+                                    "p".to_string(),
+                                    [("id".to_string(), "a".to_string())]
+                                        .iter()
+                                        .cloned()
+                                        .collect(),
+                                    vec![Text::new("hello".to_string())]
+                                ),
+                            ]
+                        )]
+                    ),
+                ]
+            )
+        );
+        assert_eq!(parse(&node.to_html()), node);
+    }
 }
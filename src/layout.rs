@@ -1,12 +1,71 @@
 use crate::{
-    dom::NodeType,
-    style::{Display, PropertyMap, StyledNode},
+    css::Direction,
+    css::{CSSValue, LengthContext, PseudoElement, Unit},
+    dom::{Element, NodeType},
+    style::{
+        pseudo_element_properties, pseudo_element_property, resolve_length, Clear, Display, Float,
+        PropertyMap, StyledNode, WritingMode,
+    },
 };
+use std::{iter, ops::Range};
+
+/// `DEFAULT_FONT_SIZE` is the font size in px of anonymous boxes, which `em` lengths refer to.
+/// `rem` lengths never reach layout since styling computes them into px with the font size of the root element.
+const DEFAULT_FONT_SIZE: f32 = 16.0;
+
+/// `REPLACED_ELEMENTS` are the elements whose content is outside the scope of CSS, like images.
+/// They are sized by their intrinsic dimensions instead of their children.
+/// See https://www.w3.org/TR/CSS2/conform.html#replaced-element
+const REPLACED_ELEMENTS: [&str; 1] = ["img"];
+
+/// `CHAR_WIDTH` is the estimated advance of a single character in px at `DEFAULT_FONT_SIZE`.
+/// Every character is assumed to have the same width for simplicity.
+const CHAR_WIDTH: f32 = DEFAULT_FONT_SIZE / 2.0;
+
+/// `FontMetrics` measures texts to lay out inline contents, so that layout works with any font backend.
+pub trait FontMetrics {
+    /// `char_width` returns the advance of `c` in px in `font_size` px.
+    fn char_width(&self, c: char, font_size: f32) -> f32;
+
+    /// `text_width` returns the advance of `text` in px in `font_size` px.
+    fn text_width(&self, text: &str, font_size: f32) -> f32 {
+        text.chars().map(|c| self.char_width(c, font_size)).sum()
+    }
+}
+
+/// `MonospaceMetrics` is the default `FontMetrics`, which measures texts like `scaled_text_width`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MonospaceMetrics;
+
+impl FontMetrics for MonospaceMetrics {
+    fn char_width(&self, _c: char, font_size: f32) -> f32 {
+        CHAR_WIDTH * font_size / DEFAULT_FONT_SIZE
+    }
+
+    fn text_width(&self, text: &str, font_size: f32) -> f32 {
+        scaled_text_width(text, font_size)
+    }
+}
 
 #[derive(Debug, PartialEq)]
 pub struct LayoutBox<'a> {
     pub box_type: BoxType<'a>,
+    pub dimensions: Dimensions,
     pub children: Vec<LayoutBox<'a>>,
+    /// `lines` are the fragments of a text box directly in a line container, one for each line the text is on.
+    /// It is empty for the other boxes, and for texts in inline elements, whose text is in `dimensions.content`.
+    pub lines: Vec<LineFragment>,
+}
+
+/// `LineFragment` is the part of a text on a single line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineFragment {
+    /// `range` is the byte range of the part in the text.
+    pub range: Range<usize>,
+    pub rect: Rect,
+    /// `first_line` tells whether the part is on the first line of its block container,
+    /// where the properties of `::first-line` apply.
+    pub first_line: bool,
 }
 
 #[derive(Debug, PartialEq)]
@@ -22,170 +81,2935 @@ pub struct BoxProps<'a> {
     pub properties: PropertyMap,
 }
 
+/// `OUTLINE_TEXT_LENGTH` is the maximum number of characters of the text preview in `OutlineEntry`.
+const OUTLINE_TEXT_LENGTH: usize = 20;
+
+/// `OutlineEntry` is a box in the outline made by `LayoutBox::outline`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineEntry {
+    /// `name` is the tag name of the element, `#text` for texts, or `(anonymous)` for anonymous boxes.
+    pub name: String,
+    /// `text` is the beginning of the text for text boxes, which is empty for the other boxes.
+    pub text: String,
+    /// `rect` is the border box.
+    pub rect: Rect,
+}
+
+/// `Dimensions` represents the box model of a box.
+/// See https://www.w3.org/TR/css-box-3/#box-model for further information.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Dimensions {
+    /// `content` is the position and size of the content area relative to the document origin.
+    pub content: Rect,
+    pub padding: EdgeSizes,
+    pub border: EdgeSizes,
+    pub margin: EdgeSizes,
+}
+
+impl Dimensions {
+    pub fn padding_box(&self) -> Rect {
+        self.content.expanded_by(self.padding)
+    }
+
+    pub fn border_box(&self) -> Rect {
+        self.padding_box().expanded_by(self.border)
+    }
+
+    pub fn margin_box(&self) -> Rect {
+        self.border_box().expanded_by(self.margin)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn expanded_by(self, edge: EdgeSizes) -> Rect {
+        Rect {
+            x: self.x - edge.left,
+            y: self.y - edge.top,
+            width: self.width + edge.left + edge.right,
+            height: self.height + edge.top + edge.bottom,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct EdgeSizes {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+}
+
+/// `to_layout_box` builds the box tree from the styled tree.
+/// Consecutive inline-level children of a block container are wrapped with an anonymous box,
+/// so that block-level boxes and inline-level boxes are never siblings in it.
+/// Inline-level children of an inline box stay its direct children, since they are on the same line as it.
+/// Children whose `display` generates no box, like `table-column`, are skipped with their descendants,
+/// and the root falls back to an inline box in that case.
+/// See https://www.w3.org/TR/CSS2/visuren.html#anonymous-block-level
 pub fn to_layout_box<'a>(snode: StyledNode<'a>) -> LayoutBox<'a> {
+    let is_grid_container = snode.display() == Display::Grid;
+    let is_block_container = snode.is_block() && !is_grid_container;
+    let first_line = pseudo_element_properties(&snode.properties, PseudoElement::FirstLine);
+    let first_letter = pseudo_element_properties(&snode.properties, PseudoElement::FirstLetter);
     let mut layout = LayoutBox {
-        box_type: match snode.display() {
-            Display::Block => BoxType::BlockBox(BoxProps {
+        box_type: if snode.is_block() {
+            BoxType::BlockBox(BoxProps {
                 node_type: snode.node_type,
                 properties: snode.properties,
-            }),
-            Display::Inline => BoxType::InlineBox(BoxProps {
+            })
+        } else {
+            BoxType::InlineBox(BoxProps {
                 node_type: snode.node_type,
                 properties: snode.properties,
-            }),
-            Display::None => unreachable!(),
+            })
         },
+        dimensions: Dimensions::default(),
         children: vec![],
+        lines: vec![],
     };
 
     for child in snode.children {
-        match child.display() {
-            Display::Block => {
-                layout.children.push(to_layout_box(child));
+        if child.is_block() {
+            layout.children.push(to_layout_box(child));
+        } else if child.is_inline() && is_grid_container {
+            // children of a grid container are blockified
+            // https://drafts.csswg.org/css-display/#blockify
+            let mut item = to_layout_box(child);
+            if let BoxType::InlineBox(props) = item.box_type {
+                item.box_type = BoxType::BlockBox(props);
             }
-            Display::Inline => {
-                match layout.children.last() {
-                    Some(&LayoutBox {
+            layout.children.push(item);
+        } else if child.is_inline() && !is_block_container {
+            layout.children.push(to_layout_box(child));
+        } else if child.is_inline() {
+            match layout.children.last() {
+                Some(&LayoutBox {
+                    box_type: BoxType::AnonymousBox,
+                    ..
+                }) => {}
+                _ => {
+                    layout.children.push(LayoutBox {
                         box_type: BoxType::AnonymousBox,
-                        ..
-                    }) => {}
-                    _ => {
-                        layout.children.push(LayoutBox {
-                            box_type: BoxType::AnonymousBox,
-                            children: vec![],
-                        });
-                    }
+                        dimensions: Dimensions::default(),
+                        children: vec![],
+                        lines: vec![],
+                    });
                 }
-                layout
-                    .children
-                    .last_mut()
-                    .unwrap()
-                    .children
-                    .push(to_layout_box(child));
             }
-            Display::None => unreachable!(),
+            layout
+                .children
+                .last_mut()
+                .unwrap()
+                .children
+                .push(to_layout_box(child));
+        }
+        // the others generate no boxes, e.g. table columns
+        // https://www.w3.org/TR/CSS2/tables.html#columns
+    }
+
+    // the first line is in the anonymous box at the beginning, if any
+    if is_block_container {
+        if let Some(
+            line @ LayoutBox {
+                box_type: BoxType::AnonymousBox,
+                ..
+            },
+        ) = layout.children.first_mut()
+        {
+            apply_first_line(line, first_line, first_letter);
         }
     }
 
     layout
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::{css::CSSValue, dom::Element};
+/// `apply_first_line` applies the properties of `::first-line` and `::first-letter` to `line`,
+/// the anonymous box of the first line of a block container.
+/// The properties of `::first-line` are stored in the text boxes under the names given by `pseudo_element_property`,
+/// since they apply only to the `lines` on the first line, which are known after layout.
+/// The first letter gets its own inline box as the child of the first text box.
+/// See https://www.w3.org/TR/css-pseudo-4/#first-line-pseudo and https://www.w3.org/TR/css-pseudo-4/#first-letter-pseudo
+///
+/// NOTE: Only texts directly in the line are styled, i.e. texts in inline elements like `<span>` are not.
+/// `::first-letter` is just the first character, including white spaces and punctuation.
+fn apply_first_line<'a>(
+    line: &mut LayoutBox<'a>,
+    first_line: PropertyMap,
+    first_letter: PropertyMap,
+) {
+    for child in &mut line.children {
+        if let BoxType::InlineBox(ref mut props) = child.box_type {
+            if let NodeType::Text(_) = props.node_type {
+                props
+                    .properties
+                    .extend(first_line.iter().map(|(name, value)| {
+                        (
+                            pseudo_element_property(PseudoElement::FirstLine, name),
+                            value.clone(),
+                        )
+                    }));
+            }
+        }
+    }
 
-    use super::*;
+    if first_letter.is_empty() {
+        return;
+    }
+    if let Some(first) = line.children.first_mut() {
+        if let BoxType::InlineBox(ref props) = first.box_type {
+            if let NodeType::Text(ref t) = props.node_type {
+                if !t.data.is_empty() {
+                    // the first letter is always on the first line
+                    let mut properties = props.properties.clone();
+                    properties.extend(first_line);
+                    properties.extend(first_letter);
+                    first.children.push(LayoutBox {
+                        box_type: BoxType::InlineBox(BoxProps {
+                            node_type: props.node_type,
+                            properties,
+                        }),
+                        dimensions: Dimensions::default(),
+                        children: vec![],
+                        lines: vec![],
+                    });
+                }
+            }
+        }
+    }
+}
 
-    #[test]
-    fn test_to_layout_box() {
-        let block = [(
-            "display".to_string(),
-            CSSValue::Keyword("block".to_string()),
-        )];
-        let inline = [(
-            "display".to_string(),
-            CSSValue::Keyword("inline".to_string()),
-        )];
-        let node = NodeType::Element(Element {
-            tag_name: "div".into(),
-            attributes: [].iter().cloned().collect(),
+/// `ContainingBlock` is the area where a box is laid out.
+/// See https://www.w3.org/TR/CSS2/visudet.html#containing-block-details
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct ContainingBlock {
+    /// `x` and `y` are the position where the margin box of the laid out box starts.
+    x: f32,
+    y: f32,
+    width: f32,
+    /// `height` is `None` when the height of the containing block is not definite.
+    /// See https://drafts.csswg.org/css-sizing-3/#definite
+    height: Option<f32>,
+    /// `direction` is the direction of the box establishing the containing block,
+    /// which anonymous boxes inherit since they have no properties.
+    direction: Direction,
+    /// `writing_mode` is the writing mode of the box establishing the containing block.
+    writing_mode: WritingMode,
+}
+
+impl<'a> LayoutBox<'a> {
+    /// `layout` computes the dimensions of the box and its descendants.
+    /// The box is placed at the top-left corner of `containing_block.content`,
+    /// and the height of `containing_block.content` is used to resolve percentage heights.
+    pub fn layout(&mut self, containing_block: Dimensions) {
+        self.layout_with_metrics(containing_block, &MonospaceMetrics);
+    }
+
+    /// `layout_with_metrics` computes the dimensions like `layout`, measuring texts with `metrics`.
+    pub fn layout_with_metrics(&mut self, containing_block: Dimensions, metrics: &dyn FontMetrics) {
+        self.layout_in(
+            ContainingBlock {
+                x: containing_block.content.x,
+                y: containing_block.content.y,
+                width: containing_block.content.width,
+                height: Some(containing_block.content.height),
+                direction: Direction::default(),
+                writing_mode: WritingMode::default(),
+            },
+            metrics,
+        );
+    }
+
+    fn layout_in(&mut self, containing_block: ContainingBlock, metrics: &dyn FontMetrics) {
+        match self.box_type {
+            BoxType::BlockBox(_) | BoxType::AnonymousBox => {
+                self.layout_block(containing_block, metrics)
+            }
+            BoxType::InlineBox(_) => self.layout_inline(containing_block, metrics),
+        }
+    }
+
+    pub fn properties(&self) -> Option<&PropertyMap> {
+        match self.box_type {
+            BoxType::BlockBox(ref p) | BoxType::InlineBox(ref p) => Some(&p.properties),
+            BoxType::AnonymousBox => None,
+        }
+    }
+
+    /// `debug_dump` prints the box tree with the content, padding, border and margin rects of each box,
+    /// indented by depth. It is useful for diagnosing layout.
+    pub fn debug_dump(&self) -> String {
+        let mut dump = String::new();
+        self.debug_dump_into(&mut dump, 0);
+        dump
+    }
+
+    fn debug_dump_into(&self, dump: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth);
+        let name = self.name();
+        let kind = match self.box_type {
+            BoxType::BlockBox(_) => "block",
+            BoxType::InlineBox(_) => "inline",
+            BoxType::AnonymousBox => "anonymous",
+        };
+        let d = &self.dimensions;
+        dump.push_str(&format!("{}{} [{}]\n", indent, name, kind));
+        for (label, rect) in [
+            ("content", d.content),
+            ("padding", d.padding_box()),
+            ("border", d.border_box()),
+            ("margin", d.margin_box()),
+        ] {
+            dump.push_str(&format!(
+                "{}  {}: x={} y={} w={} h={}\n",
+                indent, label, rect.x, rect.y, rect.width, rect.height
+            ));
+        }
+        for child in &self.children {
+            child.debug_dump_into(dump, depth + 1);
+        }
+    }
+
+    /// `name` returns the tag name of the element, or a placeholder like `#text` for the other boxes.
+    fn name(&self) -> &str {
+        match self.box_type {
+            BoxType::BlockBox(ref p) | BoxType::InlineBox(ref p) => match p.node_type {
+                NodeType::Element(ref e) => e.tag_name.as_str(),
+                NodeType::Text(_) => "#text",
+                NodeType::Comment(_) => "#comment",
+                NodeType::DocumentType(_) => "#doctype",
+            },
+            BoxType::AnonymousBox => "(anonymous)",
+        }
+    }
+
+    /// `outline` lists the boxes in the paint order with their names, text previews and border boxes.
+    /// It is more compact than `debug_dump`, which is suitable for snapshot tests of layout.
+    /// The descendants skipped by `content-visibility: hidden` are not listed as they are not painted.
+    pub fn outline(&self) -> Vec<OutlineEntry> {
+        let mut entries = vec![];
+        self.outline_into(&mut entries);
+        entries
+    }
+
+    fn outline_into(&self, entries: &mut Vec<OutlineEntry>) {
+        let text = match self.box_type {
+            BoxType::InlineBox(BoxProps {
+                node_type: NodeType::Text(t),
+                ..
+            }) => t.data.trim().chars().take(OUTLINE_TEXT_LENGTH).collect(),
+            _ => String::new(),
+        };
+        entries.push(OutlineEntry {
+            name: self.name().to_string(),
+            text,
+            rect: self.dimensions.border_box(),
         });
-        let snode = StyledNode {
-            node_type: &node,
-            properties: block.iter().cloned().collect(),
-            children: vec![
-                StyledNode {
-                    node_type: &node,
-                    properties: block.iter().cloned().collect(),
-                    children: vec![],
-                },
-                StyledNode {
-                    node_type: &node,
-                    properties: inline.iter().cloned().collect(),
-                    children: vec![
-                        StyledNode {
-                            node_type: &node,
-                            properties: block.iter().cloned().collect(),
-                            children: vec![],
-                        },
-                        StyledNode {
-                            node_type: &node,
-                            properties: block.iter().cloned().collect(),
-                            children: vec![],
-                        },
-                    ],
-                },
-                StyledNode {
-                    node_type: &node,
-                    properties: inline.iter().cloned().collect(),
-                    children: vec![],
-                },
-                StyledNode {
-                    node_type: &node,
-                    properties: block.iter().cloned().collect(),
-                    children: vec![],
-                },
-            ],
+        if !self.skips_contents() {
+            for child in &self.children {
+                child.outline_into(entries);
+            }
+        }
+    }
+
+    /// `skips_contents` tells whether the layout and painting of the descendants are skipped by `content-visibility: hidden`.
+    /// The box itself is still laid out as if it had no contents.
+    /// See https://drafts.csswg.org/css-contain-2/#content-visibility
+    ///
+    /// NOTE: `content-visibility: auto` is treated as `visible`,
+    /// as if the box were always relevant to the user, since there is no scrolling viewport.
+    pub fn skips_contents(&self) -> bool {
+        self.value("content-visibility") == Some(&CSSValue::Keyword("hidden".into()))
+    }
+
+    /// `direction` returns the `direction` of the box, or the one of `containing_block` for anonymous boxes.
+    fn direction(&self, containing_block: ContainingBlock) -> Direction {
+        match self.value("direction") {
+            Some(CSSValue::Keyword(keyword)) => Direction::from_keyword(keyword),
+            _ => None,
+        }
+        .unwrap_or(containing_block.direction)
+    }
+
+    fn value(&self, name: &str) -> Option<&CSSValue> {
+        self.properties().and_then(|p| p.get(name))
+    }
+
+    /// `font_size` returns the computed `font-size` in px, or `DEFAULT_FONT_SIZE` for anonymous boxes.
+    pub fn font_size(&self) -> f32 {
+        self.length("font-size", DEFAULT_FONT_SIZE)
+            .unwrap_or(DEFAULT_FONT_SIZE)
+    }
+
+    /// `line_value` returns the property `name` of the text in `fragment`,
+    /// where the property of `::first-line` takes precedence on the first line.
+    pub fn line_value(&self, fragment: &LineFragment, name: &str) -> Option<&CSSValue> {
+        let first_line = match fragment.first_line {
+            true => self.value(&pseudo_element_property(PseudoElement::FirstLine, name)),
+            false => None,
         };
+        first_line.or_else(|| self.value(name))
+    }
 
-        assert_eq!(
-            to_layout_box(snode),
-            LayoutBox {
-                box_type: BoxType::BlockBox(BoxProps {
-                    node_type: &node,
-                    properties: block.iter().cloned().collect(),
-                }),
-                children: vec![
-                    LayoutBox {
-                        box_type: BoxType::BlockBox(BoxProps {
-                            node_type: &node,
-                            properties: block.iter().cloned().collect(),
-                        }),
-                        children: vec![],
-                    },
-                    LayoutBox {
-                        box_type: BoxType::AnonymousBox,
-                        children: vec![
-                            LayoutBox {
-                                box_type: BoxType::InlineBox(BoxProps {
-                                    node_type: &node,
-                                    properties: inline.iter().cloned().collect(),
-                                }),
-                                children: vec![
-                                    LayoutBox {
-                                        box_type: BoxType::BlockBox(BoxProps {
-                                            node_type: &node,
-                                            properties: block.iter().cloned().collect(),
-                                        }),
-                                        children: vec![],
-                                    },
-                                    LayoutBox {
-                                        box_type: BoxType::BlockBox(BoxProps {
-                                            node_type: &node,
-                                            properties: block.iter().cloned().collect(),
-                                        }),
-                                        children: vec![],
-                                    }
-                                ],
-                            },
-                            LayoutBox {
-                                box_type: BoxType::InlineBox(BoxProps {
-                                    node_type: &node,
-                                    properties: inline.iter().cloned().collect(),
-                                }),
-                                children: vec![],
-                            }
-                        ]
+    /// `line_font_size` returns the computed `font-size` in px of the text in `fragment` like `line_value`.
+    pub fn line_font_size(&self, fragment: &LineFragment) -> f32 {
+        match fragment.first_line {
+            true => self.first_line_font_size(),
+            false => self.font_size(),
+        }
+    }
+
+    /// `first_line_font_size` returns the computed `font-size` in px of the text on the first line.
+    fn first_line_font_size(&self) -> f32 {
+        self.length(
+            &pseudo_element_property(PseudoElement::FirstLine, "font-size"),
+            DEFAULT_FONT_SIZE,
+        )
+        .unwrap_or_else(|| self.font_size())
+    }
+
+    /// `length_context` returns the context which the lengths of the box are resolved against,
+    /// where `%` refers to `percentage_basis`.
+    pub(crate) fn length_context(&self, percentage_basis: f32) -> LengthContext {
+        LengthContext {
+            font_size: self.font_size(),
+            root_font_size: DEFAULT_FONT_SIZE,
+            percentage_basis,
+        }
+    }
+
+    /// `length` resolves the property `name` into px like `resolve_length`, or returns `None` for anonymous boxes.
+    pub(crate) fn length(&self, name: &str, percentage_basis: f32) -> Option<f32> {
+        self.properties()
+            .and_then(|p| resolve_length(p, name, percentage_basis))
+    }
+
+    /// `vertical_length` resolves the property `name` like `length`,
+    /// except that percentages are resolved against the height of the containing block.
+    /// Percentages against an indefinite height are resolved to `None`.
+    fn vertical_length(&self, name: &str, containing_block: ContainingBlock) -> Option<f32> {
+        match (self.value(name), containing_block.height) {
+            (Some(CSSValue::Length((_, Unit::Percent))), None) => None,
+            (Some(_), height) => self.length(name, height.unwrap_or(0.0)),
+            (None, _) => None,
+        }
+    }
+
+    /// `aspect_ratio` returns the preferred `width / height` ratio of the box if specified.
+    /// See https://drafts.csswg.org/css-sizing-4/#aspect-ratio
+    fn aspect_ratio(&self) -> Option<f32> {
+        match self.value("aspect-ratio") {
+            Some(CSSValue::Ratio((width, height))) if *width != 0 && *height != 0 => {
+                Some(*width as f32 / *height as f32)
+            }
+            _ => None,
+        }
+    }
+
+    /// `replaced_element` returns the element of the box if it is one of `REPLACED_ELEMENTS`.
+    fn replaced_element(&self) -> Option<&Element> {
+        match self.box_type {
+            BoxType::BlockBox(BoxProps {
+                node_type: NodeType::Element(ref e),
+                ..
+            })
+            | BoxType::InlineBox(BoxProps {
+                node_type: NodeType::Element(ref e),
+                ..
+            }) if REPLACED_ELEMENTS.contains(&e.tag_name.as_str()) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// `replaced_size` returns the content size of a replaced element, or `None` for other boxes.
+    /// CSS `width` and `height` win over the intrinsic size given by the `width` and `height` attributes.
+    /// When only one of them is specified, the other one follows the intrinsic aspect ratio.
+    ///
+    /// NOTE: Images are not decoded, so only the attributes give the intrinsic size.
+    fn replaced_size(&self, containing_block: ContainingBlock) -> Option<(f32, f32)> {
+        let element = self.replaced_element()?;
+        let attribute = |name: &str| {
+            element
+                .attributes
+                .get(name)
+                .and_then(|v| v.trim().parse::<f32>().ok())
+        };
+        let (intrinsic_width, intrinsic_height) = (attribute("width"), attribute("height"));
+        let ratio = match (intrinsic_width, intrinsic_height) {
+            (Some(width), Some(height)) if height > 0.0 => Some(width / height),
+            _ => self.aspect_ratio(),
+        };
+
+        let width = self.length("width", containing_block.width);
+        let height = self.vertical_length("height", containing_block);
+        Some(match (width, height) {
+            (Some(width), Some(height)) => (width, height),
+            (Some(width), None) => (
+                width,
+                ratio.map(|r| width / r).or(intrinsic_height).unwrap_or(0.0),
+            ),
+            (None, Some(height)) => (
+                ratio.map(|r| height * r).or(intrinsic_width).unwrap_or(0.0),
+                height,
+            ),
+            (None, None) => (
+                intrinsic_width.unwrap_or(0.0),
+                intrinsic_height.unwrap_or(0.0),
+            ),
+        })
+    }
+
+    /// `clamp_height` clamps `height` into the range given by `min-height` and `max-height`.
+    /// `min-height` wins when it conflicts with `max-height`.
+    /// See https://www.w3.org/TR/CSS2/visudet.html#min-max-heights
+    fn clamp_height(&self, height: f32, containing_block: ContainingBlock) -> f32 {
+        let height = match self.vertical_length("max-height", containing_block) {
+            Some(max_height) => height.min(max_height),
+            None => height,
+        };
+        match self.vertical_length("min-height", containing_block) {
+            Some(min_height) => height.max(min_height),
+            None => height,
+        }
+    }
+
+    fn layout_block(&mut self, containing_block: ContainingBlock, metrics: &dyn FontMetrics) {
+        self.calculate_block_width(containing_block);
+        self.calculate_block_position(containing_block);
+        let definite_height = self.definite_block_height(containing_block);
+        let direction = self.direction(containing_block);
+        match self.box_type {
+            _ if self.skips_contents() => self.dimensions.content.height = 0.0,
+            BoxType::AnonymousBox => self.layout_lines(direction, metrics),
+            _ if self.is_grid_container() => self.layout_grid_children(direction, metrics),
+            _ if self.writing_mode().is_vertical() => {
+                self.layout_vertical_block_children(definite_height, direction, metrics)
+            }
+            _ => self.layout_block_children(definite_height, direction, metrics),
+        }
+        self.calculate_block_height(containing_block, definite_height);
+    }
+
+    /// `calculate_block_width` resolves the horizontal box model of a block-level box.
+    /// `auto` width fills the containing block, and otherwise `auto` margins take the remaining space,
+    /// e.g. `margin: 0 auto` centers the box. When the box is over-constrained, the end margin is adjusted,
+    /// i.e. `margin-right` in `ltr` and `margin-left` in `rtl`.
+    /// See https://www.w3.org/TR/CSS2/visudet.html#blockwidth
+    fn calculate_block_width(&mut self, containing_block: ContainingBlock) {
+        let cb_width = containing_block.width;
+        let mut margin_left = self.length("margin-left", cb_width).unwrap_or(0.0);
+        let mut margin_right = self.length("margin-right", cb_width).unwrap_or(0.0);
+        let border_left = self.length("border-left-width", cb_width).unwrap_or(0.0);
+        let border_right = self.length("border-right-width", cb_width).unwrap_or(0.0);
+        let padding_left = self.length("padding-left", cb_width).unwrap_or(0.0);
+        let padding_right = self.length("padding-right", cb_width).unwrap_or(0.0);
+
+        let width = self
+            .replaced_size(containing_block)
+            .map(|(width, _)| width)
+            .or_else(|| self.length("width", cb_width))
+            .or_else(|| {
+                match (
+                    self.aspect_ratio(),
+                    self.vertical_length("height", containing_block),
+                ) {
+                    (Some(ratio), Some(height)) => Some(height * ratio),
+                    _ => None,
+                }
+            });
+        let width = match width {
+            Some(width) => {
+                // `auto` margins are regarded as 0 here since `length` ignores them
+                let underflow = cb_width
+                    - (margin_left
+                        + margin_right
+                        + border_left
+                        + border_right
+                        + padding_left
+                        + padding_right
+                        + width);
+                let is_auto = |name| {
+                    underflow >= 0.0 && self.value(name) == Some(&CSSValue::Keyword("auto".into()))
+                };
+                // floats regard `auto` margins as 0, and the horizontal margins are not inline-axis ones in vertical flow
+                let resolves_margins =
+                    self.float().is_none() && !containing_block.writing_mode.is_vertical();
+                match (is_auto("margin-left"), is_auto("margin-right")) {
+                    _ if !resolves_margins => {}
+                    (true, true) => {
+                        margin_left = underflow / 2.0;
+                        margin_right = underflow / 2.0;
+                    }
+                    (true, false) => margin_left = underflow,
+                    (false, true) => margin_right = underflow,
+                    (false, false) => match containing_block.direction {
+                        Direction::Ltr => margin_right += underflow,
+                        Direction::Rtl => margin_left += underflow,
                     },
-                    LayoutBox {
-                        box_type: BoxType::BlockBox(BoxProps {
-                            node_type: &node,
-                            properties: block.iter().cloned().collect(),
-                        }),
-                        children: vec![],
+                }
+                width
+            }
+            None => (cb_width
+                - margin_left
+                - margin_right
+                - border_left
+                - border_right
+                - padding_left
+                - padding_right)
+                .max(0.0),
+        };
+
+        let d = &mut self.dimensions;
+        d.content.width = width;
+        d.margin.left = margin_left;
+        d.margin.right = margin_right;
+        d.border.left = border_left;
+        d.border.right = border_right;
+        d.padding.left = padding_left;
+        d.padding.right = padding_right;
+    }
+
+    /// `calculate_block_position` places the box at the top-left corner of `containing_block`.
+    fn calculate_block_position(&mut self, containing_block: ContainingBlock) {
+        let cb_width = containing_block.width;
+        let margin_top = self.length("margin-top", cb_width).unwrap_or(0.0);
+        let margin_bottom = self.length("margin-bottom", cb_width).unwrap_or(0.0);
+        let border_top = self.length("border-top-width", cb_width).unwrap_or(0.0);
+        let border_bottom = self.length("border-bottom-width", cb_width).unwrap_or(0.0);
+        let padding_top = self.length("padding-top", cb_width).unwrap_or(0.0);
+        let padding_bottom = self.length("padding-bottom", cb_width).unwrap_or(0.0);
+
+        let d = &mut self.dimensions;
+        d.margin.top = margin_top;
+        d.margin.bottom = margin_bottom;
+        d.border.top = border_top;
+        d.border.bottom = border_bottom;
+        d.padding.top = padding_top;
+        d.padding.bottom = padding_bottom;
+
+        d.content.x = containing_block.x + d.margin.left + d.border.left + d.padding.left;
+        d.content.y = containing_block.y + d.margin.top + d.border.top + d.padding.top;
+    }
+
+    /// `definite_block_height` returns the height of the box if it does not depend on its content.
+    /// The explicit `height` wins over the one derived from `aspect-ratio`.
+    fn definite_block_height(&self, containing_block: ContainingBlock) -> Option<f32> {
+        self.replaced_size(containing_block)
+            .map(|(_, height)| height)
+            .or_else(|| self.vertical_length("height", containing_block))
+            .or_else(|| {
+                self.aspect_ratio()
+                    .map(|ratio| self.dimensions.content.width / ratio)
+            })
+            .map(|height| self.clamp_height(height, containing_block))
+    }
+
+    /// `layout_block_children` stacks the children from top to bottom.
+    /// Floated children are placed at the left or right edge without taking space in the stack,
+    /// and children with `clear` are moved below the preceding floats on the cleared sides.
+    /// See https://www.w3.org/TR/CSS2/visuren.html#floats
+    ///
+    /// NOTE: Floats are not shrunk to fit their contents, and floats on the same side overlap each other.
+    /// The other boxes do not flow around floats either, so a box without `clear` overlaps the preceding floats.
+    /// Only the floats among the siblings are cleared.
+    fn layout_block_children(
+        &mut self,
+        definite_height: Option<f32>,
+        direction: Direction,
+        metrics: &dyn FontMetrics,
+    ) {
+        let d = &mut self.dimensions;
+        d.content.height = 0.0;
+        let mut floats = FloatBottoms::default();
+        for child in &mut self.children {
+            let offset = match child.clear() {
+                Some(clear) => d.content.height.max(floats.bottom(clear)),
+                None => d.content.height,
+            };
+            child.layout_in(
+                ContainingBlock {
+                    x: d.content.x,
+                    y: d.content.y + offset,
+                    width: d.content.width,
+                    height: definite_height,
+                    direction,
+                    writing_mode: WritingMode::HorizontalTb,
+                },
+                metrics,
+            );
+            let child_box = child.dimensions.margin_box();
+            match child.float() {
+                Some(float) => {
+                    if float == Float::Right {
+                        let right = d.content.x + d.content.width;
+                        child.translate(right - (child_box.x + child_box.width), 0.0);
                     }
-                ],
+                    floats.add(float, offset + child_box.height);
+                }
+                None => d.content.height = offset + child_box.height,
+            }
+        }
+    }
+
+    fn float(&self) -> Option<Float> {
+        match self.value("float") {
+            Some(CSSValue::Keyword(keyword)) => Float::from_keyword(keyword),
+            _ => None,
+        }
+    }
+
+    fn clear(&self) -> Option<Clear> {
+        match self.value("clear") {
+            Some(CSSValue::Keyword(keyword)) => Clear::from_keyword(keyword),
+            _ => None,
+        }
+    }
+
+    fn writing_mode(&self) -> WritingMode {
+        match self.value("writing-mode") {
+            Some(CSSValue::Keyword(keyword)) => {
+                WritingMode::from_keyword(keyword).unwrap_or_default()
+            }
+            _ => WritingMode::default(),
+        }
+    }
+
+    /// `layout_vertical_block_children` stacks the children horizontally, i.e. from right to left in `vertical-rl`
+    /// and from left to right in `vertical-lr`. The box is as tall as its tallest child.
+    ///
+    /// The width is the block size in vertical flow, so a child with `width: auto` is as wide as its contents
+    /// instead of filling the box.
+    ///
+    /// NOTE: Only the block flow direction is swapped for simplicity;
+    /// the contents of the children are still laid out as in `horizontal-tb`.
+    /// See https://drafts.csswg.org/css-writing-modes/#vertical-layout
+    fn layout_vertical_block_children(
+        &mut self,
+        definite_height: Option<f32>,
+        direction: Direction,
+        metrics: &dyn FontMetrics,
+    ) {
+        let writing_mode = self.writing_mode();
+        let content = self.dimensions.content;
+        let mut block_size = 0.0;
+        let mut height: f32 = 0.0;
+        for child in &mut self.children {
+            let containing_block = ContainingBlock {
+                x: content.x,
+                y: content.y,
+                width: content.width,
+                height: definite_height,
+                direction,
+                writing_mode,
+            };
+            child.layout_in(containing_block, metrics);
+            if child.has_auto_width() {
+                // lay out the child again in the width its contents need
+                let d = child.dimensions;
+                let width = d.margin_box().width - d.content.width + child.contents_width();
+                child.layout_in(
+                    ContainingBlock {
+                        width,
+                        ..containing_block
+                    },
+                    metrics,
+                );
+            }
+            let margin_box = child.dimensions.margin_box();
+            let x = match writing_mode {
+                WritingMode::VerticalLr => content.x + block_size,
+                _ => content.x + content.width - block_size - margin_box.width,
+            };
+            child.translate(x - margin_box.x, 0.0);
+            block_size += margin_box.width;
+            height = height.max(margin_box.height);
+        }
+        self.dimensions.content.height = height;
+    }
+
+    /// `has_auto_width` tells whether the box is a non-replaced block-level box with `width: auto`,
+    /// i.e. it fills its containing block.
+    fn has_auto_width(&self) -> bool {
+        !matches!(self.box_type, BoxType::InlineBox(_))
+            && self.replaced_element().is_none()
+            && !matches!(self.value("width"), Some(CSSValue::Length(_)))
+    }
+
+    /// `contents_width` returns the width which the contents of the laid out box need,
+    /// where the children with `width: auto` count their own contents instead of the width they fill.
+    ///
+    /// NOTE: Lines are measured from the left edge of the box, so lines moved by `text-align` look wider.
+    fn contents_width(&self) -> f32 {
+        let content = self.dimensions.content;
+        let child_width = |child: &LayoutBox| {
+            let d = &child.dimensions;
+            match child.has_auto_width() {
+                true => d.margin_box().width - d.content.width + child.contents_width(),
+                false => d.margin_box().width,
             }
+        };
+        // the children in vertical flow are stacked side by side, and already sized by their contents
+        if self.writing_mode().is_vertical() {
+            return self.children.iter().map(child_width).sum();
+        }
+        let lines = self
+            .lines
+            .iter()
+            .map(|line| line.rect.x + line.rect.width - content.x);
+        let children = self
+            .children
+            .iter()
+            .map(|child| child.dimensions.margin_box().x - content.x + child_width(child));
+        lines.chain(children).fold(0.0, f32::max)
+    }
+
+    /// `translate` moves the box and its descendants by `(dx, dy)`.
+    fn translate(&mut self, dx: f32, dy: f32) {
+        self.dimensions.content.x += dx;
+        self.dimensions.content.y += dy;
+        for line in &mut self.lines {
+            line.rect.x += dx;
+            line.rect.y += dy;
+        }
+        for child in &mut self.children {
+            child.translate(dx, dy);
+        }
+    }
+
+    /// `mirror` flips the box horizontally between `left` and `right`.
+    /// The fragments of a broken text are flipped one by one, so that each of them stays on its line.
+    fn mirror(&mut self, left: f32, right: f32) {
+        if self.lines.is_empty() {
+            let margin_box = self.dimensions.margin_box();
+            self.translate(left + right - margin_box.width - 2.0 * margin_box.x, 0.0);
+            return;
+        }
+        let flip = |rect: &mut Rect| rect.x = left + right - rect.x - rect.width;
+        flip(&mut self.dimensions.content);
+        for line in &mut self.lines {
+            flip(&mut line.rect);
+        }
+        for child in &mut self.children {
+            flip(&mut child.dimensions.content);
+        }
+    }
+
+    fn is_grid_container(&self) -> bool {
+        self.value("display") == Some(&CSSValue::Keyword("grid".into()))
+    }
+
+    /// `layout_grid_children` places the children into the columns given by `grid-template-columns`
+    /// row by row. Each row is as tall as its tallest item.
+    ///
+    /// NOTE: This is a minimal subset of CSS Grid Layout; rows are always auto-sized,
+    /// and only fixed lengths and `fr` are supported as column sizes.
+    /// See https://drafts.csswg.org/css-grid/ for the full algorithm.
+    fn layout_grid_children(&mut self, direction: Direction, metrics: &dyn FontMetrics) {
+        let width = self.dimensions.content.width;
+        let font_size = self.font_size();
+        let (row_gap, column_gap) = match self.value("gap") {
+            Some(CSSValue::List(values)) if values.len() == 2 => (
+                to_px(&values[0], font_size, 0.0).unwrap_or(0.0),
+                to_px(&values[1], font_size, width).unwrap_or(0.0),
+            ),
+            Some(value) => (
+                to_px(value, font_size, 0.0).unwrap_or(0.0),
+                to_px(value, font_size, width).unwrap_or(0.0),
+            ),
+            None => (0.0, 0.0),
+        };
+        let row_gap = self.length("row-gap", 0.0).unwrap_or(row_gap);
+        let column_gap = self.length("column-gap", width).unwrap_or(column_gap);
+
+        let tracks = match self.value("grid-template-columns") {
+            Some(CSSValue::List(values)) => values.iter().collect::<Vec<_>>(),
+            Some(value @ CSSValue::Length(_)) => vec![value],
+            _ => vec![],
+        };
+        let column_widths = resolve_grid_tracks(&tracks, width, column_gap, font_size);
+
+        let d = &mut self.dimensions;
+        d.content.height = 0.0;
+        for (row_index, row) in self.children.chunks_mut(column_widths.len()).enumerate() {
+            if row_index > 0 {
+                d.content.height += row_gap;
+            }
+            let mut x = 0.0;
+            let mut row_height: f32 = 0.0;
+            for (item, column_width) in row.iter_mut().zip(column_widths.iter()) {
+                item.layout_in(
+                    ContainingBlock {
+                        x: d.content.x + x,
+                        y: d.content.y + d.content.height,
+                        width: *column_width,
+                        height: None,
+                        direction,
+                        writing_mode: WritingMode::HorizontalTb,
+                    },
+                    metrics,
+                );
+                x += column_width + column_gap;
+                row_height = row_height.max(item.dimensions.margin_box().height);
+            }
+            d.content.height += row_height;
+        }
+    }
+
+    /// `calculate_block_height` decides the height of the box.
+    /// The height of the children is used when the height is not definite.
+    fn calculate_block_height(
+        &mut self,
+        containing_block: ContainingBlock,
+        definite_height: Option<f32>,
+    ) {
+        self.dimensions.content.height = match definite_height {
+            Some(height) => height,
+            None => self.clamp_height(self.dimensions.content.height, containing_block),
+        };
+    }
+
+    /// `layout_inline` lays out an inline-level box at the current inline cursor,
+    /// which is the top-left corner of `containing_block`.
+    /// For simplicity, an inline box is sized as a single rectangle that shrinks to fit its children.
+    fn layout_inline(&mut self, containing_block: ContainingBlock, metrics: &dyn FontMetrics) {
+        let cb_width = containing_block.width;
+        let margin = EdgeSizes {
+            left: self.length("margin-left", cb_width).unwrap_or(0.0),
+            right: self.length("margin-right", cb_width).unwrap_or(0.0),
+            top: 0.0,
+            bottom: 0.0,
+        };
+        let border = EdgeSizes {
+            left: self.length("border-left-width", cb_width).unwrap_or(0.0),
+            right: self.length("border-right-width", cb_width).unwrap_or(0.0),
+            top: 0.0,
+            bottom: 0.0,
+        };
+        let padding = EdgeSizes {
+            left: self.length("padding-left", cb_width).unwrap_or(0.0),
+            right: self.length("padding-right", cb_width).unwrap_or(0.0),
+            top: 0.0,
+            bottom: 0.0,
+        };
+
+        let d = &mut self.dimensions;
+        d.margin = margin;
+        d.border = border;
+        d.padding = padding;
+        d.content.x = containing_block.x + margin.left + border.left + padding.left;
+        d.content.y = containing_block.y;
+        d.content.width = (cb_width
+            - margin.left
+            - border.left
+            - padding.left
+            - padding.right
+            - border.right
+            - margin.right)
+            .max(0.0);
+
+        if let Some((width, height)) = self.replaced_size(containing_block) {
+            self.dimensions.content.width = width;
+            self.dimensions.content.height = height;
+            return;
+        }
+        if let BoxType::InlineBox(BoxProps {
+            node_type: NodeType::Text(t),
+            ..
+        }) = self.box_type
+        {
+            let font_size = self.font_size();
+            let direction = self.direction(containing_block);
+            let content = &mut self.dimensions.content;
+            let mut rest = t.data.as_str();
+            let mut letter_width = 0.0;
+            content.height = font_size;
+            // the only child of a text box is the box of `::first-letter`
+            if let Some(letter) = self.children.first_mut() {
+                let (first, others) = rest.split_at(rest.chars().next().map_or(0, char::len_utf8));
+                let letter_font_size = letter.font_size();
+                letter_width = metrics.text_width(first, letter_font_size);
+                let others_width = metrics.text_width(others, font_size);
+                letter.dimensions.content = Rect {
+                    x: match direction {
+                        Direction::Ltr => content.x,
+                        Direction::Rtl => content.x + others_width,
+                    },
+                    y: content.y,
+                    width: letter_width,
+                    height: letter_font_size,
+                };
+                content.height = content.height.max(letter_font_size);
+                rest = others;
+            }
+            content.width = letter_width + metrics.text_width(rest, font_size);
+            return;
+        }
+
+        let children_width = if self.skips_contents() {
+            0.0
+        } else {
+            self.layout_inline_children(self.direction(containing_block), metrics)
+        };
+        self.dimensions.content.width = self.length("width", cb_width).unwrap_or(children_width);
+        if let Some(height) = self.vertical_length("height", containing_block) {
+            self.dimensions.content.height = height;
+        }
+    }
+
+    /// `layout_lines` lays out the inline-level children of an anonymous box, i.e. its inline formatting context,
+    /// into lines as wide as the box. A child which does not fit in the rest of the line is moved to the next line,
+    /// and a text is placed in the `lines` of the text box. Each line is as tall as its tallest child.
+    /// See https://drafts.csswg.org/css-inline/#inline-formatting-context
+    ///
+    /// NOTE: Only texts directly in the line are broken, i.e. inline elements like `<span>` are moved as a whole.
+    /// The children are aligned at the top of the line instead of the baseline.
+    fn layout_lines(&mut self, direction: Direction, metrics: &dyn FontMetrics) {
+        let content = self.dimensions.content;
+        let containing_block = |line: &LineCursor| ContainingBlock {
+            x: content.x + line.x,
+            y: content.y + line.y,
+            width: (content.width - line.x).max(0.0),
+            height: None,
+            direction,
+            writing_mode: WritingMode::HorizontalTb,
+        };
+        let mut line = LineCursor::default();
+        for child in &mut self.children {
+            if child.is_text() {
+                child.layout_text_lines(content, &mut line, metrics);
+                continue;
+            }
+            child.layout_in(containing_block(&line), metrics);
+            if line.x > 0.0 && line.x + child.dimensions.margin_box().width > content.width {
+                line.break_line();
+                child.layout_in(containing_block(&line), metrics);
+            }
+            let child_box = child.dimensions.margin_box();
+            line.x += child_box.width;
+            line.height = line.height.max(child_box.height);
+        }
+        self.dimensions.content.height = line.y + line.height;
+
+        // `text-align` defaults to `start`, which is the right end in `rtl`
+        // https://drafts.csswg.org/css-text/#text-align-property
+        if direction == Direction::Rtl {
+            for child in &mut self.children {
+                child.mirror(content.x, content.x + content.width);
+            }
+        }
+    }
+
+    /// `is_text` returns whether the box is the box of a text.
+    fn is_text(&self) -> bool {
+        matches!(
+            self.box_type,
+            BoxType::InlineBox(BoxProps {
+                node_type: NodeType::Text(_),
+                ..
+            })
+        )
+    }
+
+    /// `wraps` returns whether the text in the box may be broken into lines,
+    /// which is not the case with `white-space: nowrap` or `pre`.
+    /// See https://drafts.csswg.org/css-text/#white-space-property
+    fn wraps(&self) -> bool {
+        !matches!(
+            self.value("white-space"),
+            Some(CSSValue::Keyword(keyword)) if keyword == "nowrap" || keyword == "pre"
+        )
+    }
+
+    /// `layout_text_lines` lays out a text box at `line` in the content area `content` of its line container,
+    /// and moves `line` to the end of the text.
+    /// The text is placed in `lines`, one fragment for each line, where it is broken by `break_lines` if it wraps,
+    /// and `dimensions.content` becomes the bounding rect of the fragments.
+    /// The fragment on the first line of the container is measured in the `font-size` of `::first-line`.
+    fn layout_text_lines(
+        &mut self,
+        content: Rect,
+        line: &mut LineCursor,
+        metrics: &dyn FontMetrics,
+    ) {
+        let text = match self.box_type {
+            BoxType::InlineBox(BoxProps {
+                node_type: NodeType::Text(t),
+                ..
+            }) => t.data.as_str(),
+            _ => return,
+        };
+        let font_size = self.font_size();
+        let first_line_font_size = self.first_line_font_size();
+        let font_size_on = |first_line: bool| match first_line {
+            true => first_line_font_size,
+            false => font_size,
+        };
+        // the box of `::first-letter` stays at the beginning of the first line
+        let (start, letter_width, letter_height) = match self.children.first() {
+            Some(letter) => {
+                let start = text.chars().next().map_or(0, char::len_utf8);
+                let letter_font_size = letter.font_size();
+                (
+                    start,
+                    metrics.text_width(&text[..start], letter_font_size),
+                    letter_font_size,
+                )
+            }
+            None => (0, 0.0, 0.0),
+        };
+        let wraps = self.wraps();
+        let break_text = |line: &LineCursor| {
+            let measure = |text: &str, first: bool| {
+                metrics.text_width(text, font_size_on(first && line.index == 0))
+            };
+            let first_width = content.width - line.x - letter_width;
+            if wraps {
+                break_lines(
+                    text,
+                    start,
+                    first_width,
+                    content.width,
+                    line.x == 0.0,
+                    measure,
+                )
+            } else if line.x > 0.0 && measure(&text[start..], true) > first_width {
+                vec![start..start, start..text.len()]
+            } else {
+                iter::once(start..text.len()).collect()
+            }
+        };
+        let mut ranges = break_text(line);
+        if ranges.len() > 1 && ranges[0].is_empty() {
+            line.break_line();
+            ranges = break_text(line);
+        }
+
+        self.lines.clear();
+        let start_x = line.x;
+        for (index, range) in ranges.into_iter().enumerate() {
+            let x = match index {
+                0 => {
+                    line.height = line.height.max(letter_height);
+                    line.x + letter_width
+                }
+                _ => {
+                    line.break_line();
+                    0.0
+                }
+            };
+            let first_line = line.index == 0;
+            let height = font_size_on(first_line);
+            let width = metrics.text_width(&text[range.clone()], height);
+            self.lines.push(LineFragment {
+                range,
+                rect: Rect {
+                    x: content.x + x,
+                    y: content.y + line.y,
+                    width,
+                    height,
+                },
+                first_line,
+            });
+            line.x = x + width;
+            line.height = line.height.max(height);
+        }
+
+        let mut rects = self.lines.iter().map(|line| line.rect).collect::<Vec<_>>();
+        if let Some(letter) = self.children.first_mut() {
+            letter.dimensions.content = Rect {
+                x: content.x + start_x,
+                y: rects[0].y,
+                width: letter_width,
+                height: letter_height,
+            };
+            rects.push(letter.dimensions.content);
+        }
+        let (left, top, right, bottom) = rects.iter().fold(
+            (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+            |(left, top, right, bottom), rect| {
+                (
+                    left.min(rect.x),
+                    top.min(rect.y),
+                    right.max(rect.x + rect.width),
+                    bottom.max(rect.y + rect.height),
+                )
+            },
+        );
+        self.dimensions.content = Rect {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        };
+    }
+
+    /// `layout_inline_children` places inline-level children from left to right in a single line.
+    /// It returns the total width of the children.
+    fn layout_inline_children(&mut self, direction: Direction, metrics: &dyn FontMetrics) -> f32 {
+        let d = &mut self.dimensions;
+        d.content.height = 0.0;
+        let mut x = 0.0;
+        for child in &mut self.children {
+            child.layout_in(
+                ContainingBlock {
+                    x: d.content.x + x,
+                    y: d.content.y,
+                    width: (d.content.width - x).max(0.0),
+                    height: None,
+                    direction,
+                    writing_mode: WritingMode::HorizontalTb,
+                },
+                metrics,
+            );
+            let child_box = child.dimensions.margin_box();
+            x += child_box.width;
+            d.content.height = d.content.height.max(child_box.height);
+        }
+
+        // mirror the children in `rtl` so that the first one is at the right end,
+        // where the right end is that of the children, i.e. the shrink-to-fit width, not of the available width
+        if direction == Direction::Rtl {
+            for child in &mut self.children {
+                let child_box = child.dimensions.margin_box();
+                let mirrored_x = d.content.x + x - (child_box.x - d.content.x) - child_box.width;
+                child.translate(mirrored_x - child_box.x, 0.0);
+            }
+        }
+        x
+    }
+}
+
+/// `LineCursor` is the position where the next inline-level box is placed in an inline formatting context,
+/// relative to the top-left corner of the content area.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct LineCursor {
+    x: f32,
+    /// `y` is the top of the current line.
+    y: f32,
+    /// `height` is the height of the current line so far.
+    height: f32,
+    /// `index` is the number of the lines before the current one, i.e. 0 on the first line.
+    index: usize,
+}
+
+impl LineCursor {
+    /// `break_line` moves the cursor to the beginning of the next line.
+    fn break_line(&mut self) {
+        self.x = 0.0;
+        self.y += self.height;
+        self.height = 0.0;
+        self.index += 1;
+    }
+}
+
+/// `break_lines` breaks `text[start..]` greedily at white spaces into lines,
+/// where the first line is `first_width` wide and the others are `width` wide.
+/// It returns the byte range of each line, where the white spaces at the end are excluded except on the last line.
+/// A word wider than its line overflows it when nothing precedes the word on the line.
+/// `at_line_start` tells whether anything precedes the text on the first line,
+/// and the first range is empty when even the first word does not fit there.
+/// `measure` measures a text, which is on the first line if the second argument is true.
+/// See https://drafts.csswg.org/css-text/#line-breaking
+///
+/// NOTE: Lines are broken only after white spaces, i.e. neither between CJK characters nor after hyphens.
+fn break_lines(
+    text: &str,
+    start: usize,
+    first_width: f32,
+    width: f32,
+    at_line_start: bool,
+    measure: impl Fn(&str, bool) -> f32,
+) -> Vec<Range<usize>> {
+    // each segment is a word followed by white spaces, given as the start of the segment and the end of the word
+    let mut segments = vec![];
+    let mut segment_start = start;
+    let mut word_end = None;
+    for (index, c) in text[start..].char_indices() {
+        let index = start + index;
+        match word_end {
+            None if c.is_whitespace() => word_end = Some(index),
+            Some(end) if !c.is_whitespace() => {
+                segments.push((segment_start, end));
+                segment_start = index;
+                word_end = None;
+            }
+            _ => {}
+        }
+    }
+    segments.push((segment_start, word_end.unwrap_or(text.len())));
+
+    let mut lines = vec![];
+    let mut line_start = start;
+    let mut line_end = start;
+    let mut available = first_width;
+    let mut is_empty = at_line_start;
+    for (segment_start, word_end) in segments {
+        if !is_empty && measure(&text[line_start..word_end], lines.is_empty()) > available {
+            lines.push(line_start..line_end);
+            line_start = segment_start;
+            available = width;
+        }
+        line_end = word_end;
+        is_empty = false;
+    }
+    lines.push(line_start..text.len());
+    lines
+}
+
+/// `FloatBottoms` tracks the lowest bottom edges of the floats placed so far on each side,
+/// relative to the top of the content area of their container.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct FloatBottoms {
+    left: f32,
+    right: f32,
+}
+
+impl FloatBottoms {
+    fn add(&mut self, float: Float, bottom: f32) {
+        match float {
+            Float::Left => self.left = self.left.max(bottom),
+            Float::Right => self.right = self.right.max(bottom),
+        }
+    }
+
+    /// `bottom` returns the position which a box with `clear` is placed at or below.
+    fn bottom(&self, clear: Clear) -> f32 {
+        match clear {
+            Clear::Left => self.left,
+            Clear::Right => self.right,
+            Clear::Both => self.left.max(self.right),
+        }
+    }
+}
+
+/// `text_width` estimates the width of `text` in px.
+/// It counts characters rather than bytes, so multibyte characters like `é` or `日` are one character each.
+///
+/// NOTE: White spaces are not processed, and grapheme clusters consisting of multiple characters
+/// (e.g. `e` followed by a combining acute accent) are counted as multiple characters.
+pub fn text_width(text: &str) -> f32 {
+    text.chars().count() as f32 * CHAR_WIDTH
+}
+
+/// `BidiRun` is a maximal run of characters in the same direction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BidiRun<'t> {
+    pub text: &'t str,
+    pub direction: Direction,
+}
+
+/// `bidi_runs` splits `text` into directional runs and returns them in the visual order, i.e. from left to right,
+/// in a paragraph whose base direction is `direction`.
+/// Characters in a run are kept in the logical order; `rtl` runs are expected to be drawn from right to left.
+/// See https://www.unicode.org/reports/tr9/
+///
+/// NOTE: This is a small subset of the Unicode Bidirectional Algorithm.
+/// Characters are either strong (letters) or neutral (the others including digits), and neutrals between
+/// runs of the same direction take that direction, or the base direction otherwise.
+/// Embeddings and `unicode-bidi` are not supported.
+pub fn bidi_runs(text: &str, direction: Direction) -> Vec<BidiRun<'_>> {
+    let chars = text
+        .char_indices()
+        .map(|(i, c)| (i, strong_direction(c)))
+        .collect::<Vec<_>>();
+
+    // resolve each sequence of neutrals with the strong characters around it
+    // https://www.unicode.org/reports/tr9/#Resolving_Neutral_Types
+    let mut resolved = vec![direction; chars.len()];
+    let mut previous = direction;
+    let mut index = 0;
+    while index < chars.len() {
+        match chars[index].1 {
+            Some(d) => {
+                resolved[index] = d;
+                previous = d;
+                index += 1;
+            }
+            None => {
+                let end = chars[index..]
+                    .iter()
+                    .position(|(_, d)| d.is_some())
+                    .map_or(chars.len(), |p| index + p);
+                let next = chars.get(end).and_then(|(_, d)| *d).unwrap_or(direction);
+                resolved[index..end].fill(if previous == next { next } else { direction });
+                index = end;
+            }
+        }
+    }
+
+    let mut runs = vec![];
+    let mut start = 0;
+    for (index, d) in resolved.iter().enumerate() {
+        if resolved.get(index + 1) != Some(d) {
+            let end = chars.get(index + 1).map_or(text.len(), |(i, _)| *i);
+            runs.push(BidiRun {
+                text: &text[start..end],
+                direction: *d,
+            });
+            start = end;
+        }
+    }
+
+    // with only two embedding levels, reordering reverses the runs in `rtl` paragraphs
+    // https://www.unicode.org/reports/tr9/#L2
+    if direction == Direction::Rtl {
+        runs.reverse();
+    }
+    runs
+}
+
+/// `strong_direction` returns the direction of a strong character, or `None` for neutral ones.
+/// Hebrew, Arabic and the other right-to-left scripts are `rtl`, and the other letters are `ltr`.
+fn strong_direction(c: char) -> Option<Direction> {
+    match c as u32 {
+        0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF => Some(Direction::Rtl),
+        _ if c.is_alphabetic() => Some(Direction::Ltr),
+        _ => None,
+    }
+}
+
+/// `scaled_text_width` estimates the width of `text` in px like `text_width`, but in `font_size` px.
+pub fn scaled_text_width(text: &str, font_size: f32) -> f32 {
+    text_width(text) * font_size / DEFAULT_FONT_SIZE
+}
+
+/// `resolve_grid_tracks` computes the widths of grid columns.
+/// Fixed lengths are used as they are, and the rest of the space is distributed to `fr` tracks.
+/// When no track is given, there is a single column filling the grid container.
+/// `font_size` is the computed `font-size` of the grid container.
+fn resolve_grid_tracks(tracks: &[&CSSValue], width: f32, gap: f32, font_size: f32) -> Vec<f32> {
+    if tracks.is_empty() {
+        return vec![width];
+    }
+
+    let fixed_width: f32 = tracks
+        .iter()
+        .filter_map(|t| to_px(t, font_size, width))
+        .sum();
+    let total_fr: f32 = tracks
+        .iter()
+        .map(|t| match t {
+            CSSValue::Length((num, Unit::Fr)) => *num,
+            _ => 0.0,
+        })
+        .sum();
+    let free_space = (width - fixed_width - gap * (tracks.len() - 1) as f32).max(0.0);
+
+    tracks
+        .iter()
+        .map(|t| match t {
+            CSSValue::Length((num, Unit::Fr)) if total_fr > 0.0 => free_space * *num / total_fr,
+            _ => to_px(t, font_size, width).unwrap_or(0.0),
+        })
+        .collect()
+}
+
+/// `to_px` converts a length value into px, where `em` refers to `font_size` and `%` to `percentage_basis`.
+/// `rem` refers to `DEFAULT_FONT_SIZE`, which only matters for values not computed by styling.
+/// Flexible lengths (`fr`) are not converted since they depend on the available space.
+pub(crate) fn to_px(value: &CSSValue, font_size: f32, percentage_basis: f32) -> Option<f32> {
+    value.to_px(&LengthContext {
+        font_size,
+        root_font_size: DEFAULT_FONT_SIZE,
+        percentage_basis,
+    })
+}
+
+/// `test_helpers` builds the inputs shared by the tests of layout and paint.
+#[cfg(test)]
+pub(crate) mod test_helpers {
+    use std::sync::LazyLock;
+
+    use crate::dom::Element;
+
+    use super::*;
+
+    /// `DIV` is the `<div>` element which `div` returns.
+    static DIV: LazyLock<NodeType> = LazyLock::new(|| {
+        NodeType::Element(Element {
+            tag_name: "div".into(),
+            ..Default::default()
+        })
+    });
+
+    /// `div` returns a `<div>` element without attributes.
+    pub(crate) fn div() -> &'static NodeType {
+        &DIV
+    }
+
+    /// `viewport` returns the dimensions of a `width` x `height` viewport at the origin.
+    pub(crate) fn viewport(width: f32, height: f32) -> Dimensions {
+        Dimensions {
+            content: Rect {
+                x: 0.0,
+                y: 0.0,
+                width,
+                height,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// `block_node` returns a `<div>` with `display: block` and `properties`, which may override `display`.
+    pub(crate) fn block_node<'a>(
+        properties: &[(&str, CSSValue)],
+        children: Vec<StyledNode<'a>>,
+    ) -> StyledNode<'a> {
+        StyledNode {
+            node_type: div(),
+            properties: [("display", CSSValue::Keyword("block".into()))]
+                .iter()
+                .chain(properties)
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            children,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        css,
+        dom::{Element, Text},
+        html,
+        style::to_styled_node,
+    };
+
+    use super::{
+        test_helpers::{block_node, div, viewport},
+        *,
+    };
+
+    #[test]
+    fn test_to_layout_box() {
+        let block = [("display".to_string(), CSSValue::Keyword("block".into()))];
+        let inline = [("display".to_string(), CSSValue::Keyword("inline".into()))];
+        let snode = block_node(
+            &[],
+            vec![
+                block_node(&[], vec![]),
+                StyledNode {
+                    node_type: div(),
+                    properties: inline.iter().cloned().collect(),
+                    children: vec![block_node(&[], vec![]), block_node(&[], vec![])],
+                },
+                StyledNode {
+                    node_type: div(),
+                    properties: inline.iter().cloned().collect(),
+                    children: vec![],
+                },
+                block_node(&[], vec![]),
+            ],
+        );
+
+        assert_eq!(
+            to_layout_box(snode),
+            LayoutBox {
+                box_type: BoxType::BlockBox(BoxProps {
+                    node_type: div(),
+                    properties: block.iter().cloned().collect(),
+                }),
+                dimensions: Dimensions::default(),
+                children: vec![
+                    LayoutBox {
+                        box_type: BoxType::BlockBox(BoxProps {
+                            node_type: div(),
+                            properties: block.iter().cloned().collect(),
+                        }),
+                        dimensions: Dimensions::default(),
+                        children: vec![],
+                        lines: vec![],
+                    },
+                    LayoutBox {
+                        box_type: BoxType::AnonymousBox,
+                        dimensions: Dimensions::default(),
+                        children: vec![
+                            LayoutBox {
+                                box_type: BoxType::InlineBox(BoxProps {
+                                    node_type: div(),
+                                    properties: inline.iter().cloned().collect(),
+                                }),
+                                dimensions: Dimensions::default(),
+                                children: vec![
+                                    LayoutBox {
+                                        box_type: BoxType::BlockBox(BoxProps {
+                                            node_type: div(),
+                                            properties: block.iter().cloned().collect(),
+                                        }),
+                                        dimensions: Dimensions::default(),
+                                        children: vec![],
+                                        lines: vec![],
+                                    },
+                                    LayoutBox {
+                                        box_type: BoxType::BlockBox(BoxProps {
+                                            node_type: div(),
+                                            properties: block.iter().cloned().collect(),
+                                        }),
+                                        dimensions: Dimensions::default(),
+                                        children: vec![],
+                                        lines: vec![],
+                                    }
+                                ],
+                                lines: vec![],
+                            },
+                            LayoutBox {
+                                box_type: BoxType::InlineBox(BoxProps {
+                                    node_type: div(),
+                                    properties: inline.iter().cloned().collect(),
+                                }),
+                                dimensions: Dimensions::default(),
+                                children: vec![],
+                                lines: vec![],
+                            }
+                        ],
+                        lines: vec![],
+                    },
+                    LayoutBox {
+                        box_type: BoxType::BlockBox(BoxProps {
+                            node_type: div(),
+                            properties: block.iter().cloned().collect(),
+                        }),
+                        dimensions: Dimensions::default(),
+                        children: vec![],
+                        lines: vec![],
+                    }
+                ],
+                lines: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn test_layout_aspect_ratio() {
+        let viewport = viewport(800.0, 600.0);
+
+        {
+            // div { display: block; width: 320px; aspect-ratio: 16 / 9; }
+            let snode = block_node(
+                &[
+                    ("width", CSSValue::Length((320.0, Unit::Px))),
+                    ("aspect-ratio", CSSValue::Ratio((16, 9))),
+                ],
+                vec![],
+            );
+            let mut layout = to_layout_box(snode);
+            layout.layout(viewport);
+            assert_eq!(layout.dimensions.content.width, 320.0);
+            assert_eq!(layout.dimensions.content.height, 180.0);
+        }
+
+        {
+            // div { display: block; height: 90px; aspect-ratio: 16 / 9; }
+            let snode = block_node(
+                &[
+                    ("height", CSSValue::Length((90.0, Unit::Px))),
+                    ("aspect-ratio", CSSValue::Ratio((16, 9))),
+                ],
+                vec![],
+            );
+            let mut layout = to_layout_box(snode);
+            layout.layout(viewport);
+            assert_eq!(layout.dimensions.content.width, 160.0);
+            assert_eq!(layout.dimensions.content.height, 90.0);
+        }
+
+        {
+            // div { display: block; aspect-ratio: 16 / 9; }
+            let snode = block_node(&[("aspect-ratio", CSSValue::Ratio((16, 9)))], vec![]);
+            let mut layout = to_layout_box(snode);
+            layout.layout(viewport);
+            assert_eq!(layout.dimensions.content.width, 800.0);
+            assert_eq!(layout.dimensions.content.height, 450.0);
+        }
+    }
+
+    #[test]
+    fn test_layout_min_max_height() {
+        let viewport = viewport(800.0, 600.0);
+        let block_with_content = |properties: &[(&str, CSSValue)]| {
+            block_node(
+                properties,
+                vec![block_node(
+                    &[("height", CSSValue::Length((50.0, Unit::Px)))],
+                    vec![],
+                )],
+            )
+        };
+
+        // div { display: block; min-height: 100px; }
+        let mut layout = to_layout_box(block_with_content(&[(
+            "min-height",
+            CSSValue::Length((100.0, Unit::Px)),
+        )]));
+        layout.layout(viewport);
+        assert_eq!(layout.dimensions.content.height, 100.0);
+
+        // div { display: block; max-height: 30px; }
+        let mut layout = to_layout_box(block_with_content(&[(
+            "max-height",
+            CSSValue::Length((30.0, Unit::Px)),
+        )]));
+        layout.layout(viewport);
+        assert_eq!(layout.dimensions.content.height, 30.0);
+
+        // div { display: block; height: 200px; min-height: 10px; max-height: 50%; }
+        let mut layout = to_layout_box(block_with_content(&[
+            ("height", CSSValue::Length((200.0, Unit::Px))),
+            ("min-height", CSSValue::Length((10.0, Unit::Px))),
+            ("max-height", CSSValue::Length((50.0, Unit::Percent))),
+        ]));
+        layout.layout(viewport);
+        assert_eq!(layout.dimensions.content.height, 200.0);
+
+        // the containing block of the inner div has an indefinite height,
+        // so its `min-height: 50%` is ignored.
+        let mut layout = to_layout_box(block_node(
+            &[],
+            vec![block_with_content(&[(
+                "min-height",
+                CSSValue::Length((50.0, Unit::Percent)),
+            )])],
+        ));
+        layout.layout(viewport);
+        assert_eq!(layout.children[0].dimensions.content.height, 50.0);
+
+        // the containing block of the inner div has a definite height, so `min-height: 50%` is 100px.
+        let mut layout = to_layout_box(block_node(
+            &[("height", CSSValue::Length((200.0, Unit::Px)))],
+            vec![block_with_content(&[(
+                "min-height",
+                CSSValue::Length((50.0, Unit::Percent)),
+            )])],
+        ));
+        layout.layout(viewport);
+        assert_eq!(layout.children[0].dimensions.content.height, 100.0);
+    }
+
+    #[test]
+    fn test_layout_grid() {
+        let viewport = viewport(800.0, 600.0);
+        let item = |height: f32| StyledNode {
+            node_type: div(),
+            properties: [
+                ("display".to_string(), CSSValue::Keyword("inline".into())),
+                ("height".to_string(), CSSValue::Length((height, Unit::Px))),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            children: vec![],
+        };
+        let grid = |properties: Vec<(String, CSSValue)>| StyledNode {
+            node_type: div(),
+            properties: properties.into_iter().collect(),
+            children: vec![item(50.0), item(30.0), item(20.0), item(40.0)],
+        };
+        let rects = |layout: &LayoutBox| {
+            layout
+                .children
+                .iter()
+                .map(|child| {
+                    let r = child.dimensions.content;
+                    (r.x, r.y, r.width, r.height)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // div { display: grid; grid-template-columns: 1fr 1fr; }
+        let mut layout = to_layout_box(grid(vec![
+            ("display".to_string(), CSSValue::Keyword("grid".into())),
+            (
+                "grid-template-columns".to_string(),
+                CSSValue::List(vec![
+                    CSSValue::Length((1.0, Unit::Fr)),
+                    CSSValue::Length((1.0, Unit::Fr)),
+                ]),
+            ),
+        ]));
+        layout.layout(viewport);
+        assert_eq!(
+            rects(&layout),
+            vec![
+                (0.0, 0.0, 400.0, 50.0),
+                (400.0, 0.0, 400.0, 30.0),
+                (0.0, 50.0, 400.0, 20.0),
+                (400.0, 50.0, 400.0, 40.0),
+            ]
+        );
+        assert_eq!(layout.dimensions.content.height, 90.0);
+
+        // div { display: grid; grid-template-columns: 100px 1fr; gap: 10px; }
+        let mut layout = to_layout_box(grid(vec![
+            ("display".to_string(), CSSValue::Keyword("grid".into())),
+            (
+                "grid-template-columns".to_string(),
+                CSSValue::List(vec![
+                    CSSValue::Length((100.0, Unit::Px)),
+                    CSSValue::Length((1.0, Unit::Fr)),
+                ]),
+            ),
+            ("gap".to_string(), CSSValue::Length((10.0, Unit::Px))),
+        ]));
+        layout.layout(viewport);
+        assert_eq!(
+            rects(&layout),
+            vec![
+                (0.0, 0.0, 100.0, 50.0),
+                (110.0, 0.0, 690.0, 30.0),
+                (0.0, 60.0, 100.0, 20.0),
+                (110.0, 60.0, 690.0, 40.0),
+            ]
+        );
+        assert_eq!(layout.dimensions.content.height, 100.0);
+    }
+
+    #[test]
+    fn test_layout_replaced_element() {
+        let img = NodeType::Element(Element {
+            tag_name: "img".into(),
+            attributes: [
+                ("width".to_string(), "100".to_string()),
+                ("height".to_string(), "50".to_string()),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            ..Default::default()
+        });
+        let viewport = viewport(800.0, 600.0);
+        let page = |img_properties: Vec<(String, CSSValue)>| {
+            block_node(
+                &[],
+                vec![StyledNode {
+                    node_type: &img,
+                    properties: img_properties.into_iter().collect(),
+                    children: vec![],
+                }],
+            )
+        };
+
+        // <div><img width="100" height="50"></div>
+        let mut layout = to_layout_box(page(vec![(
+            "display".to_string(),
+            CSSValue::Keyword("inline".into()),
+        )]));
+        layout.layout(viewport);
+        let img_box = &layout.children[0].children[0];
+        assert_eq!(img_box.dimensions.content.width, 100.0);
+        assert_eq!(img_box.dimensions.content.height, 50.0);
+        assert_eq!(layout.dimensions.content.height, 50.0);
+
+        // img { width: 200px; }
+        let mut layout = to_layout_box(page(vec![
+            ("display".to_string(), CSSValue::Keyword("inline".into())),
+            ("width".to_string(), CSSValue::Length((200.0, Unit::Px))),
+        ]));
+        layout.layout(viewport);
+        let img_box = &layout.children[0].children[0];
+        assert_eq!(img_box.dimensions.content.width, 200.0);
+        assert_eq!(img_box.dimensions.content.height, 100.0);
+
+        // img { display: block; }
+        let mut layout = to_layout_box(page(vec![(
+            "display".to_string(),
+            CSSValue::Keyword("block".into()),
+        )]));
+        layout.layout(viewport);
+        let img_box = &layout.children[0];
+        assert_eq!(img_box.dimensions.content.width, 100.0);
+        assert_eq!(img_box.dimensions.content.height, 50.0);
+
+        // the same document parsed from HTML, where `<img>` has no close tag
+        let root = html::parse(r#"<body><img width="100" height="50"></body>"#);
+        let stylesheet = css::parse("body { display: block; } img { display: inline; }");
+        let mut layout = to_layout_box(to_styled_node(&root, &stylesheet).unwrap());
+        layout.layout(viewport);
+        let img_box = &layout.children[0].children[0];
+        assert_eq!(img_box.dimensions.content.width, 100.0);
+        assert_eq!(img_box.dimensions.content.height, 50.0);
+    }
+
+    #[test]
+    fn test_to_layout_box_anonymous_block() {
+        let block = [("display".to_string(), CSSValue::Keyword("block".into()))];
+        let inline = [("display".to_string(), CSSValue::Keyword("inline".into()))];
+        let p = NodeType::Element(Element {
+            tag_name: "p".into(),
+            attributes: [].iter().cloned().collect(),
+            ..Default::default()
+        });
+        let text = NodeType::Text(Text {
+            data: "text".into(),
+        });
+
+        // <div>text<p></p>text text</div>
+        let snode = block_node(
+            &[],
+            vec![
+                StyledNode {
+                    node_type: &text,
+                    properties: inline.iter().cloned().collect(),
+                    children: vec![],
+                },
+                StyledNode {
+                    node_type: &p,
+                    properties: block.iter().cloned().collect(),
+                    children: vec![],
+                },
+                StyledNode {
+                    node_type: &text,
+                    properties: inline.iter().cloned().collect(),
+                    children: vec![],
+                },
+                StyledNode {
+                    node_type: &text,
+                    properties: inline.iter().cloned().collect(),
+                    children: vec![],
+                },
+            ],
+        );
+
+        let text_box = || LayoutBox {
+            box_type: BoxType::InlineBox(BoxProps {
+                node_type: &text,
+                properties: inline.iter().cloned().collect(),
+            }),
+            dimensions: Dimensions::default(),
+            children: vec![],
+            lines: vec![],
+        };
+        assert_eq!(
+            to_layout_box(snode),
+            LayoutBox {
+                box_type: BoxType::BlockBox(BoxProps {
+                    node_type: div(),
+                    properties: block.iter().cloned().collect(),
+                }),
+                dimensions: Dimensions::default(),
+                children: vec![
+                    LayoutBox {
+                        box_type: BoxType::AnonymousBox,
+                        dimensions: Dimensions::default(),
+                        children: vec![text_box()],
+                        lines: vec![],
+                    },
+                    LayoutBox {
+                        box_type: BoxType::BlockBox(BoxProps {
+                            node_type: &p,
+                            properties: block.iter().cloned().collect(),
+                        }),
+                        dimensions: Dimensions::default(),
+                        children: vec![],
+                        lines: vec![],
+                    },
+                    LayoutBox {
+                        box_type: BoxType::AnonymousBox,
+                        dimensions: Dimensions::default(),
+                        children: vec![text_box(), text_box()],
+                        lines: vec![],
+                    },
+                ],
+                lines: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn test_layout_inline_elements() {
+        let stylesheet =
+            css::parse("div { display: block; width: 200px; } span, b { display: inline; }");
+        let content = |layout: &LayoutBox| {
+            let r = layout.dimensions.content;
+            (r.x, r.y, r.width, r.height)
+        };
+
+        // <div><span>ab</span><span>cd</span></div>
+        // the texts in inline elements are not wrapped with anonymous boxes, so the elements shrink to fit them
+        let root = html::parse("<div><span>ab</span><span>cd</span></div>");
+        let mut layout = to_layout_box(to_styled_node(&root, &stylesheet).unwrap());
+        layout.layout(viewport(800.0, 600.0));
+        let line = &layout.children[0];
+        let spans = &line.children;
+        assert_eq!(content(&spans[0]), (0.0, 0.0, 16.0, 16.0));
+        assert_eq!(content(&spans[1]), (16.0, 0.0, 16.0, 16.0));
+        assert!(matches!(
+            spans[1].children[0].box_type,
+            BoxType::InlineBox(BoxProps {
+                node_type: NodeType::Text(_),
+                ..
+            })
+        ));
+        assert_eq!(content(&spans[1].children[0]), (16.0, 0.0, 16.0, 16.0));
+
+        // <div>ab<b>cd</b>ef</div>
+        // the text after the element follows it on the same line
+        let root = html::parse("<div>ab<b>cd</b>ef</div>");
+        let mut layout = to_layout_box(to_styled_node(&root, &stylesheet).unwrap());
+        layout.layout(viewport(800.0, 600.0));
+        let line = &layout.children[0];
+        assert_eq!(content(&line.children[1]), (16.0, 0.0, 16.0, 16.0));
+        assert_eq!(content(&line.children[2]), (32.0, 0.0, 16.0, 16.0));
+        assert_eq!(layout.dimensions.content.height, 16.0);
+    }
+
+    #[test]
+    fn test_layout_text() {
+        let inline = [("display".to_string(), CSSValue::Keyword("inline".into()))];
+        let latin = NodeType::Text(Text {
+            data: "café".into(),
+        });
+        let cjk = NodeType::Text(Text {
+            data: "日本語".into(),
+        });
+        let viewport = viewport(800.0, 600.0);
+
+        // <div>café日本語</div>
+        let mut layout = to_layout_box(block_node(
+            &[],
+            vec![
+                StyledNode {
+                    node_type: &latin,
+                    properties: inline.iter().cloned().collect(),
+                    children: vec![],
+                },
+                StyledNode {
+                    node_type: &cjk,
+                    properties: inline.iter().cloned().collect(),
+                    children: vec![],
+                },
+            ],
+        ));
+        layout.layout(viewport);
+
+        let line = &layout.children[0];
+        assert_eq!(line.children[0].dimensions.content.x, 0.0);
+        assert_eq!(line.children[0].dimensions.content.width, 4.0 * CHAR_WIDTH);
+        assert_eq!(line.children[1].dimensions.content.x, 4.0 * CHAR_WIDTH);
+        assert_eq!(line.children[1].dimensions.content.width, 3.0 * CHAR_WIDTH);
+        assert_eq!(line.dimensions.content.height, DEFAULT_FONT_SIZE);
+    }
+
+    #[test]
+    fn test_break_lines() {
+        let measure = |text: &str, _first: bool| text.chars().count() as f32;
+        assert_eq!(
+            break_lines("hello world foo", 0, 11.0, 11.0, true, measure),
+            vec![0..11, 12..15]
+        );
+        assert_eq!(
+            break_lines("hello world ", 0, 5.0, 5.0, true, measure),
+            vec![0..5, 6..12]
+        );
+        // the first word does not fit after the preceding content
+        assert_eq!(
+            break_lines("hello world", 0, 3.0, 11.0, false, measure),
+            vec![0..0, 0..11]
+        );
+        // a long word overflows the line
+        assert_eq!(
+            break_lines("abcdefgh ij", 0, 5.0, 5.0, true, measure),
+            vec![0..8, 9..11]
+        );
+        // the first letter is measured separately
+        assert_eq!(
+            break_lines("Xab cd", 1, 3.0, 3.0, true, measure),
+            vec![1..3, 4..6]
+        );
+        // the first line is measured apart, e.g. in the font size of `::first-line`
+        let measure_first_line = |text: &str, first: bool| match first {
+            true => 2.0 * measure(text, first),
+            false => measure(text, first),
+        };
+        assert_eq!(
+            break_lines("ab cd ef", 0, 5.0, 5.0, true, measure),
+            vec![0..5, 6..8]
+        );
+        assert_eq!(
+            break_lines("ab cd ef", 0, 5.0, 5.0, true, measure_first_line),
+            vec![0..2, 3..8]
+        );
+    }
+
+    #[test]
+    fn test_layout_line_breaking() {
+        struct TenPx;
+        impl FontMetrics for TenPx {
+            fn char_width(&self, _c: char, _font_size: f32) -> f32 {
+                10.0
+            }
+        }
+
+        let text = NodeType::Text(Text {
+            data: "hello world foo".into(),
+        });
+        let viewport = viewport(100.0, 600.0);
+        let keyword = |name: &str, value: &str| {
+            (
+                name.to_string(),
+                CSSValue::Keyword(value.to_string().into()),
+            )
+        };
+        let container = |properties: &[(String, CSSValue)]| StyledNode {
+            node_type: div(),
+            properties: [keyword("display", "block")]
+                .iter()
+                .chain(properties)
+                .cloned()
+                .collect(),
+            children: vec![
+                StyledNode {
+                    node_type: &text,
+                    properties: [keyword("display", "inline")]
+                        .iter()
+                        .chain(properties)
+                        .cloned()
+                        .collect(),
+                    children: vec![],
+                },
+                StyledNode {
+                    node_type: div(),
+                    properties: [
+                        keyword("display", "inline"),
+                        ("width".to_string(), CSSValue::Length((20.0, Unit::Px))),
+                        ("height".to_string(), CSSValue::Length((10.0, Unit::Px))),
+                    ]
+                    .into_iter()
+                    .collect(),
+                    children: vec![],
+                },
+            ],
+        };
+        let rect = |x: f32, y: f32, width: f32, height: f32| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+
+        // "hello world" is wider than the line, and the box after the text does not fit in the rest of the line
+        let mut layout = to_layout_box(container(&[]));
+        layout.layout_with_metrics(viewport, &TenPx);
+        let line = &layout.children[0];
+        assert_eq!(
+            line.children[0].lines,
+            vec![
+                LineFragment {
+                    range: 0..5,
+                    rect: rect(0.0, 0.0, 50.0, 16.0),
+                    first_line: true,
+                },
+                LineFragment {
+                    range: 6..15,
+                    rect: rect(0.0, 16.0, 90.0, 16.0),
+                    first_line: false,
+                },
+            ]
+        );
+        assert_eq!(
+            line.children[0].dimensions.content,
+            rect(0.0, 0.0, 90.0, 32.0)
+        );
+        assert_eq!(
+            line.children[1].dimensions.content,
+            rect(0.0, 32.0, 20.0, 10.0)
+        );
+        assert_eq!(layout.dimensions.content.height, 42.0);
+
+        // each line starts at the right end in `rtl`
+        let mut layout = to_layout_box(container(&[keyword("direction", "rtl")]));
+        layout.layout_with_metrics(viewport, &TenPx);
+        let line = &layout.children[0];
+        let xs = line.children[0]
+            .lines
+            .iter()
+            .map(|line| line.rect.x)
+            .collect::<Vec<_>>();
+        assert_eq!(xs, vec![50.0, 10.0]);
+        assert_eq!(line.children[1].dimensions.content.x, 80.0);
+
+        // texts are not broken with `white-space: nowrap`
+        let mut layout = to_layout_box(container(&[keyword("white-space", "nowrap")]));
+        layout.layout_with_metrics(viewport, &TenPx);
+        let line = &layout.children[0];
+        assert_eq!(
+            line.children[0].lines,
+            vec![LineFragment {
+                range: 0..15,
+                rect: rect(0.0, 0.0, 150.0, 16.0),
+                first_line: true,
+            }]
+        );
+        assert_eq!(
+            line.children[0].dimensions.content,
+            rect(0.0, 0.0, 150.0, 16.0)
+        );
+        assert_eq!(
+            line.children[1].dimensions.content,
+            rect(0.0, 16.0, 20.0, 10.0)
+        );
+    }
+
+    #[test]
+    fn test_layout_line_breaking_inline_elements() {
+        let stylesheet = css::parse("p { display: block; } b { display: inline; }");
+        let layout = |root, width: f32| {
+            let mut layout = to_layout_box(to_styled_node(root, &stylesheet).unwrap());
+            layout.layout(viewport(width, 600.0));
+            layout
+        };
+
+        // <p>a <b>b</b> c</p>
+        // the inline element shrinks to fit its text, and "c" follows it on the same line
+        let root = html::parse("<p>a <b>b</b> c</p>");
+        let p = layout(&root, 800.0);
+        let line = &p.children[0];
+        assert_eq!(line.children[1].dimensions.content.x, 16.0);
+        assert_eq!(line.children[1].dimensions.content.width, 8.0);
+        assert_eq!(
+            line.children[2].lines,
+            vec![LineFragment {
+                range: 0..1,
+                rect: Rect {
+                    x: 24.0,
+                    y: 0.0,
+                    width: 8.0,
+                    height: 16.0,
+                },
+                first_line: true,
+            }]
+        );
+        assert_eq!(p.dimensions.content.height, 16.0);
+
+        // the inline element is moved to the next line as a whole when it does not fit
+        let root = html::parse("<p>a <b>bbb</b></p>");
+        let p = layout(&root, 32.0);
+        let line = &p.children[0];
+        assert_eq!(line.children[1].dimensions.content.x, 0.0);
+        assert_eq!(line.children[1].dimensions.content.y, 16.0);
+        assert_eq!(p.dimensions.content.height, 32.0);
+    }
+
+    #[test]
+    fn test_to_layout_box_every_display() {
+        let root = html::parse("<div>hi<p>hello</p><span>world</span></div>");
+        for display in [
+            Display::Inline,
+            Display::Block,
+            Display::Grid,
+            Display::ListItem,
+            Display::Table,
+            Display::Flex,
+            Display::InlineBlock,
+            Display::TableColumn,
+            Display::TableColumnGroup,
+            Display::None,
+        ] {
+            let keyword = display.to_keyword();
+            for css in [
+                format!("div {{ display: {}; }}", keyword),
+                format!(
+                    "div {{ display: block; }} p, span {{ display: {}; }}",
+                    keyword
+                ),
+            ] {
+                let stylesheet = css::parse(&css);
+                if let Some(styled) = to_styled_node(&root, &stylesheet) {
+                    let mut layout = to_layout_box(styled);
+                    layout.layout(Dimensions::default());
+                }
+            }
+        }
+
+        // `table-column` generates no box for the element and its descendants
+        let stylesheet = css::parse("div { display: block; } p { display: table-column; }");
+        let layout = to_layout_box(to_styled_node(&root, &stylesheet).unwrap());
+        assert_eq!(layout.children.len(), 1);
+        assert_eq!(layout.children[0].box_type, BoxType::AnonymousBox);
+        assert_eq!(layout.children[0].children.len(), 2);
+    }
+
+    #[test]
+    fn test_to_layout_box_table_column() {
+        let block = [("display".to_string(), CSSValue::Keyword("block".into()))];
+        let column = [(
+            "display".to_string(),
+            CSSValue::Keyword("table-column".into()),
+        )];
+        let table = NodeType::Element(Element {
+            tag_name: "table".into(),
+            attributes: [].iter().cloned().collect(),
+            ..Default::default()
+        });
+        let col = NodeType::Element(Element {
+            tag_name: "col".into(),
+            attributes: [].iter().cloned().collect(),
+            ..Default::default()
+        });
+        let tbody = NodeType::Element(Element {
+            tag_name: "tbody".into(),
+            attributes: [].iter().cloned().collect(),
+            ..Default::default()
+        });
+
+        // <table><col><tbody></tbody></table>
+        let layout = to_layout_box(StyledNode {
+            node_type: &table,
+            properties: block.iter().cloned().collect(),
+            children: vec![
+                StyledNode {
+                    node_type: &col,
+                    properties: column.iter().cloned().collect(),
+                    children: vec![],
+                },
+                StyledNode {
+                    node_type: &tbody,
+                    properties: block.iter().cloned().collect(),
+                    children: vec![],
+                },
+            ],
+        });
+
+        assert_eq!(layout.children.len(), 1);
+        assert!(matches!(
+            layout.children[0].box_type,
+            BoxType::BlockBox(BoxProps {
+                node_type: NodeType::Element(Element { ref tag_name, .. }),
+                ..
+            }) if tag_name == "tbody"
+        ));
+    }
+
+    #[test]
+    fn test_debug_dump() {
+        let body = NodeType::Element(Element {
+            tag_name: "body".into(),
+            attributes: [].iter().cloned().collect(),
+            ..Default::default()
+        });
+        let p = NodeType::Element(Element {
+            tag_name: "p".into(),
+            attributes: [].iter().cloned().collect(),
+            ..Default::default()
+        });
+        let viewport = viewport(800.0, 600.0);
+
+        // <body style="margin: 8px"><p style="height: 20px; padding: 2px; border-width: 1px"></p></body>
+        let mut layout = to_layout_box(StyledNode {
+            node_type: &body,
+            properties: [
+                ("display".to_string(), CSSValue::Keyword("block".into())),
+                ("margin-top".to_string(), CSSValue::Length((8.0, Unit::Px))),
+                (
+                    "margin-bottom".to_string(),
+                    CSSValue::Length((8.0, Unit::Px)),
+                ),
+                ("margin-left".to_string(), CSSValue::Length((8.0, Unit::Px))),
+                (
+                    "margin-right".to_string(),
+                    CSSValue::Length((8.0, Unit::Px)),
+                ),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            children: vec![StyledNode {
+                node_type: &p,
+                properties: [
+                    ("display".to_string(), CSSValue::Keyword("block".into())),
+                    ("height".to_string(), CSSValue::Length((20.0, Unit::Px))),
+                    ("padding-top".to_string(), CSSValue::Length((2.0, Unit::Px))),
+                    (
+                        "padding-bottom".to_string(),
+                        CSSValue::Length((2.0, Unit::Px)),
+                    ),
+                    (
+                        "padding-left".to_string(),
+                        CSSValue::Length((2.0, Unit::Px)),
+                    ),
+                    (
+                        "padding-right".to_string(),
+                        CSSValue::Length((2.0, Unit::Px)),
+                    ),
+                    (
+                        "border-top-width".to_string(),
+                        CSSValue::Length((1.0, Unit::Px)),
+                    ),
+                    (
+                        "border-bottom-width".to_string(),
+                        CSSValue::Length((1.0, Unit::Px)),
+                    ),
+                    (
+                        "border-left-width".to_string(),
+                        CSSValue::Length((1.0, Unit::Px)),
+                    ),
+                    (
+                        "border-right-width".to_string(),
+                        CSSValue::Length((1.0, Unit::Px)),
+                    ),
+                ]
+                .iter()
+                .cloned()
+                .collect(),
+                children: vec![],
+            }],
+        });
+        layout.layout(viewport);
+
+        assert_eq!(
+            layout.debug_dump(),
+            [
+                "body [block]",
+                "  content: x=8 y=8 w=784 h=26",
+                "  padding: x=8 y=8 w=784 h=26",
+                "  border: x=8 y=8 w=784 h=26",
+                "  margin: x=0 y=0 w=800 h=42",
+                "  p [block]",
+                "    content: x=11 y=11 w=778 h=20",
+                "    padding: x=9 y=9 w=782 h=24",
+                "    border: x=8 y=8 w=784 h=26",
+                "    margin: x=8 y=8 w=784 h=26",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_layout_vertical_writing_mode() {
+        let viewport = viewport(800.0, 600.0);
+        let child = |width: f32, height: f32| {
+            block_node(
+                &[
+                    ("width", CSSValue::Length((width, Unit::Px))),
+                    ("height", CSSValue::Length((height, Unit::Px))),
+                ],
+                vec![],
+            )
+        };
+        let container = |writing_mode: &str| {
+            block_node(
+                &[(
+                    "writing-mode",
+                    CSSValue::Keyword(writing_mode.to_string().into()),
+                )],
+                vec![child(100.0, 50.0), child(200.0, 80.0)],
+            )
+        };
+
+        let mut layout = to_layout_box(container("vertical-rl"));
+        layout.layout(viewport);
+        assert_eq!(layout.children[0].dimensions.content.x, 700.0);
+        assert_eq!(layout.children[1].dimensions.content.x, 500.0);
+        assert_eq!(layout.children[0].dimensions.content.y, 0.0);
+        assert_eq!(layout.children[1].dimensions.content.y, 0.0);
+        assert_eq!(layout.dimensions.content.height, 80.0);
+
+        let mut layout = to_layout_box(container("vertical-lr"));
+        layout.layout(viewport);
+        assert_eq!(layout.children[0].dimensions.content.x, 0.0);
+        assert_eq!(layout.children[1].dimensions.content.x, 100.0);
+        assert_eq!(layout.dimensions.content.height, 80.0);
+
+        let mut layout = to_layout_box(container("horizontal-tb"));
+        layout.layout(viewport);
+        assert_eq!(layout.children[1].dimensions.content.x, 0.0);
+        assert_eq!(layout.children[1].dimensions.content.y, 50.0);
+
+        // the children with `width: auto` are as wide as their contents, including the nested ones
+        let root = html::parse("<div><p>abc</p><section><p>de</p></section><p></p></div>");
+        let stylesheet = css::parse(
+            "div, p, section { display: block; } div { writing-mode: vertical-rl; } section { padding: 0 2px; }",
+        );
+        let mut layout = to_layout_box(to_styled_node(&root, &stylesheet).unwrap());
+        layout.layout(viewport);
+        let rect = |layout: &LayoutBox| {
+            let r = layout.dimensions.border_box();
+            (r.x, r.width)
+        };
+        assert_eq!(rect(&layout.children[0]), (776.0, 24.0));
+        assert_eq!(rect(&layout.children[1]), (756.0, 20.0));
+        assert_eq!(rect(&layout.children[1].children[0]), (758.0, 16.0));
+        assert_eq!(rect(&layout.children[2]), (756.0, 0.0));
+        assert_eq!(layout.dimensions.content.height, 16.0);
+    }
+
+    #[test]
+    fn test_layout_rtl() {
+        let viewport = viewport(800.0, 600.0);
+        let inline = |width: f32| StyledNode {
+            node_type: div(),
+            properties: [
+                ("display".to_string(), CSSValue::Keyword("inline".into())),
+                ("width".to_string(), CSSValue::Length((width, Unit::Px))),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            children: vec![],
+        };
+        let container = |direction: &str| {
+            block_node(
+                &[("direction", CSSValue::Keyword(direction.to_string().into()))],
+                vec![inline(100.0), inline(50.0)],
+            )
+        };
+
+        let mut layout = to_layout_box(container("rtl"));
+        layout.layout(viewport);
+        let line = &layout.children[0];
+        assert_eq!(line.children[0].dimensions.content.x, 700.0);
+        assert_eq!(line.children[1].dimensions.content.x, 650.0);
+
+        let mut layout = to_layout_box(container("ltr"));
+        layout.layout(viewport);
+        let line = &layout.children[0];
+        assert_eq!(line.children[0].dimensions.content.x, 0.0);
+        assert_eq!(line.children[1].dimensions.content.x, 100.0);
+
+        // inline elements with texts stay in the container, from the right end
+        let stylesheet = css::parse(
+            "div { display: block; width: 200px; direction: rtl; } span, b { display: inline; }",
+        );
+        let xs = |layout: &LayoutBox| {
+            layout
+                .children
+                .iter()
+                .map(|child| child.dimensions.content.x)
+                .collect::<Vec<_>>()
+        };
+
+        // <div style="direction: rtl"><span>ab</span><span>cd</span></div>
+        let root = html::parse("<div><span>ab</span><span>cd</span></div>");
+        let mut layout = to_layout_box(to_styled_node(&root, &stylesheet).unwrap());
+        layout.layout(viewport);
+        let line = &layout.children[0];
+        assert_eq!(xs(line), vec![184.0, 168.0]);
+        assert_eq!(xs(&line.children[1]), vec![168.0]);
+
+        // <div style="direction: rtl"><span><b>ab</b><b>cd</b></span></div>
+        let root = html::parse("<div><span><b>ab</b><b>cd</b></span></div>");
+        let mut layout = to_layout_box(to_styled_node(&root, &stylesheet).unwrap());
+        layout.layout(viewport);
+        let span = &layout.children[0].children[0];
+        assert_eq!(span.dimensions.content.x, 168.0);
+        assert_eq!(span.dimensions.content.width, 32.0);
+        assert_eq!(xs(span), vec![184.0, 168.0]);
+    }
+
+    #[test]
+    fn test_layout_em() {
+        let viewport = viewport(800.0, 600.0);
+        let mut layout = to_layout_box(block_node(
+            &[
+                ("font-size", CSSValue::Length((32.0, Unit::Px))),
+                ("margin-left", CSSValue::Length((2.0, Unit::Em))),
+            ],
+            vec![],
+        ));
+        layout.layout(viewport);
+
+        // `em` refers to the font size of the box
+        assert_eq!(layout.dimensions.margin.left, 64.0);
+
+        // `rem` refers to the computed font size of the root element
+        let root = html::parse("<html><div></div></html>");
+        let stylesheet = css::parse(
+            "html, div { display: block; } html { font-size: 20px; } div { font-size: 2rem; width: 3rem; padding-top: 1rem; }",
+        );
+        let mut layout = to_layout_box(to_styled_node(&root, &stylesheet).unwrap());
+        layout.layout(viewport);
+        let div = &layout.children[0];
+        assert_eq!(div.font_size(), 40.0);
+        assert_eq!(div.dimensions.content.width, 60.0);
+        assert_eq!(div.dimensions.padding.top, 20.0);
+    }
+
+    #[test]
+    fn test_layout_auto_margins() {
+        let viewport = viewport(800.0, 600.0);
+        let margin = |value: &str| match value {
+            "auto" => CSSValue::Keyword("auto".into()),
+            _ => CSSValue::Length((value.parse().unwrap(), Unit::Px)),
+        };
+        let block = |margin_left: &str, margin_right: &str| {
+            block_node(
+                &[
+                    ("width", CSSValue::Length((200.0, Unit::Px))),
+                    ("margin-left", margin(margin_left)),
+                    ("margin-right", margin(margin_right)),
+                ],
+                vec![],
+            )
+        };
+        let container = |direction: &str, children| {
+            block_node(
+                &[("direction", CSSValue::Keyword(direction.to_string().into()))],
+                children,
+            )
+        };
+
+        let mut layout = to_layout_box(container(
+            "ltr",
+            vec![
+                block("auto", "auto"),
+                block("auto", "100"),
+                block("100", "auto"),
+                block("100", "100"),
+                block("auto", "700"),
+            ],
+        ));
+        layout.layout(viewport);
+        let margins = layout
+            .children
+            .iter()
+            .map(|child| {
+                let d = &child.dimensions;
+                (d.margin.left, d.content.x, d.margin.right)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            margins,
+            vec![
+                (300.0, 300.0, 300.0),
+                (500.0, 500.0, 100.0),
+                (100.0, 100.0, 500.0),
+                (100.0, 100.0, 500.0),
+                // over-constrained, so `auto` is regarded as 0
+                (0.0, 0.0, 600.0),
+            ]
+        );
+
+        let mut layout = to_layout_box(container("rtl", vec![block("100", "100")]));
+        layout.layout(viewport);
+        let d = &layout.children[0].dimensions;
+        assert_eq!(
+            (d.margin.left, d.content.x, d.margin.right),
+            (500.0, 500.0, 100.0)
+        );
+
+        // negative margins widen the box beyond its containing block
+        let root = html::parse("<div></div>");
+        let stylesheet = css::parse("div { display: block; margin: -10px; }");
+        let mut layout = to_layout_box(to_styled_node(&root, &stylesheet).unwrap());
+        layout.layout(viewport);
+        let d = &layout.dimensions;
+        assert_eq!(
+            (d.margin.left, d.content.x, d.content.width, d.margin.right),
+            (-10.0, -10.0, 820.0, -10.0)
+        );
+    }
+
+    #[test]
+    fn test_layout_content_visibility_hidden() {
+        let viewport = viewport(800.0, 600.0);
+        let child = block_node(&[("height", CSSValue::Length((50.0, Unit::Px)))], vec![]);
+
+        // <div style="content-visibility: hidden"><div style="height: 50px"></div></div>
+        let mut layout = to_layout_box(block_node(
+            &[("content-visibility", CSSValue::Keyword("hidden".into()))],
+            vec![child],
+        ));
+        layout.layout(viewport);
+
+        assert_eq!(layout.dimensions.content.width, 800.0);
+        assert_eq!(layout.dimensions.content.height, 0.0);
+        assert_eq!(layout.children[0].dimensions, Dimensions::default());
+    }
+
+    #[test]
+    fn test_layout_first_letter() {
+        let p = NodeType::Element(Element {
+            tag_name: "p".into(),
+            attributes: [].iter().cloned().collect(),
+            ..Default::default()
+        });
+        let hello = NodeType::Text(Text {
+            data: "hello".into(),
+        });
+        let world = NodeType::Text(Text {
+            data: "world".into(),
+        });
+        let viewport = viewport(800.0, 600.0);
+        let text = |node_type| StyledNode {
+            node_type,
+            properties: [
+                ("display".to_string(), CSSValue::Keyword("inline".into())),
+                ("font-size".to_string(), CSSValue::Length((16.0, Unit::Px))),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            children: vec![],
+        };
+
+        // p { font-size: 16px; }
+        // p::first-letter { font-size: 2em; }
+        // p::first-line { color: red; }
+        // <p>helloworld</p>
+        let mut layout = to_layout_box(StyledNode {
+            node_type: &p,
+            properties: [
+                ("display".to_string(), CSSValue::Keyword("block".into())),
+                ("font-size".to_string(), CSSValue::Length((16.0, Unit::Px))),
+                (
+                    "::first-letter font-size".to_string(),
+                    CSSValue::Length((32.0, Unit::Px)),
+                ),
+                (
+                    "::first-line color".to_string(),
+                    CSSValue::Keyword("red".into()),
+                ),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            children: vec![text(&hello), text(&world)],
+        });
+        layout.layout(viewport);
+
+        let line = &layout.children[0];
+        let (first, second) = (&line.children[0], &line.children[1]);
+        // only the first character is enlarged
+        let letter = &first.children[0];
+        assert_eq!(letter.dimensions.content.x, 0.0);
+        assert_eq!(letter.dimensions.content.width, 2.0 * CHAR_WIDTH);
+        assert_eq!(letter.dimensions.content.height, 32.0);
+        assert_eq!(first.dimensions.content.width, 6.0 * CHAR_WIDTH);
+        assert_eq!(first.dimensions.content.height, 32.0);
+        assert_eq!(second.children, vec![]);
+        assert_eq!(second.dimensions.content.x, 6.0 * CHAR_WIDTH);
+        assert_eq!(second.dimensions.content.width, 5.0 * CHAR_WIDTH);
+        assert_eq!(second.dimensions.content.height, 16.0);
+        assert_eq!(line.dimensions.content.height, 32.0);
+        // `::first-line` applies to every text in the line
+        for text in [first, second] {
+            assert_eq!(
+                text.line_value(&text.lines[0], "color"),
+                Some(&CSSValue::Keyword("red".into()))
+            );
+            assert_eq!(text.value("color"), None);
+        }
+        assert_eq!(
+            letter.value("color"),
+            Some(&CSSValue::Keyword("red".into()))
+        );
+        assert_eq!(layout.value("color"), None);
+    }
+
+    #[test]
+    fn test_layout_first_line() {
+        let root = html::parse("<p>hello world foo</p>");
+        let stylesheet = css::parse(
+            "p { display: block; width: 80px; font-size: 16px; }
+            p::first-line { color: red; font-size: 32px; }",
+        );
+        let mut layout = to_layout_box(to_styled_node(&root, &stylesheet).unwrap());
+        layout.layout(Dimensions::default());
+
+        // only the text on the first line is styled by `::first-line`,
+        // where "hello" fits in 80px in 32px but "world foo" does in 16px
+        let text = &layout.children[0].children[0];
+        assert_eq!(
+            text.lines,
+            vec![
+                LineFragment {
+                    range: 0..5,
+                    rect: Rect {
+                        x: 0.0,
+                        y: 0.0,
+                        width: 80.0,
+                        height: 32.0,
+                    },
+                    first_line: true,
+                },
+                LineFragment {
+                    range: 6..15,
+                    rect: Rect {
+                        x: 0.0,
+                        y: 32.0,
+                        width: 72.0,
+                        height: 16.0,
+                    },
+                    first_line: false,
+                },
+            ]
+        );
+        let red = CSSValue::Keyword("red".into());
+        assert_eq!(text.line_value(&text.lines[0], "color"), Some(&red));
+        assert_eq!(text.line_value(&text.lines[1], "color"), None);
+        assert_eq!(text.line_font_size(&text.lines[0]), 32.0);
+        assert_eq!(text.line_font_size(&text.lines[1]), 16.0);
+        assert_eq!(layout.dimensions.content.height, 48.0);
+    }
+
+    #[test]
+    fn test_layout_clear() {
+        let keyword = |keyword: &str| CSSValue::Keyword(keyword.to_string().into());
+        let px = |num| CSSValue::Length((num, Unit::Px));
+        let viewport = viewport(800.0, 600.0);
+
+        // <div>
+        //   <div style="float: left; width: 100px; height: 100px"></div>
+        //   <div style="float: right; width: 100px; height: 200px"></div>
+        //   <div style="height: 10px"></div>
+        //   <div style="clear: left; height: 50px"></div>
+        //   <div style="clear: both; height: 50px"></div>
+        // </div>
+        let mut layout = to_layout_box(block_node(
+            &[],
+            vec![
+                block_node(
+                    &[
+                        ("float", keyword("left")),
+                        ("width", px(100.0)),
+                        ("height", px(100.0)),
+                    ],
+                    vec![],
+                ),
+                block_node(
+                    &[
+                        ("float", keyword("right")),
+                        ("width", px(100.0)),
+                        ("height", px(200.0)),
+                    ],
+                    vec![],
+                ),
+                block_node(&[("height", px(10.0))], vec![]),
+                block_node(&[("clear", keyword("left")), ("height", px(50.0))], vec![]),
+                block_node(&[("clear", keyword("both")), ("height", px(50.0))], vec![]),
+            ],
+        ));
+        layout.layout(viewport);
+
+        let rect = |i: usize| layout.children[i].dimensions.content;
+        assert_eq!((rect(0).x, rect(0).y), (0.0, 0.0));
+        assert_eq!((rect(1).x, rect(1).y), (700.0, 0.0));
+        // floats take no space in the stack
+        assert_eq!(rect(2).y, 0.0);
+        // the clearing boxes start below the floats
+        assert_eq!(rect(3).y, 100.0);
+        assert_eq!(rect(4).y, 200.0);
+        assert_eq!(layout.dimensions.content.height, 250.0);
+    }
+
+    #[test]
+    fn test_bidi_runs() {
+        let runs = |text, direction| {
+            bidi_runs(text, direction)
+                .iter()
+                .map(|run| (run.text, run.direction))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            runs("abc שלום def", Direction::Rtl),
+            vec![
+                ("def", Direction::Ltr),
+                (" שלום ", Direction::Rtl),
+                ("abc", Direction::Ltr),
+            ]
+        );
+        assert_eq!(
+            runs("abc שלום def", Direction::Ltr),
+            vec![
+                ("abc ", Direction::Ltr),
+                ("שלום", Direction::Rtl),
+                (" def", Direction::Ltr),
+            ]
+        );
+        // neutrals between runs of the same direction join them
+        assert_eq!(
+            runs("שלום, עולם!", Direction::Ltr),
+            vec![("שלום, עולם", Direction::Rtl), ("!", Direction::Ltr)]
+        );
+        assert_eq!(
+            runs("hello", Direction::Rtl),
+            vec![("hello", Direction::Ltr)]
+        );
+        assert_eq!(runs("", Direction::Rtl), vec![]);
+    }
+
+    #[test]
+    fn test_outline() {
+        let body = NodeType::Element(Element {
+            tag_name: "body".into(),
+            ..Default::default()
+        });
+        let p = NodeType::Element(Element {
+            tag_name: "p".into(),
+            ..Default::default()
+        });
+        let text = NodeType::Text(Text {
+            data: " Lorem ipsum dolor sit amet ".into(),
+        });
+        let viewport = viewport(800.0, 600.0);
+        let block = |node_type, children| StyledNode {
+            node_type,
+            properties: [
+                ("display".to_string(), CSSValue::Keyword("block".into())),
+                (
+                    "padding-left".to_string(),
+                    CSSValue::Length((8.0, Unit::Px)),
+                ),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            children,
+        };
+
+        // <body style="padding-left: 8px"><p style="padding-left: 8px"> Lorem ipsum dolor sit amet </p><p></p></body>
+        let mut layout = to_layout_box(block(
+            &body,
+            vec![
+                block(
+                    &p,
+                    vec![StyledNode {
+                        node_type: &text,
+                        properties: [("display".to_string(), CSSValue::Keyword("inline".into()))]
+                            .iter()
+                            .cloned()
+                            .collect(),
+                        children: vec![],
+                    }],
+                ),
+                block(&p, vec![]),
+            ],
+        ));
+        layout.layout(viewport);
+
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        let entry = |name: &str, text: &str, rect| OutlineEntry {
+            name: name.into(),
+            text: text.into(),
+            rect,
+        };
+        assert_eq!(
+            layout.outline(),
+            vec![
+                entry("body", "", rect(0.0, 0.0, 800.0, 16.0)),
+                entry("p", "", rect(8.0, 0.0, 792.0, 16.0)),
+                entry("(anonymous)", "", rect(16.0, 0.0, 784.0, 16.0)),
+                entry(
+                    "#text",
+                    "Lorem ipsum dolor si",
+                    rect(16.0, 0.0, 224.0, 16.0)
+                ),
+                entry("p", "", rect(8.0, 16.0, 792.0, 0.0)),
+            ]
         );
     }
 }
@@ -2,5 +2,6 @@ pub mod css;
 pub mod dom;
 pub mod html;
 pub mod layout;
+pub mod paint;
 pub mod render;
 pub mod style;
@@ -1,10 +1,8 @@
 use mini_web_browser_tutorial::{
-    css,
-    dom::{Node, NodeType},
     html,
     layout::to_layout_box,
     render::to_element_container,
-    style::to_styled_node,
+    style::{collect_stylesheets, to_styled_node, user_agent_stylesheet},
 };
 
 const HTML: &str = r#"<body>
@@ -22,26 +20,13 @@ const HTML: &str = r#"<body>
     </style>
 </body>"#;
 
-const DEFAULT_STYLESHEET: &str = r#"
-script, style {
-    display: none;
-}
-p, div {
-    display: block;
-}
-"#;
-
 fn main() {
     let mut siv = cursive::default();
 
     let node = html::parse(HTML);
-    let stylesheet = css::parse(&format!(
-        "{}\n{}",
-        DEFAULT_STYLESHEET,
-        collect_tag_inners(&node, "style".into()).join("\n")
-    ));
+    let stylesheets = [user_agent_stylesheet(), collect_stylesheets(&node)];
 
-    let container = to_styled_node(&node, &stylesheet)
+    let container = to_styled_node(&node, &stylesheets[..])
         .and_then(|styled_node| Some(to_layout_box(styled_node)))
         .and_then(|layout_box| Some(to_element_container(layout_box)));
     if let Some(c) = container {
@@ -50,19 +35,3 @@ fn main() {
 
     siv.run();
 }
-
-pub fn collect_tag_inners(node: &Box<Node>, tag_name: &str) -> Vec<String> {
-    if let NodeType::Element(ref element) = node.node_type {
-        if element.tag_name.as_str() == tag_name {
-            return vec![node.inner_text()];
-        }
-    }
-
-    node.children
-        .iter()
-        .map(|child| collect_tag_inners(child, tag_name))
-        .collect::<Vec<Vec<String>>>()
-        .into_iter()
-        .flatten()
-        .collect()
-}
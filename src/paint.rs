@@ -0,0 +1,1393 @@
+//! This module includes some implementations on painting, which converts a box tree into drawing commands.
+
+use crate::{
+    css::{BackgroundPosition, BoxShadow, CSSValue, Color, Direction, TransformFunction},
+    dom::NodeType,
+    layout::{bidi_runs, to_px, BoxProps, BoxType, FontMetrics, LayoutBox, MonospaceMetrics, Rect},
+    style::{current_color, resolve_color},
+};
+
+/// `ELLIPSIS` is the string which replaces the truncated text by `text-overflow: ellipsis`.
+const ELLIPSIS: &str = "\u{2026}";
+
+/// `DisplayList` is the list of drawing commands in the painting order.
+/// See https://www.w3.org/TR/CSS2/zindex.html for the painting order.
+pub type DisplayList = Vec<DisplayCommand>;
+
+#[derive(Debug, PartialEq)]
+pub enum DisplayCommand {
+    /// `SolidColor` fills the rect with the color.
+    SolidColor(Color, Rect),
+    /// `RoundedRect` fills the rect with the color like `SolidColor`, but its corners are rounded with the radius in px.
+    RoundedRect(Color, Rect, f32),
+    /// `Shadow` draws the shadow of a box in the rect, which is blurred with the radius in px.
+    Shadow(Color, Rect, f32),
+    /// `Text` draws the text in the rect.
+    Text(String, Rect),
+    /// `BackgroundImage` draws the image at the URL in the rect, which is placed at the position in the rect.
+    BackgroundImage(String, Rect, BackgroundPosition),
+}
+
+/// `build_display_list` generates the drawing commands of the laid out box tree.
+pub fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
+    build_display_list_with_metrics(layout_root, &MonospaceMetrics)
+}
+
+/// `build_display_list_with_metrics` generates the drawing commands like `build_display_list`,
+/// measuring texts with `metrics`, which should be the same as the one used by `LayoutBox::layout_with_metrics`.
+pub fn build_display_list_with_metrics(
+    layout_root: &LayoutBox,
+    metrics: &dyn FontMetrics,
+) -> DisplayList {
+    let mut list = vec![];
+    render_layout_box(&mut list, layout_root, None, metrics);
+    list
+}
+
+/// `paginate` splits the display list of the laid out box tree into pages of `page_height`, as for printing.
+/// Pages break between block-level boxes, and a box crossing the end of a page is moved to the next page
+/// unless its children can be split instead. `break-inside: avoid` prevents splitting the children of a box.
+/// The commands on each page are positioned relative to the top of the page.
+/// See https://www.w3.org/TR/css-break-3/#break-within
+///
+/// NOTE: Boxes taller than a page are not split unless they have children, and they overflow the page.
+/// Backgrounds across a page break are cut at the break.
+pub fn paginate(layout_root: &LayoutBox, page_height: f32) -> Vec<DisplayList> {
+    let mut breaks = vec![layout_root.dimensions.margin_box().y];
+    collect_page_breaks(layout_root, page_height, &mut breaks);
+    let list = build_display_list(layout_root);
+    breaks
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| {
+            let end = breaks.get(i + 1).copied().unwrap_or(f32::INFINITY);
+            list.iter()
+                .filter_map(|command| command_on_page(command, start, end))
+                .collect()
+        })
+        .collect()
+}
+
+/// `collect_page_breaks` appends the positions where pages break among the children of `layout_box` to `breaks`,
+/// whose last element is the top of the current page.
+fn collect_page_breaks(layout_box: &LayoutBox, page_height: f32, breaks: &mut Vec<f32>) {
+    for child in &layout_box.children {
+        let child_box = child.dimensions.margin_box();
+        let page_start = *breaks.last().unwrap();
+        if child_box.y + child_box.height <= page_start + page_height {
+            continue;
+        }
+        let avoids_break = child.properties().and_then(|p| p.get("break-inside"))
+            == Some(&CSSValue::Keyword("avoid".into()));
+        if matches!(child.box_type, BoxType::BlockBox(_))
+            && !avoids_break
+            && !child.children.is_empty()
+        {
+            collect_page_breaks(child, page_height, breaks);
+        } else if child_box.y > page_start {
+            breaks.push(child_box.y);
+        }
+    }
+}
+
+/// `command_on_page` returns the part of `command` on the page from `start` to `end`,
+/// moved to the coordinates of the page. Texts are on the page where they start.
+fn command_on_page(command: &DisplayCommand, start: f32, end: f32) -> Option<DisplayCommand> {
+    match command {
+        DisplayCommand::SolidColor(color, rect) => {
+            clip_to_page(rect, start, end).map(|rect| DisplayCommand::SolidColor(*color, rect))
+        }
+        DisplayCommand::RoundedRect(color, rect, radius) => clip_to_page(rect, start, end)
+            .map(|rect| DisplayCommand::RoundedRect(*color, rect, *radius)),
+        DisplayCommand::Shadow(color, rect, blur) => {
+            clip_to_page(rect, start, end).map(|rect| DisplayCommand::Shadow(*color, rect, *blur))
+        }
+        DisplayCommand::Text(text, rect) => (start <= rect.y && rect.y < end).then(|| {
+            DisplayCommand::Text(
+                text.clone(),
+                Rect {
+                    y: rect.y - start,
+                    ..*rect
+                },
+            )
+        }),
+        // images are not cut, since cutting the rect would move the image positioned in it
+        DisplayCommand::BackgroundImage(url, rect, position) => (start <= rect.y && rect.y < end)
+            .then(|| {
+                DisplayCommand::BackgroundImage(
+                    url.clone(),
+                    Rect {
+                        y: rect.y - start,
+                        ..*rect
+                    },
+                    position.clone(),
+                )
+            }),
+    }
+}
+
+/// `clip_to_page` returns the part of `rect` on the page from `start` to `end` in the coordinates of the page.
+fn clip_to_page(rect: &Rect, start: f32, end: f32) -> Option<Rect> {
+    let top = rect.y.max(start);
+    let bottom = (rect.y + rect.height).min(end);
+    (top < bottom).then_some(Rect {
+        y: top - start,
+        height: bottom - top,
+        ..*rect
+    })
+}
+
+/// `render_layout_box` paints the box and its descendants.
+/// `ellipsis_edge` is the x where the text in the lines of the closest block container is truncated, if any.
+fn render_layout_box(
+    list: &mut DisplayList,
+    layout_box: &LayoutBox,
+    ellipsis_edge: Option<f32>,
+    metrics: &dyn FontMetrics,
+) {
+    let start = list.len();
+    render_box_shadows(list, layout_box);
+    render_background(list, layout_box);
+    render_borders(list, layout_box);
+    if !layout_box.skips_contents() {
+        let ellipsis_edge = match layout_box.box_type {
+            BoxType::BlockBox(_) => text_overflow_edge(layout_box),
+            _ => ellipsis_edge,
+        };
+        if !render_text(list, layout_box, ellipsis_edge, metrics) {
+            for child in &layout_box.children {
+                render_layout_box(list, child, ellipsis_edge, metrics);
+            }
+        }
+    }
+    render_outline(list, layout_box);
+    if let Some(transform) = Transform::of(layout_box) {
+        for command in &mut list[start..] {
+            transform.apply(command);
+        }
+    }
+}
+
+/// `Transform` is an affine transform without rotation nor skew, which maps a point `p` to `scale * p + translate`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Transform {
+    scale: (f32, f32),
+    translate: (f32, f32),
+}
+
+impl Transform {
+    const IDENTITY: Transform = Transform {
+        scale: (1.0, 1.0),
+        translate: (0.0, 0.0),
+    };
+
+    /// `of` returns the transform given by `transform` of the box,
+    /// or `None` if it has no transform or any function in it is not supported, e.g. `rotate()`.
+    /// The functions are composed in order around the center of the border box, i.e. the initial `transform-origin`.
+    /// See https://www.w3.org/TR/css-transforms-1/#transform-rendering
+    fn of(layout_box: &LayoutBox) -> Option<Transform> {
+        let values = match layout_box.properties()?.get("transform")? {
+            CSSValue::List(values) => values.as_slice(),
+            value => std::slice::from_ref(value),
+        };
+        let b = layout_box.dimensions.border_box();
+        let origin = (b.x + b.width / 2.0, b.y + b.height / 2.0);
+        let mut transform = Transform::translation(origin.0, origin.1);
+        for value in values {
+            let function = match value {
+                CSSValue::Transform(TransformFunction::Translate((x, ux), (y, uy))) => {
+                    let font_size = layout_box.font_size();
+                    let x = to_px(&CSSValue::Length((*x, ux.clone())), font_size, b.width)?;
+                    let y = to_px(&CSSValue::Length((*y, uy.clone())), font_size, b.height)?;
+                    Transform::translation(x, y)
+                }
+                CSSValue::Transform(TransformFunction::Scale(x, y)) => Transform {
+                    scale: (*x, *y),
+                    ..Transform::IDENTITY
+                },
+                _ => return None,
+            };
+            transform = transform.then(function);
+        }
+        Some(transform.then(Transform::translation(-origin.0, -origin.1)))
+    }
+
+    fn translation(x: f32, y: f32) -> Transform {
+        Transform {
+            translate: (x, y),
+            ..Transform::IDENTITY
+        }
+    }
+
+    /// `then` composes the transforms so that `other` is applied to points first, like multiplying matrices.
+    fn then(self, other: Transform) -> Transform {
+        Transform {
+            scale: (self.scale.0 * other.scale.0, self.scale.1 * other.scale.1),
+            translate: (
+                self.scale.0 * other.translate.0 + self.translate.0,
+                self.scale.1 * other.translate.1 + self.translate.1,
+            ),
+        }
+    }
+
+    fn apply_rect(&self, rect: &mut Rect) {
+        let (x1, x2) = (
+            self.scale.0 * rect.x + self.translate.0,
+            self.scale.0 * (rect.x + rect.width) + self.translate.0,
+        );
+        let (y1, y2) = (
+            self.scale.1 * rect.y + self.translate.1,
+            self.scale.1 * (rect.y + rect.height) + self.translate.1,
+        );
+        *rect = Rect {
+            x: x1.min(x2),
+            y: y1.min(y2),
+            width: (x2 - x1).abs(),
+            height: (y2 - y1).abs(),
+        };
+    }
+
+    /// `apply` transforms the rect of the command.
+    /// Radii are scaled by the smaller scale, since elliptical corners are not supported.
+    fn apply(&self, command: &mut DisplayCommand) {
+        let radius_scale = self.scale.0.abs().min(self.scale.1.abs());
+        match command {
+            DisplayCommand::SolidColor(_, rect)
+            | DisplayCommand::Text(_, rect)
+            | DisplayCommand::BackgroundImage(_, rect, _) => self.apply_rect(rect),
+            DisplayCommand::RoundedRect(_, rect, radius)
+            | DisplayCommand::Shadow(_, rect, radius) => {
+                self.apply_rect(rect);
+                *radius *= radius_scale;
+            }
+        }
+    }
+}
+
+/// `text_overflow_edge` returns the right edge of the content box of a block container
+/// with `overflow: hidden`, `white-space: nowrap` and `text-overflow: ellipsis`,
+/// where the text overflowing its lines is truncated with an ellipsis.
+/// See https://www.w3.org/TR/css-overflow-3/#text-overflow
+///
+/// NOTE: Lines are assumed to be laid out from left to right, so `rtl` lines are truncated at the right end too.
+fn text_overflow_edge(layout_box: &LayoutBox) -> Option<f32> {
+    let properties = layout_box.properties()?;
+    let is = |name: &str, keyword: &str| match properties.get(name) {
+        Some(CSSValue::Keyword(k)) => k == keyword,
+        _ => false,
+    };
+    if is("overflow", "hidden") && is("white-space", "nowrap") && is("text-overflow", "ellipsis") {
+        let content = layout_box.dimensions.content;
+        Some(content.x + content.width)
+    } else {
+        None
+    }
+}
+
+/// `render_text` draws the text of a text box, and returns whether the box is a text box.
+/// The first character is drawn in the rect of the `::first-letter` box, if any.
+/// The rest is drawn line by line in the `lines` of the box, each in its own font size,
+/// or in the content rect for texts in inline elements, and run by run in the visual order given by `bidi_runs`.
+fn render_text(
+    list: &mut DisplayList,
+    layout_box: &LayoutBox,
+    ellipsis_edge: Option<f32>,
+    metrics: &dyn FontMetrics,
+) -> bool {
+    let text = match layout_box.box_type {
+        BoxType::InlineBox(BoxProps {
+            node_type: NodeType::Text(t),
+            ..
+        }) => t.data.as_str(),
+        _ => return false,
+    };
+    let mut content = layout_box.dimensions.content;
+    let mut rest = text;
+    if let Some(letter) = layout_box.children.first() {
+        let (first, others) = text.split_at(text.chars().next().map_or(0, char::len_utf8));
+        let letter_rect = letter.dimensions.content;
+        render_text_run(
+            list,
+            first,
+            letter_rect,
+            letter.font_size(),
+            ellipsis_edge,
+            metrics,
+        );
+        if letter_rect.x == content.x {
+            content.x += letter_rect.width;
+        }
+        content.width -= letter_rect.width;
+        rest = others;
+    }
+    let direction = match layout_box.properties().and_then(|p| p.get("direction")) {
+        Some(CSSValue::Keyword(keyword)) => Direction::from_keyword(keyword).unwrap_or_default(),
+        _ => Direction::default(),
+    };
+    let lines = match layout_box.lines.is_empty() {
+        true => vec![(rest, content, layout_box.font_size())],
+        false => layout_box
+            .lines
+            .iter()
+            .map(|line| {
+                (
+                    &text[line.range.clone()],
+                    line.rect,
+                    layout_box.line_font_size(line),
+                )
+            })
+            .collect(),
+    };
+    for (line, rect, font_size) in lines {
+        let mut x = rect.x;
+        for run in bidi_runs(line, direction) {
+            let width = metrics.text_width(run.text, font_size);
+            let rect = Rect { x, width, ..rect };
+            render_text_run(list, run.text, rect, font_size, ellipsis_edge, metrics);
+            x += width;
+        }
+    }
+    true
+}
+
+/// `render_text_run` draws `text` in `rect`. When the text overflows `ellipsis_edge`,
+/// its tail is replaced with an ellipsis so that it ends at the edge, and the text after the edge is not drawn.
+fn render_text_run(
+    list: &mut DisplayList,
+    text: &str,
+    rect: Rect,
+    font_size: f32,
+    ellipsis_edge: Option<f32>,
+    metrics: &dyn FontMetrics,
+) {
+    if text.is_empty() {
+        return;
+    }
+    match ellipsis_edge {
+        Some(edge) if rect.x >= edge => {}
+        Some(edge) if rect.x + rect.width > edge => {
+            // keep as many characters as fit before the ellipsis
+            let mut available = edge - rect.x - metrics.text_width(ELLIPSIS, font_size);
+            let truncated = text
+                .chars()
+                .take_while(|&c| {
+                    available -= metrics.char_width(c, font_size);
+                    available >= 0.0
+                })
+                .collect::<String>()
+                + ELLIPSIS;
+            list.push(DisplayCommand::Text(
+                truncated,
+                Rect {
+                    width: edge - rect.x,
+                    ..rect
+                },
+            ));
+        }
+        _ => list.push(DisplayCommand::Text(text.to_string(), rect)),
+    }
+}
+
+/// `render_background` fills the border box with `background-color`, and draws `background-image` over it
+/// in the padding box at `background-position`.
+/// The corners are rounded by `border-radius`, whose percentage refers to the width of the border box.
+/// See https://www.w3.org/TR/css-backgrounds-3/#background-painting-area
+///
+/// NOTE: `border-radius` takes a single length for all the corners, and elliptical corners are not supported.
+/// Background images are neither clipped by `border-radius` nor repeated.
+fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
+    let color = layout_box
+        .properties()
+        .and_then(|p| resolve_color(p, "background-color"));
+    if let Some(color) = color {
+        // transparent backgrounds draw nothing
+        if color.a > 0 {
+            let border_box = layout_box.dimensions.border_box();
+            match layout_box.length("border-radius", border_box.width) {
+                Some(radius) if radius > 0.0 => {
+                    list.push(DisplayCommand::RoundedRect(color, border_box, radius))
+                }
+                _ => list.push(DisplayCommand::SolidColor(color, border_box)),
+            }
+        }
+    }
+    if let Some(CSSValue::Url(url)) = layout_box
+        .properties()
+        .and_then(|p| p.get("background-image"))
+    {
+        let position = layout_box
+            .properties()
+            .and_then(|p| p.get("background-position"))
+            .and_then(BackgroundPosition::from_value)
+            .unwrap_or(BackgroundPosition::INITIAL);
+        list.push(DisplayCommand::BackgroundImage(
+            url.clone(),
+            layout_box.dimensions.padding_box(),
+            position,
+        ));
+    }
+}
+
+/// `render_box_shadows` draws the shadows given by `box-shadow` behind the box.
+/// Each shadow is the border box moved by the offsets and expanded by the spread.
+/// The first shadow is drawn on top of the others.
+fn render_box_shadows(list: &mut DisplayList, layout_box: &LayoutBox) {
+    let properties = match layout_box.properties() {
+        Some(properties) => properties,
+        None => return,
+    };
+    let values = match properties.get("box-shadow") {
+        Some(CSSValue::List(values)) => values.as_slice(),
+        Some(value) => std::slice::from_ref(value),
+        None => return,
+    };
+    let color = current_color(properties);
+    let context = layout_box.length_context(0.0);
+    let shadows = values
+        .split(|v| *v == CSSValue::Comma)
+        .filter_map(|values| BoxShadow::from_values(values, color, &context))
+        .collect::<Vec<_>>();
+    let b = layout_box.dimensions.border_box();
+    for shadow in shadows.iter().rev() {
+        if shadow.color.a == 0 {
+            continue;
+        }
+        let rect = Rect {
+            x: b.x + shadow.offset_x - shadow.spread,
+            y: b.y + shadow.offset_y - shadow.spread,
+            width: (b.width + shadow.spread * 2.0).max(0.0),
+            height: (b.height + shadow.spread * 2.0).max(0.0),
+        };
+        list.push(DisplayCommand::Shadow(shadow.color, rect, shadow.blur));
+    }
+}
+
+/// `render_borders` draws the border of each side in the border area, i.e. between the border box and the padding box.
+/// A side is drawn when its `border-*-style` is neither `none` nor `hidden`,
+/// and `border-*-color` defaults to `color`, as well as `currentcolor`.
+/// See https://www.w3.org/TR/css-backgrounds-3/#borders
+///
+/// NOTE: All the styles are drawn as `solid`, and the corners are not rounded by `border-radius`.
+fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
+    let properties = match layout_box.properties() {
+        Some(properties) => properties,
+        None => return,
+    };
+    let d = &layout_box.dimensions;
+    let b = d.border_box();
+    let sides = [
+        (
+            "top",
+            Rect {
+                x: b.x,
+                y: b.y,
+                width: b.width,
+                height: d.border.top,
+            },
+        ),
+        (
+            "right",
+            Rect {
+                x: b.x + b.width - d.border.right,
+                y: b.y + d.border.top,
+                width: d.border.right,
+                height: b.height - d.border.top - d.border.bottom,
+            },
+        ),
+        (
+            "bottom",
+            Rect {
+                x: b.x,
+                y: b.y + b.height - d.border.bottom,
+                width: b.width,
+                height: d.border.bottom,
+            },
+        ),
+        (
+            "left",
+            Rect {
+                x: b.x,
+                y: b.y + d.border.top,
+                width: d.border.left,
+                height: b.height - d.border.top - d.border.bottom,
+            },
+        ),
+    ];
+    for (side, rect) in sides {
+        if rect.width <= 0.0 || rect.height <= 0.0 {
+            continue;
+        }
+        match properties.get(&format!("border-{}-style", side)) {
+            Some(CSSValue::Keyword(keyword)) if keyword != "none" && keyword != "hidden" => {}
+            _ => continue,
+        }
+        let name = format!("border-{}-color", side);
+        let color = match properties.get(&name) {
+            Some(_) => resolve_color(properties, &name),
+            None => Some(current_color(properties)),
+        };
+        match color {
+            Some(color) if color.a > 0 => list.push(DisplayCommand::SolidColor(color, rect)),
+            _ => {}
+        }
+    }
+}
+
+/// `render_outline` draws the outline around the border box over the contents.
+/// The outline takes up no space, so it may overlap the other boxes.
+/// `outline-color: currentcolor` is resolved to `color`.
+/// See https://www.w3.org/TR/css-ui-4/#outline-props
+///
+/// NOTE: Only solid outlines are drawn, and `outline-offset` is not supported.
+fn render_outline(list: &mut DisplayList, layout_box: &LayoutBox) {
+    let properties = match layout_box.properties() {
+        Some(properties) => properties,
+        None => return,
+    };
+    if properties.get("outline-style") != Some(&CSSValue::Keyword("solid".into())) {
+        return;
+    }
+    let width = match properties.get("outline-width") {
+        Some(CSSValue::Keyword(keyword)) => match keyword.as_ref() {
+            "thin" => 1.0,
+            "medium" => 3.0,
+            "thick" => 5.0,
+            _ => return,
+        },
+        _ => match layout_box.length("outline-width", 0.0) {
+            Some(width) if width > 0.0 => width,
+            _ => return,
+        },
+    };
+    let color = match resolve_color(properties, "outline-color") {
+        Some(color) if color.a > 0 => color,
+        _ => return,
+    };
+    let b = layout_box.dimensions.border_box();
+    for rect in [
+        // top
+        Rect {
+            x: b.x - width,
+            y: b.y - width,
+            width: b.width + width * 2.0,
+            height: width,
+        },
+        // right
+        Rect {
+            x: b.x + b.width,
+            y: b.y,
+            width,
+            height: b.height,
+        },
+        // bottom
+        Rect {
+            x: b.x - width,
+            y: b.y + b.height,
+            width: b.width + width * 2.0,
+            height: width,
+        },
+        // left
+        Rect {
+            x: b.x - width,
+            y: b.y,
+            width,
+            height: b.height,
+        },
+    ] {
+        list.push(DisplayCommand::SolidColor(color, rect));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        css::{self, Declaration, Unit},
+        dom::Text,
+        html,
+        layout::{
+            test_helpers::{block_node, viewport},
+            to_layout_box,
+        },
+        style::{expand_shorthand, to_styled_node, StyledNode},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_build_display_list_background() {
+        let block = |background: &str, children| {
+            block_node(
+                &[
+                    ("height", CSSValue::Length((50.0, Unit::Px))),
+                    (
+                        "background-color",
+                        CSSValue::Keyword(background.to_string().into()),
+                    ),
+                ],
+                children,
+            )
+        };
+        let viewport = viewport(800.0, 600.0);
+
+        // <div style="background-color: transparent"><div style="background-color: blue"></div></div>
+        let mut layout = to_layout_box(block("transparent", vec![block("blue", vec![])]));
+        layout.layout(viewport);
+
+        assert_eq!(
+            build_display_list(&layout),
+            vec![DisplayCommand::SolidColor(
+                Color::rgb(0, 0, 255),
+                Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    width: 800.0,
+                    height: 50.0,
+                }
+            )]
+        );
+
+        // `currentcolor` refers to `color`, which is black by default
+        let mut layout = to_layout_box(block("currentcolor", vec![]));
+        layout.layout(viewport);
+        assert_eq!(
+            build_display_list(&layout),
+            vec![DisplayCommand::SolidColor(
+                Color::rgb(0, 0, 0),
+                Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    width: 800.0,
+                    height: 50.0,
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_build_display_list_content_visibility() {
+        let block = |background: &str, content_visibility: &str, children| {
+            block_node(
+                &[
+                    ("height", CSSValue::Length((50.0, Unit::Px))),
+                    (
+                        "background-color",
+                        CSSValue::Keyword(background.to_string().into()),
+                    ),
+                    (
+                        "content-visibility",
+                        CSSValue::Keyword(content_visibility.to_string().into()),
+                    ),
+                ],
+                children,
+            )
+        };
+        let viewport = viewport(800.0, 600.0);
+        let rect = |y: f32| Rect {
+            x: 0.0,
+            y,
+            width: 800.0,
+            height: 50.0,
+        };
+
+        // <div>
+        //   <div style="content-visibility: hidden; background-color: red"><div style="background-color: blue"></div></div>
+        //   <div style="background-color: lime"></div>
+        // </div>
+        let mut layout = to_layout_box(block_node(
+            &[],
+            vec![
+                block("red", "hidden", vec![block("blue", "visible", vec![])]),
+                block("lime", "visible", vec![]),
+            ],
+        ));
+        layout.layout(viewport);
+
+        assert_eq!(
+            build_display_list(&layout),
+            vec![
+                DisplayCommand::SolidColor(Color::rgb(255, 0, 0), rect(0.0)),
+                DisplayCommand::SolidColor(Color::rgb(0, 255, 0), rect(50.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_display_list_text_overflow() {
+        let text = NodeType::Text(Text {
+            data: "abcdefghijklmnopqrstuvwxyz".into(),
+        });
+        let viewport = viewport(800.0, 600.0);
+        let layout = |properties: &[(&str, &str)], metrics: &dyn FontMetrics| {
+            let properties = [("width", CSSValue::Length((100.0, Unit::Px)))]
+                .into_iter()
+                .chain(
+                    properties
+                        .iter()
+                        .map(|&(name, value)| (name, CSSValue::Keyword(value.to_string().into()))),
+                )
+                .collect::<Vec<_>>();
+            let mut layout = to_layout_box(block_node(
+                &properties,
+                vec![StyledNode {
+                    node_type: &text,
+                    properties: [("display".to_string(), CSSValue::Keyword("inline".into()))]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                    children: vec![],
+                }],
+            ));
+            layout.layout_with_metrics(viewport, metrics);
+            build_display_list_with_metrics(&layout, metrics)
+        };
+        let ellipsis = [
+            ("overflow", "hidden"),
+            ("white-space", "nowrap"),
+            ("text-overflow", "ellipsis"),
+        ];
+
+        // <div style="width: 100px; overflow: hidden; white-space: nowrap; text-overflow: ellipsis">abc...xyz</div>
+        // 100px fits 12 characters, one of which is the ellipsis
+        assert_eq!(
+            layout(&ellipsis, &MonospaceMetrics),
+            vec![DisplayCommand::Text(
+                "abcdefghijk\u{2026}".to_string(),
+                Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    width: 100.0,
+                    height: 16.0,
+                }
+            )]
+        );
+        // the text is not truncated without all of the properties
+        assert_eq!(
+            layout(
+                &[("overflow", "hidden"), ("text-overflow", "ellipsis")],
+                &MonospaceMetrics
+            ),
+            vec![DisplayCommand::Text(
+                "abcdefghijklmnopqrstuvwxyz".to_string(),
+                Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    width: 208.0,
+                    height: 16.0,
+                }
+            )]
+        );
+
+        // the text is measured with the metrics used by the layout
+        struct TenPx;
+        impl FontMetrics for TenPx {
+            fn char_width(&self, _c: char, _font_size: f32) -> f32 {
+                10.0
+            }
+        }
+        assert_eq!(
+            layout(&ellipsis, &TenPx),
+            vec![DisplayCommand::Text(
+                "abcdefghi\u{2026}".to_string(),
+                Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    width: 100.0,
+                    height: 16.0,
+                }
+            )]
+        );
+        assert_eq!(
+            layout(&[], &TenPx),
+            vec![DisplayCommand::Text(
+                "abcdefghijklmnopqrstuvwxyz".to_string(),
+                Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    width: 260.0,
+                    height: 16.0,
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_build_display_list_bidi() {
+        let text = NodeType::Text(Text {
+            data: "abc שלום".into(),
+        });
+        let viewport = viewport(800.0, 600.0);
+        let rect = |x: f32, width: f32| Rect {
+            x,
+            y: 0.0,
+            width,
+            height: 16.0,
+        };
+
+        // <div dir="rtl">abc שלום</div>
+        let rtl = [("direction".to_string(), CSSValue::Keyword("rtl".into()))];
+        let mut layout = to_layout_box(block_node(
+            &[("direction", CSSValue::Keyword("rtl".into()))],
+            vec![StyledNode {
+                node_type: &text,
+                properties: [("display".to_string(), CSSValue::Keyword("inline".into()))]
+                    .into_iter()
+                    .chain(rtl.iter().cloned())
+                    .collect(),
+                children: vec![],
+            }],
+        ));
+        layout.layout(viewport);
+
+        // the text is at the right end, and the Hebrew run comes first from the left
+        assert_eq!(
+            build_display_list(&layout),
+            vec![
+                DisplayCommand::Text(" שלום".to_string(), rect(736.0, 40.0)),
+                DisplayCommand::Text("abc".to_string(), rect(776.0, 24.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_display_list_line_breaking() {
+        let text = NodeType::Text(Text {
+            data: "hello world".into(),
+        });
+        let viewport = viewport(80.0, 600.0);
+        let rect = |y: f32, width: f32, height: f32| Rect {
+            x: 0.0,
+            y,
+            width,
+            height,
+        };
+        let layout = |properties: &[(&str, CSSValue)]| {
+            let mut layout = to_layout_box(block_node(
+                properties,
+                vec![StyledNode {
+                    node_type: &text,
+                    properties: [("display".to_string(), CSSValue::Keyword("inline".into()))]
+                        .into_iter()
+                        .collect(),
+                    children: vec![],
+                }],
+            ));
+            layout.layout(viewport);
+            build_display_list(&layout)
+        };
+
+        // <div>hello world</div> in a viewport narrower than the text
+        assert_eq!(
+            layout(&[]),
+            vec![
+                DisplayCommand::Text("hello".to_string(), rect(0.0, 40.0, 16.0)),
+                DisplayCommand::Text("world".to_string(), rect(16.0, 40.0, 16.0)),
+            ]
+        );
+        // div::first-line { font-size: 24px; } enlarges only the first line
+        assert_eq!(
+            layout(&[("::first-line font-size", CSSValue::Length((24.0, Unit::Px)),)]),
+            vec![
+                DisplayCommand::Text("hello".to_string(), rect(0.0, 60.0, 24.0)),
+                DisplayCommand::Text("world".to_string(), rect(24.0, 40.0, 16.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_paginate() {
+        let colored = |background: &str| {
+            block_node(
+                &[
+                    ("height", CSSValue::Length((40.0, Unit::Px))),
+                    (
+                        "background-color",
+                        CSSValue::Keyword(background.to_string().into()),
+                    ),
+                ],
+                vec![],
+            )
+        };
+        let viewport = viewport(800.0, 600.0);
+        let rect = |y: f32| Rect {
+            x: 0.0,
+            y,
+            width: 800.0,
+            height: 40.0,
+        };
+        let red = Color::rgb(255, 0, 0);
+        let lime = Color::rgb(0, 255, 0);
+        let blue = Color::rgb(0, 0, 255);
+
+        // <div>
+        //   <div style="height: 40px; background-color: red"></div>
+        //   <div style="break-inside: ...">
+        //     <div style="height: 40px; background-color: lime"></div>
+        //     <div style="height: 40px; background-color: blue"></div>
+        //   </div>
+        // </div>
+        let pages = |break_inside: &str| {
+            let mut layout = to_layout_box(block_node(
+                &[],
+                vec![
+                    colored("red"),
+                    block_node(
+                        &[(
+                            "break-inside",
+                            CSSValue::Keyword(break_inside.to_string().into()),
+                        )],
+                        vec![colored("lime"), colored("blue")],
+                    ),
+                ],
+            ));
+            layout.layout(viewport);
+            paginate(&layout, 100.0)
+        };
+
+        // the page breaks between the lime and the blue boxes
+        assert_eq!(
+            pages("auto"),
+            vec![
+                vec![
+                    DisplayCommand::SolidColor(red, rect(0.0)),
+                    DisplayCommand::SolidColor(lime, rect(40.0)),
+                ],
+                vec![DisplayCommand::SolidColor(blue, rect(0.0))],
+            ]
+        );
+        // the page breaks before the box avoiding breaks inside
+        assert_eq!(
+            pages("avoid"),
+            vec![
+                vec![DisplayCommand::SolidColor(red, rect(0.0))],
+                vec![
+                    DisplayCommand::SolidColor(lime, rect(0.0)),
+                    DisplayCommand::SolidColor(blue, rect(40.0)),
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_display_list_outline() {
+        let block = |outline: Option<Vec<CSSValue>>| {
+            let mut node = block_node(
+                &[
+                    ("width", CSSValue::Length((100.0, Unit::Px))),
+                    ("height", CSSValue::Length((50.0, Unit::Px))),
+                    ("margin-top", CSSValue::Length((10.0, Unit::Px))),
+                    ("margin-left", CSSValue::Length((10.0, Unit::Px))),
+                ],
+                vec![],
+            );
+            if let Some(values) = outline {
+                node.properties.extend(
+                    expand_shorthand(&Declaration {
+                        name: "outline".to_string(),
+                        value: CSSValue::List(values),
+                        important: false,
+                    })
+                    .into_iter()
+                    .map(|d| (d.name, d.value)),
+                );
+            }
+            node
+        };
+        let viewport = viewport(800.0, 600.0);
+
+        // <div style="outline: 2px solid red"></div>
+        let mut outlined = to_layout_box(block(Some(vec![
+            CSSValue::Length((2.0, Unit::Px)),
+            CSSValue::Keyword("solid".into()),
+            CSSValue::Keyword("red".into()),
+        ])));
+        outlined.layout(viewport);
+        let mut plain = to_layout_box(block(None));
+        plain.layout(viewport);
+
+        // the outline does not move the box
+        assert_eq!(outlined.dimensions, plain.dimensions);
+        assert_eq!(build_display_list(&plain), vec![]);
+
+        let red = Color::rgb(255, 0, 0);
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(
+            build_display_list(&outlined),
+            vec![
+                DisplayCommand::SolidColor(red, rect(8.0, 8.0, 104.0, 2.0)),
+                DisplayCommand::SolidColor(red, rect(110.0, 10.0, 2.0, 50.0)),
+                DisplayCommand::SolidColor(red, rect(8.0, 60.0, 104.0, 2.0)),
+                DisplayCommand::SolidColor(red, rect(8.0, 10.0, 2.0, 50.0)),
+            ]
+        );
+
+        // outline: 2px dashed red is not drawn
+        let mut dashed = to_layout_box(block(Some(vec![
+            CSSValue::Length((2.0, Unit::Px)),
+            CSSValue::Keyword("dashed".into()),
+            CSSValue::Keyword("red".into()),
+        ])));
+        dashed.layout(viewport);
+        assert_eq!(build_display_list(&dashed), vec![]);
+    }
+
+    #[test]
+    fn test_build_display_list_border() {
+        let block = |border: Vec<CSSValue>, extra: &[(&str, CSSValue)]| {
+            let mut node = block_node(
+                &[
+                    ("width", CSSValue::Length((100.0, Unit::Px))),
+                    ("height", CSSValue::Length((50.0, Unit::Px))),
+                    ("color", CSSValue::Keyword("blue".into())),
+                    ("background-color", CSSValue::Keyword("lime".into())),
+                ],
+                vec![],
+            );
+            node.properties.extend(
+                expand_shorthand(&Declaration {
+                    name: "border".to_string(),
+                    value: CSSValue::List(border),
+                    important: false,
+                })
+                .into_iter()
+                .map(|d| (d.name, d.value)),
+            );
+            node.properties.extend(
+                extra
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.clone())),
+            );
+            node
+        };
+        let viewport = viewport(800.0, 600.0);
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        let lime = Color::rgb(0, 255, 0);
+        let blue = Color::rgb(0, 0, 255);
+        let red = Color::rgb(255, 0, 0);
+
+        // <div style="border: 2px solid"></div>, whose border color is `color`
+        let mut layout = to_layout_box(block(
+            vec![
+                CSSValue::Length((2.0, Unit::Px)),
+                CSSValue::Keyword("solid".into()),
+            ],
+            &[],
+        ));
+        layout.layout(viewport);
+        assert_eq!(
+            build_display_list(&layout),
+            vec![
+                DisplayCommand::SolidColor(lime, rect(0.0, 0.0, 104.0, 54.0)),
+                DisplayCommand::SolidColor(blue, rect(0.0, 0.0, 104.0, 2.0)),
+                DisplayCommand::SolidColor(blue, rect(102.0, 2.0, 2.0, 50.0)),
+                DisplayCommand::SolidColor(blue, rect(0.0, 52.0, 104.0, 2.0)),
+                DisplayCommand::SolidColor(blue, rect(0.0, 2.0, 2.0, 50.0)),
+            ]
+        );
+
+        // <div style="border: 2px solid red; border-left-style: none"></div>
+        let mut layout = to_layout_box(block(
+            vec![
+                CSSValue::Length((2.0, Unit::Px)),
+                CSSValue::Keyword("solid".into()),
+                CSSValue::Keyword("red".into()),
+            ],
+            &[("border-left-style", CSSValue::Keyword("none".into()))],
+        ));
+        layout.layout(viewport);
+        assert_eq!(
+            build_display_list(&layout),
+            vec![
+                DisplayCommand::SolidColor(lime, rect(0.0, 0.0, 104.0, 54.0)),
+                DisplayCommand::SolidColor(red, rect(0.0, 0.0, 104.0, 2.0)),
+                DisplayCommand::SolidColor(red, rect(102.0, 2.0, 2.0, 50.0)),
+                DisplayCommand::SolidColor(red, rect(0.0, 52.0, 104.0, 2.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_display_list_border_radius() {
+        let block = |border_radius: CSSValue| {
+            block_node(
+                &[
+                    ("height", CSSValue::Length((50.0, Unit::Px))),
+                    ("background-color", CSSValue::Keyword("blue".into())),
+                    ("border-radius", border_radius),
+                ],
+                vec![],
+            )
+        };
+        let viewport = viewport(800.0, 600.0);
+        let border_box = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 800.0,
+            height: 50.0,
+        };
+        let blue = Color::rgb(0, 0, 255);
+
+        // <div style="border-radius: 4px"></div>
+        let mut layout = to_layout_box(block(CSSValue::Length((4.0, Unit::Px))));
+        layout.layout(viewport);
+        assert_eq!(layout.dimensions.border_box(), border_box);
+        assert_eq!(
+            build_display_list(&layout),
+            vec![DisplayCommand::RoundedRect(blue, border_box, 4.0)]
+        );
+
+        // <div style="border-radius: 1%"></div>
+        let mut layout = to_layout_box(block(CSSValue::Length((1.0, Unit::Percent))));
+        layout.layout(viewport);
+        assert_eq!(
+            build_display_list(&layout),
+            vec![DisplayCommand::RoundedRect(blue, border_box, 8.0)]
+        );
+
+        // <div style="border-radius: 0"></div>
+        let mut layout = to_layout_box(block(CSSValue::Length((0.0, Unit::Px))));
+        layout.layout(viewport);
+        assert_eq!(
+            build_display_list(&layout),
+            vec![DisplayCommand::SolidColor(blue, border_box)]
+        );
+    }
+
+    #[test]
+    fn test_build_display_list_box_shadow() {
+        let block = |box_shadow: CSSValue| {
+            block_node(
+                &[
+                    ("width", CSSValue::Length((100.0, Unit::Px))),
+                    ("height", CSSValue::Length((50.0, Unit::Px))),
+                    ("background-color", CSSValue::Keyword("blue".into())),
+                    ("box-shadow", box_shadow),
+                ],
+                vec![],
+            )
+        };
+        let viewport = viewport(800.0, 600.0);
+        let px = |n| CSSValue::Length((n, Unit::Px));
+        let keyword = |s: &str| CSSValue::Keyword(s.to_string().into());
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        let blue = Color::rgb(0, 0, 255);
+        let background = DisplayCommand::SolidColor(blue, rect(0.0, 0.0, 100.0, 50.0));
+
+        // box-shadow: 2px 2px 4px black
+        let mut layout = to_layout_box(block(CSSValue::List(vec![
+            px(2.0),
+            px(2.0),
+            px(4.0),
+            keyword("black"),
+        ])));
+        layout.layout(viewport);
+        assert_eq!(
+            build_display_list(&layout),
+            vec![
+                DisplayCommand::Shadow(Color::rgb(0, 0, 0), rect(2.0, 2.0, 100.0, 50.0), 4.0),
+                background,
+            ]
+        );
+
+        // box-shadow: red 1px 1px, -2px -2px 0 2px lime, inset 3px 3px
+        let mut layout = to_layout_box(block(CSSValue::List(vec![
+            keyword("red"),
+            px(1.0),
+            px(1.0),
+            CSSValue::Comma,
+            px(-2.0),
+            px(-2.0),
+            CSSValue::Number(0.0),
+            px(2.0),
+            keyword("lime"),
+            CSSValue::Comma,
+            keyword("inset"),
+            px(3.0),
+            px(3.0),
+        ])));
+        layout.layout(viewport);
+        let background = DisplayCommand::SolidColor(blue, rect(0.0, 0.0, 100.0, 50.0));
+        assert_eq!(
+            build_display_list(&layout),
+            vec![
+                DisplayCommand::Shadow(Color::rgb(0, 255, 0), rect(-4.0, -4.0, 104.0, 54.0), 0.0),
+                DisplayCommand::Shadow(Color::rgb(255, 0, 0), rect(1.0, 1.0, 100.0, 50.0), 0.0),
+                background,
+            ]
+        );
+
+        // negative offsets from a stylesheet
+        let root = html::parse("<div></div>");
+        let stylesheet = css::parse(
+            "div { display: block; width: 100px; height: 50px; box-shadow: -2px -2px 4px black; }",
+        );
+        let mut layout = to_layout_box(to_styled_node(&root, &stylesheet).unwrap());
+        layout.layout(viewport);
+        assert_eq!(
+            build_display_list(&layout),
+            vec![DisplayCommand::Shadow(
+                Color::rgb(0, 0, 0),
+                rect(-2.0, -2.0, 100.0, 50.0),
+                4.0
+            )]
+        );
+    }
+
+    #[test]
+    fn test_build_display_list_transform() {
+        let block = |background: &str, transform: Option<CSSValue>, children| {
+            let mut node = block_node(
+                &[
+                    ("width", CSSValue::Length((100.0, Unit::Px))),
+                    ("height", CSSValue::Length((50.0, Unit::Px))),
+                    (
+                        "background-color",
+                        CSSValue::Keyword(background.to_string().into()),
+                    ),
+                ],
+                children,
+            );
+            if let Some(transform) = transform {
+                node.properties.insert("transform".to_string(), transform);
+            }
+            node
+        };
+        let viewport = viewport(800.0, 600.0);
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        let translate =
+            |x, y| CSSValue::Transform(TransformFunction::Translate((x, Unit::Px), (y, Unit::Px)));
+        let (red, blue) = (Color::rgb(255, 0, 0), Color::rgb(0, 0, 255));
+
+        // <div style="transform: translate(10px, 5px)"><div style="height: 50px"></div></div>
+        let mut layout = to_layout_box(block(
+            "red",
+            Some(translate(10.0, 5.0)),
+            vec![block("blue", None, vec![])],
+        ));
+        layout.layout(viewport);
+        let mut plain = to_layout_box(block("red", None, vec![block("blue", None, vec![])]));
+        plain.layout(viewport);
+
+        // the layout is not affected, but the box and its descendants are painted at the translated position
+        assert_eq!(layout.dimensions, plain.dimensions);
+        assert_eq!(layout.children[0].dimensions, plain.children[0].dimensions);
+        assert_eq!(
+            build_display_list(&layout),
+            vec![
+                DisplayCommand::SolidColor(red, rect(10.0, 5.0, 100.0, 50.0)),
+                DisplayCommand::SolidColor(blue, rect(10.0, 5.0, 100.0, 50.0)),
+            ]
+        );
+
+        // transform: translate(10px, 5px) scale(2), which scales around the center (50px, 25px) and then translates
+        let mut layout = to_layout_box(block(
+            "red",
+            Some(CSSValue::List(vec![
+                translate(10.0, 5.0),
+                CSSValue::Transform(TransformFunction::Scale(2.0, 2.0)),
+            ])),
+            vec![],
+        ));
+        layout.layout(viewport);
+        assert_eq!(
+            build_display_list(&layout),
+            vec![DisplayCommand::SolidColor(
+                red,
+                rect(-40.0, -20.0, 200.0, 100.0)
+            )]
+        );
+
+        // the transforms of the ancestors are applied after the ones of the descendants
+        let mut layout = to_layout_box(block(
+            "red",
+            Some(translate(10.0, 0.0)),
+            vec![block(
+                "blue",
+                Some(CSSValue::Transform(TransformFunction::Scale(0.5, 1.0))),
+                vec![],
+            )],
+        ));
+        layout.layout(viewport);
+        assert_eq!(
+            build_display_list(&layout),
+            vec![
+                DisplayCommand::SolidColor(red, rect(10.0, 0.0, 100.0, 50.0)),
+                DisplayCommand::SolidColor(blue, rect(35.0, 0.0, 50.0, 50.0)),
+            ]
+        );
+
+        // unsupported functions like `rotate(45deg)` are ignored with the whole `transform`
+        let mut layout = to_layout_box(block(
+            "red",
+            Some(CSSValue::List(vec![
+                translate(10.0, 0.0),
+                CSSValue::Function("rotate".into(), "45deg".into()),
+            ])),
+            vec![],
+        ));
+        layout.layout(viewport);
+        assert_eq!(
+            build_display_list(&layout),
+            vec![DisplayCommand::SolidColor(red, rect(0.0, 0.0, 100.0, 50.0))]
+        );
+    }
+
+    #[test]
+    fn test_build_display_list_background_image() {
+        let viewport = viewport(800.0, 600.0);
+
+        // <div style="height: 50px; padding-top: 10px; background-image: url(a.png); background-position: 10px 20px"></div>
+        let mut layout = to_layout_box(block_node(
+            &[
+                ("height", CSSValue::Length((50.0, Unit::Px))),
+                ("padding-top", CSSValue::Length((10.0, Unit::Px))),
+                ("background-image", CSSValue::Url("a.png".to_string())),
+                (
+                    "background-position",
+                    CSSValue::List(vec![
+                        CSSValue::Length((10.0, Unit::Px)),
+                        CSSValue::Length((20.0, Unit::Px)),
+                    ]),
+                ),
+            ],
+            vec![],
+        ));
+        layout.layout(viewport);
+
+        assert_eq!(
+            build_display_list(&layout),
+            vec![DisplayCommand::BackgroundImage(
+                "a.png".to_string(),
+                Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    width: 800.0,
+                    height: 60.0,
+                },
+                BackgroundPosition {
+                    x: (10.0, Unit::Px),
+                    y: (20.0, Unit::Px),
+                }
+            )]
+        );
+    }
+}
@@ -48,6 +48,10 @@ pub fn to_element_container<'a>(layout: LayoutBox<'a>) -> ElementContainer {
                     (DummyView {}).into_boxed_view()
                 }
             }
+            BoxProps {
+                node_type: NodeType::Comment(_) | NodeType::DocumentType(_),
+                ..
+            } => (DummyView {}).into_boxed_view(),
         },
         BoxType::AnonymousBox => {
             let mut p = Panel::new(LinearLayout::horizontal());
@@ -1,20 +1,200 @@
 //! This module includes some implementations on node styles.
 
 use crate::{
-    css::{CSSValue, Stylesheet},
+    css::{
+        self, BackgroundPosition, BoxShadow, CSSValue, Color, Declaration, Direction,
+        LengthContext, MatchContext, MediaQuery, Origin, PseudoElement, Rule, SortedStylesheet,
+        Specificity, Stylesheet, SupportsCondition, TransformFunction, Unit,
+    },
     dom::{Node, NodeType},
 };
-use std::collections::HashMap;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 pub type PropertyMap = HashMap<String, CSSValue>;
 
-#[derive(Debug, PartialEq)]
+/// `MEDIUM_FONT_SIZE` is the font size in px of the `medium` keyword, which is also the initial font size.
+pub const MEDIUM_FONT_SIZE: f32 = 16.0;
+
+/// `FONT_SIZE_RATIO` is the scaling factor of the relative-size keywords `smaller` and `larger`.
+/// See https://drafts.csswg.org/css-fonts/#relative-size-value
+const FONT_SIZE_RATIO: f32 = 1.2;
+
+/// `PSEUDO_ELEMENT_PROPERTIES` are the properties which `::first-line` and `::first-letter` can set.
+///
+/// NOTE: Only a subset of the properties in https://www.w3.org/TR/css-pseudo-4/#first-line-styling is supported.
+const PSEUDO_ELEMENT_PROPERTIES: [&str; 2] = ["color", "font-size"];
+
+/// `GENERIC_FONT_FAMILIES` are the generic font families, which are always available.
+/// See https://drafts.csswg.org/css-fonts/#generic-font-families
+const GENERIC_FONT_FAMILIES: [&str; 6] = [
+    "serif",
+    "sans-serif",
+    "monospace",
+    "cursive",
+    "fantasy",
+    "system-ui",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Display {
     Inline,
     Block,
+    Grid,
+    /// `ListItem`, `Table` and `Flex` are block-level, and their contents are laid out in block flow for simplicity.
+    ListItem,
+    Table,
+    Flex,
+    /// `InlineBlock` is inline-level, and its contents are laid out like an inline box for simplicity.
+    InlineBlock,
+    /// `TableColumn` and `TableColumnGroup` do not generate boxes by themselves.
+    /// They only contribute to the column widths of the table.
+    /// See https://www.w3.org/TR/CSS2/tables.html#columns
+    TableColumn,
+    TableColumnGroup,
     None,
 }
 
+/// `Inherited` holds the computed values of the parent which its children inherit.
+#[derive(Debug, Clone, PartialEq)]
+struct Inherited {
+    /// `font_size` is the computed font size in px.
+    font_size: f32,
+    /// `root_font_size` is the computed font size in px of the root element, which `rem` refers to.
+    /// It is `None` for the root element itself.
+    root_font_size: Option<f32>,
+    direction: Direction,
+    line_height: LineHeight,
+    /// `properties` are the values of `INHERITED_PROPERTIES` which the parent has.
+    properties: PropertyMap,
+}
+
+impl Default for Inherited {
+    fn default() -> Self {
+        Inherited {
+            font_size: MEDIUM_FONT_SIZE,
+            root_font_size: None,
+            direction: Direction::default(),
+            line_height: LineHeight::default(),
+            properties: PropertyMap::new(),
+        }
+    }
+}
+
+/// `INHERITED_PROPERTIES` are the properties which children take from their parents unless specified.
+/// `font-size`, `line-height` and `direction` are not listed since they are computed before being inherited.
+/// See https://www.w3.org/TR/CSS2/propidx.html
+const INHERITED_PROPERTIES: [&str; 13] = [
+    "color",
+    "font-family",
+    "font-style",
+    "font-weight",
+    "letter-spacing",
+    "list-style-type",
+    "text-align",
+    "text-indent",
+    "text-transform",
+    "visibility",
+    "white-space",
+    "word-spacing",
+    "writing-mode",
+];
+
+/// `inherited_values` picks the values which children take from `properties` of their parent,
+/// i.e. the values of `INHERITED_PROPERTIES` and the computed `font-size`, `line-height` and `direction`.
+fn inherited_values(properties: &PropertyMap) -> PropertyMap {
+    let mut values = inherited_properties(properties);
+    for name in ["font-size", "line-height", "direction"] {
+        if let Some(value) = properties.get(name) {
+            values.insert(name.to_string(), value.clone());
+        }
+    }
+    values
+}
+
+/// `inherited_properties` picks the values of `INHERITED_PROPERTIES` out of `properties`.
+fn inherited_properties(properties: &PropertyMap) -> PropertyMap {
+    INHERITED_PROPERTIES
+        .iter()
+        .filter_map(|&name| {
+            properties
+                .get(name)
+                .map(|value| (name.to_string(), value.clone()))
+        })
+        .collect()
+}
+
+/// `LineHeight` is the computed value of `line-height`.
+/// See https://drafts.csswg.org/css-inline/#line-height-property
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum LineHeight {
+    #[default]
+    Normal,
+    /// `Number` is kept as it is, so that children multiply it by their own font sizes.
+    Number(f32),
+    /// `Px` is a length or a percentage resolved against the font size of the element itself,
+    /// which children inherit as it is.
+    Px(f32),
+}
+
+impl LineHeight {
+    /// `compute` resolves `value` with the computed `font_size` of the element.
+    /// It returns `None` for invalid values.
+    fn compute(value: &CSSValue, font_size: f32) -> Option<LineHeight> {
+        match value {
+            CSSValue::Keyword(keyword) if keyword == "normal" => Some(LineHeight::Normal),
+            CSSValue::Number(num) => Some(LineHeight::Number(*num)),
+            CSSValue::Length((num, unit)) => match unit {
+                Unit::Px => Some(*num),
+                Unit::Em => Some(num * font_size),
+                Unit::Rem => Some(num * MEDIUM_FONT_SIZE),
+                Unit::Percent => Some(font_size * num / 100.0),
+                Unit::Fr => None,
+            }
+            .map(LineHeight::Px),
+            _ => None,
+        }
+    }
+
+    fn to_value(self) -> CSSValue {
+        match self {
+            LineHeight::Normal => CSSValue::Keyword("normal".into()),
+            LineHeight::Number(num) => CSSValue::Number(num),
+            LineHeight::Px(px) => CSSValue::Length((px, Unit::Px)),
+        }
+    }
+}
+
+/// `WritingMode` is the direction in which blocks are stacked.
+/// See https://drafts.csswg.org/css-writing-modes/#block-flow
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum WritingMode {
+    #[default]
+    HorizontalTb,
+    VerticalRl,
+    VerticalLr,
+}
+
+/// `Float` is the side to which a box is floated.
+/// See https://www.w3.org/TR/CSS2/visuren.html#float-position
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Float {
+    Left,
+    Right,
+}
+
+/// `Clear` is the sides of the preceding floats which a box is placed below.
+/// See https://www.w3.org/TR/CSS2/visuren.html#flow-control
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Clear {
+    Left,
+    Right,
+    Both,
+}
+
 /// `StyledNode` wraps `Node` with related CSS properties.
 /// It forms a tree as `Node` does.
 #[derive(Debug, PartialEq)]
@@ -24,33 +204,776 @@ pub struct StyledNode<'a> {
     pub children: Vec<StyledNode<'a>>,
 }
 
-pub fn to_styled_node<'a>(node: &'a Box<Node>, stylesheet: &Stylesheet) -> Option<StyledNode<'a>> {
+/// `CompactStyledNode` is a memory-compact alternative of `StyledNode` for large documents.
+/// It omits the properties which have the same values as the ones inherited from the parent,
+/// or the ones in `INITIAL_VALUES`, and `value` restores them on demand.
+#[derive(Debug, PartialEq)]
+pub struct CompactStyledNode<'a> {
+    pub node_type: &'a NodeType,
+    /// `properties` are the values which differ from `inherited` and `INITIAL_VALUES`.
+    /// `None` marks a property which the parent has but the node does not, e.g. an unset `line-height`.
+    properties: HashMap<String, Option<CSSValue>>,
+    /// `inherited` are the values of the parent which the node inherits.
+    /// It is shared among the siblings, and with the children unless the node changes any of them.
+    inherited: Rc<PropertyMap>,
+    pub children: Vec<CompactStyledNode<'a>>,
+}
+
+/// `INITIAL_VALUES` are the values which `to_styled_node` sets when no rule specifies them and nothing is inherited.
+static INITIAL_VALUES: [(&str, CSSValue); 4] = [
+    ("display", CSSValue::Keyword(Cow::Borrowed("inline"))),
+    ("font-weight", CSSValue::Keyword(Cow::Borrowed("normal"))),
+    ("font-size", CSSValue::Length((MEDIUM_FONT_SIZE, Unit::Px))),
+    ("direction", CSSValue::Keyword(Cow::Borrowed("ltr"))),
+];
+
+/// `StyleContext` represents the environment where a document is styled, e.g. user preferences.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StyleContext {
+    pub color_scheme: ColorScheme,
+    /// `default_display` is the `display` value of each tag used when no rule sets `display`.
+    /// Tags not in the map fall back to `inline`.
+    pub default_display: HashMap<String, Display>,
+    pub mode: DocumentMode,
+    /// `available_fonts` are the font families installed, which `font-family` is resolved into.
+    /// `font-family` is kept as specified when it is `None`.
+    pub available_fonts: Option<AvailableFonts>,
+}
+
+/// `AvailableFonts` is a set of font family names, which are compared case-insensitively.
+/// The names are stored lowercased so that a lookup is a single hash lookup rather than a scan.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AvailableFonts(HashSet<String>);
+
+impl AvailableFonts {
+    /// `contains` tells whether the family `name` is available, ignoring ASCII case.
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains(&name.to_ascii_lowercase())
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for AvailableFonts {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        AvailableFonts(
+            iter.into_iter()
+                .map(|name| name.as_ref().to_ascii_lowercase())
+                .collect(),
+        )
+    }
+}
+
+/// `DocumentMode` is the mode of a document, which switches some behaviors for legacy documents.
+/// See https://dom.spec.whatwg.org/#concept-document-mode
+///
+/// NOTE: Only the following quirks are supported for simplicity:
+/// - https://quirks.spec.whatwg.org/#the-unitless-length-quirk
+/// - tables do not inherit `font-size` (https://html.spec.whatwg.org/multipage/rendering.html#tables-2)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum DocumentMode {
+    #[default]
+    NoQuirks,
+    Quirks,
+}
+
+impl DocumentMode {
+    /// `from_document` infers the mode of the document whose root node is `root`.
+    /// A document is in quirks mode unless it has `<!DOCTYPE html>` at the top level.
+    /// See https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode
+    pub fn from_document(root: &Node) -> Self {
+        let doctype = std::iter::once(root)
+            .chain(root.children.iter().map(|c| c.as_ref()))
+            .find_map(|n| match n.node_type {
+                NodeType::DocumentType(ref d) => Some(d),
+                _ => None,
+            });
+        match doctype {
+            Some(d) if d.name.eq_ignore_ascii_case("html") => DocumentMode::NoQuirks,
+            _ => DocumentMode::Quirks,
+        }
+    }
+}
+
+/// `UNITLESS_LENGTH_PROPERTIES` are the properties which accept numbers as px in quirks mode.
+/// See https://quirks.spec.whatwg.org/#the-unitless-length-quirk
+const UNITLESS_LENGTH_PROPERTIES: [&str; 25] = [
+    "font-size",
+    "width",
+    "height",
+    "min-width",
+    "min-height",
+    "max-width",
+    "max-height",
+    "margin",
+    "margin-top",
+    "margin-right",
+    "margin-bottom",
+    "margin-left",
+    "padding",
+    "padding-top",
+    "padding-right",
+    "padding-bottom",
+    "padding-left",
+    "border-top-width",
+    "border-right-width",
+    "border-bottom-width",
+    "border-left-width",
+    "top",
+    "right",
+    "bottom",
+    "left",
+];
+
+/// `ColorScheme` is the color scheme preferred by the user.
+/// See https://drafts.csswg.org/mediaqueries-5/#prefers-color-scheme
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ColorScheme {
+    #[default]
+    Light,
+    Dark,
+}
+
+impl From<&str> for ColorScheme {
+    /// Unknown preferences fall back to `light`.
+    fn from(s: &str) -> Self {
+        match s {
+            "dark" => ColorScheme::Dark,
+            _ => ColorScheme::Light,
+        }
+    }
+}
+
+impl StyleContext {
+    /// `matches_media` evaluates `query` in this context.
+    /// Unknown media features never match.
+    pub fn matches_media(&self, query: &MediaQuery) -> bool {
+        query
+            .features
+            .iter()
+            .all(|feature| match (feature.name.as_str(), &feature.value) {
+                ("prefers-color-scheme", CSSValue::Keyword(scheme)) => match scheme.as_ref() {
+                    "light" => self.color_scheme == ColorScheme::Light,
+                    "dark" => self.color_scheme == ColorScheme::Dark,
+                    _ => false,
+                },
+                _ => false,
+            })
+    }
+}
+
+/// `ValueTest` tells whether a value is accepted by a property.
+type ValueTest = fn(&CSSValue) -> bool;
+
+/// `SUPPORTED_PROPERTIES` are the longhand properties which this crate understands,
+/// each with the test of the values it accepts. It is the only list `supports` refers to,
+/// so a property must be listed here once style, layout or paint starts consuming it.
+/// The tests reuse the parsers of the consumers where they exist, e.g. `Display::from_keyword`.
+const SUPPORTED_PROPERTIES: [(&str, ValueTest); 54] = [
+    ("display", |v| {
+        keyword_of(v).is_some_and(|k| Display::from_keyword(k).is_some())
+    }),
+    ("direction", |v| {
+        keyword_of(v).is_some_and(|k| Direction::from_keyword(k).is_some())
+    }),
+    ("writing-mode", |v| {
+        keyword_of(v).is_some_and(|k| WritingMode::from_keyword(k).is_some())
+    }),
+    ("float", |v| {
+        keyword_of(v).is_some_and(|k| k == "none" || Float::from_keyword(k).is_some())
+    }),
+    ("clear", |v| {
+        keyword_of(v).is_some_and(|k| k == "none" || Clear::from_keyword(k).is_some())
+    }),
+    ("content-visibility", |v| {
+        is_keyword_of(v, &["visible", "hidden", "auto"])
+    }),
+    ("overflow", |v| is_keyword_of(v, &["visible", "hidden"])),
+    ("white-space", |v| is_keyword_of(v, &["normal", "nowrap"])),
+    ("text-overflow", |v| is_keyword_of(v, &["clip", "ellipsis"])),
+    ("break-inside", |v| is_keyword_of(v, &["auto", "avoid"])),
+    ("color", is_color),
+    ("background-color", is_color),
+    ("border-top-color", is_color),
+    ("border-right-color", is_color),
+    ("border-bottom-color", is_color),
+    ("border-left-color", is_color),
+    ("outline-color", is_color),
+    ("border-top-style", is_line_style),
+    ("border-right-style", is_line_style),
+    ("border-bottom-style", is_line_style),
+    ("border-left-style", is_line_style),
+    ("outline-style", is_line_style),
+    ("border-top-width", is_line_width),
+    ("border-right-width", is_line_width),
+    ("border-bottom-width", is_line_width),
+    ("border-left-width", is_line_width),
+    ("outline-width", is_line_width),
+    ("border-radius", is_length),
+    ("background-image", |v| {
+        matches!(v, CSSValue::Url(_)) || is_keyword_of(v, &["none"])
+    }),
+    ("background-position", |v| {
+        BackgroundPosition::from_value(v).is_some()
+    }),
+    ("box-shadow", |v| {
+        let values = match v {
+            CSSValue::Keyword(keyword) => return keyword == "none",
+            CSSValue::List(values) => values.as_slice(),
+            value => std::slice::from_ref(value),
+        };
+        let context = LengthContext {
+            font_size: MEDIUM_FONT_SIZE,
+            root_font_size: MEDIUM_FONT_SIZE,
+            percentage_basis: 0.0,
+        };
+        values
+            .split(|v| *v == CSSValue::Comma)
+            .all(|values| BoxShadow::from_values(values, Color::rgb(0, 0, 0), &context).is_some())
+    }),
+    ("transform", |v| match v {
+        CSSValue::Keyword(keyword) => keyword == "none",
+        CSSValue::Transform(_) => true,
+        CSSValue::List(values) => values.iter().all(|v| matches!(v, CSSValue::Transform(_))),
+        _ => false,
+    }),
+    ("font-size", |v| match v {
+        CSSValue::Keyword(keyword) => {
+            matches!(keyword.as_ref(), "smaller" | "larger")
+                || absolute_font_size(keyword).is_some()
+        }
+        CSSValue::Length((_, unit)) => *unit != Unit::Fr,
+        _ => false,
+    }),
+    ("font-weight", |v| is_keyword_of(v, &["normal", "bold"])),
+    ("font-style", |v| {
+        is_keyword_of(v, &["normal", "italic", "oblique"])
+    }),
+    ("font-family", |v| match v {
+        CSSValue::List(values) => values.iter().all(|v| {
+            matches!(
+                v,
+                CSSValue::Keyword(_) | CSSValue::String(_) | CSSValue::Comma
+            )
+        }),
+        v => matches!(v, CSSValue::Keyword(_) | CSSValue::String(_)),
+    }),
+    ("line-height", |v| {
+        LineHeight::compute(v, MEDIUM_FONT_SIZE).is_some()
+    }),
+    ("width", |v| is_length(v) || is_keyword_of(v, &["auto"])),
+    ("height", |v| is_length(v) || is_keyword_of(v, &["auto"])),
+    ("min-height", is_length),
+    ("max-height", |v| {
+        is_length(v) || is_keyword_of(v, &["none"])
+    }),
+    ("aspect-ratio", |v| {
+        matches!(v, CSSValue::Ratio(_)) || is_keyword_of(v, &["auto"])
+    }),
+    ("grid-template-columns", |v| match v {
+        CSSValue::Keyword(keyword) => keyword == "none",
+        CSSValue::Length(_) => true,
+        CSSValue::List(values) => values.iter().all(is_length),
+        _ => false,
+    }),
+    ("gap", |v| match v {
+        CSSValue::Length(_) => true,
+        CSSValue::List(values) => values.len() == 2 && values.iter().all(is_length),
+        _ => false,
+    }),
+    ("row-gap", is_length),
+    ("column-gap", is_length),
+    ("margin-top", is_length),
+    ("margin-right", is_length),
+    ("margin-bottom", is_length),
+    ("margin-left", is_length),
+    ("padding-top", is_length),
+    ("padding-right", is_length),
+    ("padding-bottom", is_length),
+    ("padding-left", is_length),
+];
+
+/// `keyword_of` returns the keyword of `value`, or `None` if it is not a keyword.
+fn keyword_of(value: &CSSValue) -> Option<&str> {
+    match value {
+        CSSValue::Keyword(keyword) => Some(keyword),
+        _ => None,
+    }
+}
+
+/// `is_keyword_of` tells whether `value` is one of `keywords`.
+fn is_keyword_of(value: &CSSValue, keywords: &[&str]) -> bool {
+    keyword_of(value).is_some_and(|keyword| keywords.contains(&keyword))
+}
+
+fn is_length(value: &CSSValue) -> bool {
+    matches!(value, CSSValue::Length(_))
+}
+
+/// `is_color` accepts the values which `resolve_color` resolves, including `currentcolor`.
+fn is_color(value: &CSSValue) -> bool {
+    value.to_color().is_some() || is_keyword_of(value, &["currentcolor"])
+}
+
+/// `is_line_style` accepts the values of `border-style` and `outline-style`. See `line_values`.
+fn is_line_style(value: &CSSValue) -> bool {
+    is_keyword_of(
+        value,
+        &[
+            "none", "auto", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge",
+            "inset", "outset",
+        ],
+    )
+}
+
+/// `is_line_width` accepts the values of `border-width` and `outline-width`.
+fn is_line_width(value: &CSSValue) -> bool {
+    is_length(value) || is_keyword_of(value, &["thin", "medium", "thick"])
+}
+
+/// `supports` tells whether this crate understands `declaration`. It is used to evaluate `@supports`.
+/// Shorthands like `margin` are supported when all of their longhands are, which are looked up in `SUPPORTED_PROPERTIES`.
+pub fn supports(declaration: &Declaration) -> bool {
+    let longhands = expand_shorthand(declaration);
+    !longhands.is_empty()
+        && longhands.iter().all(|longhand| {
+            SUPPORTED_PROPERTIES
+                .iter()
+                .any(|(name, test)| *name == longhand.name && test(&longhand.value))
+        })
+}
+
+/// `evaluate_supports` evaluates the condition of `@supports`.
+pub fn evaluate_supports(condition: &SupportsCondition) -> bool {
+    match condition {
+        SupportsCondition::Declaration(declaration) => supports(declaration),
+        SupportsCondition::Not(condition) => !evaluate_supports(condition),
+        SupportsCondition::And(conditions) => conditions.iter().all(evaluate_supports),
+        SupportsCondition::Or(conditions) => conditions.iter().any(evaluate_supports),
+        SupportsCondition::Unknown => false,
+    }
+}
+
+/// `Cascade` is a stylesheet which gives the declarations applying to a node in the cascade order.
+/// It is implemented by `Stylesheet`, which sorts the matched declarations for each node,
+/// `SortedStylesheet`, which is sorted in advance,
+/// and `[Stylesheet]`, which cascades multiple stylesheets tagged with their origins by `Stylesheet::with_origin`.
+pub trait Cascade {
+    /// `cascaded_declarations` returns the declarations applying to `node`, or its `pseudo_element` if any, in `context`.
+    /// They are in the cascade order, i.e. each one overrides the preceding ones, and shorthands are expanded.
+    fn cascaded_declarations(
+        &self,
+        node: &Node,
+        match_context: &MatchContext,
+        context: &StyleContext,
+        pseudo_element: Option<PseudoElement>,
+    ) -> Vec<Declaration>;
+}
+
+impl Cascade for Stylesheet {
+    fn cascaded_declarations(
+        &self,
+        node: &Node,
+        match_context: &MatchContext,
+        context: &StyleContext,
+        pseudo_element: Option<PseudoElement>,
+    ) -> Vec<Declaration> {
+        std::slice::from_ref(self).cascaded_declarations(
+            node,
+            match_context,
+            context,
+            pseudo_element,
+        )
+    }
+}
+
+impl Cascade for [Stylesheet] {
+    /// The stylesheets are regarded as one in the order of the slice, where the origins of the rules take precedence
+    /// over the layers and the specificities.
+    fn cascaded_declarations(
+        &self,
+        node: &Node,
+        match_context: &MatchContext,
+        context: &StyleContext,
+        pseudo_element: Option<PseudoElement>,
+    ) -> Vec<Declaration> {
+        let mut declarations = self
+            .iter()
+            .flat_map(|stylesheet| {
+                matched_declarations(stylesheet, context, |r| match pseudo_element {
+                    Some(pseudo_element) => {
+                        r.pseudo_element_specificity(node, match_context, pseudo_element)
+                    }
+                    None => r.specificity(node, match_context),
+                })
+            })
+            .collect::<Vec<_>>();
+        sort_in_cascade_order(&mut declarations);
+        declarations.into_iter().map(|(d, _)| d).collect()
+    }
+}
+
+impl Cascade for SortedStylesheet {
+    fn cascaded_declarations(
+        &self,
+        node: &Node,
+        match_context: &MatchContext,
+        context: &StyleContext,
+        pseudo_element: Option<PseudoElement>,
+    ) -> Vec<Declaration> {
+        // NOTE: A rule with multiple matching selectors is applied more than once,
+        // which gives the same result as applying it once with the highest specificity.
+        let (mut important, normal): (Vec<_>, Vec<_>) = self
+            .order
+            .iter()
+            .map(|&(i, j)| (&self.rules()[i], &self.rules()[i].selectors[j]))
+            .filter(|(rule, selector)| {
+                applies(rule, context)
+                    && match pseudo_element {
+                        Some(pseudo_element) => {
+                            selector.matches_pseudo_element(node, match_context, pseudo_element)
+                        }
+                        None => selector.matches(node, match_context),
+                    }
+            })
+            .flat_map(|(rule, _)| {
+                rule.declarations
+                    .iter()
+                    .flat_map(expand_shorthand)
+                    .map(move |d| (rule.origin, d))
+            })
+            .partition(|(_, d)| d.important);
+        // `!important` declarations win over all the normal ones, where the order of the origins is reversed
+        important.sort_by_key(|(origin, _)| origin.cascade_order(true));
+        normal
+            .into_iter()
+            .chain(important)
+            .map(|(_, d)| d)
+            .collect()
+    }
+}
+
+/// `USER_AGENT_STYLESHEET` is the default stylesheet of the browser, which makes raw HTML readable.
+/// See https://html.spec.whatwg.org/multipage/rendering.html
+///
+/// NOTE: Only the properties this crate understands are set, e.g. margins are omitted.
+pub const USER_AGENT_STYLESHEET: &str = r#"
+head, script, style, title, meta, link, template {
+    display: none;
+}
+html, body, div, p, h1, h2, h3, h4, h5, h6, ul, ol, dl, dt, dd, blockquote, pre, hr,
+address, article, aside, footer, header, main, nav, section, figure, figcaption, form {
+    display: block;
+}
+li {
+    display: list-item;
+}
+table {
+    display: table;
+}
+h1, h2, h3, h4, h5, h6, b, strong, th {
+    font-weight: bold;
+}
+em, i, cite, var, dfn, address {
+    font-style: italic;
+}
+h1 {
+    font-size: 2em;
+}
+h2 {
+    font-size: 1.5em;
+}
+h3 {
+    font-size: 1.17em;
+}
+h5 {
+    font-size: 0.83em;
+}
+h6 {
+    font-size: 0.67em;
+}
+"#;
+
+/// `user_agent_stylesheet` parses `USER_AGENT_STYLESHEET` with the rules marked as `Origin::UserAgent`.
+/// Cascade it with the author stylesheets, e.g. as a slice of stylesheets, where the author rules win over it by origin.
+pub fn user_agent_stylesheet() -> Stylesheet {
+    css::parse(USER_AGENT_STYLESHEET).with_origin(Origin::UserAgent)
+}
+
+/// `ResourceLoader` loads the external resources referenced by a document, e.g. from files or the network.
+pub trait ResourceLoader {
+    /// `load` returns the contents of `url`, or `None` when it cannot be loaded.
+    fn load(&self, url: &str) -> Option<String>;
+}
+
+/// A map from URLs to their contents works as an in-memory loader.
+impl ResourceLoader for HashMap<String, String> {
+    fn load(&self, url: &str) -> Option<String> {
+        self.get(url).cloned()
+    }
+}
+
+/// `collect_stylesheets` parses the contents of the `<style>` elements in the tree of `root` in the document order,
+/// and combines them into one author stylesheet. Stylesheets which cannot be parsed are skipped.
+/// See https://html.spec.whatwg.org/multipage/semantics.html#the-style-element
+pub fn collect_stylesheets(root: &Node) -> Stylesheet {
+    let mut sources = vec![];
+    collect_style_sources(root, None, &mut sources);
+    Stylesheet::merge(
+        sources
+            .iter()
+            .filter_map(|source| css::try_parse(source).ok()),
+    )
+}
+
+/// `collect_stylesheets_with_loader` is the same as `collect_stylesheets`, except that it also loads
+/// the external stylesheets of `<link rel="stylesheet" href="...">` with `loader` in the document order.
+/// Stylesheets which cannot be loaded or parsed are skipped, and `href` is passed to `loader` as it is.
+/// See https://html.spec.whatwg.org/multipage/links.html#link-type-stylesheet
+///
+/// NOTE: Alternative stylesheets, i.e. `rel="alternate stylesheet"`, are never loaded.
+pub fn collect_stylesheets_with_loader(root: &Node, loader: &impl ResourceLoader) -> Stylesheet {
+    let mut sources = vec![];
+    collect_style_sources(root, Some(loader), &mut sources);
+    Stylesheet::merge(
+        sources
+            .iter()
+            .filter_map(|source| css::try_parse(source).ok()),
+    )
+}
+
+/// `collect_style_sources` collects the CSS of the `<style>` elements, and the linked stylesheets if `loader` is given.
+fn collect_style_sources(
+    node: &Node,
+    loader: Option<&dyn ResourceLoader>,
+    sources: &mut Vec<String>,
+) {
+    match node.node_type {
+        NodeType::Element(ref e) if e.tag_name == "style" => sources.push(node.inner_text()),
+        NodeType::Element(ref e) if e.tag_name == "link" => {
+            if !e.has_token("rel", "stylesheet") || e.has_token("rel", "alternate") {
+                return;
+            }
+            if let Some(source) = loader
+                .zip(e.attributes.get("href"))
+                .and_then(|(loader, href)| loader.load(href))
+            {
+                sources.push(source);
+            }
+        }
+        _ => {
+            for child in &node.children {
+                collect_style_sources(child, loader, sources);
+            }
+        }
+    }
+}
+
+pub fn to_styled_node<'a>(
+    node: &'a Box<Node>,
+    stylesheet: &(impl Cascade + ?Sized),
+) -> Option<StyledNode<'a>> {
+    to_styled_node_with_context(node, stylesheet, &StyleContext::default())
+}
+
+pub fn to_styled_node_with_context<'a>(
+    node: &'a Box<Node>,
+    stylesheet: &(impl Cascade + ?Sized),
+    context: &StyleContext,
+) -> Option<StyledNode<'a>> {
+    style_node(
+        node,
+        &MatchContext::for_document(node),
+        &Inherited::default(),
+        stylesheet,
+        context,
+    )
+}
+
+/// `style_node` styles `node` which is matched with `match_context`.
+/// `parent` is the computed values inherited from the parent.
+fn style_node<'a>(
+    node: &'a Node,
+    match_context: &MatchContext,
+    parent: &Inherited,
+    stylesheet: &(impl Cascade + ?Sized),
+    context: &StyleContext,
+) -> Option<StyledNode<'a>> {
+    // comments and doctypes are never rendered
+    if let NodeType::Comment(_) | NodeType::DocumentType(_) = node.node_type {
+        return None;
+    }
+
     let mut properties = PropertyMap::new();
-    let children = to_styled_nodes(&node.children, stylesheet);
 
-    // match CSS rules
-    for matched_rule in stylesheet.rules.iter().filter(|r| r.matches(node)) {
-        for declaration in &matched_rule.declarations {
-            properties.insert(declaration.name.clone(), declaration.value.clone());
+    // match CSS rules, and then apply the `style` attribute, which wins over the rules of the same importance
+    // https://drafts.csswg.org/css-cascade-4/#style-attr
+    let mut declarations = stylesheet.cascaded_declarations(node, match_context, context, None);
+    declarations.extend(inline_declarations(node));
+    // `sort_by_key` is stable, and the cascaded declarations are already sorted with the important ones at the end
+    // NOTE: The style attribute is regarded as the last author declarations, so its `!important` declarations
+    // win over the important UA and user ones too for simplicity.
+    declarations.sort_by_key(|d| d.important);
+    for declaration in declarations {
+        // numbers are invalid for lengths outside quirks mode, so such declarations are dropped
+        // instead of overriding the valid ones
+        // https://drafts.csswg.org/css-syntax-3/#consume-declaration
+        if context.mode != DocumentMode::Quirks
+            && matches!(declaration.value, CSSValue::Number(_))
+            && UNITLESS_LENGTH_PROPERTIES.contains(&declaration.name.as_str())
+        {
+            continue;
+        }
+        properties.insert(declaration.name, declaration.value);
+    }
+
+    // resolve font-family into the family which is actually used
+    // before inheriting it, since the inherited one is already resolved by the parent
+    // https://drafts.csswg.org/css-fonts/#font-family-prop
+    if let (Some(available_fonts), Some(value)) =
+        (&context.available_fonts, properties.get("font-family"))
+    {
+        let family = resolve_font_family(value, available_fonts);
+        properties.insert("font-family".into(), family);
+    }
+
+    // inherit the properties of the parent which no rule specifies
+    // https://www.w3.org/TR/css-cascade-4/#inheriting
+    for (name, value) in &parent.properties {
+        if !properties.contains_key(name) {
+            properties.insert(name.clone(), value.clone());
+        }
+    }
+
+    // numbers are regarded as px for some properties in quirks mode
+    // https://quirks.spec.whatwg.org/#the-unitless-length-quirk
+    if context.mode == DocumentMode::Quirks {
+        for name in UNITLESS_LENGTH_PROPERTIES {
+            if let Some(&CSSValue::Number(num)) = properties.get(name) {
+                properties.insert(name.to_string(), CSSValue::Length((num, Unit::Px)));
+            }
         }
     }
 
-    // set the initial display property `inline` if not set
+    // set the default display property of the tag if not set, or the initial value later
     // https://drafts.csswg.org/css-display/#the-display-properties
     if properties.get("display") == None {
-        properties.insert("display".into(), CSSValue::Keyword("inline".into()));
+        if let NodeType::Element(e) = &node.node_type {
+            if let Some(display) = context.default_display.get(&e.tag_name) {
+                properties.insert(
+                    "display".into(),
+                    CSSValue::Keyword(display.to_keyword().into()),
+                );
+            }
+        }
     }
 
+    // `display: none` removes the whole subtree, so its descendants are not even styled
+    // https://drafts.csswg.org/css-display/#valdef-display-none
     if properties.get("display") == Some(&CSSValue::Keyword("none".into())) {
         return None;
     }
 
-    // set the initial font-weight property `normal` if not set
+    // compute font-size into px since descendants inherit the computed value
+    // https://drafts.csswg.org/css-fonts/#font-size-prop
+    // tables do not inherit font-size in quirks mode
+    // https://html.spec.whatwg.org/multipage/rendering.html#tables-2
+    let parent_font_size = match node.node_type {
+        NodeType::Element(ref e)
+            if context.mode == DocumentMode::Quirks && e.tag_name == "table" =>
+        {
+            MEDIUM_FONT_SIZE
+        }
+        _ => parent.font_size,
+    };
+    // `rem` in the font size of the root element refers to the initial font size
+    // https://drafts.csswg.org/css-values/#rem
+    let font_size = compute_font_size(
+        properties.get("font-size"),
+        parent_font_size,
+        parent.root_font_size.unwrap_or(MEDIUM_FONT_SIZE),
+    );
+    properties.insert("font-size".into(), CSSValue::Length((font_size, Unit::Px)));
+
+    // compute `rem` lengths into px since only the root element knows what they refer to,
+    // so that layout never resolves them
+    let root_font_size = parent.root_font_size.unwrap_or(font_size);
+    for value in properties.values_mut() {
+        resolve_rem(value, root_font_size);
+    }
+
+    // compute line-height against the font size of the element itself since descendants inherit the computed value
+    // https://drafts.csswg.org/css-inline/#line-height-property
+    let line_height = match properties.get("line-height") {
+        Some(value) => LineHeight::compute(value, font_size).unwrap_or(parent.line_height),
+        None => parent.line_height,
+    };
+    if properties.contains_key("line-height") || line_height != LineHeight::Normal {
+        properties.insert("line-height".into(), line_height.to_value());
+    }
+
+    // the properties of `::first-line` and `::first-letter` are stored with prefixed names like `::first-letter color`,
+    // and layout applies them to the boxes in the first line
+    // https://www.w3.org/TR/css-pseudo-4/#first-text-line
+    for pseudo_element in PseudoElement::ALL {
+        let mut pseudo_properties = PropertyMap::new();
+        for declaration in
+            stylesheet.cascaded_declarations(node, match_context, context, Some(pseudo_element))
+        {
+            if PSEUDO_ELEMENT_PROPERTIES.contains(&declaration.name.as_str()) {
+                pseudo_properties.insert(declaration.name, declaration.value);
+            }
+        }
+        if let Some(value) = pseudo_properties.get("font-size") {
+            // the pseudo-elements inherit from the element itself
+            let pseudo_font_size = compute_font_size(Some(value), font_size, root_font_size);
+            pseudo_properties.insert(
+                "font-size".into(),
+                CSSValue::Length((pseudo_font_size, Unit::Px)),
+            );
+        }
+        for (name, value) in pseudo_properties {
+            properties.insert(pseudo_element_property(pseudo_element, &name), value);
+        }
+    }
+
+    // `direction` is inherited, and the `dir` attribute sets it unless CSS does
+    // https://html.spec.whatwg.org/multipage/rendering.html#bidi-rendering
+    let direction = direction_of(&properties)
+        .or_else(|| match node.node_type {
+            NodeType::Element(ref e) => e
+                .attributes
+                .get("dir")
+                .and_then(|d| Direction::from_keyword(d)),
+            _ => None,
+        })
+        .unwrap_or(parent.direction);
+    properties.insert(
+        "direction".into(),
+        CSSValue::Keyword(direction.to_keyword().into()),
+    );
+
+    let children_context = match_context.for_children(node);
+    // set the initial values of `display` and `font-weight` if not set
+    // https://drafts.csswg.org/css-display/#the-display-properties
     // https://drafts.csswg.org/css-fonts/#font-weight-prop
-    if properties.get("font-weight") == None {
-        properties.insert("font-weight".into(), CSSValue::Keyword("normal".into()));
+    for (name, value) in &INITIAL_VALUES {
+        if !properties.contains_key(*name) {
+            properties.insert(name.to_string(), value.clone());
+        }
     }
 
+    let inherited = Inherited {
+        font_size,
+        root_font_size: Some(root_font_size),
+        direction,
+        line_height,
+        properties: inherited_properties(&properties),
+    };
+    let children = node
+        .children
+        .iter()
+        .filter_map(|x| style_node(x, &children_context, &inherited, stylesheet, context))
+        .collect();
+
     Some(StyledNode {
         node_type: &node.node_type,
         properties,
@@ -58,47 +981,1149 @@ pub fn to_styled_node<'a>(node: &'a Box<Node>, stylesheet: &Stylesheet) -> Optio
     })
 }
 
-pub fn to_styled_nodes<'a>(
-    nodes: &'a Vec<Box<Node>>,
+/// `inline_declarations` returns the declarations in the `style` attribute of `node`, with shorthands expanded.
+/// See https://drafts.csswg.org/css-style-attr/
+fn inline_declarations(node: &Node) -> Vec<Declaration> {
+    match node.node_type {
+        NodeType::Element(ref e) => match e.attributes.get("style") {
+            Some(style) => css::parse_declarations(style)
+                .iter()
+                .flat_map(expand_shorthand)
+                .collect(),
+            None => vec![],
+        },
+        _ => vec![],
+    }
+}
+
+/// `restyle_subtree` restyles `new_root` reusing `old`, the styled tree of `old_root`,
+/// where `new_root` is a copy of `old_root` (e.g. made by `Node::clone_tree`) whose node at `path` has changed its attributes.
+/// `path` is the list of the child indices from the root to the changed node.
+///
+/// Only the changed node and its descendants are matched again.
+/// The other nodes reuse their styles in `old`, which is valid as long as no selector depends on
+/// the attributes of siblings or descendants.
+pub fn restyle_subtree<'a>(
+    old: StyledNode<'_>,
+    old_root: &Node,
+    new_root: &'a Node,
+    path: &[usize],
     stylesheet: &Stylesheet,
-) -> Vec<StyledNode<'a>> {
-    nodes
+) -> Option<StyledNode<'a>> {
+    restyle_subtree_with_context(
+        old,
+        old_root,
+        new_root,
+        path,
+        stylesheet,
+        &StyleContext::default(),
+    )
+}
+
+pub fn restyle_subtree_with_context<'a>(
+    old: StyledNode<'_>,
+    old_root: &Node,
+    new_root: &'a Node,
+    path: &[usize],
+    stylesheet: &Stylesheet,
+    context: &StyleContext,
+) -> Option<StyledNode<'a>> {
+    restyle_node(
+        old,
+        old_root,
+        new_root,
+        path,
+        &MatchContext::for_document(new_root),
+        &Inherited::default(),
+        stylesheet,
+        context,
+    )
+}
+
+/// `restyle_node` restyles `new_node`, whose changed descendant is at `path`.
+/// The other arguments are the same as `style_node`.
+#[allow(clippy::too_many_arguments)]
+fn restyle_node<'a>(
+    old: StyledNode<'_>,
+    old_node: &Node,
+    new_node: &'a Node,
+    path: &[usize],
+    match_context: &MatchContext,
+    parent: &Inherited,
+    stylesheet: &Stylesheet,
+    context: &StyleContext,
+) -> Option<StyledNode<'a>> {
+    let (&index, path) = match path.split_first() {
+        Some(x) => x,
+        None => return style_node(new_node, match_context, parent, stylesheet, context),
+    };
+
+    let font_size = match old.properties.get("font-size") {
+        Some(CSSValue::Length((size, Unit::Px))) => *size,
+        _ => parent.font_size,
+    };
+    let inherited = Inherited {
+        font_size,
+        root_font_size: Some(parent.root_font_size.unwrap_or(font_size)),
+        direction: direction_of(&old.properties).unwrap_or(parent.direction),
+        // `line-height` is not stored when it is `normal` and not specified
+        line_height: old
+            .properties
+            .get("line-height")
+            .and_then(|value| LineHeight::compute(value, parent.font_size))
+            .unwrap_or_default(),
+        properties: inherited_properties(&old.properties),
+    };
+    let children_context = match_context.for_children(new_node);
+    let mut old_children = old.children.into_iter().peekable();
+    let children = old_node
+        .children
         .iter()
-        .filter_map(|x| to_styled_node(x, stylesheet))
+        .zip(new_node.children.iter())
+        .enumerate()
+        .filter_map(|(i, (old_child, new_child))| {
+            // children which are not styled (e.g. `display: none`) have no counterpart in `old`
+            let old_styled =
+                old_children.next_if(|c| std::ptr::eq(c.node_type, &old_child.node_type));
+            match (i == index, old_styled) {
+                (true, Some(old_styled)) => restyle_node(
+                    old_styled,
+                    old_child,
+                    new_child,
+                    path,
+                    &children_context,
+                    &inherited,
+                    stylesheet,
+                    context,
+                ),
+                (true, None) => style_node(
+                    new_child,
+                    &children_context,
+                    &inherited,
+                    stylesheet,
+                    context,
+                ),
+                (false, old_styled) => old_styled.map(|s| rebind(s, old_child, new_child)),
+            }
+        })
+        .collect();
+
+    Some(StyledNode {
+        node_type: &new_node.node_type,
+        properties: old.properties,
+        children,
+    })
+}
+
+/// `matched_declarations` collects the declarations of the rules in `stylesheet` which apply in `context`
+/// along with their importance, and the origins, the layer orders and the specificities of the rules. `specificity` returns `None` for the rules not matching.
+/// Shorthands are expanded into their longhands.
+fn matched_declarations(
+    stylesheet: &Stylesheet,
+    context: &StyleContext,
+    specificity: impl Fn(&Rule) -> Option<Specificity>,
+) -> Vec<(Declaration, (bool, usize, usize, Specificity))> {
+    stylesheet
+        .rules
+        .iter()
+        .filter(|r| applies(r, context))
+        .filter_map(|r| specificity(r).map(|specificity| (r, (r.layer_order(), specificity))))
+        .flat_map(|(r, (layer_order, specificity))| {
+            r.declarations
+                .iter()
+                .flat_map(expand_shorthand)
+                .map(move |declaration| {
+                    let important = declaration.important;
+                    let origin = r.origin.cascade_order(important);
+                    (declaration, (important, origin, layer_order, specificity))
+                })
+        })
         .collect()
 }
 
-impl<'a> StyledNode<'a> {
-    pub fn display(&self) -> Display {
-        match self.properties.get("display") {
-            Some(CSSValue::Keyword(s)) => match s.as_str() {
-                "block" => Display::Block,
-                "none" => Display::None,
-                _ => Display::Inline,
-            },
-            _ => Display::Inline,
-        }
+/// `applies` tells whether the conditions of `rule` like `@media` and `@supports` hold in `context`.
+fn applies(rule: &Rule, context: &StyleContext) -> bool {
+    rule.media
+        .as_ref()
+        .is_none_or(|media| context.matches_media(media))
+        && rule.supports.as_ref().is_none_or(evaluate_supports)
+}
+
+/// `PropertyTrace` shows how the cascade determines the value of a property of a node.
+#[derive(Debug, PartialEq)]
+pub struct PropertyTrace {
+    /// `candidates` are the declarations of the property in the rules matching the node,
+    /// sorted in the cascade order, i.e. each one overrides the preceding ones.
+    pub candidates: Vec<CascadeCandidate>,
+    /// `winner` is the index of the winning declaration in `candidates`, or `None` if there is no candidate.
+    pub winner: Option<usize>,
+}
+
+/// `CascadeCandidate` is a declaration competing in the cascade.
+#[derive(Debug, PartialEq)]
+pub struct CascadeCandidate {
+    /// `rule` is the index of the rule in the stylesheet.
+    pub rule: usize,
+    pub declaration: Declaration,
+    pub specificity: Specificity,
+    pub origin: Origin,
+}
+
+/// `trace_property` collects the declarations of `property` which apply to `node` in the same way as `to_styled_node`,
+/// where `node` is regarded as the root of the document. Shorthands are expanded into the longhands.
+/// It makes the cascade observable, e.g. to find out why a declaration does not take effect.
+///
+/// NOTE: The declarations in the `style` attribute are not traced since they do not belong to any rule.
+pub fn trace_property(node: &Node, stylesheet: &Stylesheet, property: &str) -> PropertyTrace {
+    let candidates = cascade_candidates(
+        node,
+        &MatchContext::for_document(node),
+        stylesheet,
+        &StyleContext::default(),
+    )
+    .into_iter()
+    .filter(|c| c.declaration.name == property)
+    .collect::<Vec<_>>();
+    PropertyTrace {
+        winner: candidates.len().checked_sub(1),
+        candidates,
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::{
-        css::{AttributeSelectorOp, Declaration, Rule, SimpleSelector},
-        dom::Element,
+/// `cascade_candidates` collects the declarations of all the properties in the rules matching `node`
+/// in the cascade order, i.e. the last one of each property wins.
+fn cascade_candidates(
+    node: &Node,
+    match_context: &MatchContext,
+    stylesheet: &Stylesheet,
+    context: &StyleContext,
+) -> Vec<CascadeCandidate> {
+    let mut candidates = stylesheet
+        .rules
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| applies(r, context))
+        .filter_map(|(i, r)| {
+            r.specificity(node, match_context)
+                .map(|specificity| (i, r, specificity))
+        })
+        .flat_map(|(i, r, specificity)| {
+            r.declarations
+                .iter()
+                .flat_map(expand_shorthand)
+                .map(move |declaration| CascadeCandidate {
+                    rule: i,
+                    declaration,
+                    specificity,
+                    origin: r.origin,
+                })
+        })
+        .collect::<Vec<_>>();
+    // the same order as `Cascade::cascaded_declarations`
+    candidates.sort_by_key(|c| {
+        (
+            c.declaration.important,
+            c.origin.cascade_order(c.declaration.important),
+            stylesheet.rules[c.rule].layer_order(),
+            c.specificity,
+        )
+    });
+    candidates
+}
+
+/// `pseudo_element_property` returns the name under which the property `name` of `pseudo_element` is stored.
+pub(crate) fn pseudo_element_property(pseudo_element: PseudoElement, name: &str) -> String {
+    format!("::{} {}", pseudo_element.name(), name)
+}
+
+/// `pseudo_element_properties` extracts the properties of `pseudo_element` from `properties` of its element.
+/// It is empty when no rule applies to the pseudo-element.
+pub fn pseudo_element_properties(
+    properties: &PropertyMap,
+    pseudo_element: PseudoElement,
+) -> PropertyMap {
+    PSEUDO_ELEMENT_PROPERTIES
+        .iter()
+        .filter_map(|name| {
+            properties
+                .get(&pseudo_element_property(pseudo_element, name))
+                .map(|value| (name.to_string(), value.clone()))
+        })
+        .collect()
+}
+
+/// `direction_of` returns the `direction` in `properties`, or `None` if it is not set or invalid.
+fn direction_of(properties: &PropertyMap) -> Option<Direction> {
+    match properties.get("direction") {
+        Some(CSSValue::Keyword(keyword)) => Direction::from_keyword(keyword),
+        _ => None,
+    }
+}
+
+/// `rebind` moves the styles in `old`, the styled tree of `old_node`, onto `new_node`,
+/// which has the same structure as `old_node`.
+fn rebind<'a>(old: StyledNode<'_>, old_node: &Node, new_node: &'a Node) -> StyledNode<'a> {
+    let mut old_children = old.children.into_iter().peekable();
+    let children = old_node
+        .children
+        .iter()
+        .zip(new_node.children.iter())
+        .filter_map(|(old_child, new_child)| {
+            old_children
+                .next_if(|c| std::ptr::eq(c.node_type, &old_child.node_type))
+                .map(|c| rebind(c, old_child, new_child))
+        })
+        .collect();
+
+    StyledNode {
+        node_type: &new_node.node_type,
+        properties: old.properties,
+        children,
+    }
+}
+
+/// `expand_shorthand` expands a declaration of a shorthand property into the ones of its longhand properties.
+/// The other declarations are returned as they are, and the longhands are `!important` if the shorthand is.
+/// See https://www.w3.org/TR/css-cascade-3/#shorthand
+pub fn expand_shorthand(declaration: &Declaration) -> Vec<Declaration> {
+    let mut longhands = match declaration.name.as_str() {
+        "background" => expand_background(&declaration.value),
+        "font" => expand_font(&declaration.value),
+        "outline" => expand_outline(&declaration.value),
+        "margin" => expand_box_sides("margin-", "", &declaration.value),
+        "padding" => expand_box_sides("padding-", "", &declaration.value),
+        "border-width" => expand_box_sides("border-", "-width", &declaration.value),
+        "border-style" => expand_box_sides("border-", "-style", &declaration.value),
+        "border-color" => expand_box_sides("border-", "-color", &declaration.value),
+        "border" => expand_border(&declaration.value),
+        _ => return vec![declaration.clone()],
     };
+    for longhand in &mut longhands {
+        longhand.important = declaration.important;
+    }
+    longhands
+}
 
-    use super::*;
+/// `BOX_SIDES` are the sides in the order of the values of box shorthands like `margin`.
+const BOX_SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
 
-    #[test]
-    fn test_to_styled_node_single() {
-        let e = &Element::new(
-            "p".to_string(),
-            [("id".to_string(), "test".to_string())]
-                .iter()
-                .cloned()
-                .collect(),
-            vec![],
+/// `expand_box_sides` expands a shorthand with one to four values like `margin: 10px 20px`
+/// into the longhands named `{prefix}{side}{suffix}` for each side, e.g. `margin-top`.
+/// Omitted sides copy the opposite ones, i.e. `bottom` copies `top` and `left` copies `right`.
+/// It returns nothing when the number of the values is invalid.
+/// See https://www.w3.org/TR/css-box-4/#margin-shorthand
+fn expand_box_sides(prefix: &str, suffix: &str, value: &CSSValue) -> Vec<Declaration> {
+    let values = match value {
+        CSSValue::List(values) => values.iter().collect(),
+        value => vec![value],
+    };
+    if values
+        .iter()
+        .any(|v| matches!(v, CSSValue::Comma | CSSValue::Slash))
+    {
+        return vec![];
+    }
+    let indices = match values.len() {
+        1 => [0, 0, 0, 0],
+        2 => [0, 1, 0, 1],
+        3 => [0, 1, 2, 1],
+        4 => [0, 1, 2, 3],
+        _ => return vec![],
+    };
+    BOX_SIDES
+        .iter()
+        .zip(indices)
+        .map(|(side, i)| Declaration {
+            name: format!("{}{}{}", prefix, side, suffix),
+            value: values[i].clone(),
+            important: false,
+        })
+        .collect()
+}
+
+/// `expand_border` expands `border` into the width, the style and the color of all the four sides,
+/// e.g. `border-top-width`, in the same way as `outline`.
+/// See https://www.w3.org/TR/css-backgrounds-3/#border-shorthands
+///
+/// NOTE: `auto` is accepted as a style as well as `outline` for simplicity.
+fn expand_border(value: &CSSValue) -> Vec<Declaration> {
+    let [width, style, color] = match line_values(value) {
+        Some(values) => values,
+        None => return vec![],
+    };
+    BOX_SIDES
+        .iter()
+        .flat_map(|side| {
+            [("width", &width), ("style", &style), ("color", &color)].map(|(name, value)| {
+                Declaration {
+                    name: format!("border-{}-{}", side, name),
+                    value: value.clone(),
+                    important: false,
+                }
+            })
+        })
+        .collect()
+}
+
+/// `expand_background` expands `background` into `background-color`, `background-image` and `background-repeat`.
+/// Omitted longhands are set to their initial values, and the other values like `center` are ignored.
+/// See https://www.w3.org/TR/css-backgrounds-3/#background
+fn expand_background(value: &CSSValue) -> Vec<Declaration> {
+    let values = match value {
+        CSSValue::List(values) => values.iter().collect(),
+        value => vec![value],
+    };
+    let mut color = CSSValue::Keyword("transparent".into());
+    let mut image = CSSValue::Keyword("none".into());
+    let mut repeat = CSSValue::Keyword("repeat".into());
+    for value in values {
+        match value {
+            CSSValue::Url(_) => image = value.clone(),
+            CSSValue::Keyword(keyword) if keyword == "none" => image = value.clone(),
+            CSSValue::Keyword(keyword)
+                if matches!(
+                    keyword.as_ref(),
+                    "repeat" | "repeat-x" | "repeat-y" | "no-repeat" | "space" | "round"
+                ) =>
+            {
+                repeat = value.clone()
+            }
+            _ if value.to_color().is_some() => color = value.clone(),
+            _ => {}
+        }
+    }
+    vec![
+        Declaration {
+            name: "background-color".into(),
+            value: color,
+            important: false,
+        },
+        Declaration {
+            name: "background-image".into(),
+            value: image,
+            important: false,
+        },
+        Declaration {
+            name: "background-repeat".into(),
+            value: repeat,
+            important: false,
+        },
+    ]
+}
+
+/// `expand_outline` expands `outline` into `outline-width`, `outline-style` and `outline-color` in any order.
+/// Omitted longhands are set to their initial values.
+/// It returns nothing when the value contains anything else.
+/// See https://www.w3.org/TR/css-ui-4/#outline
+fn expand_outline(value: &CSSValue) -> Vec<Declaration> {
+    let [width, style, color] = match line_values(value) {
+        Some(values) => values,
+        None => return vec![],
+    };
+    vec![
+        Declaration {
+            name: "outline-width".into(),
+            value: width,
+            important: false,
+        },
+        Declaration {
+            name: "outline-style".into(),
+            value: style,
+            important: false,
+        },
+        Declaration {
+            name: "outline-color".into(),
+            value: color,
+            important: false,
+        },
+    ]
+}
+
+/// `line_values` splits the value of `outline` or `border` into the width, the style and the color.
+/// Omitted ones are set to their initial values, and it returns `None` when the value contains anything else.
+fn line_values(value: &CSSValue) -> Option<[CSSValue; 3]> {
+    let values = match value {
+        CSSValue::List(values) => values.iter().collect(),
+        value => vec![value],
+    };
+    let mut width = CSSValue::Keyword("medium".into());
+    let mut style = CSSValue::Keyword("none".into());
+    let mut color = CSSValue::Keyword("currentcolor".into());
+    for value in values {
+        match value {
+            CSSValue::Length(_) => width = value.clone(),
+            CSSValue::Keyword(keyword)
+                if matches!(keyword.as_ref(), "thin" | "medium" | "thick") =>
+            {
+                width = value.clone()
+            }
+            CSSValue::Keyword(keyword)
+                if matches!(
+                    keyword.as_ref(),
+                    "none"
+                        | "auto"
+                        | "dotted"
+                        | "dashed"
+                        | "solid"
+                        | "double"
+                        | "groove"
+                        | "ridge"
+                        | "inset"
+                        | "outset"
+                ) =>
+            {
+                style = value.clone()
+            }
+            CSSValue::Keyword(keyword) if keyword == "currentcolor" => color = value.clone(),
+            _ if value.to_color().is_some() => color = value.clone(),
+            _ => return None,
+        }
+    }
+    Some([width, style, color])
+}
+
+/// `expand_font` expands `font` into `font-style`, `font-weight`, `font-size`, `line-height` and `font-family`.
+/// Omitted longhands are set to their initial values.
+/// It returns nothing when the value is invalid, e.g. `font-size` or `font-family` is missing.
+/// See https://drafts.csswg.org/css-fonts/#font-prop
+///
+/// NOTE: `font-variant` and `font-stretch` are accepted but ignored for simplicity.
+fn expand_font(value: &CSSValue) -> Vec<Declaration> {
+    let mut values = match value {
+        CSSValue::List(values) => values.iter().collect::<Vec<_>>(),
+        value => vec![value],
+    }
+    .into_iter()
+    .peekable();
+    let mut style = CSSValue::Keyword("normal".into());
+    let mut weight = CSSValue::Keyword("normal".into());
+    let mut line_height = CSSValue::Keyword("normal".into());
+
+    // [ <font-style> || <font-variant-css2> || <font-weight> || <font-stretch-css3> ]?
+    let is_font_size = |value: &CSSValue| match value {
+        CSSValue::Length(_) => true,
+        CSSValue::Keyword(keyword) => {
+            matches!(keyword.as_ref(), "smaller" | "larger")
+                || absolute_font_size(keyword).is_some()
+        }
+        _ => false,
+    };
+    while let Some(value) = values.next_if(|v| !is_font_size(v)) {
+        match value {
+            CSSValue::Keyword(keyword) => match keyword.as_ref() {
+                "normal" => {}
+                "italic" | "oblique" => style = value.clone(),
+                "bold" | "bolder" | "lighter" => weight = value.clone(),
+                "small-caps" | "condensed" | "semi-condensed" | "expanded" | "semi-expanded" => {}
+                _ => return vec![],
+            },
+            CSSValue::Number(num) if (1.0..=1000.0).contains(num) => weight = value.clone(),
+            _ => return vec![],
+        }
+    }
+
+    // <font-size> [ / <line-height> ]?
+    let size = match values.next() {
+        Some(value) => value.clone(),
+        None => return vec![],
+    };
+    if values.next_if_eq(&&CSSValue::Slash).is_some() {
+        line_height = match values.next() {
+            Some(value) => value.clone(),
+            None => return vec![],
+        };
+    }
+
+    // <font-family>
+    let family = match values.collect::<Vec<_>>()[..] {
+        [] => return vec![],
+        [value] => value.clone(),
+        ref values => CSSValue::List(values.iter().map(|v| (*v).clone()).collect()),
+    };
+
+    vec![
+        Declaration {
+            name: "font-style".into(),
+            value: style,
+            important: false,
+        },
+        Declaration {
+            name: "font-weight".into(),
+            value: weight,
+            important: false,
+        },
+        Declaration {
+            name: "font-size".into(),
+            value: size,
+            important: false,
+        },
+        Declaration {
+            name: "line-height".into(),
+            value: line_height,
+            important: false,
+        },
+        Declaration {
+            name: "font-family".into(),
+            value: family,
+            important: false,
+        },
+    ]
+}
+
+/// `resolve_font_family` returns the first family in the `font-family` stack `value`
+/// which is in `available_fonts` or generic, falling back to `sans-serif` when nothing is available.
+/// Family names are compared case-insensitively. Named families result in strings and generic ones in keywords.
+///
+/// NOTE: Unlike the standard, the fallback is decided per element rather than per character.
+pub fn resolve_font_family(value: &CSSValue, available_fonts: &AvailableFonts) -> CSSValue {
+    let values = match value {
+        CSSValue::List(values) => values.as_slice(),
+        value => std::slice::from_ref(value),
+    };
+    for family in values.split(|v| *v == CSSValue::Comma) {
+        match family {
+            [CSSValue::Keyword(keyword)] if GENERIC_FONT_FAMILIES.contains(&keyword.as_ref()) => {
+                return CSSValue::Keyword(keyword.clone());
+            }
+            [CSSValue::String(name)] => {
+                if available_fonts.contains(name) {
+                    return CSSValue::String(name.clone());
+                }
+            }
+            // unquoted names can be a sequence of identifiers like `Times New Roman`
+            family => {
+                let names = family
+                    .iter()
+                    .map(|v| match v {
+                        CSSValue::Keyword(keyword) => Some(keyword.as_ref()),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>();
+                if let Some(name) = names.map(|names| names.join(" ")) {
+                    if !name.is_empty() && available_fonts.contains(&name) {
+                        return CSSValue::String(name);
+                    }
+                }
+            }
+        }
+    }
+    CSSValue::Keyword("sans-serif".into())
+}
+
+/// `compute_font_size` resolves the specified `font-size` into px.
+/// Relative values are resolved against `parent_font_size` except `rem` against `root_font_size`,
+/// and invalid values are ignored to inherit it.
+fn compute_font_size(value: Option<&CSSValue>, parent_font_size: f32, root_font_size: f32) -> f32 {
+    match value {
+        Some(CSSValue::Keyword(keyword)) => match keyword.as_ref() {
+            "smaller" => parent_font_size / FONT_SIZE_RATIO,
+            "larger" => parent_font_size * FONT_SIZE_RATIO,
+            _ => absolute_font_size(keyword).unwrap_or(parent_font_size),
+        },
+        Some(CSSValue::Length((num, unit))) => match unit {
+            Unit::Px => *num,
+            Unit::Em => num * parent_font_size,
+            Unit::Rem => num * root_font_size,
+            Unit::Percent => parent_font_size * num / 100.0,
+            Unit::Fr => parent_font_size,
+        },
+        _ => parent_font_size,
+    }
+}
+
+/// `resolve_rem` computes the `rem` lengths in `value` into px with `root_font_size`.
+/// See https://drafts.csswg.org/css-values/#rem
+fn resolve_rem(value: &mut CSSValue, root_font_size: f32) {
+    let resolve = |length: &mut (f32, Unit)| {
+        if length.1 == Unit::Rem {
+            *length = (length.0 * root_font_size, Unit::Px);
+        }
+    };
+    match value {
+        CSSValue::Length(length) => resolve(length),
+        CSSValue::Transform(TransformFunction::Translate(x, y)) => {
+            resolve(x);
+            resolve(y);
+        }
+        CSSValue::List(values) => {
+            for value in values {
+                resolve_rem(value, root_font_size);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `absolute_font_size` returns the font size in px of an absolute-size keyword like `large`.
+/// See https://drafts.csswg.org/css-fonts/#absolute-size-mapping
+pub fn absolute_font_size(keyword: &str) -> Option<f32> {
+    let scale = match keyword {
+        "xx-small" => 3.0 / 5.0,
+        "x-small" => 3.0 / 4.0,
+        "small" => 8.0 / 9.0,
+        "medium" => 1.0,
+        "large" => 6.0 / 5.0,
+        "x-large" => 3.0 / 2.0,
+        "xx-large" => 2.0,
+        "xxx-large" => 3.0,
+        _ => return None,
+    };
+    Some(MEDIUM_FONT_SIZE * scale)
+}
+
+pub fn to_styled_nodes<'a>(
+    nodes: &'a Vec<Box<Node>>,
+    stylesheet: &Stylesheet,
+) -> Vec<StyledNode<'a>> {
+    nodes
+        .iter()
+        .filter_map(|x| to_styled_node(x, stylesheet))
+        .collect()
+}
+
+/// `merge_properties` applies `incoming` declarations to `base` in the cascade order.
+/// `!important` declarations win over the normal ones, then declarations with higher specificity win,
+/// and the latter one wins when specificities are the same.
+/// See https://www.w3.org/TR/css-cascade-3/#cascade-sort
+pub fn merge_properties(base: &mut PropertyMap, incoming: &[(Declaration, Specificity)]) {
+    let mut sorted = incoming
+        .iter()
+        .map(|(d, s)| (d, (d.important, *s)))
+        .collect::<Vec<_>>();
+    sort_in_cascade_order(&mut sorted);
+    for (declaration, _) in sorted {
+        base.insert(declaration.name.clone(), declaration.value.clone());
+    }
+}
+
+/// `sort_in_cascade_order` sorts the declarations in the ascending order of `key`,
+/// which is the specificity preceded by the importance and optionally the origin and the layer order.
+fn sort_in_cascade_order<T, K: Ord + Copy>(declarations: &mut [(T, K)]) {
+    // NOTE: `sort_by_key` is stable, so the source order is kept among the same key.
+    declarations.sort_by_key(|(_, key)| *key);
+}
+
+/// `unused_rules` returns the indices of the rules in `stylesheet` which match no node in the tree of `root`.
+/// It is useful to find dead CSS.
+pub fn unused_rules(root: &Node, stylesheet: &Stylesheet) -> Vec<usize> {
+    let mut matched = vec![false; stylesheet.rules.len()];
+    mark_matched_rules(
+        root,
+        &MatchContext::for_document(root),
+        stylesheet,
+        &mut matched,
+    );
+    matched
+        .iter()
+        .enumerate()
+        .filter(|(_, &m)| !m)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// `rules_exceeding_specificity` returns the indices of the rules in `stylesheet` which have a selector
+/// more specific than `threshold`. It is useful to flag overly specific CSS, e.g. ID selectors in a design system.
+pub fn rules_exceeding_specificity(stylesheet: &Stylesheet, threshold: Specificity) -> Vec<usize> {
+    stylesheet
+        .rules
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| r.selectors.iter().any(|s| s.specificity() > threshold))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// `ineffective_rules` returns the indices of the rules in `stylesheet` which match some nodes in the tree of `root`,
+/// but have no effect since all of their declarations are overridden by the other rules on every matched node.
+/// Together with `unused_rules`, it is useful to find dead CSS.
+///
+/// NOTE: Rules matching pseudo-elements are always regarded as effective.
+pub fn ineffective_rules(root: &Node, stylesheet: &Stylesheet) -> Vec<usize> {
+    let mut matched = vec![false; stylesheet.rules.len()];
+    let mut effective = vec![false; stylesheet.rules.len()];
+    mark_effective_rules(
+        root,
+        &MatchContext::for_document(root),
+        stylesheet,
+        &mut matched,
+        &mut effective,
+    );
+    matched
+        .iter()
+        .zip(effective.iter())
+        .enumerate()
+        .filter(|(_, (&m, &e))| m && !e)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+fn mark_effective_rules(
+    node: &Node,
+    match_context: &MatchContext,
+    stylesheet: &Stylesheet,
+    matched: &mut [bool],
+    effective: &mut [bool],
+) {
+    let context = StyleContext::default();
+    for (i, rule) in stylesheet.rules.iter().enumerate() {
+        if !applies(rule, &context) {
+            continue;
+        }
+        if rule.matches(node, match_context) {
+            matched[i] = true;
+        }
+        if PseudoElement::ALL.iter().any(|&pseudo_element| {
+            rule.pseudo_element_specificity(node, match_context, pseudo_element)
+                .is_some()
+        }) {
+            matched[i] = true;
+            effective[i] = true;
+        }
+    }
+    let mut winners = HashMap::new();
+    for candidate in cascade_candidates(node, match_context, stylesheet, &context) {
+        winners.insert(candidate.declaration.name, candidate.rule);
+    }
+    for rule in winners.into_values() {
+        effective[rule] = true;
+    }
+    let children_context = match_context.for_children(node);
+    for child in &node.children {
+        mark_effective_rules(child, &children_context, stylesheet, matched, effective);
+    }
+}
+
+fn mark_matched_rules(
+    node: &Node,
+    match_context: &MatchContext,
+    stylesheet: &Stylesheet,
+    matched: &mut [bool],
+) {
+    for (i, rule) in stylesheet.rules.iter().enumerate() {
+        if !matched[i]
+            && (rule.matches(node, match_context)
+                || PseudoElement::ALL.iter().any(|&pseudo_element| {
+                    rule.pseudo_element_specificity(node, match_context, pseudo_element)
+                        .is_some()
+                }))
+        {
+            matched[i] = true;
+        }
+    }
+    let children_context = match_context.for_children(node);
+    for child in &node.children {
+        mark_matched_rules(child, &children_context, stylesheet, matched);
+    }
+}
+
+impl Display {
+    /// `from_keyword` returns `None` for the values which this crate does not support.
+    pub fn from_keyword(keyword: &str) -> Option<Display> {
+        match keyword {
+            "inline" => Some(Display::Inline),
+            "block" => Some(Display::Block),
+            "grid" => Some(Display::Grid),
+            "list-item" => Some(Display::ListItem),
+            "table" => Some(Display::Table),
+            "flex" => Some(Display::Flex),
+            "inline-block" => Some(Display::InlineBlock),
+            "table-column" => Some(Display::TableColumn),
+            "table-column-group" => Some(Display::TableColumnGroup),
+            "none" => Some(Display::None),
+            _ => None,
+        }
+    }
+
+    pub fn to_keyword(&self) -> &'static str {
+        match self {
+            Display::Inline => "inline",
+            Display::Block => "block",
+            Display::Grid => "grid",
+            Display::ListItem => "list-item",
+            Display::Table => "table",
+            Display::Flex => "flex",
+            Display::InlineBlock => "inline-block",
+            Display::TableColumn => "table-column",
+            Display::TableColumnGroup => "table-column-group",
+            Display::None => "none",
+        }
+    }
+}
+
+impl WritingMode {
+    pub fn from_keyword(keyword: &str) -> Option<WritingMode> {
+        match keyword {
+            "horizontal-tb" => Some(WritingMode::HorizontalTb),
+            "vertical-rl" => Some(WritingMode::VerticalRl),
+            "vertical-lr" => Some(WritingMode::VerticalLr),
+            _ => None,
+        }
+    }
+
+    pub fn is_vertical(&self) -> bool {
+        *self != WritingMode::HorizontalTb
+    }
+}
+
+impl Float {
+    /// `from_keyword` returns `None` for `none` and invalid keywords.
+    pub fn from_keyword(keyword: &str) -> Option<Float> {
+        match keyword {
+            "left" => Some(Float::Left),
+            "right" => Some(Float::Right),
+            _ => None,
+        }
+    }
+}
+
+impl Clear {
+    /// `from_keyword` returns `None` for `none` and invalid keywords.
+    pub fn from_keyword(keyword: &str) -> Option<Clear> {
+        match keyword {
+            "left" => Some(Clear::Left),
+            "right" => Some(Clear::Right),
+            "both" => Some(Clear::Both),
+            _ => None,
+        }
+    }
+}
+
+/// `current_color` returns the value of `color` in `properties`, which is black when not specified.
+/// See https://www.w3.org/TR/css-color-4/#the-color-property
+pub fn current_color(properties: &PropertyMap) -> Color {
+    properties
+        .get("color")
+        .and_then(|v| v.to_color())
+        .unwrap_or(Color::rgb(0, 0, 0))
+}
+
+/// `resolve_length` resolves the property `name` in `properties` into px, where `em` refers to the computed `font-size`
+/// and `percentage_basis` is used for `%` values.
+/// It returns `None` when the property is not set or not a length (e.g. `auto`).
+///
+/// NOTE: `rem` refers to `MEDIUM_FONT_SIZE` here, since `rem` lengths of styled nodes are already computed into px.
+pub fn resolve_length(properties: &PropertyMap, name: &str, percentage_basis: f32) -> Option<f32> {
+    let font_size = match properties.get("font-size") {
+        Some(CSSValue::Length((size, Unit::Px))) => *size,
+        _ => MEDIUM_FONT_SIZE,
+    };
+    properties.get(name)?.to_px(&LengthContext {
+        font_size,
+        root_font_size: MEDIUM_FONT_SIZE,
+        percentage_basis,
+    })
+}
+
+/// `resolve_color` resolves the color property `name` in `properties`, where `currentcolor` refers to `color`.
+/// It returns `None` when the property is not set or not a color.
+/// See https://www.w3.org/TR/css-color-4/#currentcolor-color
+pub fn resolve_color(properties: &PropertyMap, name: &str) -> Option<Color> {
+    match properties.get(name)? {
+        CSSValue::Keyword(keyword) if keyword.eq_ignore_ascii_case("currentcolor") => {
+            Some(current_color(properties))
+        }
+        value => value.to_color(),
+    }
+}
+
+impl<'a> StyledNode<'a> {
+    pub fn display(&self) -> Display {
+        match self.properties.get("display") {
+            Some(CSSValue::Keyword(s)) => Display::from_keyword(s).unwrap_or(Display::Inline),
+            _ => Display::Inline,
+        }
+    }
+
+    /// `is_block` tells whether the node is block-level, i.e. it participates in a block formatting context.
+    /// See https://drafts.csswg.org/css-display/#block-level
+    pub fn is_block(&self) -> bool {
+        matches!(
+            self.display(),
+            Display::Block | Display::Grid | Display::ListItem | Display::Table | Display::Flex
+        )
+    }
+
+    /// `is_inline` tells whether the node is inline-level, i.e. it participates in an inline formatting context.
+    /// See https://drafts.csswg.org/css-display/#inline-level
+    pub fn is_inline(&self) -> bool {
+        matches!(self.display(), Display::Inline | Display::InlineBlock)
+    }
+
+    pub fn value(&self, name: &str) -> Option<&CSSValue> {
+        self.properties.get(name)
+    }
+
+    /// `color` returns the foreground color of the node, which is black when not specified.
+    /// See https://www.w3.org/TR/css-color-4/#the-color-property
+    pub fn color(&self) -> Color {
+        current_color(&self.properties)
+    }
+
+    /// `background_color` returns the background color of the node, which is transparent when not specified.
+    /// `currentcolor` refers to `color`.
+    /// See https://www.w3.org/TR/css-backgrounds-3/#background-color
+    pub fn background_color(&self) -> Color {
+        resolve_color(&self.properties, "background-color")
+            .unwrap_or(Color::from_keyword("transparent").unwrap())
+    }
+
+    /// `length` resolves the property `name` into px like `resolve_length`.
+    pub fn length(&self, name: &str, percentage_basis: f32) -> Option<f32> {
+        resolve_length(&self.properties, name, percentage_basis)
+    }
+
+    /// `snapshot` dumps the styled tree with the properties of each node sorted by name, indented by depth.
+    /// The output is deterministic, which is suitable for golden tests with `assert_styled_snapshot`.
+    pub fn snapshot(&self) -> String {
+        let mut snapshot = String::new();
+        self.snapshot_into(&mut snapshot, 0);
+        snapshot
+    }
+
+    fn snapshot_into(&self, snapshot: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth);
+        let name = match self.node_type {
+            NodeType::Element(ref e) => e.tag_name.clone(),
+            NodeType::Text(ref t) => format!("#text {:?}", t.data),
+            NodeType::Comment(_) => "#comment".to_string(),
+            NodeType::DocumentType(_) => "#doctype".to_string(),
+        };
+        snapshot.push_str(&format!("{}{}\n", indent, name));
+        let mut properties = self.properties.iter().collect::<Vec<_>>();
+        properties.sort_by_key(|(name, _)| name.as_str());
+        for (name, value) in properties {
+            snapshot.push_str(&format!("{}  {}: {};\n", indent, name, value));
+        }
+        for child in &self.children {
+            child.snapshot_into(snapshot, depth + 1);
+        }
+    }
+}
+
+/// `assert_styled_snapshot` asserts that the snapshot of `styled` is `expected`.
+/// A leading newline of `expected` is ignored so that it can start on the line after the opening quote.
+#[cfg(test)]
+#[track_caller]
+pub(crate) fn assert_styled_snapshot(styled: &StyledNode, expected: &str) {
+    let expected = expected.strip_prefix('\n').unwrap_or(expected);
+    assert_eq!(styled.snapshot(), expected);
+}
+
+impl<'a> CompactStyledNode<'a> {
+    /// `value` returns the property `name` just as `StyledNode::value` does.
+    pub fn value(&self, name: &str) -> Option<&CSSValue> {
+        match self.properties.get(name) {
+            Some(value) => value.as_ref(),
+            None => self.inherited.get(name).or_else(|| initial_value(name)),
+        }
+    }
+
+    /// `stored_len` returns the number of the properties actually stored in the node.
+    pub fn stored_len(&self) -> usize {
+        self.properties.len()
+    }
+
+    /// `compact` converts `node`, whose parent has the values `inherited` for its children.
+    fn compact(node: StyledNode<'a>, inherited: Rc<PropertyMap>) -> Self {
+        let mut properties = inherited
+            .keys()
+            .filter(|name| !node.properties.contains_key(*name))
+            .map(|name| (name.clone(), None))
+            .collect::<HashMap<_, _>>();
+        for (name, value) in &node.properties {
+            if inherited.get(name).or_else(|| initial_value(name)) != Some(value) {
+                properties.insert(name.clone(), Some(value.clone()));
+            }
+        }
+        properties.shrink_to_fit();
+
+        let values = inherited_values(&node.properties);
+        let children_inherited = if values == *inherited {
+            Rc::clone(&inherited)
+        } else {
+            Rc::new(values)
+        };
+        CompactStyledNode {
+            node_type: node.node_type,
+            properties,
+            inherited,
+            children: node
+                .children
+                .into_iter()
+                .map(|child| CompactStyledNode::compact(child, Rc::clone(&children_inherited)))
+                .collect(),
+        }
+    }
+}
+
+impl<'a> From<StyledNode<'a>> for CompactStyledNode<'a> {
+    fn from(node: StyledNode<'a>) -> Self {
+        CompactStyledNode::compact(node, Rc::new(PropertyMap::new()))
+    }
+}
+
+/// `initial_value` returns the value of `name` in `INITIAL_VALUES`, if any.
+fn initial_value(name: &str) -> Option<&'static CSSValue> {
+    INITIAL_VALUES
+        .iter()
+        .find(|(initial_name, _)| *initial_name == name)
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        css::{
+            parse, AttributeSelectorOp, Declaration, MediaFeature, NestedRule, NestedSelector,
+            PseudoClass, PseudoElement, Rule, SimpleSelector,
+        },
+        dom::{build_tree, AttrMap, DocumentType, Element, Text, TreeSpec},
+        html,
+    };
+    use std::cell::RefCell;
+
+    use super::*;
+
+    /// `Recording` is a `Cascade` which records the declarations `inner` returns for each node.
+    struct Recording<'a, C> {
+        inner: &'a C,
+        declarations: RefCell<Vec<Vec<Declaration>>>,
+    }
+
+    impl<'a, C: Cascade> Recording<'a, C> {
+        fn new(inner: &'a C) -> Self {
+            Recording {
+                inner,
+                declarations: RefCell::new(vec![]),
+            }
+        }
+    }
+
+    impl<C: Cascade> Cascade for Recording<'_, C> {
+        fn cascaded_declarations(
+            &self,
+            node: &Node,
+            match_context: &MatchContext,
+            context: &StyleContext,
+            pseudo_element: Option<PseudoElement>,
+        ) -> Vec<Declaration> {
+            let declarations =
+                self.inner
+                    .cascaded_declarations(node, match_context, context, pseudo_element);
+            self.declarations.borrow_mut().push(declarations.clone());
+            declarations
+        }
+    }
+
+    #[test]
+    fn test_to_styled_node_single() {
+        let e = &Element::new(
+            "p".to_string(),
+            [("id".to_string(), "test".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            vec![],
         );
         let testcases = vec![
             (
@@ -107,15 +2132,16 @@ mod tests {
                     selectors: vec![SimpleSelector::UniversalSelector],
                     declarations: vec![Declaration {
                         name: "display".to_string(),
-                        value: CSSValue::Keyword("block".to_string()),
+                        value: CSSValue::Keyword("block".into()),
+                        important: false,
                     }],
+                    ..Default::default()
                 }]),
                 vec![
-                    (
-                        "display".to_string(),
-                        CSSValue::Keyword("block".to_string()),
-                    ),
+                    ("display".to_string(), CSSValue::Keyword("block".into())),
                     ("font-weight".into(), CSSValue::Keyword("normal".into())),
+                    ("font-size".into(), CSSValue::Length((16.0, Unit::Px))),
+                    ("direction".into(), CSSValue::Keyword("ltr".into())),
                 ],
             ),
             (
@@ -126,15 +2152,16 @@ mod tests {
                     }],
                     declarations: vec![Declaration {
                         name: "display".into(),
-                        value: CSSValue::Keyword("block".to_string()),
+                        value: CSSValue::Keyword("block".into()),
+                        important: false,
                     }],
+                    ..Default::default()
                 }]),
                 vec![
-                    (
-                        "display".to_string(),
-                        CSSValue::Keyword("inline".to_string()),
-                    ),
+                    ("display".to_string(), CSSValue::Keyword("inline".into())),
                     ("font-weight".into(), CSSValue::Keyword("normal".into())),
+                    ("font-size".into(), CSSValue::Length((16.0, Unit::Px))),
+                    ("direction".into(), CSSValue::Keyword("ltr".into())),
                 ],
             ),
             (
@@ -146,7 +2173,9 @@ mod tests {
                         declarations: vec![Declaration {
                             name: "display".to_string(),
                             value: CSSValue::Keyword("block".into()),
+                            important: false,
                         }],
+                        ..Default::default()
                     },
                     Rule {
                         selectors: vec![SimpleSelector::TypeSelector {
@@ -155,15 +2184,16 @@ mod tests {
                         declarations: vec![Declaration {
                             name: "display".into(),
                             value: CSSValue::Keyword("inline".into()),
+                            important: false,
                         }],
+                        ..Default::default()
                     },
                 ]),
                 vec![
-                    (
-                        "display".to_string(),
-                        CSSValue::Keyword("block".to_string()),
-                    ),
+                    ("display".to_string(), CSSValue::Keyword("block".into())),
                     ("font-weight".into(), CSSValue::Keyword("normal".into())),
+                    ("font-size".into(), CSSValue::Length((16.0, Unit::Px))),
+                    ("direction".into(), CSSValue::Keyword("ltr".into())),
                 ],
             ),
             (
@@ -175,7 +2205,9 @@ mod tests {
                         declarations: vec![Declaration {
                             name: "display".to_string(),
                             value: CSSValue::Keyword("block".into()),
+                            important: false,
                         }],
+                        ..Default::default()
                     },
                     Rule {
                         selectors: vec![SimpleSelector::TypeSelector {
@@ -185,17 +2217,22 @@ mod tests {
                             Declaration {
                                 name: "display".into(),
                                 value: CSSValue::Keyword("inline".into()),
+                                important: false,
                             },
                             Declaration {
                                 name: "testname".into(),
                                 value: CSSValue::Keyword("testvalue".into()),
+                                important: false,
                             },
                         ],
+                        ..Default::default()
                     },
                 ]),
                 vec![
                     ("display".into(), CSSValue::Keyword("inline".into())),
                     ("font-weight".into(), CSSValue::Keyword("normal".into())),
+                    ("font-size".into(), CSSValue::Length((16.0, Unit::Px))),
+                    ("direction".into(), CSSValue::Keyword("ltr".into())),
                     ("testname".into(), CSSValue::Keyword("testvalue".into())),
                 ],
             ),
@@ -208,11 +2245,13 @@ mod tests {
                         declarations: vec![Declaration {
                             name: "display".to_string(),
                             value: CSSValue::Keyword("block".into()),
+                            important: false,
                         }],
+                        ..Default::default()
                     },
                     Rule {
                         selectors: vec![SimpleSelector::AttributeSelector {
-                            tag_name: "p".into(),
+                            tag_name: Some("p".into()),
                             op: AttributeSelectorOp::Eq,
                             attribute: "id".into(),
                             value: "hello".into(),
@@ -220,12 +2259,16 @@ mod tests {
                         declarations: vec![Declaration {
                             name: "testname".into(),
                             value: CSSValue::Keyword("testvalue".into()),
+                            important: false,
                         }],
+                        ..Default::default()
                     },
                 ]),
                 vec![
                     ("display".into(), CSSValue::Keyword("block".into())),
                     ("font-weight".into(), CSSValue::Keyword("normal".into())),
+                    ("font-size".into(), CSSValue::Length((16.0, Unit::Px))),
+                    ("direction".into(), CSSValue::Keyword("ltr".into())),
                 ],
             ),
             (
@@ -237,11 +2280,13 @@ mod tests {
                         declarations: vec![Declaration {
                             name: "display".to_string(),
                             value: CSSValue::Keyword("block".into()),
+                            important: false,
                         }],
+                        ..Default::default()
                     },
                     Rule {
                         selectors: vec![SimpleSelector::AttributeSelector {
-                            tag_name: "p".into(),
+                            tag_name: Some("p".into()),
                             op: AttributeSelectorOp::Eq,
                             attribute: "id".into(),
                             value: "test".into(),
@@ -249,12 +2294,16 @@ mod tests {
                         declarations: vec![Declaration {
                             name: "testname".into(),
                             value: CSSValue::Keyword("testvalue".into()),
+                            important: false,
                         }],
+                        ..Default::default()
                     },
                 ]),
                 vec![
                     ("display".into(), CSSValue::Keyword("block".into())),
                     ("font-weight".into(), CSSValue::Keyword("normal".into())),
+                    ("font-size".into(), CSSValue::Length((16.0, Unit::Px))),
+                    ("direction".into(), CSSValue::Keyword("ltr".into())),
                     ("testname".into(), CSSValue::Keyword("testvalue".into())),
                 ],
             ),
@@ -297,7 +2346,8 @@ mod tests {
                 .collect(),
             vec![],
         )
-        .node_type;
+        .node_type
+        .clone();
 
         {
             // * { display: block; }
@@ -305,8 +2355,10 @@ mod tests {
                 selectors: vec![SimpleSelector::UniversalSelector],
                 declarations: vec![Declaration {
                     name: "display".to_string(),
-                    value: CSSValue::Keyword("block".to_string()),
+                    value: CSSValue::Keyword("block".into()),
+                    important: false,
                 }],
+                ..Default::default()
             }]);
 
             assert_eq!(
@@ -314,11 +2366,10 @@ mod tests {
                 Some(StyledNode {
                     node_type: &parent.node_type,
                     properties: [
-                        (
-                            "display".to_string(),
-                            CSSValue::Keyword("block".to_string()),
-                        ),
-                        ("font-weight".into(), CSSValue::Keyword("normal".into()))
+                        ("display".to_string(), CSSValue::Keyword("block".into()),),
+                        ("font-weight".into(), CSSValue::Keyword("normal".into())),
+                        ("font-size".into(), CSSValue::Length((16.0, Unit::Px))),
+                        ("direction".into(), CSSValue::Keyword("ltr".into())),
                     ]
                     .iter()
                     .cloned()
@@ -326,14 +2377,13 @@ mod tests {
                     children: vec![StyledNode {
                         node_type: &child_node_type,
                         properties: [
-                            (
-                                "display".to_string(),
-                                CSSValue::Keyword("block".to_string()),
-                            ),
+                            ("display".to_string(), CSSValue::Keyword("block".into()),),
                             (
                                 "font-weight".to_string(),
-                                CSSValue::Keyword("normal".to_string()),
-                            )
+                                CSSValue::Keyword("normal".into()),
+                            ),
+                            ("font-size".into(), CSSValue::Length((16.0, Unit::Px))),
+                            ("direction".into(), CSSValue::Keyword("ltr".into())),
                         ]
                         .iter()
                         .cloned()
@@ -352,8 +2402,10 @@ mod tests {
                 }],
                 declarations: vec![Declaration {
                     name: "display".to_string(),
-                    value: CSSValue::Keyword("block".to_string()),
+                    value: CSSValue::Keyword("block".into()),
+                    important: false,
                 }],
+                ..Default::default()
             }]);
 
             assert_eq!(
@@ -361,11 +2413,10 @@ mod tests {
                 Some(StyledNode {
                     node_type: &parent.node_type,
                     properties: [
-                        (
-                            "display".to_string(),
-                            CSSValue::Keyword("inline".to_string()),
-                        ),
-                        ("font-weight".into(), CSSValue::Keyword("normal".into()))
+                        ("display".to_string(), CSSValue::Keyword("inline".into()),),
+                        ("font-weight".into(), CSSValue::Keyword("normal".into())),
+                        ("font-size".into(), CSSValue::Length((16.0, Unit::Px))),
+                        ("direction".into(), CSSValue::Keyword("ltr".into())),
                     ]
                     .iter()
                     .cloned()
@@ -373,14 +2424,13 @@ mod tests {
                     children: vec![StyledNode {
                         node_type: &child_node_type,
                         properties: [
-                            (
-                                "display".to_string(),
-                                CSSValue::Keyword("block".to_string()),
-                            ),
+                            ("display".to_string(), CSSValue::Keyword("block".into()),),
                             (
                                 "font-weight".to_string(),
-                                CSSValue::Keyword("normal".to_string()),
-                            )
+                                CSSValue::Keyword("normal".into()),
+                            ),
+                            ("font-size".into(), CSSValue::Length((16.0, Unit::Px))),
+                            ("direction".into(), CSSValue::Keyword("ltr".into())),
                         ]
                         .iter()
                         .cloned()
@@ -410,8 +2460,10 @@ mod tests {
             }],
             declarations: vec![Declaration {
                 name: "display".to_string(),
-                value: CSSValue::Keyword("none".to_string()),
+                value: CSSValue::Keyword("none".into()),
+                important: false,
             }],
+            ..Default::default()
         }]);
 
         assert_eq!(to_styled_node(parent, &stylesheet), None);
@@ -442,8 +2494,10 @@ mod tests {
             }],
             declarations: vec![Declaration {
                 name: "display".to_string(),
-                value: CSSValue::Keyword("none".to_string()),
+                value: CSSValue::Keyword("none".into()),
+                important: false,
             }],
+            ..Default::default()
         }]);
 
         assert_eq!(
@@ -451,14 +2505,13 @@ mod tests {
             Some(StyledNode {
                 node_type: &parent.node_type,
                 properties: [
-                    (
-                        "display".to_string(),
-                        CSSValue::Keyword("inline".to_string()),
-                    ),
+                    ("display".to_string(), CSSValue::Keyword("inline".into()),),
                     (
                         "font-weight".to_string(),
-                        CSSValue::Keyword("normal".to_string()),
-                    )
+                        CSSValue::Keyword("normal".into()),
+                    ),
+                    ("font-size".into(), CSSValue::Length((16.0, Unit::Px))),
+                    ("direction".into(), CSSValue::Keyword("ltr".into())),
                 ]
                 .iter()
                 .cloned()
@@ -467,4 +2520,2626 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn test_to_styled_node_none_ancestor() {
+        let parent = &Element::new(
+            "div".to_string(),
+            [("id".to_string(), "test".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            vec![Element::new(
+                "div".to_string(),
+                [("class".to_string(), "hidden".to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                vec![Element::new(
+                    "p".to_string(),
+                    AttrMap::new(),
+                    vec![Text::new("hello".to_string())],
+                )],
+            )],
+        );
+
+        // .hidden { display: none; }
+        // p { display: block; }
+        let stylesheet = Stylesheet::new(vec![
+            Rule {
+                selectors: vec![SimpleSelector::ClassSelector {
+                    class_names: vec!["hidden".into()],
+                }],
+                declarations: vec![Declaration {
+                    name: "display".to_string(),
+                    value: CSSValue::Keyword("none".into()),
+                    important: false,
+                }],
+                ..Default::default()
+            },
+            Rule {
+                selectors: vec![SimpleSelector::TypeSelector {
+                    tag_name: "p".into(),
+                }],
+                declarations: vec![Declaration {
+                    name: "display".to_string(),
+                    value: CSSValue::Keyword("block".into()),
+                    important: false,
+                }],
+                ..Default::default()
+            },
+        ]);
+
+        assert_eq!(
+            to_styled_node(parent, &stylesheet),
+            Some(StyledNode {
+                node_type: &parent.node_type,
+                properties: [
+                    ("display".to_string(), CSSValue::Keyword("inline".into()),),
+                    (
+                        "font-weight".to_string(),
+                        CSSValue::Keyword("normal".into()),
+                    ),
+                    ("font-size".into(), CSSValue::Length((16.0, Unit::Px))),
+                    ("direction".into(), CSSValue::Keyword("ltr".into())),
+                ]
+                .iter()
+                .cloned()
+                .collect(),
+                children: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn test_unused_rules() {
+        let root = &Element::new(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![Element::new(
+                "p".to_string(),
+                [("class".to_string(), "note".to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                vec![Text::new("hello".to_string())],
+            )],
+        );
+
+        // .note { display: block; }
+        // span { display: block; }
+        let stylesheet = Stylesheet::new(vec![
+            Rule {
+                selectors: vec![SimpleSelector::ClassSelector {
+                    class_names: vec!["note".into()],
+                }],
+                declarations: vec![Declaration {
+                    name: "display".to_string(),
+                    value: CSSValue::Keyword("block".into()),
+                    important: false,
+                }],
+                ..Default::default()
+            },
+            Rule {
+                selectors: vec![SimpleSelector::TypeSelector {
+                    tag_name: "span".into(),
+                }],
+                declarations: vec![Declaration {
+                    name: "display".to_string(),
+                    value: CSSValue::Keyword("block".into()),
+                    important: false,
+                }],
+                ..Default::default()
+            },
+        ]);
+
+        assert_eq!(unused_rules(root, &stylesheet), vec![1]);
+    }
+
+    #[test]
+    fn test_ineffective_rules() {
+        // <div><p id="x"></p></div>
+        let root = &Element::new(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![Element::new(
+                "p".to_string(),
+                [("id".to_string(), "x".to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                vec![],
+            )],
+        );
+        let rule = |selector: SimpleSelector, declarations: &[(&str, &str)]| Rule {
+            selectors: vec![selector],
+            declarations: declarations
+                .iter()
+                .map(|(name, value)| Declaration {
+                    name: name.to_string(),
+                    value: CSSValue::keyword(*value),
+                    important: false,
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let type_selector = |tag_name: &str| SimpleSelector::TypeSelector {
+            tag_name: tag_name.into(),
+        };
+
+        // p { color: red; }
+        // #x { color: blue; }
+        // p { color: green; font-weight: bold; }
+        // span { color: black; }
+        // div { color: red; }
+        // div { color: blue; }
+        // * { display: block; }
+        let stylesheet = Stylesheet::new(vec![
+            rule(type_selector("p"), &[("color", "red")]),
+            rule(
+                SimpleSelector::IdSelector { id: "x".into() },
+                &[("color", "blue")],
+            ),
+            rule(
+                type_selector("p"),
+                &[("color", "green"), ("font-weight", "bold")],
+            ),
+            rule(type_selector("span"), &[("color", "black")]),
+            rule(type_selector("div"), &[("color", "red")]),
+            rule(type_selector("div"), &[("color", "blue")]),
+            rule(SimpleSelector::UniversalSelector, &[("display", "block")]),
+        ]);
+
+        // the rule overridden by a more specific one, and the one overridden by a later one
+        assert_eq!(ineffective_rules(root, &stylesheet), vec![0, 4]);
+        // rules matching nothing are reported by `unused_rules` instead
+        assert_eq!(unused_rules(root, &stylesheet), vec![3]);
+    }
+
+    #[test]
+    fn test_rules_exceeding_specificity() {
+        let rule = |selectors| Rule {
+            selectors,
+            ..Default::default()
+        };
+
+        // p { }
+        // #main, .note { }
+        // p:not(.note) { }
+        // .a.b { }
+        let stylesheet = Stylesheet::new(vec![
+            rule(vec![SimpleSelector::TypeSelector {
+                tag_name: "p".into(),
+            }]),
+            rule(vec![
+                SimpleSelector::IdSelector { id: "main".into() },
+                SimpleSelector::ClassSelector {
+                    class_names: vec!["note".into()],
+                },
+            ]),
+            rule(vec![SimpleSelector::PseudoClassSelector {
+                tag_name: Some("p".into()),
+                pseudo_class: PseudoClass::Not(Box::new(SimpleSelector::ClassSelector {
+                    class_names: vec!["note".into()],
+                })),
+            }]),
+            rule(vec![SimpleSelector::ClassSelector {
+                class_names: vec!["a".into(), "b".into()],
+            }]),
+        ]);
+
+        assert_eq!(
+            rules_exceeding_specificity(&stylesheet, (0, 1, 0)),
+            vec![1, 2, 3]
+        );
+        assert_eq!(rules_exceeding_specificity(&stylesheet, (0, 2, 0)), vec![1]);
+        assert_eq!(
+            rules_exceeding_specificity(&stylesheet, (1, 0, 0)),
+            Vec::<usize>::new()
+        );
+    }
+
+    #[test]
+    fn test_trace_property() {
+        let e = &Element::new(
+            "p".to_string(),
+            [("class".to_string(), "note".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            vec![],
+        );
+        let color = |keyword: &str| Declaration {
+            name: "color".to_string(),
+            value: CSSValue::keyword(keyword),
+            important: false,
+        };
+
+        // .note { color: red; }
+        // p { color: blue; font-weight: bold; }
+        // div { color: green; }
+        let stylesheet = Stylesheet::new(vec![
+            Rule {
+                selectors: vec![SimpleSelector::ClassSelector {
+                    class_names: vec!["note".into()],
+                }],
+                declarations: vec![color("red")],
+                ..Default::default()
+            },
+            Rule {
+                selectors: vec![SimpleSelector::TypeSelector {
+                    tag_name: "p".into(),
+                }],
+                declarations: vec![
+                    color("blue"),
+                    Declaration {
+                        name: "font-weight".to_string(),
+                        value: CSSValue::keyword("bold"),
+                        important: false,
+                    },
+                ],
+                origin: Origin::UserAgent,
+                ..Default::default()
+            },
+            Rule {
+                selectors: vec![SimpleSelector::TypeSelector {
+                    tag_name: "div".into(),
+                }],
+                declarations: vec![color("green")],
+                ..Default::default()
+            },
+        ]);
+
+        let trace = trace_property(e, &stylesheet, "color");
+        assert_eq!(
+            trace.candidates,
+            vec![
+                CascadeCandidate {
+                    rule: 1,
+                    declaration: color("blue"),
+                    specificity: (0, 0, 1),
+                    origin: Origin::UserAgent,
+                },
+                CascadeCandidate {
+                    rule: 0,
+                    declaration: color("red"),
+                    specificity: (0, 1, 0),
+                    origin: Origin::Author,
+                },
+            ]
+        );
+        assert_eq!(trace.winner, Some(1));
+        // the winner is the value `to_styled_node` computes
+        assert_eq!(
+            to_styled_node(e, &stylesheet)
+                .unwrap()
+                .properties
+                .get("color"),
+            Some(&trace.candidates[1].declaration.value)
+        );
+
+        assert_eq!(
+            trace_property(e, &stylesheet, "width"),
+            PropertyTrace {
+                candidates: vec![],
+                winner: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_to_styled_node_prefers_color_scheme() {
+        let e = &Element::new("p".to_string(), AttrMap::new(), vec![]);
+
+        // p { color: black; }
+        // @media (prefers-color-scheme: dark) { p { color: white; } }
+        let stylesheet = Stylesheet::new(vec![
+            Rule {
+                selectors: vec![SimpleSelector::TypeSelector {
+                    tag_name: "p".into(),
+                }],
+                declarations: vec![Declaration {
+                    name: "color".to_string(),
+                    value: CSSValue::Keyword("black".into()),
+                    important: false,
+                }],
+                ..Default::default()
+            },
+            Rule {
+                selectors: vec![SimpleSelector::TypeSelector {
+                    tag_name: "p".into(),
+                }],
+                declarations: vec![Declaration {
+                    name: "color".to_string(),
+                    value: CSSValue::Keyword("white".into()),
+                    important: false,
+                }],
+                media: Some(MediaQuery {
+                    features: vec![MediaFeature {
+                        name: "prefers-color-scheme".to_string(),
+                        value: CSSValue::Keyword("dark".into()),
+                    }],
+                }),
+                ..Default::default()
+            },
+        ]);
+
+        let testcases = vec![
+            (StyleContext::default(), "black"),
+            (
+                StyleContext {
+                    color_scheme: ColorScheme::from("dark"),
+                    ..Default::default()
+                },
+                "white",
+            ),
+            (
+                StyleContext {
+                    color_scheme: ColorScheme::from("no-preference"),
+                    ..Default::default()
+                },
+                "black",
+            ),
+        ];
+        for (context, color) in testcases {
+            assert_eq!(
+                to_styled_node_with_context(e, &stylesheet, &context)
+                    .unwrap()
+                    .properties
+                    .get("color"),
+                Some(&CSSValue::Keyword(color.to_string().into()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_styled_node_supports() {
+        let e = &Element::new("p".to_string(), AttrMap::new(), vec![]);
+        let display = |value: &str| {
+            SupportsCondition::Declaration(Declaration {
+                name: "display".to_string(),
+                value: CSSValue::Keyword(value.to_string().into()),
+                important: false,
+            })
+        };
+        let rule = |name: &str, supports: SupportsCondition| Rule {
+            selectors: vec![SimpleSelector::TypeSelector {
+                tag_name: "p".into(),
+            }],
+            declarations: vec![Declaration {
+                name: name.to_string(),
+                value: CSSValue::Keyword("yes".into()),
+                important: false,
+            }],
+            supports: Some(supports),
+            ..Default::default()
+        };
+
+        // @supports (display: contents) { p { contents: yes; } }
+        // @supports (display: block) { p { block: yes; } }
+        // ...
+        let stylesheet = Stylesheet::new(vec![
+            rule("contents", display("contents")),
+            rule("grid", display("grid")),
+            rule("block", display("block")),
+            rule(
+                "not-contents",
+                SupportsCondition::Not(Box::new(display("contents"))),
+            ),
+            rule(
+                "block-and-contents",
+                SupportsCondition::And(vec![display("block"), display("contents")]),
+            ),
+            rule(
+                "block-or-contents",
+                SupportsCondition::Or(vec![display("block"), display("contents")]),
+            ),
+            rule("unknown", SupportsCondition::Unknown),
+        ]);
+
+        let properties = to_styled_node(e, &stylesheet).unwrap().properties;
+        assert_eq!(properties.get("contents"), None);
+        assert!(properties.get("grid").is_some());
+        assert!(properties.get("block").is_some());
+        assert!(properties.get("not-contents").is_some());
+        assert_eq!(properties.get("block-and-contents"), None);
+        assert!(properties.get("block-or-contents").is_some());
+        assert_eq!(properties.get("unknown"), None);
+    }
+
+    #[test]
+    fn test_supports() {
+        for (raw, expected) in [
+            ("color: red", true),
+            ("color: currentcolor", true),
+            ("color: foo", false),
+            ("border-left-color: #00f", true),
+            ("font-style: italic", true),
+            ("line-height: 1.5", true),
+            ("background-position: right top", true),
+            ("box-shadow: 2px 2px red, 4px 4px blue", true),
+            ("box-shadow: red", false),
+            ("transform: translate(10px, 5px)", true),
+            // shorthands are supported when all of their longhands are
+            ("margin: 1px 2px", true),
+            ("border: 1px solid red", true),
+            ("border: 1px solid foo", false),
+            ("unknown: 1px", false),
+        ] {
+            let declaration = &css::parse_declarations(raw)[0];
+            assert_eq!(supports(declaration), expected, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn test_to_styled_node_specificity() {
+        // <p id="a" class="b c" lang="en"></p>
+        let e = &Element::new(
+            "p".to_string(),
+            [
+                ("id".to_string(), "a".to_string()),
+                ("class".to_string(), "b c".to_string()),
+                ("lang".to_string(), "en".to_string()),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            vec![],
+        );
+        let type_selector = |tag_name: &str| SimpleSelector::TypeSelector {
+            tag_name: tag_name.into(),
+        };
+        let class_selector = |class_names: &[&str]| SimpleSelector::ClassSelector {
+            class_names: class_names.iter().map(|c| c.to_string()).collect(),
+        };
+        let id_selector = |id: &str| SimpleSelector::IdSelector { id: id.into() };
+        let pseudo_class_selector = |tag_name: Option<&str>, pseudo_class: PseudoClass| {
+            SimpleSelector::PseudoClassSelector {
+                tag_name: tag_name.map(|t| t.into()),
+                pseudo_class,
+            }
+        };
+        let not = |selector: SimpleSelector| PseudoClass::Not(Box::new(selector));
+
+        // each case is a list of rules in the source order, and the value of the winning rule
+        let cases = vec![
+            // .b.c is (0, 2, 0) and #a is (1, 0, 0)
+            (
+                vec![
+                    (id_selector("a"), "id"),
+                    (class_selector(&["b", "c"]), "class"),
+                ],
+                "id",
+            ),
+            (
+                vec![
+                    (class_selector(&["b", "c"]), "class"),
+                    (id_selector("a"), "id"),
+                ],
+                "id",
+            ),
+            // `*` contributes nothing
+            (
+                vec![
+                    (type_selector("p"), "type"),
+                    (SimpleSelector::UniversalSelector, "universal"),
+                ],
+                "type",
+            ),
+            // p[lang=en] is (0, 1, 1) and .b is (0, 1, 0)
+            (
+                vec![
+                    (
+                        SimpleSelector::AttributeSelector {
+                            tag_name: Some("p".into()),
+                            op: AttributeSelectorOp::Eq,
+                            attribute: "lang".into(),
+                            value: "en".into(),
+                        },
+                        "attribute",
+                    ),
+                    (class_selector(&["b"]), "class"),
+                ],
+                "attribute",
+            ),
+            // :not(#x) is (1, 0, 0)
+            (
+                vec![
+                    (pseudo_class_selector(None, not(id_selector("x"))), "not"),
+                    (class_selector(&["b", "c"]), "class"),
+                ],
+                "not",
+            ),
+            // p:not(.x) is (0, 1, 1)
+            (
+                vec![
+                    (
+                        pseudo_class_selector(Some("p"), not(class_selector(&["x"]))),
+                        "not",
+                    ),
+                    (class_selector(&["b"]), "class"),
+                ],
+                "not",
+            ),
+            // :not(*) is (0, 0, 0) but never matches
+            (
+                vec![
+                    (SimpleSelector::UniversalSelector, "universal"),
+                    (
+                        pseudo_class_selector(None, not(SimpleSelector::UniversalSelector)),
+                        "not",
+                    ),
+                ],
+                "universal",
+            ),
+            // :not(p) does not match, so the less specific rule wins
+            (
+                vec![
+                    (pseudo_class_selector(None, not(type_selector("p"))), "not"),
+                    (SimpleSelector::UniversalSelector, "universal"),
+                ],
+                "universal",
+            ),
+            // the latter one wins when specificities are the same
+            (
+                vec![
+                    (SimpleSelector::UniversalSelector, "first"),
+                    (SimpleSelector::UniversalSelector, "second"),
+                ],
+                "second",
+            ),
+            (
+                vec![
+                    (class_selector(&["b"]), "first"),
+                    (class_selector(&["c"]), "second"),
+                ],
+                "second",
+            ),
+            (
+                vec![
+                    (class_selector(&["c"]), "first"),
+                    (class_selector(&["b"]), "second"),
+                ],
+                "second",
+            ),
+            // :is(#x, p) is (1, 0, 0) even though it matches by p
+            (
+                vec![
+                    (
+                        pseudo_class_selector(
+                            None,
+                            PseudoClass::Is(vec![id_selector("x"), type_selector("p")]),
+                        ),
+                        "is",
+                    ),
+                    (class_selector(&["b", "c"]), "class"),
+                ],
+                "is",
+            ),
+            // :where(#a) is (0, 0, 0)
+            (
+                vec![
+                    (type_selector("p"), "type"),
+                    (
+                        pseudo_class_selector(None, PseudoClass::Where(vec![id_selector("a")])),
+                        "where",
+                    ),
+                ],
+                "type",
+            ),
+            (
+                vec![
+                    (
+                        pseudo_class_selector(Some("p"), PseudoClass::Lang("en".into())),
+                        "lang",
+                    ),
+                    (
+                        pseudo_class_selector(Some("p"), PseudoClass::FirstOfType),
+                        "first-of-type",
+                    ),
+                ],
+                "first-of-type",
+            ),
+            (
+                vec![
+                    (
+                        pseudo_class_selector(None, not(class_selector(&["x"]))),
+                        "not",
+                    ),
+                    (class_selector(&["b"]), "class"),
+                ],
+                "class",
+            ),
+            (
+                vec![
+                    (class_selector(&["b"]), "class"),
+                    (
+                        pseudo_class_selector(None, not(class_selector(&["x"]))),
+                        "not",
+                    ),
+                ],
+                "not",
+            ),
+        ];
+
+        for (i, (rules, expected)) in cases.into_iter().enumerate() {
+            let stylesheet = Stylesheet::new(
+                rules
+                    .into_iter()
+                    .map(|(selector, value)| Rule {
+                        selectors: vec![selector],
+                        declarations: vec![Declaration {
+                            name: "color".to_string(),
+                            value: CSSValue::Keyword(value.to_string().into()),
+                            important: false,
+                        }],
+                        ..Default::default()
+                    })
+                    .collect(),
+            );
+            let styled = to_styled_node(e, &stylesheet).unwrap();
+            assert_eq!(
+                styled.properties.get("color"),
+                Some(&CSSValue::Keyword(expected.to_string().into())),
+                "case {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn test_compact_styled_node() {
+        // <div><p class="a">x</p><p dir="rtl"><span></span></p></div>
+        let e = &Element::new(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![
+                Element::new(
+                    "p".to_string(),
+                    [("class".to_string(), "a".to_string())]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                    vec![Text::new("x".to_string())],
+                ),
+                Element::new(
+                    "p".to_string(),
+                    [("dir".to_string(), "rtl".to_string())]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                    vec![Element::new("span".to_string(), AttrMap::new(), vec![])],
+                ),
+            ],
+        );
+        // div, p { display: block; }
+        // .a { font-size: 20px; font-weight: bold; line-height: normal; }
+        let stylesheet = Stylesheet::new(vec![
+            Rule {
+                selectors: vec![
+                    SimpleSelector::TypeSelector {
+                        tag_name: "div".into(),
+                    },
+                    SimpleSelector::TypeSelector {
+                        tag_name: "p".into(),
+                    },
+                ],
+                declarations: vec![Declaration {
+                    name: "display".to_string(),
+                    value: CSSValue::Keyword("block".into()),
+                    important: false,
+                }],
+                ..Default::default()
+            },
+            Rule {
+                selectors: vec![SimpleSelector::ClassSelector {
+                    class_names: vec!["a".into()],
+                }],
+                declarations: vec![
+                    Declaration {
+                        name: "font-size".to_string(),
+                        value: CSSValue::Length((20.0, Unit::Px)),
+                        important: false,
+                    },
+                    Declaration {
+                        name: "font-weight".to_string(),
+                        value: CSSValue::Keyword("bold".into()),
+                        important: false,
+                    },
+                    Declaration {
+                        name: "line-height".to_string(),
+                        value: CSSValue::Keyword("normal".into()),
+                        important: false,
+                    },
+                ],
+                ..Default::default()
+            },
+        ]);
+
+        let full = to_styled_node(e, &stylesheet).unwrap();
+        let compact = CompactStyledNode::from(to_styled_node(e, &stylesheet).unwrap());
+
+        fn assert_same_values(full: &StyledNode, compact: &CompactStyledNode) {
+            for name in
+                full.properties
+                    .keys()
+                    .map(|n| n.as_str())
+                    .chain(["color", "width", "line-height"])
+            {
+                assert_eq!(full.value(name), compact.value(name), "{}", name);
+            }
+            assert!(compact.stored_len() <= full.properties.len());
+            assert_eq!(full.children.len(), compact.children.len());
+            for (full, compact) in full.children.iter().zip(compact.children.iter()) {
+                assert_same_values(full, compact);
+            }
+        }
+        assert_same_values(&full, &compact);
+
+        // the span stores nothing since it has the same values as its parent or the initial ones,
+        // and the text stores only that `line-height` is unset unlike its parent
+        assert_eq!(compact.children[0].children[0].stored_len(), 1);
+        assert_eq!(compact.children[0].children[0].value("line-height"), None);
+        assert_eq!(compact.children[1].children[0].stored_len(), 0);
+        // the ps store `display` and the values they change
+        assert_eq!(compact.children[0].stored_len(), 4);
+        assert_eq!(compact.children[1].stored_len(), 2);
+        // only `display` is stored for the div
+        assert_eq!(compact.stored_len(), 1);
+        // the siblings share the inherited values
+        assert!(Rc::ptr_eq(
+            &compact.children[0].inherited,
+            &compact.children[1].inherited
+        ));
+    }
+
+    #[test]
+    fn test_styled_node_is_block_or_inline() {
+        let node = NodeType::Element(Element {
+            tag_name: "div".into(),
+            attributes: AttrMap::new(),
+            ..Default::default()
+        });
+        let styled = |display: Display| StyledNode {
+            node_type: &node,
+            properties: [(
+                "display".to_string(),
+                CSSValue::Keyword(display.to_keyword().into()),
+            )]
+            .iter()
+            .cloned()
+            .collect(),
+            children: vec![],
+        };
+
+        for (display, is_block, is_inline) in [
+            (Display::Inline, false, true),
+            (Display::Block, true, false),
+            (Display::Grid, true, false),
+            (Display::ListItem, true, false),
+            (Display::Table, true, false),
+            (Display::Flex, true, false),
+            (Display::InlineBlock, false, true),
+            (Display::TableColumn, false, false),
+            (Display::TableColumnGroup, false, false),
+            (Display::None, false, false),
+        ] {
+            assert_eq!(Display::from_keyword(display.to_keyword()), Some(display));
+            assert_eq!(styled(display).is_block(), is_block, "{:?}", display);
+            assert_eq!(styled(display).is_inline(), is_inline, "{:?}", display);
+        }
+    }
+
+    #[test]
+    fn test_styled_node_length() {
+        let node = NodeType::Element(Element {
+            tag_name: "div".into(),
+            attributes: AttrMap::new(),
+            ..Default::default()
+        });
+        let styled = StyledNode {
+            node_type: &node,
+            properties: [
+                ("font-size", CSSValue::Length((20.0, Unit::Px))),
+                ("margin-top", CSSValue::Length((2.0, Unit::Em))),
+                ("margin-left", CSSValue::Length((1.5, Unit::Rem))),
+                ("width", CSSValue::Length((50.0, Unit::Percent))),
+                ("height", CSSValue::keyword("auto")),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect(),
+            children: vec![],
+        };
+
+        assert_eq!(styled.length("font-size", 100.0), Some(20.0));
+        // `em` refers to the font size of the node itself
+        assert_eq!(styled.length("margin-top", 100.0), Some(40.0));
+        assert_eq!(styled.length("margin-left", 100.0), Some(24.0));
+        assert_eq!(styled.length("width", 300.0), Some(150.0));
+        assert_eq!(styled.length("height", 300.0), None);
+        assert_eq!(styled.length("padding-top", 300.0), None);
+    }
+
+    #[test]
+    fn test_styled_node_colors() {
+        let node = NodeType::Element(Element {
+            tag_name: "div".into(),
+            attributes: AttrMap::new(),
+            ..Default::default()
+        });
+        let styled = |properties: &[(&str, CSSValue)]| StyledNode {
+            node_type: &node,
+            properties: properties
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            children: vec![],
+        };
+
+        let default = styled(&[]);
+        assert_eq!(default.color(), Color::rgb(0, 0, 0));
+        assert_eq!(default.background_color().a, 0);
+
+        let colored = styled(&[
+            ("color", CSSValue::keyword("red")),
+            ("background-color", CSSValue::Color(Color::rgb(0, 0, 255))),
+        ]);
+        assert_eq!(colored.color(), Color::rgb(255, 0, 0));
+        assert_eq!(colored.background_color(), Color::rgb(0, 0, 255));
+
+        let current = styled(&[
+            ("color", CSSValue::keyword("green")),
+            ("background-color", CSSValue::keyword("currentcolor")),
+        ]);
+        assert_eq!(current.background_color(), Color::rgb(0, 128, 0));
+    }
+
+    #[test]
+    fn test_to_styled_node_important() {
+        // <p id="x" class="a">hello</p>
+        let root = &build_tree(&TreeSpec::Element(
+            "p",
+            &[("id", "x"), ("class", "a")],
+            &[TreeSpec::Text("hello")],
+        ));
+        let stylesheet = || {
+            parse(
+                "p { color: red !important; margin: 1px !important; } \
+                 .a { color: green !important; } \
+                 #x { color: blue; margin-top: 2px; font-weight: bold; }",
+            )
+        };
+
+        let styled = to_styled_node(root, &stylesheet()).unwrap();
+        let sorted = to_styled_node(root, &stylesheet().into_sorted()).unwrap();
+        assert_eq!(styled, sorted);
+        // `!important` wins over higher specificity, and specificity still decides among important ones
+        assert_eq!(styled.value("color"), Some(&CSSValue::keyword("green")));
+        // the longhands of an important shorthand are important
+        assert_eq!(
+            styled.value("margin-top"),
+            Some(&CSSValue::Length((1.0, Unit::Px)))
+        );
+        assert_eq!(
+            styled.value("font-weight"),
+            Some(&CSSValue::keyword("bold"))
+        );
+
+        let trace = trace_property(root, &stylesheet(), "color");
+        assert_eq!(trace.candidates.len(), 3);
+        assert_eq!(trace.candidates[trace.winner.unwrap()].rule, 1);
+    }
+
+    #[test]
+    fn test_to_styled_node_inline_style() {
+        // <p id="x" style="color: red; margin: 1px; font-weight: bold !important">hello</p>
+        let root = &build_tree(&TreeSpec::Element(
+            "p",
+            &[
+                ("id", "x"),
+                (
+                    "style",
+                    "color: red; margin: 1px; font-weight: bold !important",
+                ),
+            ],
+            &[TreeSpec::Text("hello")],
+        ));
+        let stylesheet = parse(
+            "#x { color: blue; margin-top: 2px !important; font-weight: normal !important; }",
+        );
+
+        let styled = to_styled_node(root, &stylesheet).unwrap();
+        // the style attribute wins over the rules of the same importance
+        assert_eq!(styled.value("color"), Some(&CSSValue::keyword("red")));
+        assert_eq!(
+            styled.value("font-weight"),
+            Some(&CSSValue::keyword("bold"))
+        );
+        assert_eq!(
+            styled.value("margin-top"),
+            Some(&CSSValue::Length((2.0, Unit::Px)))
+        );
+        assert_eq!(
+            styled.value("margin-left"),
+            Some(&CSSValue::Length((1.0, Unit::Px)))
+        );
+        // children inherit the values from the style attribute
+        assert_eq!(
+            styled.children[0].value("color"),
+            Some(&CSSValue::keyword("red"))
+        );
+    }
+
+    #[test]
+    fn test_user_agent_stylesheet() {
+        let ua = user_agent_stylesheet();
+        assert!(!ua.rules.is_empty());
+        assert!(ua.rules.iter().all(|r| r.origin == Origin::UserAgent));
+
+        // <body><h1>title</h1><p>a <b>b</b> <em>c</em></p><script>x</script></body>
+        let root = &build_tree(&TreeSpec::Element(
+            "body",
+            &[],
+            &[
+                TreeSpec::Element("h1", &[], &[TreeSpec::Text("title")]),
+                TreeSpec::Element(
+                    "p",
+                    &[],
+                    &[
+                        TreeSpec::Text("a "),
+                        TreeSpec::Element("b", &[], &[TreeSpec::Text("b")]),
+                        TreeSpec::Element("em", &[], &[TreeSpec::Text("c")]),
+                    ],
+                ),
+                TreeSpec::Element("script", &[], &[TreeSpec::Text("x")]),
+            ],
+        ));
+        let stylesheet = Stylesheet::merge([ua, parse("p { display: inline; }")]);
+
+        let styled = to_styled_node(root, &stylesheet).unwrap();
+        assert_eq!(styled.display(), Display::Block);
+        // the script is not rendered
+        assert_eq!(styled.children.len(), 2);
+        let (h1, p) = (&styled.children[0], &styled.children[1]);
+        assert_eq!(h1.display(), Display::Block);
+        assert_eq!(h1.value("font-weight"), Some(&CSSValue::keyword("bold")));
+        assert_eq!(h1.length("font-size", 0.0), Some(32.0));
+        // the author stylesheet overrides the UA stylesheet
+        assert_eq!(p.display(), Display::Inline);
+        assert_eq!(
+            p.children[1].value("font-weight"),
+            Some(&CSSValue::keyword("bold"))
+        );
+        assert_eq!(
+            p.children[2].value("font-style"),
+            Some(&CSSValue::keyword("italic"))
+        );
+    }
+
+    #[test]
+    fn test_to_styled_node_origins() {
+        // <p id="x">hello</p>
+        let root = &build_tree(&TreeSpec::Element(
+            "p",
+            &[("id", "x")],
+            &[TreeSpec::Text("hello")],
+        ));
+        let stylesheets = || {
+            [
+                parse("#x { color: red; font-style: italic !important; }")
+                    .with_origin(Origin::UserAgent),
+                parse("p { font-style: normal !important; text-align: left; }")
+                    .with_origin(Origin::User),
+                parse("* { color: blue; font-style: oblique !important; text-align: center; }")
+                    .with_origin(Origin::Author),
+            ]
+        };
+
+        let styled = to_styled_node(root, &stylesheets()[..]).unwrap();
+        // author declarations win over UA and user ones regardless of specificity
+        assert_eq!(styled.value("color"), Some(&CSSValue::keyword("blue")));
+        assert_eq!(
+            styled.value("text-align"),
+            Some(&CSSValue::keyword("center"))
+        );
+        // the order is reversed for important declarations
+        assert_eq!(
+            styled.value("font-style"),
+            Some(&CSSValue::keyword("italic"))
+        );
+
+        // merging the stylesheets gives the same result
+        let merged = || Stylesheet::merge(stylesheets());
+        assert_eq!(to_styled_node(root, &merged()), Some(styled));
+        assert_eq!(
+            to_styled_node(root, &merged()),
+            to_styled_node(root, &merged().into_sorted())
+        );
+
+        let trace = trace_property(root, &merged(), "color");
+        assert_eq!(
+            trace.candidates[trace.winner.unwrap()].origin,
+            Origin::Author
+        );
+    }
+
+    #[test]
+    fn test_collect_stylesheets() {
+        let root = &html::parse(
+            "<html><head><style>p { color: red; }</style></head>\
+             <body><p>hello</p><div><style>.a { color: blue; } p { font-weight: bold; }</style></div></body></html>",
+        );
+
+        let stylesheet = collect_stylesheets(root);
+        assert_eq!(stylesheet.rules.len(), 3);
+        // the rules are in the document order
+        assert_eq!(
+            stylesheet.rules[1].selectors,
+            vec![SimpleSelector::ClassSelector {
+                class_names: vec!["a".into()],
+            }]
+        );
+        assert!(stylesheet.rules.iter().all(|r| r.origin == Origin::Author));
+
+        assert_eq!(
+            collect_stylesheets(&html::parse("<p>no style</p>")).rules,
+            vec![]
+        );
+
+        // stylesheets which cannot be parsed are skipped
+        let root = &build_tree(&TreeSpec::Element(
+            "html",
+            &[],
+            &[
+                TreeSpec::Element("style", &[], &[TreeSpec::Text("p { color: ; }")]),
+                TreeSpec::Element("style", &[], &[TreeSpec::Text("p { color: red; } @@@")]),
+                TreeSpec::Element(
+                    "style",
+                    &[],
+                    &[TreeSpec::Text(".btn-primary { color: red; }")],
+                ),
+            ],
+        ));
+        assert_eq!(collect_stylesheets(root).rules.len(), 1);
+    }
+
+    #[test]
+    fn test_collect_stylesheets_with_loader() {
+        let link = |attributes| TreeSpec::Element("link", attributes, &[]);
+        let head = [
+            link(&[("rel", "stylesheet"), ("href", "a.css")]),
+            TreeSpec::Element("style", &[], &[TreeSpec::Text("p { color: red; }")]),
+            link(&[("rel", "stylesheet"), ("href", "missing.css")]),
+            link(&[("rel", "alternate stylesheet"), ("href", "b.css")]),
+            link(&[("rel", "icon"), ("href", "b.css")]),
+            link(&[("rel", "stylesheet"), ("href", "c.css")]),
+            link(&[("rel", "stylesheet"), ("href", "broken.css")]),
+            link(&[("rel", "stylesheet"), ("href", "trailing.css")]),
+        ];
+        let root = &build_tree(&TreeSpec::Element(
+            "html",
+            &[],
+            &[TreeSpec::Element("head", &[], &head)],
+        ));
+        let loader = [
+            ("a.css", "p { color: green; font-weight: bold; }"),
+            ("b.css", "p { display: none; }"),
+            ("c.css", "p { color: blue; }"),
+            ("broken.css", "p { color: "),
+            ("trailing.css", "p { color: black; } @@@"),
+        ]
+        .iter()
+        .map(|(url, css)| (url.to_string(), css.to_string()))
+        .collect::<HashMap<_, _>>();
+
+        let stylesheet = collect_stylesheets_with_loader(root, &loader);
+        let colors = stylesheet
+            .rules
+            .iter()
+            .filter_map(|r| r.declarations.iter().find(|d| d.name == "color"))
+            .map(|d| d.value.clone())
+            .collect::<Vec<_>>();
+        // the stylesheets are in the document order, and the broken ones are skipped as a whole
+        assert_eq!(
+            colors,
+            vec![
+                CSSValue::keyword("green"),
+                CSSValue::keyword("red"),
+                CSSValue::keyword("blue"),
+            ]
+        );
+
+        // links are ignored without a loader
+        assert_eq!(collect_stylesheets(root).rules.len(), 1);
+    }
+
+    #[test]
+    fn test_to_styled_node_sorted_stylesheet() {
+        // <div class="a"><p id="x" class="a">hello</p><p></p></div>
+        let root = &Element::new(
+            "div".to_string(),
+            [("class".to_string(), "a".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            vec![
+                Element::new(
+                    "p".to_string(),
+                    [
+                        ("id".to_string(), "x".to_string()),
+                        ("class".to_string(), "a".to_string()),
+                    ]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                    vec![Text::new("hello".to_string())],
+                ),
+                Element::new("p".to_string(), AttrMap::new(), vec![]),
+            ],
+        );
+        let declaration = |name: &str, value: &str| Declaration {
+            name: name.to_string(),
+            value: CSSValue::keyword(value),
+            important: false,
+        };
+        let stylesheet = || {
+            Stylesheet::new(vec![
+                // #x, div { color: red; }
+                Rule {
+                    selectors: vec![
+                        SimpleSelector::IdSelector { id: "x".into() },
+                        SimpleSelector::TypeSelector {
+                            tag_name: "div".into(),
+                        },
+                    ],
+                    declarations: vec![declaration("color", "red")],
+                    ..Default::default()
+                },
+                // .a { color: blue; font-weight: bold; }
+                Rule {
+                    selectors: vec![SimpleSelector::ClassSelector {
+                        class_names: vec!["a".into()],
+                    }],
+                    declarations: vec![
+                        declaration("color", "blue"),
+                        declaration("font-weight", "bold"),
+                    ],
+                    ..Default::default()
+                },
+                // p { color: green; display: block; }
+                Rule {
+                    selectors: vec![SimpleSelector::TypeSelector {
+                        tag_name: "p".into(),
+                    }],
+                    declarations: vec![
+                        declaration("color", "green"),
+                        declaration("display", "block"),
+                    ],
+                    ..Default::default()
+                },
+                // * { color: black; }
+                Rule {
+                    selectors: vec![SimpleSelector::UniversalSelector],
+                    declarations: vec![declaration("color", "black")],
+                    ..Default::default()
+                },
+            ])
+        };
+        let unsorted = stylesheet();
+        let sorted = stylesheet().into_sorted();
+        assert_eq!(sorted.rules(), unsorted.rules.as_slice());
+
+        // the selectors are sorted once in advance, so styling does not need to sort the matched ones again
+        let keys = sorted
+            .order
+            .iter()
+            .map(|&(i, j)| {
+                let rule = &sorted.rules()[i];
+                (
+                    rule.origin.cascade_order(false),
+                    rule.layer_order(),
+                    rule.selectors[j].specificity(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert!(keys.windows(2).all(|w| w[0] <= w[1]));
+
+        // each node gets the same declarations in the same order as sorting on the fly
+        let unsorted_recording = Recording::new(&unsorted);
+        let expected = to_styled_node(root, &unsorted_recording).unwrap();
+        let sorted_recording = Recording::new(&sorted);
+        let styled = to_styled_node(root, &sorted_recording).unwrap();
+        assert_eq!(
+            sorted_recording.declarations.into_inner(),
+            unsorted_recording.declarations.into_inner()
+        );
+
+        assert_eq!(styled, expected);
+        assert_eq!(
+            styled.properties.get("color"),
+            Some(&CSSValue::keyword("blue"))
+        );
+        // #x wins over .a
+        assert_eq!(
+            styled.children[0].properties.get("color"),
+            Some(&CSSValue::keyword("red"))
+        );
+        assert_eq!(
+            styled.children[1].properties.get("color"),
+            Some(&CSSValue::keyword("green"))
+        );
+    }
+
+    #[test]
+    fn test_merge_properties() {
+        let declaration = |value: &str| Declaration {
+            name: "color".to_string(),
+            value: CSSValue::Keyword(value.to_string().into()),
+            important: false,
+        };
+
+        let mut properties = PropertyMap::new();
+        merge_properties(
+            &mut properties,
+            &[
+                (declaration("red"), (0, 1, 0)),
+                (declaration("blue"), (0, 0, 1)),
+            ],
+        );
+        assert_eq!(
+            properties.get("color"),
+            Some(&CSSValue::Keyword("red".into()))
+        );
+
+        let mut properties = PropertyMap::new();
+        merge_properties(
+            &mut properties,
+            &[
+                (declaration("blue"), (0, 0, 1)),
+                (declaration("red"), (0, 1, 0)),
+            ],
+        );
+        assert_eq!(
+            properties.get("color"),
+            Some(&CSSValue::Keyword("red".into()))
+        );
+
+        let mut properties = PropertyMap::new();
+        merge_properties(
+            &mut properties,
+            &[
+                (declaration("blue"), (0, 0, 1)),
+                (declaration("red"), (0, 0, 1)),
+            ],
+        );
+        assert_eq!(
+            properties.get("color"),
+            Some(&CSSValue::Keyword("red".into()))
+        );
+    }
+
+    #[test]
+    fn test_to_styled_node_table_column() {
+        let e = &Element::new(
+            "table".to_string(),
+            AttrMap::new(),
+            vec![Element::new(
+                "colgroup".to_string(),
+                AttrMap::new(),
+                vec![Element::new("col".to_string(), AttrMap::new(), vec![])],
+            )],
+        );
+
+        // table { display: block; }
+        // colgroup { display: table-column-group; }
+        // col { display: table-column; }
+        let rule = |tag_name: &str, display: &str| Rule {
+            selectors: vec![SimpleSelector::TypeSelector {
+                tag_name: tag_name.into(),
+            }],
+            declarations: vec![Declaration {
+                name: "display".to_string(),
+                value: CSSValue::Keyword(display.to_string().into()),
+                important: false,
+            }],
+            ..Default::default()
+        };
+        let stylesheet = Stylesheet::new(vec![
+            rule("table", "block"),
+            rule("colgroup", "table-column-group"),
+            rule("col", "table-column"),
+        ]);
+
+        let table = to_styled_node(e, &stylesheet).unwrap();
+        assert_eq!(table.display(), Display::Block);
+        assert_eq!(table.children.len(), 1);
+        let colgroup = &table.children[0];
+        assert_eq!(colgroup.display(), Display::TableColumnGroup);
+        assert_eq!(colgroup.children.len(), 1);
+        assert_eq!(colgroup.children[0].display(), Display::TableColumn);
+        assert_eq!(
+            colgroup.children[0].properties.get("font-weight"),
+            Some(&CSSValue::Keyword("normal".into()))
+        );
+    }
+
+    #[test]
+    fn test_to_styled_node_default_display() {
+        let e = &Element::new(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![
+                Element::new("span".to_string(), AttrMap::new(), vec![]),
+                Element::new("p".to_string(), AttrMap::new(), vec![]),
+            ],
+        );
+        let stylesheet = Stylesheet::new(vec![]);
+
+        let styled = to_styled_node(e, &stylesheet).unwrap();
+        assert_eq!(styled.children[0].display(), Display::Inline);
+
+        let context = StyleContext {
+            default_display: [("span".to_string(), Display::Block)]
+                .iter()
+                .cloned()
+                .collect(),
+            ..Default::default()
+        };
+        let styled = to_styled_node_with_context(e, &stylesheet, &context).unwrap();
+        assert_eq!(styled.display(), Display::Inline);
+        assert_eq!(styled.children[0].display(), Display::Block);
+        assert_eq!(styled.children[1].display(), Display::Inline);
+
+        // span { display: grid; }
+        let stylesheet = Stylesheet::new(vec![Rule {
+            selectors: vec![SimpleSelector::TypeSelector {
+                tag_name: "span".into(),
+            }],
+            declarations: vec![Declaration {
+                name: "display".to_string(),
+                value: CSSValue::Keyword("grid".into()),
+                important: false,
+            }],
+            ..Default::default()
+        }]);
+        let styled = to_styled_node_with_context(e, &stylesheet, &context).unwrap();
+        assert_eq!(styled.children[0].display(), Display::Grid);
+    }
+
+    #[test]
+    fn test_to_styled_node_first_of_type() {
+        // <div><div></div><p></p><p></p></div>
+        let e = &Element::new(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![
+                Element::new("div".to_string(), AttrMap::new(), vec![]),
+                Element::new("p".to_string(), AttrMap::new(), vec![]),
+                Element::new("p".to_string(), AttrMap::new(), vec![]),
+            ],
+        );
+
+        // p:first-of-type { display: block; }
+        let stylesheet = Stylesheet::new(vec![Rule {
+            selectors: vec![SimpleSelector::PseudoClassSelector {
+                tag_name: Some("p".into()),
+                pseudo_class: PseudoClass::FirstOfType,
+            }],
+            declarations: vec![Declaration {
+                name: "display".to_string(),
+                value: CSSValue::Keyword("block".into()),
+                important: false,
+            }],
+            ..Default::default()
+        }]);
+
+        let styled = to_styled_node(e, &stylesheet).unwrap();
+        assert_eq!(styled.display(), Display::Inline);
+        assert_eq!(styled.children[0].display(), Display::Inline);
+        assert_eq!(styled.children[1].display(), Display::Block);
+        assert_eq!(styled.children[2].display(), Display::Inline);
+    }
+
+    #[test]
+    fn test_to_styled_node_lang() {
+        // <html lang="en"><p></p><div lang="fr"><p></p></div></html>
+        let e = &Element::new(
+            "html".to_string(),
+            [("lang".to_string(), "en".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            vec![
+                Element::new("p".to_string(), AttrMap::new(), vec![]),
+                Element::new(
+                    "div".to_string(),
+                    [("lang".to_string(), "fr".to_string())]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                    vec![Element::new("p".to_string(), AttrMap::new(), vec![])],
+                ),
+            ],
+        );
+
+        // p:lang(en) { display: block; }
+        let stylesheet = Stylesheet::new(vec![Rule {
+            selectors: vec![SimpleSelector::PseudoClassSelector {
+                tag_name: Some("p".into()),
+                pseudo_class: PseudoClass::Lang("en".into()),
+            }],
+            declarations: vec![Declaration {
+                name: "display".to_string(),
+                value: CSSValue::Keyword("block".into()),
+                important: false,
+            }],
+            ..Default::default()
+        }]);
+
+        let styled = to_styled_node(e, &stylesheet).unwrap();
+        assert_eq!(styled.children[0].display(), Display::Block);
+        assert_eq!(styled.children[1].children[0].display(), Display::Inline);
+    }
+
+    #[test]
+    fn test_to_styled_node_font_size() {
+        // <div><p><span></span></p></div>
+        let e = &Element::new(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![Element::new(
+                "p".to_string(),
+                AttrMap::new(),
+                vec![Element::new("span".to_string(), AttrMap::new(), vec![])],
+            )],
+        );
+        let rule = |tag_name: &str, value: CSSValue| Rule {
+            selectors: vec![SimpleSelector::TypeSelector {
+                tag_name: tag_name.into(),
+            }],
+            declarations: vec![Declaration {
+                name: "font-size".to_string(),
+                value,
+                important: false,
+            }],
+            ..Default::default()
+        };
+        let font_size = |n: &StyledNode| match n.properties.get("font-size") {
+            Some(CSSValue::Length((size, Unit::Px))) => *size,
+            v => panic!("unexpected font-size: {:?}", v),
+        };
+
+        // div { font-size: large; }
+        // p { font-size: larger; }
+        // span { font-size: 0.5em; }
+        let stylesheet = Stylesheet::new(vec![
+            rule("div", CSSValue::Keyword("large".into())),
+            rule("p", CSSValue::Keyword("larger".into())),
+            rule("span", CSSValue::Length((0.5, Unit::Em))),
+        ]);
+        let div = to_styled_node(e, &stylesheet).unwrap();
+        let p = &div.children[0];
+        let span = &p.children[0];
+        assert_eq!(font_size(&div), 19.2);
+        assert!(font_size(p) > font_size(&div));
+        assert_eq!(font_size(span), font_size(p) / 2.0);
+
+        // p { font-size: smaller; }
+        let stylesheet = Stylesheet::new(vec![rule("p", CSSValue::Keyword("smaller".into()))]);
+        let div = to_styled_node(e, &stylesheet).unwrap();
+        let p = &div.children[0];
+        assert_eq!(font_size(&div), MEDIUM_FONT_SIZE);
+        assert!(font_size(p) < MEDIUM_FONT_SIZE);
+        assert_eq!(font_size(&p.children[0]), font_size(p));
+
+        // `rem` refers to the font size of the root element, except in its own font size
+        let stylesheet = parse(
+            "div { font-size: 1.25rem; width: 2rem; } p { font-size: 2rem; margin: 1rem 0; } span { font-size: 1em; }",
+        );
+        let div = to_styled_node(e, &stylesheet).unwrap();
+        let p = &div.children[0];
+        assert_eq!(font_size(&div), 20.0);
+        assert_eq!(
+            div.properties.get("width"),
+            Some(&CSSValue::Length((40.0, Unit::Px)))
+        );
+        assert_eq!(font_size(p), 40.0);
+        assert_eq!(
+            p.properties.get("margin-top"),
+            Some(&CSSValue::Length((20.0, Unit::Px)))
+        );
+        assert_eq!(font_size(&p.children[0]), 40.0);
+    }
+
+    #[test]
+    fn test_to_styled_node_direction() {
+        // <div dir="rtl"><p><span></span></p><p dir="ltr"></p></div>
+        let e = &Element::new(
+            "div".to_string(),
+            [("dir".to_string(), "rtl".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            vec![
+                Element::new(
+                    "p".to_string(),
+                    AttrMap::new(),
+                    vec![Element::new("span".to_string(), AttrMap::new(), vec![])],
+                ),
+                Element::new(
+                    "p".to_string(),
+                    [("dir".to_string(), "ltr".to_string())]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                    vec![],
+                ),
+            ],
+        );
+        let direction = |n: &StyledNode| n.properties.get("direction").cloned();
+        let rtl = Some(CSSValue::Keyword("rtl".into()));
+        let ltr = Some(CSSValue::Keyword("ltr".into()));
+
+        let div = to_styled_node(e, &Stylesheet::new(vec![])).unwrap();
+        assert_eq!(direction(&div), rtl);
+        assert_eq!(direction(&div.children[0].children[0]), rtl);
+        assert_eq!(direction(&div.children[1]), ltr);
+
+        // p { direction: ltr; }
+        let stylesheet = Stylesheet::new(vec![Rule {
+            selectors: vec![SimpleSelector::TypeSelector {
+                tag_name: "p".into(),
+            }],
+            declarations: vec![Declaration {
+                name: "direction".to_string(),
+                value: CSSValue::Keyword("ltr".into()),
+                important: false,
+            }],
+            ..Default::default()
+        }]);
+        let div = to_styled_node(e, &stylesheet).unwrap();
+        assert_eq!(direction(&div), rtl);
+        assert_eq!(direction(&div.children[0]), ltr);
+        assert_eq!(direction(&div.children[0].children[0]), ltr);
+    }
+
+    #[test]
+    fn test_restyle_subtree() {
+        // <div><p class="a"><span></span></p><p class="b"></p></div>
+        let old_root = &Element::new(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![
+                Element::new(
+                    "p".to_string(),
+                    [("class".to_string(), "a".to_string())]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                    vec![Element::new("span".to_string(), AttrMap::new(), vec![])],
+                ),
+                Element::new(
+                    "p".to_string(),
+                    [("class".to_string(), "b".to_string())]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                    vec![],
+                ),
+            ],
+        );
+
+        // .a { font-size: large; }
+        // .c { font-size: small; display: block; }
+        let stylesheet = Stylesheet::new(vec![
+            Rule {
+                selectors: vec![SimpleSelector::ClassSelector {
+                    class_names: vec!["a".into()],
+                }],
+                declarations: vec![Declaration {
+                    name: "font-size".to_string(),
+                    value: CSSValue::Keyword("large".into()),
+                    important: false,
+                }],
+                ..Default::default()
+            },
+            Rule {
+                selectors: vec![SimpleSelector::ClassSelector {
+                    class_names: vec!["c".into()],
+                }],
+                declarations: vec![
+                    Declaration {
+                        name: "font-size".to_string(),
+                        value: CSSValue::Keyword("small".into()),
+                        important: false,
+                    },
+                    Declaration {
+                        name: "display".to_string(),
+                        value: CSSValue::Keyword("block".into()),
+                        important: false,
+                    },
+                ],
+                ..Default::default()
+            },
+        ]);
+        let mut old = to_styled_node(old_root, &stylesheet).unwrap();
+        // mark the untouched subtree to check that its styles are reused
+        old.children[1]
+            .properties
+            .insert("marker".into(), CSSValue::Keyword("reused".into()));
+
+        // change the class of the first `p` from `a` to `c`
+        let mut new_root = old_root.clone_tree();
+        if let NodeType::Element(ref mut e) = new_root.children[0].node_type {
+            e.attributes.insert("class".into(), "c".into());
+        }
+
+        let restyled = restyle_subtree(old, old_root, &new_root, &[0], &stylesheet).unwrap();
+        let expected = to_styled_node(&new_root, &stylesheet).unwrap();
+        assert_eq!(restyled.properties, expected.properties);
+        assert_eq!(restyled.children[0], expected.children[0]);
+        assert_eq!(restyled.children[0].display(), Display::Block);
+        assert_eq!(
+            restyled.children[0].children[0].properties.get("font-size"),
+            absolute_font_size("small")
+                .map(|size| CSSValue::Length((size, Unit::Px)))
+                .as_ref()
+        );
+        assert!(std::ptr::eq(
+            restyled.children[1].node_type,
+            &new_root.children[1].node_type
+        ));
+        assert_eq!(
+            restyled.children[1].properties.get("marker"),
+            Some(&CSSValue::Keyword("reused".into()))
+        );
+    }
+
+    #[test]
+    fn test_document_mode_from_document() {
+        let body = || Element::new("body".to_string(), AttrMap::new(), vec![]);
+        let html = |children| Element::new("html".to_string(), AttrMap::new(), children);
+
+        assert_eq!(
+            DocumentMode::from_document(&html(vec![
+                DocumentType::new_node("html".to_string()),
+                body()
+            ])),
+            DocumentMode::NoQuirks
+        );
+        assert_eq!(
+            DocumentMode::from_document(&html(vec![
+                DocumentType::new_node("HTML".to_string()),
+                body()
+            ])),
+            DocumentMode::NoQuirks
+        );
+        assert_eq!(
+            DocumentMode::from_document(&html(vec![
+                DocumentType::new_node("foo".to_string()),
+                body()
+            ])),
+            DocumentMode::Quirks
+        );
+        assert_eq!(
+            DocumentMode::from_document(&html(vec![body()])),
+            DocumentMode::Quirks
+        );
+    }
+
+    #[test]
+    fn test_to_styled_node_quirks_mode() {
+        // <div><table></table></div>
+        let e = &Element::new(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![Element::new("table".to_string(), AttrMap::new(), vec![])],
+        );
+
+        // div { width: 100; font-size: large; }
+        let stylesheet = Stylesheet::new(vec![Rule {
+            selectors: vec![SimpleSelector::TypeSelector {
+                tag_name: "div".into(),
+            }],
+            declarations: vec![
+                Declaration {
+                    name: "width".to_string(),
+                    value: CSSValue::Number(100.0),
+                    important: false,
+                },
+                Declaration {
+                    name: "font-size".to_string(),
+                    value: CSSValue::Keyword("large".into()),
+                    important: false,
+                },
+            ],
+            ..Default::default()
+        }]);
+        let large = absolute_font_size("large").unwrap();
+
+        let quirks = StyleContext {
+            mode: DocumentMode::Quirks,
+            ..Default::default()
+        };
+        let styled = to_styled_node_with_context(e, &stylesheet, &quirks).unwrap();
+        assert_eq!(
+            styled.properties.get("width"),
+            Some(&CSSValue::Length((100.0, Unit::Px)))
+        );
+        assert_eq!(
+            styled.children[0].properties.get("font-size"),
+            Some(&CSSValue::Length((MEDIUM_FONT_SIZE, Unit::Px)))
+        );
+
+        let styled = to_styled_node(e, &stylesheet).unwrap();
+        assert_eq!(styled.properties.get("width"), None);
+        assert_eq!(
+            styled.children[0].properties.get("font-size"),
+            Some(&CSSValue::Length((large, Unit::Px)))
+        );
+
+        // the invalid number does not override the valid length in standards mode
+        let stylesheet = parse("div { width: 50px; } div { width: 100; }");
+        let styled = to_styled_node(e, &stylesheet).unwrap();
+        assert_eq!(
+            styled.properties.get("width"),
+            Some(&CSSValue::Length((50.0, Unit::Px)))
+        );
+        let styled = to_styled_node_with_context(e, &stylesheet, &quirks).unwrap();
+        assert_eq!(
+            styled.properties.get("width"),
+            Some(&CSSValue::Length((100.0, Unit::Px)))
+        );
+    }
+
+    #[test]
+    fn test_to_styled_node_background() {
+        let e = &Element::new("p".to_string(), AttrMap::new(), vec![]);
+        let rule = |declarations: Vec<Declaration>| Rule {
+            selectors: vec![SimpleSelector::TypeSelector {
+                tag_name: "p".into(),
+            }],
+            declarations,
+            ..Default::default()
+        };
+        let background = |value: CSSValue| Declaration {
+            name: "background".to_string(),
+            value,
+            important: false,
+        };
+
+        // p { background: #00f; }
+        let stylesheet = Stylesheet::new(vec![rule(vec![background(CSSValue::Color(
+            Color::rgb(0, 0, 255),
+        ))])]);
+        let properties = to_styled_node(e, &stylesheet).unwrap().properties;
+        assert_eq!(
+            properties.get("background-color"),
+            Some(&CSSValue::Color(Color::rgb(0, 0, 255)))
+        );
+        assert_eq!(
+            properties.get("background-image"),
+            Some(&CSSValue::Keyword("none".into()))
+        );
+        assert_eq!(
+            properties.get("background-repeat"),
+            Some(&CSSValue::Keyword("repeat".into()))
+        );
+        assert_eq!(properties.get("background"), None);
+
+        // p { background-color: blue; background: red url(x.png) center no-repeat; }
+        let stylesheet = Stylesheet::new(vec![rule(vec![
+            Declaration {
+                name: "background-color".to_string(),
+                value: CSSValue::Keyword("blue".into()),
+                important: false,
+            },
+            background(CSSValue::List(vec![
+                CSSValue::Keyword("red".into()),
+                CSSValue::Url("x.png".to_string()),
+                CSSValue::Keyword("center".into()),
+                CSSValue::Keyword("no-repeat".into()),
+            ])),
+        ])]);
+        let properties = to_styled_node(e, &stylesheet).unwrap().properties;
+        assert_eq!(
+            properties.get("background-color"),
+            Some(&CSSValue::Keyword("red".into()))
+        );
+        assert_eq!(
+            properties.get("background-image"),
+            Some(&CSSValue::Url("x.png".to_string()))
+        );
+        assert_eq!(
+            properties.get("background-repeat"),
+            Some(&CSSValue::Keyword("no-repeat".into()))
+        );
+    }
+
+    #[test]
+    fn test_expand_shorthand_outline() {
+        let outline = |values: Vec<CSSValue>| Declaration {
+            name: "outline".to_string(),
+            value: CSSValue::List(values),
+            important: false,
+        };
+        let keyword = |s: &str| CSSValue::Keyword(s.to_string().into());
+        let longhands = |values: Vec<CSSValue>| {
+            ["outline-width", "outline-style", "outline-color"]
+                .iter()
+                .zip(values)
+                .map(|(name, value)| Declaration {
+                    name: name.to_string(),
+                    value,
+                    important: false,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // outline: red solid 2px
+        assert_eq!(
+            expand_shorthand(&outline(vec![
+                keyword("red"),
+                keyword("solid"),
+                CSSValue::Length((2.0, Unit::Px)),
+            ])),
+            longhands(vec![
+                CSSValue::Length((2.0, Unit::Px)),
+                keyword("solid"),
+                keyword("red"),
+            ])
+        );
+
+        // outline: dashed
+        assert_eq!(
+            expand_shorthand(&Declaration {
+                name: "outline".to_string(),
+                value: keyword("dashed"),
+                important: false,
+            }),
+            longhands(vec![
+                keyword("medium"),
+                keyword("dashed"),
+                keyword("currentcolor"),
+            ])
+        );
+
+        // outline: solid center
+        assert_eq!(
+            expand_shorthand(&outline(vec![keyword("solid"), keyword("center")])),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_expand_shorthand_box_sides() {
+        let px = |num: f32| CSSValue::Length((num, Unit::Px));
+        let expand = |name: &str, values: Vec<CSSValue>| {
+            expand_shorthand(&Declaration {
+                name: name.to_string(),
+                value: CSSValue::List(values),
+                important: false,
+            })
+            .into_iter()
+            .map(|d| (d.name, d.value))
+            .collect::<Vec<_>>()
+        };
+        let longhands = |names: [&str; 4], values: [CSSValue; 4]| {
+            names
+                .iter()
+                .map(|n| n.to_string())
+                .zip(values)
+                .collect::<Vec<_>>()
+        };
+        let margins = ["margin-top", "margin-right", "margin-bottom", "margin-left"];
+
+        // margin: 10px
+        assert_eq!(
+            expand_shorthand(&Declaration {
+                name: "margin".to_string(),
+                value: px(10.0),
+                important: false,
+            })
+            .len(),
+            4
+        );
+        // margin: 10px 20px
+        assert_eq!(
+            expand("margin", vec![px(10.0), px(20.0)]),
+            longhands(margins, [px(10.0), px(20.0), px(10.0), px(20.0)])
+        );
+        // margin: 10px auto 30px
+        assert_eq!(
+            expand(
+                "margin",
+                vec![px(10.0), CSSValue::keyword("auto"), px(30.0)]
+            ),
+            longhands(
+                margins,
+                [
+                    px(10.0),
+                    CSSValue::keyword("auto"),
+                    px(30.0),
+                    CSSValue::keyword("auto")
+                ]
+            )
+        );
+        // padding: 1px 2px 3px 4px
+        assert_eq!(
+            expand("padding", vec![px(1.0), px(2.0), px(3.0), px(4.0)]),
+            longhands(
+                [
+                    "padding-top",
+                    "padding-right",
+                    "padding-bottom",
+                    "padding-left"
+                ],
+                [px(1.0), px(2.0), px(3.0), px(4.0)]
+            )
+        );
+        // border-width: 1px 2px
+        assert_eq!(
+            expand("border-width", vec![px(1.0), px(2.0)]),
+            longhands(
+                [
+                    "border-top-width",
+                    "border-right-width",
+                    "border-bottom-width",
+                    "border-left-width"
+                ],
+                [px(1.0), px(2.0), px(1.0), px(2.0)]
+            )
+        );
+        // margin: 1px 2px 3px 4px 5px
+        assert_eq!(
+            expand("margin", vec![px(1.0), px(2.0), px(3.0), px(4.0), px(5.0)]),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_expand_shorthand_border() {
+        // border: 2px solid red
+        let longhands = expand_shorthand(&Declaration {
+            name: "border".to_string(),
+            value: CSSValue::List(vec![
+                CSSValue::Length((2.0, Unit::Px)),
+                CSSValue::keyword("solid"),
+                CSSValue::keyword("red"),
+            ]),
+            important: false,
+        });
+        assert_eq!(longhands.len(), 12);
+        for side in ["top", "right", "bottom", "left"] {
+            let value = |name: String| {
+                longhands
+                    .iter()
+                    .find(|d| d.name == name)
+                    .map(|d| d.value.clone())
+            };
+            assert_eq!(
+                value(format!("border-{}-width", side)),
+                Some(CSSValue::Length((2.0, Unit::Px)))
+            );
+            assert_eq!(
+                value(format!("border-{}-style", side)),
+                Some(CSSValue::keyword("solid"))
+            );
+            assert_eq!(
+                value(format!("border-{}-color", side)),
+                Some(CSSValue::keyword("red"))
+            );
+        }
+
+        // border: solid center
+        assert_eq!(
+            expand_shorthand(&Declaration {
+                name: "border".to_string(),
+                value: CSSValue::List(vec![
+                    CSSValue::keyword("solid"),
+                    CSSValue::keyword("center"),
+                ]),
+                important: false,
+            }),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_expand_shorthand_font() {
+        let font = |values: Vec<CSSValue>| Declaration {
+            name: "font".to_string(),
+            value: CSSValue::List(values),
+            important: false,
+        };
+        let keyword = |s: &str| CSSValue::Keyword(s.to_string().into());
+        let longhands = |values: Vec<CSSValue>| {
+            [
+                "font-style",
+                "font-weight",
+                "font-size",
+                "line-height",
+                "font-family",
+            ]
+            .iter()
+            .zip(values)
+            .map(|(name, value)| Declaration {
+                name: name.to_string(),
+                value,
+                important: false,
+            })
+            .collect::<Vec<_>>()
+        };
+
+        // font: italic bold 16px/1.5 sans-serif
+        assert_eq!(
+            expand_shorthand(&font(vec![
+                keyword("italic"),
+                keyword("bold"),
+                CSSValue::Length((16.0, Unit::Px)),
+                CSSValue::Slash,
+                CSSValue::Number(1.5),
+                keyword("sans-serif"),
+            ])),
+            longhands(vec![
+                keyword("italic"),
+                keyword("bold"),
+                CSSValue::Length((16.0, Unit::Px)),
+                CSSValue::Number(1.5),
+                keyword("sans-serif"),
+            ])
+        );
+
+        // font: large Times New Roman
+        assert_eq!(
+            expand_shorthand(&font(vec![
+                keyword("large"),
+                keyword("Times"),
+                keyword("New"),
+                keyword("Roman"),
+            ])),
+            longhands(vec![
+                keyword("normal"),
+                keyword("normal"),
+                keyword("large"),
+                keyword("normal"),
+                CSSValue::List(vec![keyword("Times"), keyword("New"), keyword("Roman")]),
+            ])
+        );
+
+        // font: bold serif (no font-size)
+        assert_eq!(
+            expand_shorthand(&font(vec![keyword("bold"), keyword("serif")])),
+            vec![]
+        );
+
+        // font: 12px (no font-family)
+        assert_eq!(
+            expand_shorthand(&Declaration {
+                name: "font".to_string(),
+                value: CSSValue::Length((12.0, Unit::Px)),
+                important: false,
+            }),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_to_styled_node_pseudo_elements() {
+        let e = &Element::new(
+            "p".to_string(),
+            AttrMap::new(),
+            vec![Text::new("hello".to_string())],
+        );
+        let pseudo_element_rule = |pseudo_element, declarations| Rule {
+            selectors: vec![SimpleSelector::PseudoElementSelector {
+                selector: Box::new(SimpleSelector::TypeSelector {
+                    tag_name: "p".into(),
+                }),
+                pseudo_element,
+            }],
+            declarations,
+            ..Default::default()
+        };
+
+        // p { font-size: 10px; }
+        // p::first-letter { font-size: 2em; display: block; }
+        // p::first-line { color: red; }
+        let stylesheet = Stylesheet::new(vec![
+            Rule {
+                selectors: vec![SimpleSelector::TypeSelector {
+                    tag_name: "p".into(),
+                }],
+                declarations: vec![Declaration {
+                    name: "font-size".to_string(),
+                    value: CSSValue::Length((10.0, Unit::Px)),
+                    important: false,
+                }],
+                ..Default::default()
+            },
+            pseudo_element_rule(
+                PseudoElement::FirstLetter,
+                vec![
+                    Declaration {
+                        name: "font-size".to_string(),
+                        value: CSSValue::Length((2.0, Unit::Em)),
+                        important: false,
+                    },
+                    Declaration {
+                        name: "display".to_string(),
+                        value: CSSValue::Keyword("block".into()),
+                        important: false,
+                    },
+                ],
+            ),
+            pseudo_element_rule(
+                PseudoElement::FirstLine,
+                vec![Declaration {
+                    name: "color".to_string(),
+                    value: CSSValue::Keyword("red".into()),
+                    important: false,
+                }],
+            ),
+        ]);
+        let styled = to_styled_node(e, &stylesheet).unwrap();
+
+        // the element itself is not affected
+        assert_eq!(
+            styled.properties.get("font-size"),
+            Some(&CSSValue::Length((10.0, Unit::Px)))
+        );
+        assert_eq!(styled.properties.get("color"), None);
+        // unsupported properties are ignored, and `em` is resolved against the element
+        assert_eq!(
+            pseudo_element_properties(&styled.properties, PseudoElement::FirstLetter),
+            [("font-size".to_string(), CSSValue::Length((20.0, Unit::Px)))]
+                .iter()
+                .cloned()
+                .collect()
+        );
+        assert_eq!(
+            pseudo_element_properties(&styled.properties, PseudoElement::FirstLine),
+            [("color".to_string(), CSSValue::Keyword("red".into()))]
+                .iter()
+                .cloned()
+                .collect()
+        );
+        // the text has no pseudo-elements of its own
+        assert_eq!(
+            pseudo_element_properties(&styled.children[0].properties, PseudoElement::FirstLetter),
+            PropertyMap::new()
+        );
+        assert_eq!(unused_rules(e, &stylesheet), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_to_styled_node_nested_rule() {
+        // <div><div class="card"><p class="title"></p></div><p class="title"></p></div>
+        let root = &build_tree(&TreeSpec::Element(
+            "div",
+            &[],
+            &[
+                TreeSpec::Element(
+                    "div",
+                    &[("class", "card")],
+                    &[TreeSpec::Element("p", &[("class", "title")], &[])],
+                ),
+                TreeSpec::Element("p", &[("class", "title")], &[]),
+            ],
+        ));
+        // .card { & .title { font-weight: bold } }
+        let card = Rule {
+            selectors: vec![SimpleSelector::ClassSelector {
+                class_names: vec!["card".into()],
+            }],
+            ..Default::default()
+        };
+        let title = NestedRule {
+            selectors: vec![NestedSelector::Descendant(SimpleSelector::ClassSelector {
+                class_names: vec!["title".into()],
+            })],
+            declarations: vec![Declaration {
+                name: "font-weight".into(),
+                value: CSSValue::keyword("bold"),
+                important: false,
+            }],
+        }
+        .desugar(&card);
+        let stylesheet = Stylesheet::new(vec![card, title]);
+
+        let styled = to_styled_node(root, &stylesheet).unwrap();
+        assert_eq!(
+            styled.children[0].children[0].properties.get("font-weight"),
+            Some(&CSSValue::keyword("bold"))
+        );
+        assert_eq!(
+            styled.children[1].properties.get("font-weight"),
+            Some(&CSSValue::keyword("normal"))
+        );
+    }
+
+    #[test]
+    fn test_resolve_font_family() {
+        let available_fonts = ["Arial", "Times New Roman"]
+            .iter()
+            .collect::<AvailableFonts>();
+        // "Helvetica", Arial, sans-serif
+        let stack = CSSValue::List(vec![
+            CSSValue::String("Helvetica".into()),
+            CSSValue::Comma,
+            CSSValue::keyword("Arial"),
+            CSSValue::Comma,
+            CSSValue::keyword("sans-serif"),
+        ]);
+        assert_eq!(
+            resolve_font_family(&stack, &available_fonts),
+            CSSValue::String("Arial".into())
+        );
+        // Times New Roman, serif
+        let stack = CSSValue::List(vec![
+            CSSValue::keyword("Times"),
+            CSSValue::keyword("New"),
+            CSSValue::keyword("Roman"),
+            CSSValue::Comma,
+            CSSValue::keyword("serif"),
+        ]);
+        assert_eq!(
+            resolve_font_family(&stack, &available_fonts),
+            CSSValue::String("Times New Roman".into())
+        );
+        // "Helvetica", monospace
+        let stack = CSSValue::List(vec![
+            CSSValue::String("Helvetica".into()),
+            CSSValue::Comma,
+            CSSValue::keyword("monospace"),
+        ]);
+        assert_eq!(
+            resolve_font_family(&stack, &available_fonts),
+            CSSValue::keyword("monospace")
+        );
+        // "Helvetica"
+        assert_eq!(
+            resolve_font_family(&CSSValue::String("Helvetica".into()), &available_fonts),
+            CSSValue::keyword("sans-serif")
+        );
+        // "ARIAL"
+        assert_eq!(
+            resolve_font_family(&CSSValue::String("ARIAL".into()), &available_fonts),
+            CSSValue::String("ARIAL".into())
+        );
+    }
+
+    #[test]
+    fn test_to_styled_node_font_family() {
+        let root = &build_tree(&TreeSpec::Element(
+            "p",
+            &[],
+            &[TreeSpec::Element("span", &[], &[])],
+        ));
+        let stylesheet = Stylesheet::new(vec![Rule {
+            selectors: vec![SimpleSelector::TypeSelector {
+                tag_name: "p".into(),
+            }],
+            declarations: vec![Declaration {
+                name: "font-family".into(),
+                value: CSSValue::List(vec![
+                    CSSValue::String("Helvetica".into()),
+                    CSSValue::Comma,
+                    CSSValue::keyword("Arial"),
+                ]),
+                important: false,
+            }],
+            ..Default::default()
+        }]);
+
+        let context = StyleContext {
+            available_fonts: Some(["Arial".to_string()].into_iter().collect()),
+            ..Default::default()
+        };
+        let styled = to_styled_node_with_context(root, &stylesheet, &context).unwrap();
+        assert_eq!(
+            styled.properties.get("font-family"),
+            Some(&CSSValue::String("Arial".into()))
+        );
+        // the span inherits the resolved family
+        assert_eq!(
+            styled.children[0].properties.get("font-family"),
+            Some(&CSSValue::String("Arial".into()))
+        );
+
+        // kept as specified without available fonts
+        let styled = to_styled_node(root, &stylesheet).unwrap();
+        assert_eq!(
+            styled.properties.get("font-family"),
+            Some(&CSSValue::List(vec![
+                CSSValue::String("Helvetica".into()),
+                CSSValue::Comma,
+                CSSValue::keyword("Arial"),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_to_styled_node_line_height() {
+        // <p><span></span></p><div><span></span></div>
+        let root = &build_tree(&TreeSpec::Element(
+            "body",
+            &[],
+            &[
+                TreeSpec::Element("p", &[], &[TreeSpec::Element("span", &[], &[])]),
+                TreeSpec::Element("div", &[], &[TreeSpec::Element("span", &[], &[])]),
+            ],
+        ));
+        let rule = |tag_name: &str, declarations: Vec<(&str, CSSValue)>| Rule {
+            selectors: vec![SimpleSelector::TypeSelector {
+                tag_name: tag_name.into(),
+            }],
+            declarations: declarations
+                .into_iter()
+                .map(|(name, value)| Declaration {
+                    name: name.into(),
+                    value,
+                    important: false,
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let stylesheet = Stylesheet::new(vec![
+            // p { font-size: 20px; line-height: 150%; }
+            rule(
+                "p",
+                vec![
+                    ("font-size", CSSValue::Length((20.0, Unit::Px))),
+                    ("line-height", CSSValue::Length((150.0, Unit::Percent))),
+                ],
+            ),
+            // div { font-size: 20px; line-height: 1.5; }
+            rule(
+                "div",
+                vec![
+                    ("font-size", CSSValue::Length((20.0, Unit::Px))),
+                    ("line-height", CSSValue::Number(1.5)),
+                ],
+            ),
+            // span { font-size: 10px; }
+            rule(
+                "span",
+                vec![("font-size", CSSValue::Length((10.0, Unit::Px)))],
+            ),
+        ]);
+
+        let styled = to_styled_node(root, &stylesheet).unwrap();
+        assert_eq!(styled.properties.get("line-height"), None);
+        let (p, div) = (&styled.children[0], &styled.children[1]);
+        assert_eq!(
+            p.properties.get("line-height"),
+            Some(&CSSValue::Length((30.0, Unit::Px)))
+        );
+        // the percentage is inherited as the computed length
+        assert_eq!(
+            p.children[0].properties.get("line-height"),
+            Some(&CSSValue::Length((30.0, Unit::Px)))
+        );
+        // numbers are inherited as they are
+        assert_eq!(
+            div.properties.get("line-height"),
+            Some(&CSSValue::Number(1.5))
+        );
+        assert_eq!(
+            div.children[0].properties.get("line-height"),
+            Some(&CSSValue::Number(1.5))
+        );
+    }
+
+    #[test]
+    fn test_to_styled_node_inheritance() {
+        // <div><p class="a">hello</p></div>
+        let root = &build_tree(&TreeSpec::Element(
+            "div",
+            &[],
+            &[TreeSpec::Element(
+                "p",
+                &[("class", "a")],
+                &[TreeSpec::Text("hello")],
+            )],
+        ));
+        let declaration = |name: &str, value: &str| Declaration {
+            name: name.into(),
+            value: CSSValue::keyword(value),
+            important: false,
+        };
+        let stylesheet = Stylesheet::new(vec![
+            // div { color: red; text-align: center; font-weight: bold; background-color: yellow; }
+            Rule {
+                selectors: vec![SimpleSelector::TypeSelector {
+                    tag_name: "div".into(),
+                }],
+                declarations: vec![
+                    declaration("color", "red"),
+                    declaration("text-align", "center"),
+                    declaration("font-weight", "bold"),
+                    declaration("background-color", "yellow"),
+                ],
+                ..Default::default()
+            },
+            // .a { color: blue; }
+            Rule {
+                selectors: vec![SimpleSelector::ClassSelector {
+                    class_names: vec!["a".into()],
+                }],
+                declarations: vec![declaration("color", "blue")],
+                ..Default::default()
+            },
+        ]);
+
+        let styled = to_styled_node(root, &stylesheet).unwrap();
+        let p = &styled.children[0];
+        let text = &p.children[0];
+        for node in [p, text] {
+            // the specified value overrides the inherited one
+            assert_eq!(node.value("color"), Some(&CSSValue::keyword("blue")));
+            assert_eq!(node.value("text-align"), Some(&CSSValue::keyword("center")));
+            assert_eq!(node.value("font-weight"), Some(&CSSValue::keyword("bold")));
+            // non-inherited properties are not taken from the parent
+            assert_eq!(node.value("background-color"), None);
+        }
+    }
+
+    #[test]
+    fn test_to_styled_node_combinators() {
+        // <div><p>a</p><section><p>b</p></section></div>
+        let root = &build_tree(&TreeSpec::Element(
+            "div",
+            &[],
+            &[
+                TreeSpec::Element("p", &[], &[TreeSpec::Text("a")]),
+                TreeSpec::Element(
+                    "section",
+                    &[],
+                    &[TreeSpec::Element("p", &[], &[TreeSpec::Text("b")])],
+                ),
+            ],
+        ));
+        let stylesheet = parse("div p { color: red; } div > p { color: blue; }");
+
+        let styled = to_styled_node(root, &stylesheet).unwrap();
+        let color = |path: &[usize]| {
+            let node = path.iter().fold(&styled, |n, &i| &n.children[i]);
+            node.value("color").cloned()
+        };
+        assert_eq!(color(&[0]), Some(CSSValue::keyword("blue")));
+        assert_eq!(color(&[1, 0]), Some(CSSValue::keyword("red")));
+    }
+
+    #[test]
+    fn test_to_styled_node_cascade_layers() {
+        let root = &build_tree(&TreeSpec::Element("p", &[("class", "a")], &[]));
+        let rule = |selector: SimpleSelector, color: &str, layer: Option<usize>| Rule {
+            selectors: vec![selector],
+            declarations: vec![Declaration {
+                name: "color".into(),
+                value: CSSValue::keyword(color),
+                important: false,
+            }],
+            layer,
+            ..Default::default()
+        };
+        let class = || SimpleSelector::ClassSelector {
+            class_names: vec!["a".into()],
+        };
+        let p = || SimpleSelector::TypeSelector {
+            tag_name: "p".into(),
+        };
+        let color = |stylesheet: Stylesheet| {
+            let expected = to_styled_node(root, &stylesheet).unwrap();
+            let sorted = to_styled_node(root, &stylesheet.into_sorted()).unwrap();
+            assert_eq!(sorted, expected);
+            expected.properties.get("color").cloned()
+        };
+
+        // @layer base, components;
+        // @layer components { .a { color: blue; } }
+        // @layer base { .a { color: red; } }
+        assert_eq!(
+            color(Stylesheet::new(vec![
+                rule(class(), "blue", Some(1)),
+                rule(class(), "red", Some(0)),
+            ])),
+            Some(CSSValue::keyword("blue"))
+        );
+        // specificity does not matter across layers
+        // @layer base { .a { color: red; } }
+        // @layer components { p { color: blue; } }
+        assert_eq!(
+            color(Stylesheet::new(vec![
+                rule(class(), "red", Some(0)),
+                rule(p(), "blue", Some(1)),
+            ])),
+            Some(CSSValue::keyword("blue"))
+        );
+        // unlayered rules win over all the layers
+        // p { color: green; }
+        // @layer components { .a { color: blue; } }
+        let stylesheet = Stylesheet::new(vec![
+            rule(p(), "green", None),
+            rule(class(), "blue", Some(1)),
+        ]);
+        assert_eq!(trace_property(root, &stylesheet, "color").winner, Some(1));
+        assert_eq!(color(stylesheet), Some(CSSValue::keyword("green")));
+    }
+
+    #[test]
+    fn test_styled_node_snapshot() {
+        // <div id="main"><p class="a">hello</p></div>
+        let root = &build_tree(&TreeSpec::Element(
+            "div",
+            &[("id", "main")],
+            &[TreeSpec::Element(
+                "p",
+                &[("class", "a")],
+                &[TreeSpec::Text("hello")],
+            )],
+        ));
+        let stylesheet = Stylesheet::new(vec![
+            // #main { display: block; }
+            Rule {
+                selectors: vec![SimpleSelector::IdSelector { id: "main".into() }],
+                declarations: vec![Declaration {
+                    name: "display".into(),
+                    value: CSSValue::keyword("block"),
+                    important: false,
+                }],
+                ..Default::default()
+            },
+            // .a { font: bold 2em serif; }
+            Rule {
+                selectors: vec![SimpleSelector::ClassSelector {
+                    class_names: vec!["a".into()],
+                }],
+                declarations: vec![Declaration {
+                    name: "font".into(),
+                    value: CSSValue::List(vec![
+                        CSSValue::keyword("bold"),
+                        CSSValue::Length((2.0, Unit::Em)),
+                        CSSValue::keyword("serif"),
+                    ]),
+                    important: false,
+                }],
+                ..Default::default()
+            },
+        ]);
+
+        let styled = to_styled_node(root, &stylesheet).unwrap();
+        // the snapshot is stable regardless of the order in the hash map
+        assert_eq!(
+            styled.snapshot(),
+            to_styled_node(root, &stylesheet).unwrap().snapshot()
+        );
+        assert_styled_snapshot(
+            &styled,
+            r#"
+div
+  direction: ltr;
+  display: block;
+  font-size: 16px;
+  font-weight: normal;
+  p
+    direction: ltr;
+    display: inline;
+    font-family: serif;
+    font-size: 32px;
+    font-style: normal;
+    font-weight: bold;
+    line-height: normal;
+    #text "hello"
+      direction: ltr;
+      display: inline;
+      font-family: serif;
+      font-size: 32px;
+      font-style: normal;
+      font-weight: bold;
+"#,
+        );
+    }
 }